@@ -1,3 +1,5 @@
+use crate::cost::{ModelPrice, PriceTable};
+use crate::llm::{CassetteConfig, CassetteMode};
 use crate::utils::get_git_repository_root;
 use anyhow::{Context, Result};
 use serde::Deserialize;
@@ -10,6 +12,9 @@ pub const IGNORE_FILE: &str = ".dogeignore";
 
 #[derive(Debug, Clone)]
 pub struct AppConfig {
+    // LLM backend: "openai" (OpenAI-compatible chat completions), "openai-responses",
+    // "anthropic", "ollama" or "llamacpp"
+    pub provider: String,
     pub base_url: String,
     pub model: String,
     pub api_key: Option<String>,
@@ -17,11 +22,10 @@ pub struct AppConfig {
     pub git_root: Option<PathBuf>,
     pub llm: LlmConfig,
     pub watch_config: WatchConfig, // Added watch configuration
-    pub enable_stream_tools: bool,
-    pub theme: String,                             // newly added
+    pub theme: String,             // newly added
     pub project_instructions_file: Option<String>, // newly added
-    pub no_repomap: bool,                          // newly added
-    pub resume: bool,                              // newly added
+    pub no_repomap: bool,          // newly added
+    pub resume: bool,              // newly added
     // Auto-compact threshold (configurable via env or config file)
     pub auto_compact_prompt_token_threshold: u32,
     // Per-model overrides for auto-compact threshold
@@ -32,6 +36,185 @@ pub struct AppConfig {
     // Allowed paths for file access
     pub allowed_paths: Vec<PathBuf>,
     pub mcp_servers: Vec<McpServerConfig>,
+    // Capabilities probed from a local inference server at startup
+    pub model_capabilities: Option<crate::llm::ModelCapabilities>,
+    // How tools are offered to the model (native function calling or prompt-based)
+    pub tool_call_mode: ToolCallMode,
+    // Per-model overrides for the tool calling mode
+    pub tool_call_mode_overrides: HashMap<String, ToolCallMode>,
+    // Maximum number of read-only tool calls run concurrently within one turn
+    pub max_parallel_tools: usize,
+    // Per-model context window sizes, overriding the built-in registry
+    pub context_window_overrides: HashMap<String, u32>,
+    // Per-model token prices, overriding the built-in price list
+    pub model_prices: HashMap<String, ModelPrice>,
+    // Cassette to record LLM traffic to or replay it from (--record / --replay)
+    pub cassette: Option<CassetteConfig>,
+    // Models tried in order when the primary one keeps failing during a turn
+    pub fallback_models: Vec<FallbackModel>,
+    // Per-model image input support, overriding the built-in registry
+    pub vision_overrides: HashMap<String, bool>,
+    // Plan mode: only non-mutating tools, and the turn ends with a plan for review
+    pub plan_mode: bool,
+    // Which tool calls run freely, need the user's approval or are refused
+    pub permissions: PermissionsConfig,
+    // Isolation of execute_bash and shell-mode commands
+    pub sandbox: SandboxConfig,
+    // Run execute_bash in one long-lived shell, so cd and exports carry over
+    pub persistent_shell: bool,
+    // Allow and deny rules for every command of a shell command line
+    pub command_policy: CommandPolicyConfig,
+    // Commit the files changed by each completed instruction, with a generated message
+    pub auto_commit: bool,
+    // Run the repository's git hooks for auto-commits; they run unsandboxed
+    pub auto_commit_hooks: bool,
+}
+
+/// How tool definitions are offered to the model and tool calls read back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolCallMode {
+    /// Send tools in the request's `tools` field and read `tool_calls` back
+    Native,
+    /// Describe tools in the system prompt and parse calls out of the reply text
+    Prompt,
+    /// Start native and switch to prompt mode if the model answers with
+    /// tool calls written as text, or announces an action without calling a
+    /// tool and does call one when asked again in prompt mode
+    #[default]
+    Auto,
+}
+
+impl std::str::FromStr for ToolCallMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "native" => Ok(Self::Native),
+            "prompt" => Ok(Self::Prompt),
+            "auto" => Ok(Self::Auto),
+            other => anyhow::bail!("unknown tool call mode: {other}"),
+        }
+    }
+}
+
+/// What happens when the agent calls a tool.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionMode {
+    /// Run without asking
+    #[default]
+    Allow,
+    /// Run once the user approves the call, unless an allow rule matches it
+    Ask,
+    /// Never run
+    Deny,
+}
+
+impl std::fmt::Display for PermissionMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Allow => "allow",
+            Self::Ask => "ask",
+            Self::Deny => "deny",
+        })
+    }
+}
+
+/// Tool permissions: a mode per tool plus rules that pre-approve calls in ask mode.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PermissionsConfig {
+    // Mode of tools without an entry in `tools`
+    pub default_mode: PermissionMode,
+    pub tools: HashMap<String, PermissionMode>,
+    // Rules such as `execute_bash(cargo test*)` or `fs_write(src/*)`
+    pub allow: Vec<String>,
+}
+
+impl PermissionsConfig {
+    pub fn mode_for_tool(&self, tool: &str) -> PermissionMode {
+        self.tools.get(tool).copied().unwrap_or(self.default_mode)
+    }
+}
+
+/// Namespace sandbox for shell commands: the project root and
+/// `writable_paths` stay writable, the rest of the filesystem is read-only and
+/// the network is cut off unless `network` is set. The project's `.doge`
+/// directory is always read-only, and so is `.git` unless `git_writable` is
+/// set, which means `git add`, `git commit` and the like fail in the sandbox.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxConfig {
+    pub enabled: bool,
+    pub network: bool,
+    // Extra writable paths; relative ones are resolved against the project root
+    pub writable_paths: Vec<PathBuf>,
+    // Let commands write to the project's .git directory, hooks included
+    pub git_writable: bool,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            // Built on Linux namespaces
+            enabled: cfg!(target_os = "linux"),
+            network: false,
+            writable_paths: vec![std::env::temp_dir()],
+            git_writable: false,
+        }
+    }
+}
+
+/// Rule of the command policy, checked against each simple command of a shell
+/// command line.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum CommandRule {
+    /// `program [args...]`: commands starting with these words, as in
+    /// `allowed_commands`
+    Prefix(String),
+    Match {
+        // Program name without its directory, or `*` for any program
+        program: String,
+        // First argument that is not an option, e.g. `push` in `git push -f`
+        #[serde(default)]
+        subcommand: Option<String>,
+        // Regex searched for in the arguments joined by spaces
+        #[serde(default)]
+        args: Option<String>,
+        // Explanation given when the rule rejects a command
+        #[serde(default)]
+        reason: Option<String>,
+    },
+}
+
+/// Which commands `execute_bash`, shell mode and remote MCP tools may run.
+/// Deny rules always apply; once there is an allow rule, every command must
+/// match one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandPolicyConfig {
+    pub allow: Vec<CommandRule>,
+    pub deny: Vec<CommandRule>,
+}
+
+/// When to mark requests with explicit prompt cache breakpoints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptCacheMode {
+    /// Only for backends known to support explicit caching (Anthropic models)
+    #[default]
+    Auto,
+    /// Always, e.g. for a proxy that forwards `cache_control` to its upstream
+    On,
+    Off,
+}
+
+/// Model to switch to when the previous one in the chain is unavailable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FallbackModel {
+    pub provider: String,
+    pub model: String,
+    pub base_url: String,
+    pub api_key: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -56,6 +239,7 @@ impl Default for McpServerConfig {
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
+            provider: "openai".to_string(),
             base_url: "https://api.openai.com/v1".to_string(),
             model: "gpt-4o-mini".to_string(),
             api_key: None,
@@ -63,7 +247,6 @@ impl Default for AppConfig {
             git_root: None,
             llm: LlmConfig::default(),
             watch_config: WatchConfig::default(), // Added default watch config
-            enable_stream_tools: false,
             theme: "dark".to_string(),
             project_instructions_file: None,
             no_repomap: false,
@@ -74,6 +257,22 @@ impl Default for AppConfig {
             allowed_commands: vec![],
             allowed_paths: vec![],
             mcp_servers: vec![McpServerConfig::default()],
+            model_capabilities: None,
+            tool_call_mode: ToolCallMode::default(),
+            tool_call_mode_overrides: HashMap::new(),
+            max_parallel_tools: DEFAULT_MAX_PARALLEL_TOOLS,
+            context_window_overrides: HashMap::new(),
+            model_prices: HashMap::new(),
+            cassette: None,
+            fallback_models: vec![],
+            vision_overrides: HashMap::new(),
+            plan_mode: false,
+            permissions: PermissionsConfig::default(),
+            sandbox: SandboxConfig::default(),
+            persistent_shell: false,
+            command_policy: CommandPolicyConfig::default(),
+            auto_commit: false,
+            auto_commit_hooks: false,
         }
     }
 }
@@ -87,7 +286,18 @@ pub struct LlmConfig {
     pub retry_base_ms: u64,
     pub retry_jitter_ms: u64,
     pub respect_retry_after: bool,
+    // Upper bound of a single backoff wait
+    pub retry_max_delay_ms: u64,
+    // Total time one request may spend retrying
+    pub retry_budget_ms: u64,
+    // Consecutive outage failures that open an endpoint's circuit; 0 disables it
+    pub circuit_breaker_threshold: u32,
+    pub circuit_breaker_cooldown_ms: u64,
     pub timeout_ms: u64,
+    // Whether cache breakpoints are added to requests
+    pub prompt_cache: PromptCacheMode,
+    // Whether captured reasoning is sent back with earlier assistant messages
+    pub resend_reasoning: bool,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -142,33 +352,40 @@ impl Default for LlmConfig {
             connect_timeout_ms: 5_000,
             request_timeout_ms: 60_000,
             read_idle_timeout_ms: 20_000,
-            max_retries: 100,
+            max_retries: 8,
             retry_base_ms: 1000,
             retry_jitter_ms: 5000,
             respect_retry_after: true,
+            retry_max_delay_ms: 60_000,
+            retry_budget_ms: 600_000,
+            circuit_breaker_threshold: 5,
+            circuit_breaker_cooldown_ms: 30_000,
             timeout_ms: 600_000, // 10 minutes
+            prompt_cache: PromptCacheMode::default(),
+            resend_reasoning: false,
         }
     }
 }
 
 // Default threshold for auto-compacting conversation history
 pub const DEFAULT_AUTO_COMPACT_PROMPT_TOKEN_THRESHOLD: u32 = 250_000;
+pub const DEFAULT_MAX_PARALLEL_TOOLS: usize = 4;
 
 // Threshold constant removed; use AppConfig.auto_compact_prompt_token_threshold at runtime
 
 #[derive(Debug, Clone, Default, Deserialize, PartialEq)]
 pub struct FileConfig {
+    pub provider: Option<String>,
     pub base_url: Option<String>,
     pub model: Option<String>,
     pub api_key: Option<String>,
     pub project_root: Option<std::path::PathBuf>,
     pub llm: Option<PartialLlmConfig>,
     pub watch: Option<PartialWatchConfig>, // Added watch configuration
-    pub enable_stream_tools: Option<bool>,
-    pub theme: Option<String>,                     // newly added
+    pub theme: Option<String>,             // newly added
     pub project_instructions_file: Option<String>, // newly added
-    pub no_repomap: Option<bool>,                  // newly added
-    pub resume: Option<bool>,                      // newly added
+    pub no_repomap: Option<bool>,          // newly added
+    pub resume: Option<bool>,              // newly added
     // Auto-compact threshold (optional in config file)
     pub auto_compact_prompt_token_threshold: Option<u32>,
     // Auto-compact threshold overrides keyed by model name
@@ -179,6 +396,63 @@ pub struct FileConfig {
     // Allowed paths for file access
     pub allowed_paths: Option<Vec<PathBuf>>,
     pub mcp_servers: Option<Vec<PartialMcpServerConfig>>,
+    // Tool calling mode: "native", "prompt" or "auto"
+    pub tool_call_mode: Option<ToolCallMode>,
+    // Tool calling mode overrides keyed by model name
+    pub tool_call_modes: Option<HashMap<String, ToolCallMode>>,
+    // Concurrency limit for read-only tool calls
+    pub max_parallel_tools: Option<usize>,
+    // Context window sizes keyed by model name
+    pub context_windows: Option<HashMap<String, u32>>,
+    // Token prices (USD per million tokens) keyed by model name
+    pub prices: Option<HashMap<String, ModelPrice>>,
+    // Ordered fallback chain used when the primary model keeps failing
+    pub fallback_models: Option<Vec<PartialFallbackModel>>,
+    // Image input support keyed by model name
+    pub vision: Option<HashMap<String, bool>>,
+    // Tool permission modes and allow rules
+    pub permissions: Option<PartialPermissionsConfig>,
+    // Sandbox for shell commands
+    pub sandbox: Option<PartialSandboxConfig>,
+    // Keep one shell session per agent session for execute_bash
+    pub persistent_shell: Option<bool>,
+    // Allow and deny rules for shell commands
+    pub command_policy: Option<PartialCommandPolicyConfig>,
+    // Commit every instruction's file changes to the current branch
+    pub auto_commit: Option<bool>,
+    // Let auto-commits run pre-commit and other git hooks
+    pub auto_commit_hooks: Option<bool>,
+}
+
+/// Fallback entry as written in a config file; unset fields are inherited from
+/// the primary provider, or from the provider's defaults when it differs.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct PartialFallbackModel {
+    pub provider: Option<String>,
+    pub model: String,
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct PartialPermissionsConfig {
+    pub default: Option<PermissionMode>,
+    pub tools: Option<HashMap<String, PermissionMode>>,
+    pub allow: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct PartialCommandPolicyConfig {
+    pub allow: Option<Vec<CommandRule>>,
+    pub deny: Option<Vec<CommandRule>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct PartialSandboxConfig {
+    pub enabled: Option<bool>,
+    pub network: Option<bool>,
+    pub writable_paths: Option<Vec<PathBuf>>,
+    pub git_writable: Option<bool>,
 }
 
 #[derive(Debug, Clone, Default, Deserialize, PartialEq)]
@@ -207,7 +481,32 @@ pub struct PartialLlmConfig {
     pub retry_base_ms: Option<u64>,
     pub retry_jitter_ms: Option<u64>,
     pub respect_retry_after: Option<bool>,
+    pub retry_max_delay_ms: Option<u64>,
+    pub retry_budget_ms: Option<u64>,
+    pub circuit_breaker_threshold: Option<u32>,
+    pub circuit_breaker_cooldown_ms: Option<u64>,
     pub timeout_ms: Option<u64>,
+    pub prompt_cache: Option<PromptCacheMode>,
+    pub resend_reasoning: Option<bool>,
+}
+
+fn default_base_url(provider: &str) -> &'static str {
+    match provider {
+        "anthropic" => "https://api.anthropic.com",
+        "ollama" => "http://localhost:11434",
+        "llamacpp" => "http://localhost:8080/v1",
+        _ => "https://api.openai.com/v1",
+    }
+}
+
+fn env_api_key(provider: &str) -> Option<String> {
+    if provider == "anthropic" {
+        std::env::var("ANTHROPIC_API_KEY")
+            .ok()
+            .or_else(|| std::env::var("OPENAI_API_KEY").ok())
+    } else {
+        std::env::var("OPENAI_API_KEY").ok()
+    }
 }
 
 impl AppConfig {
@@ -222,6 +521,77 @@ impl AppConfig {
         self.auto_compact_prompt_token_threshold_for_model(&self.model)
     }
 
+    /// Tool calling mode for `model`. A per-model override wins; otherwise a
+    /// probed model without native tool support is switched to prompt mode.
+    pub fn tool_call_mode_for_model(&self, model: &str) -> ToolCallMode {
+        if let Some(mode) = self.tool_call_mode_overrides.get(model) {
+            return *mode;
+        }
+        if model == self.model
+            && self
+                .model_capabilities
+                .as_ref()
+                .is_some_and(|caps| !caps.supports_tools)
+        {
+            return ToolCallMode::Prompt;
+        }
+        self.tool_call_mode
+    }
+
+    /// Context window of `model`: a per-model override, then the window probed
+    /// from a local server for the current model, then the built-in registry.
+    pub fn context_window_for_model(&self, model: &str) -> u32 {
+        if let Some(window) = self.context_window_overrides.get(model) {
+            return *window;
+        }
+        if model == self.model
+            && let Some(window) = self
+                .model_capabilities
+                .as_ref()
+                .and_then(|caps| caps.context_length)
+        {
+            return window;
+        }
+        crate::llm::known_context_window(model).unwrap_or(crate::llm::DEFAULT_CONTEXT_WINDOW)
+    }
+
+    /// Whether `model` accepts images: a per-model override, then what a local
+    /// server reported for the current model, then the built-in registry.
+    pub fn supports_vision(&self, model: &str) -> bool {
+        if let Some(vision) = self.vision_overrides.get(model) {
+            return *vision;
+        }
+        if model == self.model
+            && let Some(vision) = self
+                .model_capabilities
+                .as_ref()
+                .and_then(|caps| caps.supports_vision)
+        {
+            return vision;
+        }
+        crate::llm::known_vision_support(model)
+    }
+
+    /// Price table combining configured prices with the built-in list.
+    pub fn price_table(&self) -> PriceTable {
+        PriceTable::new(self.model_prices.clone())
+    }
+
+    /// Record probed model capabilities and derive the auto-compact threshold
+    /// for the current model from its context window, unless a per-model
+    /// override is already configured.
+    pub fn apply_model_capabilities(&mut self, caps: crate::llm::ModelCapabilities) {
+        if let Some(context_length) = caps.context_length {
+            // Leave headroom for the response and the next tool result
+            let derived = (context_length as u64 * 4 / 5) as u32;
+            let threshold = derived.min(self.auto_compact_prompt_token_threshold);
+            self.auto_compact_prompt_token_threshold_overrides
+                .entry(self.model.clone())
+                .or_insert(threshold);
+        }
+        self.model_capabilities = Some(caps);
+    }
+
     pub fn from_cli(cli: crate::Cli) -> Result<Self> {
         let project_root = std::env::current_dir().context("resolve current dir")?;
         let git_root = get_git_repository_root(&project_root);
@@ -232,17 +602,32 @@ impl AppConfig {
         // Load global configuration
         let file_cfg = load_file_config().unwrap_or_default();
 
+        let provider = if cli.provider.is_empty() {
+            std::env::var("DOGE_PROVIDER")
+                .ok()
+                .or(project_cfg.provider)
+                .or(file_cfg.provider)
+                .unwrap_or_else(|| "openai".to_string())
+        } else {
+            cli.provider
+        };
+        let is_anthropic = provider == "anthropic";
+
         let api_key = cli
             .api_key
-            .or_else(|| std::env::var("OPENAI_API_KEY").ok())
+            .or_else(|| env_api_key(&provider))
             .or(project_cfg.api_key)
             .or(file_cfg.api_key);
         let base_url = if cli.base_url.is_empty() {
-            std::env::var("OPENAI_BASE_URL")
-                .ok()
-                .or(project_cfg.base_url)
-                .or(file_cfg.base_url)
-                .unwrap_or_else(|| "https://api.openai.com/v1".to_string())
+            std::env::var(if is_anthropic {
+                "ANTHROPIC_BASE_URL"
+            } else {
+                "OPENAI_BASE_URL"
+            })
+            .ok()
+            .or(project_cfg.base_url)
+            .or(file_cfg.base_url)
+            .unwrap_or_else(|| default_base_url(&provider).to_string())
         } else {
             cli.base_url
         };
@@ -282,7 +667,21 @@ impl AppConfig {
                         respect_retry_after: project_llm
                             .respect_retry_after
                             .or(file_llm.respect_retry_after),
+                        retry_max_delay_ms: project_llm
+                            .retry_max_delay_ms
+                            .or(file_llm.retry_max_delay_ms),
+                        retry_budget_ms: project_llm.retry_budget_ms.or(file_llm.retry_budget_ms),
+                        circuit_breaker_threshold: project_llm
+                            .circuit_breaker_threshold
+                            .or(file_llm.circuit_breaker_threshold),
+                        circuit_breaker_cooldown_ms: project_llm
+                            .circuit_breaker_cooldown_ms
+                            .or(file_llm.circuit_breaker_cooldown_ms),
                         timeout_ms: project_llm.timeout_ms.or(file_llm.timeout_ms),
+                        prompt_cache: project_llm.prompt_cache.or(file_llm.prompt_cache),
+                        resend_reasoning: project_llm
+                            .resend_reasoning
+                            .or(file_llm.resend_reasoning),
                     })
                 }
                 (Some(project_llm), None) => Some(project_llm.clone()),
@@ -307,7 +706,19 @@ impl AppConfig {
                     respect_retry_after: p
                         .respect_retry_after
                         .unwrap_or(llm_defaults.respect_retry_after),
+                    retry_max_delay_ms: p
+                        .retry_max_delay_ms
+                        .unwrap_or(llm_defaults.retry_max_delay_ms),
+                    retry_budget_ms: p.retry_budget_ms.unwrap_or(llm_defaults.retry_budget_ms),
+                    circuit_breaker_threshold: p
+                        .circuit_breaker_threshold
+                        .unwrap_or(llm_defaults.circuit_breaker_threshold),
+                    circuit_breaker_cooldown_ms: p
+                        .circuit_breaker_cooldown_ms
+                        .unwrap_or(llm_defaults.circuit_breaker_cooldown_ms),
                     timeout_ms: p.timeout_ms.unwrap_or(llm_defaults.timeout_ms),
+                    prompt_cache: p.prompt_cache.unwrap_or(llm_defaults.prompt_cache),
+                    resend_reasoning: p.resend_reasoning.unwrap_or(llm_defaults.resend_reasoning),
                 }
             } else {
                 llm_defaults
@@ -404,6 +815,146 @@ impl AppConfig {
             }
         }
 
+        // Determine tool calling mode (priority: env var -> project config -> global config -> default)
+        let tool_call_mode = std::env::var("DOGE_TOOL_CALL_MODE")
+            .ok()
+            .and_then(|v| v.parse::<ToolCallMode>().ok())
+            .or(project_cfg.tool_call_mode)
+            .or(file_cfg.tool_call_mode)
+            .unwrap_or_default();
+
+        // Determine read-only tool concurrency (priority: env var -> project config -> global config -> default)
+        let max_parallel_tools = std::env::var("DOGE_MAX_PARALLEL_TOOLS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .or(project_cfg.max_parallel_tools)
+            .or(file_cfg.max_parallel_tools)
+            .unwrap_or(DEFAULT_MAX_PARALLEL_TOOLS)
+            .max(1);
+
+        let mut context_window_overrides = file_cfg.context_windows.clone().unwrap_or_default();
+        if let Some(project_overrides) = project_cfg.context_windows.clone() {
+            context_window_overrides.extend(project_overrides);
+        }
+
+        let cassette = match (cli.record, cli.replay) {
+            (Some(_), Some(_)) => anyhow::bail!("--record and --replay cannot be used together"),
+            (Some(path), None) => Some(CassetteConfig {
+                mode: CassetteMode::Record,
+                path,
+            }),
+            (None, Some(path)) => Some(CassetteConfig {
+                mode: CassetteMode::Replay,
+                path,
+            }),
+            (None, None) => None,
+        };
+
+        let fallback_models = project_cfg
+            .fallback_models
+            .or(file_cfg.fallback_models.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|fb| {
+                let fb_provider = fb.provider.unwrap_or_else(|| provider.clone());
+                let same_provider = fb_provider == provider;
+                FallbackModel {
+                    base_url: fb.base_url.unwrap_or_else(|| {
+                        if same_provider {
+                            base_url.clone()
+                        } else {
+                            default_base_url(&fb_provider).to_string()
+                        }
+                    }),
+                    api_key: fb.api_key.or_else(|| {
+                        if same_provider {
+                            api_key.clone()
+                        } else {
+                            env_api_key(&fb_provider)
+                        }
+                    }),
+                    provider: fb_provider,
+                    model: fb.model,
+                }
+            })
+            .collect();
+
+        let mut model_prices = file_cfg.prices.clone().unwrap_or_default();
+        if let Some(project_prices) = project_cfg.prices.clone() {
+            model_prices.extend(project_prices);
+        }
+
+        let mut vision_overrides = file_cfg.vision.clone().unwrap_or_default();
+        if let Some(project_overrides) = project_cfg.vision.clone() {
+            vision_overrides.extend(project_overrides);
+        }
+
+        let mut tool_call_mode_overrides = file_cfg.tool_call_modes.clone().unwrap_or_default();
+        if let Some(project_overrides) = project_cfg.tool_call_modes.clone() {
+            tool_call_mode_overrides.extend(project_overrides);
+        }
+
+        // Project modes override file modes; allow rules from both apply
+        let mut permissions = PermissionsConfig::default();
+        for partial in [&file_cfg.permissions, &project_cfg.permissions]
+            .into_iter()
+            .flatten()
+        {
+            if let Some(mode) = partial.default {
+                permissions.default_mode = mode;
+            }
+            if let Some(tools) = &partial.tools {
+                permissions.tools.extend(tools.clone());
+            }
+            if let Some(allow) = &partial.allow {
+                permissions.allow.extend(allow.iter().cloned());
+            }
+        }
+
+        // Rules from both files apply
+        let mut command_policy = CommandPolicyConfig::default();
+        for partial in [&file_cfg.command_policy, &project_cfg.command_policy]
+            .into_iter()
+            .flatten()
+        {
+            command_policy
+                .allow
+                .extend(partial.allow.iter().flatten().cloned());
+            command_policy
+                .deny
+                .extend(partial.deny.iter().flatten().cloned());
+        }
+        for rule in command_policy.allow.iter().chain(&command_policy.deny) {
+            if let CommandRule::Match {
+                args: Some(pattern),
+                ..
+            } = rule
+            {
+                regex::Regex::new(pattern).with_context(|| {
+                    format!("invalid args pattern in [command_policy]: {pattern}")
+                })?;
+            }
+        }
+
+        let mut sandbox = SandboxConfig::default();
+        for partial in [&file_cfg.sandbox, &project_cfg.sandbox]
+            .into_iter()
+            .flatten()
+        {
+            if let Some(enabled) = partial.enabled {
+                sandbox.enabled = enabled;
+            }
+            if let Some(network) = partial.network {
+                sandbox.network = network;
+            }
+            if let Some(paths) = &partial.writable_paths {
+                sandbox.writable_paths = paths.clone();
+            }
+            if let Some(git_writable) = partial.git_writable {
+                sandbox.git_writable = git_writable;
+            }
+        }
+
         // Handle watch configuration (project config takes precedence over file config)
         let watch_config = {
             let default_watch_cfg = WatchConfig::default();
@@ -451,19 +1002,14 @@ impl AppConfig {
         };
 
         Ok(Self {
+            provider,
             base_url,
             model,
             api_key,
             project_root,
             git_root,
             llm,
-            watch_config, // Added watch config
-            enable_stream_tools: std::env::var("DOGE_STREAM_TOOLS")
-                .ok()
-                .and_then(|v| v.parse().ok())
-                .or(project_cfg.enable_stream_tools)
-                .or(file_cfg.enable_stream_tools)
-                .unwrap_or(false),
+            watch_config,              // Added watch config
             theme,                     // newly added
             project_instructions_file, // newly added
             no_repomap: cli.no_repomap
@@ -485,6 +1031,31 @@ impl AppConfig {
                 .or(file_cfg.allowed_paths)
                 .unwrap_or_default(),
             mcp_servers,
+            model_capabilities: None,
+            tool_call_mode,
+            tool_call_mode_overrides,
+            max_parallel_tools,
+            context_window_overrides,
+            model_prices,
+            cassette,
+            fallback_models,
+            vision_overrides,
+            plan_mode: false,
+            permissions,
+            sandbox,
+            persistent_shell: project_cfg
+                .persistent_shell
+                .or(file_cfg.persistent_shell)
+                .unwrap_or(false),
+            command_policy,
+            auto_commit: project_cfg
+                .auto_commit
+                .or(file_cfg.auto_commit)
+                .unwrap_or(false),
+            auto_commit_hooks: project_cfg
+                .auto_commit_hooks
+                .or(file_cfg.auto_commit_hooks)
+                .unwrap_or(false),
         })
     }
 }
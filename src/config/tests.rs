@@ -1,6 +1,7 @@
 use crate::Cli;
-use crate::config::{AppConfig, FileConfig, load_project_config};
+use crate::config::{AppConfig, FileConfig, PromptCacheMode, load_project_config};
 use std::fs;
+use std::path::PathBuf;
 use tempfile::TempDir;
 
 #[test]
@@ -20,6 +21,8 @@ show_diff = false
 [llm]
 max_retries = 5
 retry_base_ms = 500
+prompt_cache = "off"
+resend_reasoning = true
 "#;
 
     fs::write(doge_dir.join("config.toml"), config_content).unwrap();
@@ -37,6 +40,8 @@ retry_base_ms = 500
     let llm_cfg = project_cfg.llm.unwrap();
     assert_eq!(llm_cfg.max_retries, Some(5));
     assert_eq!(llm_cfg.retry_base_ms, Some(500));
+    assert_eq!(llm_cfg.prompt_cache, Some(PromptCacheMode::Off));
+    assert_eq!(llm_cfg.resend_reasoning, Some(true));
 }
 
 #[test]
@@ -66,6 +71,14 @@ auto_compact_prompt_token_threshold = 111
 [auto_compact_prompt_token_thresholds]
 project-model = 222
 other-model = 333
+
+[[fallback_models]]
+model = "backup-model"
+
+[[fallback_models]]
+provider = "anthropic"
+model = "claude-backup"
+api_key = "fallback-key"
 "#;
 
     fs::write(doge_dir.join("config.toml"), config_content).unwrap();
@@ -88,18 +101,44 @@ other-model = 333
     set_env("DOGE_CODE_CONFIG", global_config_path.to_str().unwrap());
 
     let cli = Cli {
+        provider: "".to_string(),
         base_url: "".to_string(),
         model: "".to_string(),
         api_key: None,
         no_repomap: false,
         instructions_file: None,
         resume: false,
+        record: None,
+        replay: Some(PathBuf::from("session.json")),
         command: None,
     };
 
+    let both = Cli {
+        record: Some(PathBuf::from("session.json")),
+        ..cli.clone()
+    };
     let cfg = AppConfig::from_cli(cli).unwrap();
+    let err = AppConfig::from_cli(both).unwrap_err();
+    assert!(err.to_string().contains("--record and --replay"), "{err}");
 
     assert_eq!(cfg.auto_compact_prompt_token_threshold, 4444);
+    assert_eq!(
+        cfg.cassette,
+        Some(crate::llm::CassetteConfig {
+            mode: crate::llm::CassetteMode::Replay,
+            path: PathBuf::from("session.json"),
+        })
+    );
+    assert_eq!(cfg.fallback_models.len(), 2);
+    assert_eq!(cfg.fallback_models[0].provider, cfg.provider);
+    assert_eq!(cfg.fallback_models[0].model, "backup-model");
+    assert_eq!(cfg.fallback_models[0].base_url, cfg.base_url);
+    assert_eq!(cfg.fallback_models[1].provider, "anthropic");
+    assert_eq!(cfg.fallback_models[1].base_url, "https://api.anthropic.com");
+    assert_eq!(
+        cfg.fallback_models[1].api_key.as_deref(),
+        Some("fallback-key")
+    );
     assert_eq!(
         cfg.auto_compact_prompt_token_threshold_for_model("project-model"),
         222
@@ -133,3 +172,246 @@ other-model = 333
 
     std::env::set_current_dir(prev_dir).unwrap();
 }
+
+#[test]
+fn test_apply_model_capabilities_derives_threshold() {
+    use crate::llm::ModelCapabilities;
+
+    let mut cfg = AppConfig {
+        model: "qwen2.5-coder:7b".to_string(),
+        ..AppConfig::default()
+    };
+    cfg.apply_model_capabilities(ModelCapabilities {
+        context_length: Some(32_768),
+        supports_tools: false,
+        supports_vision: None,
+    });
+    assert_eq!(
+        cfg.auto_compact_prompt_token_threshold_for_current_model(),
+        26_214
+    );
+    assert!(!cfg.model_capabilities.as_ref().unwrap().supports_tools);
+
+    // Explicit per-model overrides win over the derived value
+    let mut cfg = AppConfig {
+        model: "llama3.1".to_string(),
+        ..AppConfig::default()
+    };
+    cfg.auto_compact_prompt_token_threshold_overrides
+        .insert("llama3.1".to_string(), 1_000);
+    cfg.apply_model_capabilities(ModelCapabilities {
+        context_length: Some(131_072),
+        supports_tools: true,
+        supports_vision: None,
+    });
+    assert_eq!(
+        cfg.auto_compact_prompt_token_threshold_for_current_model(),
+        1_000
+    );
+}
+
+#[test]
+fn test_tool_call_mode_for_model() {
+    use crate::config::ToolCallMode;
+    use crate::llm::ModelCapabilities;
+
+    let parsed: FileConfig = toml::from_str(
+        r#"
+tool_call_mode = "native"
+
+[tool_call_modes]
+"gemma:2b" = "prompt"
+"#,
+    )
+    .unwrap();
+    assert_eq!(parsed.tool_call_mode, Some(ToolCallMode::Native));
+    assert_eq!(
+        parsed.tool_call_modes.unwrap().get("gemma:2b"),
+        Some(&ToolCallMode::Prompt)
+    );
+
+    let mut cfg = AppConfig {
+        model: "phi3".to_string(),
+        ..AppConfig::default()
+    };
+    assert_eq!(cfg.tool_call_mode_for_model("phi3"), ToolCallMode::Auto);
+
+    // Probed models without native tool support fall back to prompt mode
+    cfg.apply_model_capabilities(ModelCapabilities {
+        context_length: None,
+        supports_tools: false,
+        supports_vision: None,
+    });
+    assert_eq!(cfg.tool_call_mode_for_model("phi3"), ToolCallMode::Prompt);
+    assert_eq!(cfg.tool_call_mode_for_model("other"), ToolCallMode::Auto);
+
+    // Explicit overrides win
+    cfg.tool_call_mode_overrides
+        .insert("phi3".to_string(), ToolCallMode::Native);
+    assert_eq!(cfg.tool_call_mode_for_model("phi3"), ToolCallMode::Native);
+
+    assert_eq!(
+        "Prompt".parse::<ToolCallMode>().unwrap(),
+        ToolCallMode::Prompt
+    );
+    assert!("bogus".parse::<ToolCallMode>().is_err());
+}
+
+#[test]
+fn test_context_window_for_model() {
+    use crate::llm::ModelCapabilities;
+
+    let parsed: FileConfig = toml::from_str(
+        r#"
+[context_windows]
+"my-finetune" = 64000
+"#,
+    )
+    .unwrap();
+
+    let mut cfg = AppConfig {
+        model: "qwen2.5-coder:7b".to_string(),
+        context_window_overrides: parsed.context_windows.unwrap(),
+        ..AppConfig::default()
+    };
+    assert_eq!(cfg.context_window_for_model("my-finetune"), 64_000);
+    assert_eq!(cfg.context_window_for_model("gpt-4o"), 128_000);
+    assert_eq!(cfg.context_window_for_model("qwen2.5-coder:7b"), 32_768);
+
+    // A window probed from the local server beats the registry
+    cfg.apply_model_capabilities(ModelCapabilities {
+        context_length: Some(16_384),
+        supports_tools: true,
+        supports_vision: None,
+    });
+    assert_eq!(cfg.context_window_for_model("qwen2.5-coder:7b"), 16_384);
+    assert_eq!(
+        cfg.context_window_for_model("unknown-model"),
+        crate::llm::DEFAULT_CONTEXT_WINDOW
+    );
+}
+
+#[test]
+fn test_supports_vision() {
+    use crate::llm::ModelCapabilities;
+
+    let parsed: FileConfig = toml::from_str(
+        r#"
+[vision]
+"my-finetune" = true
+"gpt-4o" = false
+"#,
+    )
+    .unwrap();
+
+    let mut cfg = AppConfig {
+        model: "gemma3:12b".to_string(),
+        vision_overrides: parsed.vision.unwrap(),
+        ..AppConfig::default()
+    };
+    assert!(cfg.supports_vision("my-finetune"));
+    assert!(!cfg.supports_vision("gpt-4o"));
+    assert!(cfg.supports_vision("claude-sonnet-4-5"));
+    assert!(cfg.supports_vision("gemma3:12b"));
+
+    // What the local server reports beats the registry
+    cfg.apply_model_capabilities(ModelCapabilities {
+        context_length: None,
+        supports_tools: true,
+        supports_vision: Some(false),
+    });
+    assert!(!cfg.supports_vision("gemma3:12b"));
+}
+
+#[test]
+fn test_price_table_from_config() {
+    let parsed: FileConfig = toml::from_str(
+        r#"
+[prices]
+"my-finetune" = { input = 1.0, output = 4.0 }
+"gpt-4o" = { input = 2.0, output = 8.0, cached_input = 1.0 }
+"#,
+    )
+    .unwrap();
+
+    let cfg = AppConfig {
+        model_prices: parsed.prices.unwrap(),
+        ..AppConfig::default()
+    };
+    let table = cfg.price_table();
+    let finetune = table.price_for("my-finetune").unwrap();
+    assert_eq!(finetune.output, 4.0);
+    assert_eq!(finetune.cached_input, None);
+    assert_eq!(table.price_for("gpt-4o").unwrap().input, 2.0);
+    // Models not configured fall back to the built-in list
+    assert_eq!(table.price_for("claude-opus-4-1").unwrap().input, 15.0);
+}
+
+#[test]
+fn test_permissions_from_config() {
+    use crate::config::{PermissionMode, PermissionsConfig};
+
+    let parsed: FileConfig = toml::from_str(
+        r#"
+[permissions]
+default = "ask"
+allow = ["execute_bash(cargo test*)"]
+
+[permissions.tools]
+fs_read = "allow"
+execute_bash = "deny"
+"#,
+    )
+    .unwrap();
+
+    let partial = parsed.permissions.unwrap();
+    let permissions = PermissionsConfig {
+        default_mode: partial.default.unwrap(),
+        tools: partial.tools.unwrap(),
+        allow: partial.allow.unwrap(),
+    };
+    assert_eq!(permissions.mode_for_tool("fs_read"), PermissionMode::Allow);
+    assert_eq!(
+        permissions.mode_for_tool("execute_bash"),
+        PermissionMode::Deny
+    );
+    assert_eq!(permissions.mode_for_tool("fs_write"), PermissionMode::Ask);
+    assert_eq!(
+        PermissionsConfig::default().mode_for_tool("fs_write"),
+        PermissionMode::Allow
+    );
+}
+
+#[test]
+fn test_command_policy_from_config() {
+    use crate::config::CommandRule;
+
+    let parsed: FileConfig = toml::from_str(
+        r#"
+[command_policy]
+allow = ["git", "cargo build"]
+deny = [
+    { program = "git", subcommand = "push", args = "--force", reason = "no force pushes" },
+]
+"#,
+    )
+    .unwrap();
+
+    let policy = parsed.command_policy.unwrap();
+    assert_eq!(
+        policy.allow.unwrap(),
+        vec![
+            CommandRule::Prefix("git".to_string()),
+            CommandRule::Prefix("cargo build".to_string()),
+        ]
+    );
+    assert_eq!(
+        policy.deny.unwrap(),
+        vec![CommandRule::Match {
+            program: "git".to_string(),
+            subcommand: Some("push".to_string()),
+            args: Some("--force".to_string()),
+            reason: Some("no force pushes".to_string()),
+        }]
+    );
+}
@@ -0,0 +1,11 @@
+//! Token usage accounting and spend estimation.
+//!
+//! Providers record one [`RequestUsage`] per completed request in their
+//! [`UsageLog`]. Callers drain the log after a run, price it with a
+//! [`PriceTable`] and fold it into a [`CostSummary`] stored with the session.
+
+mod pricing;
+mod usage;
+
+pub use pricing::{ModelPrice, PriceTable};
+pub use usage::{CostSummary, ModelCost, RequestUsage, UsageLog, format_usd};
@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::RequestUsage;
+
+/// Prices in USD per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    /// Price of prompt tokens served from the provider's cache; defaults to `input`
+    #[serde(default)]
+    pub cached_input: Option<f64>,
+}
+
+impl ModelPrice {
+    const fn new(input: f64, output: f64, cached_input: f64) -> Self {
+        Self {
+            input,
+            output,
+            cached_input: Some(cached_input),
+        }
+    }
+
+    /// Cost of one request in USD.
+    pub fn cost(&self, usage: &RequestUsage) -> f64 {
+        let cached = usage.cached_tokens.min(usage.input_tokens);
+        let uncached = usage.input_tokens - cached;
+        (uncached as f64 * self.input
+            + cached as f64 * self.cached_input.unwrap_or(self.input)
+            + usage.output_tokens as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+/// Built-in list prices keyed by model name prefix; the longest match wins.
+const DEFAULT_PRICES: &[(&str, ModelPrice)] = &[
+    // OpenAI
+    ("gpt-4o", ModelPrice::new(2.5, 10.0, 1.25)),
+    ("gpt-4o-mini", ModelPrice::new(0.15, 0.6, 0.075)),
+    ("gpt-4.1", ModelPrice::new(2.0, 8.0, 0.5)),
+    ("gpt-4.1-mini", ModelPrice::new(0.4, 1.6, 0.1)),
+    ("gpt-4.1-nano", ModelPrice::new(0.1, 0.4, 0.025)),
+    ("gpt-5", ModelPrice::new(1.25, 10.0, 0.125)),
+    ("gpt-5-mini", ModelPrice::new(0.25, 2.0, 0.025)),
+    ("gpt-5-nano", ModelPrice::new(0.05, 0.4, 0.005)),
+    ("o3", ModelPrice::new(2.0, 8.0, 0.5)),
+    ("o4-mini", ModelPrice::new(1.1, 4.4, 0.275)),
+    // Anthropic
+    ("claude-opus-4", ModelPrice::new(15.0, 75.0, 1.5)),
+    ("claude-sonnet-4", ModelPrice::new(3.0, 15.0, 0.3)),
+    ("claude-3-7-sonnet", ModelPrice::new(3.0, 15.0, 0.3)),
+    ("claude-3-5-sonnet", ModelPrice::new(3.0, 15.0, 0.3)),
+    ("claude-haiku-4", ModelPrice::new(1.0, 5.0, 0.1)),
+    ("claude-3-5-haiku", ModelPrice::new(0.8, 4.0, 0.08)),
+    // Google
+    ("gemini-2.5-pro", ModelPrice::new(1.25, 10.0, 0.31)),
+    ("gemini-2.5-flash", ModelPrice::new(0.3, 2.5, 0.075)),
+    // xAI
+    ("grok-4", ModelPrice::new(3.0, 15.0, 0.75)),
+    ("grok-4-fast", ModelPrice::new(0.2, 0.5, 0.05)),
+    ("grok-code-fast", ModelPrice::new(0.2, 1.5, 0.02)),
+    // DeepSeek
+    ("deepseek-chat", ModelPrice::new(0.27, 1.1, 0.07)),
+    ("deepseek-reasoner", ModelPrice::new(0.55, 2.19, 0.14)),
+];
+
+/// Per-model prices: configured entries first, then the built-in list.
+#[derive(Debug, Clone, Default)]
+pub struct PriceTable {
+    overrides: HashMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    pub fn new(overrides: HashMap<String, ModelPrice>) -> Self {
+        Self { overrides }
+    }
+
+    /// Price of `model`, if known. Configured names match exactly or by
+    /// prefix; routing prefixes such as `openai/` are ignored for the built-in list.
+    pub fn price_for(&self, model: &str) -> Option<ModelPrice> {
+        if let Some(price) = self.overrides.get(model) {
+            return Some(*price);
+        }
+        let name = model
+            .rsplit('/')
+            .next()
+            .unwrap_or(model)
+            .to_ascii_lowercase();
+        let configured = self
+            .overrides
+            .iter()
+            .map(|(prefix, price)| (prefix.as_str(), *price));
+        let built_in = DEFAULT_PRICES
+            .iter()
+            .map(|(prefix, price)| (*prefix, *price));
+        // Configured prefixes win over built-in ones of the same length
+        configured
+            .chain(built_in)
+            .filter(|(prefix, _)| name.starts_with(&prefix.to_ascii_lowercase()))
+            .fold(None, |best: Option<(&str, ModelPrice)>, cand| match best {
+                Some(b) if b.0.len() >= cand.0.len() => Some(b),
+                _ => Some(cand),
+            })
+            .map(|(_, price)| price)
+    }
+
+    /// Cost of one request in USD, or `None` when the model has no known price.
+    pub fn cost(&self, usage: &RequestUsage) -> Option<f64> {
+        self.price_for(&usage.model).map(|price| price.cost(usage))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(model: &str, input: u32, cached: u32, output: u32) -> RequestUsage {
+        RequestUsage {
+            model: model.into(),
+            input_tokens: input,
+            output_tokens: output,
+            cached_tokens: cached,
+        }
+    }
+
+    #[test]
+    fn prices_by_longest_prefix() {
+        let table = PriceTable::default();
+        assert_eq!(
+            table.price_for("gpt-4o-mini-2024-07-18").unwrap().input,
+            0.15
+        );
+        assert_eq!(table.price_for("gpt-4o-2024-08-06").unwrap().input, 2.5);
+        assert_eq!(table.price_for("openai/gpt-5").unwrap().output, 10.0);
+        assert!(table.price_for("qwen2.5-coder:7b").is_none());
+    }
+
+    #[test]
+    fn cached_tokens_use_the_cached_price() {
+        let table = PriceTable::default();
+        // 1M uncached input at 3.0, 1M cached at 0.3, 1M output at 15.0
+        let cost = table
+            .cost(&usage("claude-sonnet-4-5", 2_000_000, 1_000_000, 1_000_000))
+            .unwrap();
+        assert!((cost - 18.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn configured_prices_win() {
+        let table = PriceTable::new(HashMap::from([
+            ("gpt-4o".to_string(), ModelPrice::new(1.0, 2.0, 0.5)),
+            (
+                "qwen".to_string(),
+                ModelPrice {
+                    input: 0.0,
+                    output: 0.0,
+                    cached_input: None,
+                },
+            ),
+        ]));
+        assert_eq!(table.price_for("gpt-4o").unwrap().input, 1.0);
+        // The longer built-in prefix is more specific
+        assert_eq!(table.price_for("gpt-4o-mini").unwrap().input, 0.15);
+        assert_eq!(table.cost(&usage("qwen3:8b", 1000, 0, 1000)), Some(0.0));
+    }
+}
@@ -0,0 +1,229 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use super::PriceTable;
+
+/// Token usage of one completed request as reported by the provider.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RequestUsage {
+    pub model: String,
+    /// Prompt tokens, including those served from cache
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    /// Prompt tokens served from the provider's cache
+    pub cached_tokens: u32,
+}
+
+/// Requests recorded by a provider since the log was last drained.
+///
+/// Clones share the same log, so a client and its reasoning-disabled variant
+/// report into one place.
+#[derive(Debug, Clone, Default)]
+pub struct UsageLog(Arc<Mutex<Vec<RequestUsage>>>);
+
+impl UsageLog {
+    pub fn record(&self, usage: RequestUsage) {
+        if let Ok(mut log) = self.0.lock() {
+            log.push(usage);
+        }
+    }
+
+    /// Number of requests recorded since the log was last drained.
+    pub fn recorded(&self) -> usize {
+        self.0.lock().map(|log| log.len()).unwrap_or_default()
+    }
+
+    /// Copy of the requests recorded after the first `start` ones.
+    pub fn recorded_since(&self, start: usize) -> Vec<RequestUsage> {
+        self.0
+            .lock()
+            .map(|log| log.get(start..).unwrap_or_default().to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Take every recorded request, leaving the log empty.
+    pub fn drain(&self) -> Vec<RequestUsage> {
+        self.0
+            .lock()
+            .map(|mut log| std::mem::take(&mut *log))
+            .unwrap_or_default()
+    }
+}
+
+/// Usage and spend of a single model.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelCost {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cached_tokens: u64,
+    /// `None` when the model has no known price
+    pub cost_usd: Option<f64>,
+}
+
+/// Accumulated usage and spend, overall and per model.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CostSummary {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cached_tokens: u64,
+    /// Spend of priced requests in USD
+    pub cost_usd: f64,
+    /// Requests to models without a known price, excluded from `cost_usd`
+    pub unpriced_requests: u64,
+    pub by_model: BTreeMap<String, ModelCost>,
+}
+
+impl CostSummary {
+    /// Summarize `usages` priced with `prices`.
+    pub fn from_usages(usages: &[RequestUsage], prices: &PriceTable) -> Self {
+        let mut summary = Self::default();
+        for usage in usages {
+            summary.record(usage, prices);
+        }
+        summary
+    }
+
+    pub fn record(&mut self, usage: &RequestUsage, prices: &PriceTable) {
+        let cost = prices.cost(usage);
+        let entry = self.by_model.entry(usage.model.clone()).or_default();
+        entry.requests += 1;
+        entry.input_tokens += u64::from(usage.input_tokens);
+        entry.output_tokens += u64::from(usage.output_tokens);
+        entry.cached_tokens += u64::from(usage.cached_tokens);
+
+        self.requests += 1;
+        self.input_tokens += u64::from(usage.input_tokens);
+        self.output_tokens += u64::from(usage.output_tokens);
+        self.cached_tokens += u64::from(usage.cached_tokens);
+        match cost {
+            Some(cost) => {
+                *entry.cost_usd.get_or_insert(0.0) += cost;
+                self.cost_usd += cost;
+            }
+            None => self.unpriced_requests += 1,
+        }
+    }
+
+    /// Add the totals of `other` into this summary.
+    pub fn merge(&mut self, other: &CostSummary) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cached_tokens += other.cached_tokens;
+        self.cost_usd += other.cost_usd;
+        self.unpriced_requests += other.unpriced_requests;
+        for (model, cost) in &other.by_model {
+            let entry = self.by_model.entry(model.clone()).or_default();
+            entry.requests += cost.requests;
+            entry.input_tokens += cost.input_tokens;
+            entry.output_tokens += cost.output_tokens;
+            entry.cached_tokens += cost.cached_tokens;
+            if let Some(c) = cost.cost_usd {
+                *entry.cost_usd.get_or_insert(0.0) += c;
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests == 0
+    }
+
+    /// Multi-line report with totals and a per-model breakdown.
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "Total cost: {}", format_usd(self.cost_usd));
+        let _ = writeln!(
+            out,
+            "Requests: {} | Input: {} (cached: {}) | Output: {}",
+            self.requests, self.input_tokens, self.cached_tokens, self.output_tokens
+        );
+        if self.unpriced_requests > 0 {
+            let _ = writeln!(
+                out,
+                "Unpriced requests: {} (add the model under [prices] in config.toml)",
+                self.unpriced_requests
+            );
+        }
+        for (model, cost) in &self.by_model {
+            let _ = writeln!(
+                out,
+                "  {model}: {} | {} requests | in {} (cached {}) | out {}",
+                cost.cost_usd
+                    .map_or_else(|| "unpriced".to_string(), format_usd),
+                cost.requests,
+                cost.input_tokens,
+                cost.cached_tokens,
+                cost.output_tokens
+            );
+        }
+        out.trim_end().to_string()
+    }
+}
+
+/// Format a USD amount, keeping sub-cent precision for small totals.
+pub fn format_usd(amount: f64) -> String {
+    if amount < 1.0 {
+        format!("${amount:.4}")
+    } else {
+        format!("${amount:.2}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(model: &str, input: u32, cached: u32, output: u32) -> RequestUsage {
+        RequestUsage {
+            model: model.into(),
+            input_tokens: input,
+            output_tokens: output,
+            cached_tokens: cached,
+        }
+    }
+
+    #[test]
+    fn log_is_shared_between_clones() {
+        let log = UsageLog::default();
+        log.clone().record(usage("gpt-5", 10, 0, 5));
+        assert_eq!(log.drain().len(), 1);
+        assert!(log.drain().is_empty());
+    }
+
+    #[test]
+    fn summary_totals_and_unpriced_models() {
+        let prices = PriceTable::default();
+        let summary = CostSummary::from_usages(
+            &[
+                usage("gpt-4o", 1_000_000, 0, 0),
+                usage("gpt-4o", 0, 0, 100_000),
+                usage("local-model", 500, 0, 50),
+            ],
+            &prices,
+        );
+        assert_eq!(summary.requests, 3);
+        assert_eq!(summary.unpriced_requests, 1);
+        assert!((summary.cost_usd - 3.5).abs() < 1e-9);
+        assert_eq!(summary.by_model["gpt-4o"].requests, 2);
+        assert_eq!(summary.by_model["local-model"].cost_usd, None);
+    }
+
+    #[test]
+    fn merge_adds_per_model_totals() {
+        let prices = PriceTable::default();
+        let mut total = CostSummary::from_usages(&[usage("gpt-4o", 1000, 0, 10)], &prices);
+        let run = CostSummary::from_usages(
+            &[usage("gpt-4o", 1000, 500, 10), usage("o3", 10, 0, 10)],
+            &prices,
+        );
+        total.merge(&run);
+        assert_eq!(total.requests, 3);
+        assert_eq!(total.by_model["gpt-4o"].cached_tokens, 500);
+        assert_eq!(total.by_model.len(), 2);
+        assert!(total.report().contains("o3: $"));
+    }
+}
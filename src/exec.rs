@@ -5,17 +5,48 @@
 
 use crate::analysis::RepoMap;
 use crate::config::AppConfig;
+use crate::cost::{CostSummary, format_usd};
+use crate::features::auto_commit::{self, TurnCommit, TurnStart};
 use crate::hooks::{HookManager, repomap_update::RepomapUpdateHook};
-use crate::llm::{self, OpenAIClient};
+use crate::llm::{self, LlmProvider, ResponseSchema};
 use crate::session::SessionManager;
 use crate::tools::FsTools;
 use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::io::Write;
 use std::path::Path;
+use std::sync::mpsc::Receiver;
 use std::sync::{Arc, Mutex};
 use tokio::fs;
 use tokio::sync::RwLock;
 use tracing::info;
 
+/// Print streamed assistant text (`::append:` events) to stdout until the
+/// sender is dropped. Returns whether anything was printed.
+fn spawn_stream_printer(rx: Receiver<String>) -> std::thread::JoinHandle<bool> {
+    std::thread::spawn(move || {
+        let mut stdout = std::io::stdout();
+        let mut printed = false;
+        let mut at_line_start = true;
+        for msg in rx {
+            if let Some(token) = msg.strip_prefix("::append:") {
+                let _ = write!(stdout, "{token}");
+                let _ = stdout.flush();
+                printed = true;
+                at_line_start = token.ends_with('\n');
+            } else if msg == "::status:processing" && !at_line_start {
+                // Keep text from separate turns on separate lines
+                let _ = writeln!(stdout);
+                at_line_start = true;
+            }
+        }
+        if !at_line_start {
+            let _ = writeln!(stdout);
+        }
+        printed
+    })
+}
+
 /// Executor for the `exec` subcommand.
 /// This struct holds the necessary components to interact with the LLM and tools.
 pub struct Executor {
@@ -23,7 +54,7 @@ pub struct Executor {
     tools: FsTools,
     #[allow(dead_code)] // Used internally by FsTools
     repomap: Arc<RwLock<Option<RepoMap>>>,
-    client: Option<OpenAIClient>,
+    client: Option<Arc<dyn LlmProvider>>,
     conversation_history: Arc<Mutex<Vec<llm::types::ChatMessage>>>,
     hook_manager: HookManager,
 }
@@ -46,10 +77,7 @@ impl Executor {
             info!("Repomap initialization skipped due to --no-repomap flag");
         }
 
-        let client = match cfg.api_key.clone() {
-            Some(key) => Some(OpenAIClient::new(cfg.base_url.clone(), key)?),
-            None => None,
-        };
+        let client = llm::build_provider(&cfg)?;
 
         // Initialize conversation history
         let conversation_history = Arc::new(Mutex::new(Vec::new()));
@@ -100,6 +128,8 @@ impl Executor {
             content: Some(sys_prompt),
             tool_calls: vec![],
             tool_call_id: None,
+            reasoning: None,
+            images: vec![],
         });
 
         // Add existing conversation history (should be empty for exec mode, but let's be safe)
@@ -107,20 +137,38 @@ impl Executor {
             msgs.extend(history.clone());
         }
 
+        // Image files mentioned as @path are sent along with the instruction
+        let images = llm::image_references(&instruction, &self.cfg.project_root)
+            .iter()
+            .map(|path| llm::load_image(path))
+            .collect::<Result<Vec<_>>>()?;
         msgs.push(llm::types::ChatMessage {
             role: "user".into(),
             content: Some(instruction.clone()),
             tool_calls: vec![],
             tool_call_id: None,
+            reasoning: None,
+            images,
         });
 
-        // Create a channel to receive the final assistant message
-        // Since we are not in a TUI, we will collect the output directly.
-        let (tx, _rx) = std::sync::mpsc::channel::<String>(); // Buffer size is unbounded for std::sync::mpsc
+        // Changes already pending now are the user's and stay out of the auto-commit
+        let turn_start = if self.cfg.auto_commit {
+            TurnStart::capture(&self.cfg.project_root).unwrap_or_else(|e| {
+                eprintln!("Cannot auto-commit this instruction: {e:#}");
+                None
+            })
+        } else {
+            None
+        };
+
+        // Assistant text is streamed to stdout as it arrives; JSON output is
+        // only written once the run completes.
+        let (tx, rx) = std::sync::mpsc::channel::<String>(); // Buffer size is unbounded for std::sync::mpsc
+        let printer = (!json).then(|| spawn_stream_printer(rx));
 
         // Call run_agent_loop
         let res = llm::run_agent_loop(
-            client,
+            client.as_ref(),
             &model,
             &fs_tools,
             msgs,
@@ -131,8 +179,32 @@ impl Executor {
         )
         .await;
 
-        // Get token usage after the agent loop completes
+        // The sender was dropped with the agent loop, so the printer drains and exits
+        let streamed = printer.is_some_and(|p| p.join().unwrap_or(false));
+
+        // Commit before pricing the run so the commit message request is counted
+        let commit = match (&res, &turn_start) {
+            (Ok(_), Some(start)) => {
+                let writer = client.with_reasoning_disabled();
+                let session = format!("exec-{}", uuid::Uuid::now_v7());
+                Some(
+                    auto_commit::commit_turn(
+                        writer.as_ref(),
+                        &model,
+                        &fs_tools,
+                        start,
+                        &instruction,
+                        &session,
+                    )
+                    .await,
+                )
+            }
+            _ => None,
+        };
+
+        // Get token usage and spend after the agent loop completes
         let tokens_used = client.get_prompt_tokens_used();
+        let cost = CostSummary::from_usages(&client.usage_log().drain(), &self.cfg.price_table());
 
         match res {
             Ok((updated_messages, final_msg)) => {
@@ -142,6 +214,8 @@ impl Executor {
                     content: Some(final_msg.content.clone()),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
                 };
 
                 if let Err(e) = self
@@ -164,8 +238,10 @@ impl Executor {
                         "success": true,
                         "response": response,
                         "tokens_used": tokens_used,
+                        "cost": cost,
                         "tools_called": [], // TODO: Track tools called during execution
-                        "conversation_length": updated_messages.len()
+                        "conversation_length": updated_messages.len(),
+                        "commit": commit.as_ref().map(commit_json),
                     });
                     println!(
                         "{}",
@@ -174,8 +250,14 @@ impl Executor {
                         })
                     );
                 } else {
-                    println!("{}", final_msg.content);
+                    if !streamed {
+                        println!("{}", final_msg.content);
+                    }
+                    if let Some(commit) = &commit {
+                        report_commit(commit);
+                    }
                     eprintln!("Total prompt tokens used: {}", tokens_used);
+                    eprintln!("Total cost: {}", format_usd(cost.cost_usd));
                 }
             }
             Err(e) => {
@@ -183,7 +265,8 @@ impl Executor {
                     let output = serde_json::json!({
                         "success": false,
                         "error": e.to_string(),
-                        "tokens_used": tokens_used
+                        "tokens_used": tokens_used,
+                        "cost": cost
                     });
                     println!(
                         "{}",
@@ -194,6 +277,7 @@ impl Executor {
                 } else {
                     eprintln!("LLM error: {}", e);
                     eprintln!("Total prompt tokens used: {}", tokens_used);
+                    eprintln!("Total cost: {}", format_usd(cost.cost_usd));
                 }
             }
         }
@@ -244,6 +328,8 @@ impl Executor {
             content: Some(sys_prompt),
             tool_calls: vec![],
             tool_call_id: None,
+            reasoning: None,
+            images: vec![],
         });
 
         msgs.push(llm::types::ChatMessage {
@@ -251,33 +337,36 @@ impl Executor {
             content: Some(request.clone()),
             tool_calls: vec![],
             tool_call_id: None,
+            reasoning: None,
+            images: vec![],
         });
 
-        let (tx, _rx) = std::sync::mpsc::channel::<String>();
-
-        let res = llm::run_agent_loop(
-            client,
-            &model,
-            &fs_tools,
-            msgs,
-            Some(tx),
-            None,
-            &self.cfg,
-            None,
-        )
-        .await;
+        let res = client
+            .chat_structured(&model, msgs.clone(), &rewrite_schema(), None)
+            .await;
 
         let tokens_used = client.get_prompt_tokens_used();
+        let cost = CostSummary::from_usages(&client.usage_log().drain(), &self.cfg.price_table());
 
-        match res {
-            Ok((updated_messages, final_msg)) => {
-                // Execute hooks after the agent loop completes
+        let outcome = res.and_then(|payload| {
+            let raw_response = payload.to_string();
+            let rewrite = rewrite_from_payload(payload, snippet)?;
+            Ok((raw_response, rewrite))
+        });
+
+        match outcome {
+            Ok((raw_response, rewrite)) => {
+                // Execute hooks after the rewrite completes
                 let final_assistant_msg = crate::llm::types::ChatMessage {
                     role: "assistant".into(),
-                    content: Some(final_msg.content.clone()),
+                    content: Some(raw_response.clone()),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
                 };
+                let mut updated_messages = msgs;
+                updated_messages.push(final_assistant_msg.clone());
 
                 if let Err(e) = self
                     .hook_manager
@@ -293,50 +382,28 @@ impl Executor {
                     tracing::error!("Error executing hooks: {}", e);
                 }
 
-                let raw_response = final_msg.content.clone();
-                if let Some(rewritten) = extract_rewritten_code(&raw_response, snippet) {
-                    if json {
-                        let output = serde_json::json!({
-                            "success": true,
-                            "mode": "rewrite",
-                            "rewritten_code": rewritten,
-                            "tokens_used": tokens_used,
-                            "raw_response": raw_response,
-                            "file_path": original_file_path,
-                            "display_path": display_path,
-                        });
-                        println!(
-                            "{}",
-                            serde_json::to_string_pretty(&output).unwrap_or_else(|_| {
-                                r#"{"error": "JSON serialization failed"}"#.to_string()
-                            })
-                        );
-                    } else {
-                        println!("{}", rewritten);
-                        eprintln!("Total prompt tokens used: {}", tokens_used);
-                    }
+                if json {
+                    let output = serde_json::json!({
+                        "success": true,
+                        "mode": "rewrite",
+                        "rewritten_code": rewrite.rewritten_code,
+                        "explanation": rewrite.explanation,
+                        "tokens_used": tokens_used,
+                        "cost": cost,
+                        "raw_response": raw_response,
+                        "file_path": original_file_path,
+                        "display_path": display_path,
+                    });
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&output).unwrap_or_else(|_| {
+                            r#"{"error": "JSON serialization failed"}"#.to_string()
+                        })
+                    );
                 } else {
-                    let parse_error = "Failed to parse rewritten code from model response";
-                    if json {
-                        let output = serde_json::json!({
-                            "success": false,
-                            "error": parse_error,
-                            "raw_response": raw_response,
-                            "tokens_used": tokens_used,
-                            "file_path": original_file_path,
-                            "display_path": display_path,
-                        });
-                        println!(
-                            "{}",
-                            serde_json::to_string_pretty(&output).unwrap_or_else(|_| {
-                                r#"{"error": "JSON serialization failed"}"#.to_string()
-                            })
-                        );
-                    } else {
-                        eprintln!("{}", parse_error);
-                        eprintln!("{}", raw_response);
-                        eprintln!("Total prompt tokens used: {}", tokens_used);
-                    }
+                    println!("{}", rewrite.rewritten_code);
+                    eprintln!("Total prompt tokens used: {}", tokens_used);
+                    eprintln!("Total cost: {}", format_usd(cost.cost_usd));
                 }
             }
             Err(e) => {
@@ -345,6 +412,7 @@ impl Executor {
                         "success": false,
                         "error": e.to_string(),
                         "tokens_used": tokens_used,
+                        "cost": cost,
                         "file_path": original_file_path,
                         "display_path": display_path,
                     });
@@ -357,6 +425,7 @@ impl Executor {
                 } else {
                     eprintln!("LLM error: {}", e);
                     eprintln!("Total prompt tokens used: {}", tokens_used);
+                    eprintln!("Total cost: {}", format_usd(cost.cost_usd));
                 }
             }
         }
@@ -375,8 +444,35 @@ impl Executor {
     }
 }
 
-const REWRITE_MARKER_START: &str = "<REWRITTEN_CODE>";
-const REWRITE_MARKER_END: &str = "</REWRITTEN_CODE>";
+/// Auto-commit outcome for `--json` output.
+fn commit_json(commit: &Result<TurnCommit>) -> serde_json::Value {
+    match commit {
+        Ok(TurnCommit { commit, skipped }) => serde_json::json!({
+            "hash": commit.as_ref().map(|(hash, _)| hash),
+            "summary": commit.as_ref().map(|(_, summary)| summary),
+            "skipped": skipped,
+        }),
+        Err(e) => serde_json::json!({ "error": format!("{e:#}") }),
+    }
+}
+
+fn report_commit(commit: &Result<TurnCommit>) {
+    match commit {
+        Ok(TurnCommit { commit, skipped }) => {
+            if let Some((hash, summary)) = commit {
+                eprintln!("Committed {} {summary}", &hash[..hash.len().min(8)]);
+            }
+            if !skipped.is_empty() {
+                eprintln!(
+                    "Left out files that already had uncommitted changes: {}",
+                    skipped.join(", ")
+                );
+            }
+        }
+        Err(e) => eprintln!("Failed to commit the changes: {e:#}"),
+    }
+}
+
 const SNIPPET_MARKER_START: &str = "<ORIGINAL_SNIPPET>";
 const SNIPPET_MARKER_END: &str = "</ORIGINAL_SNIPPET>";
 
@@ -403,15 +499,13 @@ Only work with the provided snippet; do not assume or modify code outside it.\n\
 User request (natural language):\n{}\n\
 Original snippet (from {}), wrapped between {} and {} markers:\n{}\n\
 Rewrite the snippet so it satisfies the request.\n\
-Return only the rewritten snippet enclosed between {} and {} markers.\n\
-Do not include explanations, commentary, code fences, or additional text outside the markers.",
+Reply with a JSON object: `rewritten_code` holds only the rewritten snippet, without markers or code fences, \
+and `explanation` briefly describes the change.",
         prompt.trim(),
         location_hint,
         SNIPPET_MARKER_START,
         SNIPPET_MARKER_END,
         wrap_with_snippet_markers(snippet),
-        REWRITE_MARKER_START,
-        REWRITE_MARKER_END
     )
 }
 
@@ -422,12 +516,44 @@ fn wrap_with_snippet_markers(snippet: &str) -> String {
     )
 }
 
-fn extract_rewritten_code(response: &str, original_snippet: &str) -> Option<String> {
-    let start = response.find(REWRITE_MARKER_START)? + REWRITE_MARKER_START.len();
-    let rest = &response[start..];
-    let end = rest.find(REWRITE_MARKER_END)?;
-    let snippet = &rest[..end];
-    Some(adjust_rewrite_payload(snippet, original_snippet))
+/// Structured reply of `dgc rewrite`.
+#[derive(Debug, Deserialize)]
+struct RewritePayload {
+    rewritten_code: String,
+    #[serde(default)]
+    explanation: Option<String>,
+}
+
+fn rewrite_schema() -> ResponseSchema {
+    ResponseSchema {
+        name: "rewrite".to_string(),
+        description: Some("Submit the rewritten snippet.".to_string()),
+        schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "rewritten_code": {
+                    "type": "string",
+                    "description": "The rewritten snippet only, without markers or code fences"
+                },
+                "explanation": {
+                    "type": "string",
+                    "description": "Short description of the change"
+                }
+            },
+            "required": ["rewritten_code", "explanation"],
+            "additionalProperties": false
+        }),
+        strict: true,
+    }
+}
+
+fn rewrite_from_payload(
+    payload: serde_json::Value,
+    original_snippet: &str,
+) -> Result<RewritePayload> {
+    let mut rewrite: RewritePayload = llm::from_payload(payload)?;
+    rewrite.rewritten_code = adjust_rewrite_payload(&rewrite.rewritten_code, original_snippet);
+    Ok(rewrite)
 }
 
 fn adjust_rewrite_payload(payload: &str, original_snippet: &str) -> String {
@@ -469,6 +595,7 @@ mod tests {
 
         // Create a minimal config without API key
         let cfg = AppConfig {
+            provider: "openai".to_string(),
             base_url: "http://localhost:8080".to_string(),
             model: "test-model".to_string(),
             api_key: None, // No API key
@@ -476,7 +603,6 @@ mod tests {
             git_root: Some(project_root.clone()),
             llm: crate::config::LlmConfig::default(), // Add default LlmConfig
             watch_config: crate::config::WatchConfig::default(), // Add default WatchConfig
-            enable_stream_tools: false,               // Add enable_stream_tools
             theme: "default".to_string(),
             project_instructions_file: Some("PROJECT.md".to_string()), // Add project_instructions_file
             no_repomap: true, // Disable repomap for simplicity
@@ -488,6 +614,22 @@ mod tests {
             allowed_paths: vec![],
             allowed_commands: vec![], // Add allowed_commands
             mcp_servers: vec![crate::config::McpServerConfig::default()], // Add mcp_servers field
+            model_capabilities: None,
+            tool_call_mode: crate::config::ToolCallMode::default(),
+            tool_call_mode_overrides: HashMap::new(),
+            max_parallel_tools: crate::config::DEFAULT_MAX_PARALLEL_TOOLS,
+            context_window_overrides: HashMap::new(),
+            model_prices: HashMap::new(),
+            cassette: None,
+            fallback_models: vec![],
+            vision_overrides: HashMap::new(),
+            plan_mode: false,
+            permissions: crate::config::PermissionsConfig::default(),
+            sandbox: crate::config::SandboxConfig::default(),
+            persistent_shell: false,
+            command_policy: crate::config::CommandPolicyConfig::default(),
+            auto_commit: false,
+            auto_commit_hooks: false,
         };
 
         let executor = Executor::new(cfg);
@@ -503,6 +645,7 @@ mod tests {
 
         // Create a minimal config without API key
         let cfg = AppConfig {
+            provider: "openai".to_string(),
             base_url: "http://localhost:8080".to_string(),
             model: "test-model".to_string(),
             api_key: None, // No API key
@@ -510,7 +653,6 @@ mod tests {
             git_root: Some(project_root.clone()),
             llm: crate::config::LlmConfig::default(), // Add default LlmConfig
             watch_config: crate::config::WatchConfig::default(), // Add default WatchConfig
-            enable_stream_tools: false,               // Add enable_stream_tools
             theme: "default".to_string(),
             project_instructions_file: Some("PROJECT.md".to_string()), // Add project_instructions_file
             no_repomap: true, // Disable repomap for simplicity
@@ -522,6 +664,22 @@ mod tests {
             allowed_paths: vec![],
             allowed_commands: vec![], // Add allowed_commands
             mcp_servers: vec![crate::config::McpServerConfig::default()], // Add mcp_servers field
+            model_capabilities: None,
+            tool_call_mode: crate::config::ToolCallMode::default(),
+            tool_call_mode_overrides: HashMap::new(),
+            max_parallel_tools: crate::config::DEFAULT_MAX_PARALLEL_TOOLS,
+            context_window_overrides: HashMap::new(),
+            model_prices: HashMap::new(),
+            cassette: None,
+            fallback_models: vec![],
+            vision_overrides: HashMap::new(),
+            plan_mode: false,
+            permissions: crate::config::PermissionsConfig::default(),
+            sandbox: crate::config::SandboxConfig::default(),
+            persistent_shell: false,
+            command_policy: crate::config::CommandPolicyConfig::default(),
+            auto_commit: false,
+            auto_commit_hooks: false,
         };
 
         let mut executor = Executor::new(cfg).unwrap();
@@ -539,32 +697,24 @@ mod tests {
     }
 
     #[test]
-    fn test_extract_rewritten_code_preserves_trailing_newline() {
+    fn test_rewrite_from_payload_preserves_trailing_newline() {
         let snippet = "fn main() { println(\"hi\"); }";
-        let response = format!(
-            "{}\n{}\n{}",
-            super::REWRITE_MARKER_START,
-            snippet,
-            super::REWRITE_MARKER_END
-        );
+        let payload = serde_json::json!({"rewritten_code": snippet, "explanation": "same"});
         let original = format!("{}\n", snippet);
-        let rewritten = super::extract_rewritten_code(&response, &original).unwrap();
-        assert!(rewritten.ends_with('\n'));
-        assert!(rewritten.starts_with("fn main()"));
+        let rewrite = super::rewrite_from_payload(payload, &original).unwrap();
+        assert!(rewrite.rewritten_code.ends_with('\n'));
+        assert!(rewrite.rewritten_code.starts_with("fn main()"));
+        assert_eq!(rewrite.explanation.as_deref(), Some("same"));
     }
 
     #[test]
-    fn test_extract_rewritten_code_trims_padding() {
+    fn test_rewrite_from_payload_trims_padding() {
         let snippet = "fn add(a: i32, b: i32) -> i32 { a + b }";
-        let response = format!(
-            "{}\n\n{}\n\n{}",
-            super::REWRITE_MARKER_START,
-            snippet,
-            super::REWRITE_MARKER_END
-        );
-        let original = "fn add(a: i32, b: i32) -> i32 { a + b }";
-        let rewritten = super::extract_rewritten_code(&response, original).unwrap();
-        assert_eq!(rewritten, snippet);
+        let payload = serde_json::json!({"rewritten_code": format!("\n\n{}\n\n", snippet)});
+        let rewrite = super::rewrite_from_payload(payload, snippet).unwrap();
+        assert_eq!(rewrite.rewritten_code, snippet);
+
+        assert!(super::rewrite_from_payload(serde_json::json!({"code": "x"}), snippet).is_err());
     }
 
     #[test]
@@ -0,0 +1,509 @@
+use crate::llm::provider::LlmProvider;
+use crate::llm::types::ChatMessage;
+use crate::tools::FsTools;
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Trailer linking an agent commit to the session that made it. Only commits
+/// carrying it are ever reset or reverted by `/undo`.
+pub const SESSION_TRAILER: &str = "Doge-Session";
+
+/// Pathspec keeping the agent's own state (checkpoints, sessions) in `.doge`
+/// directories out of auto-commits.
+const EXCLUDE_DOGE_DIRS: &str = ":(exclude,glob)**/.doge/**";
+
+/// Bytes of the diff shown to the model when writing the commit message.
+const MAX_DIFF_BYTES: usize = 20_000;
+
+const COMMIT_MESSAGE_PROMPT: &str = "You write git commit messages. Reply with the message only, \
+without code fences: a summary line of at most 72 characters in the imperative mood, then, only \
+if the change needs explaining, a blank line and a short body wrapped at 72 columns.";
+
+/// Changes of one turn staged for an auto-commit.
+#[derive(Debug)]
+pub struct StagedTurn {
+    /// Paths relative to the repository root.
+    pub paths: Vec<String>,
+    pub diff: String,
+}
+
+/// Result of auto-committing a turn.
+#[derive(Debug, Default)]
+pub struct TurnCommit {
+    /// Hash and summary line of the new commit, if anything was committed.
+    pub commit: Option<(String, String)>,
+    /// Files the turn changed that already had uncommitted changes when it
+    /// started. They are left for the user to commit.
+    pub skipped: Vec<String>,
+}
+
+/// The uncommitted changes in a repository when a turn started, so the
+/// auto-commit takes only what the turn itself changed, whether through file
+/// tools or shell commands.
+#[derive(Debug, Clone)]
+pub struct TurnStart {
+    repo: GitRepo,
+    // Paths differing from HEAD and the blob of their content then, `None`
+    // for deleted ones
+    dirty: BTreeMap<String, Option<String>>,
+}
+
+impl TurnStart {
+    /// Record the state of the repository containing `dir`, or `None`
+    /// outside a repository.
+    pub fn capture(dir: &Path) -> Result<Option<Self>> {
+        let Some(repo) = GitRepo::discover(dir) else {
+            return Ok(None);
+        };
+        let dirty = repo.contents(&repo.dirty_paths()?)?;
+        Ok(Some(Self { repo, dirty }))
+    }
+
+    pub fn repo(&self) -> &GitRepo {
+        &self.repo
+    }
+
+    /// Paths the turn changed that were clean when it started, and the
+    /// already dirty ones it changed as well.
+    pub fn changes(&self) -> Result<(Vec<String>, Vec<String>)> {
+        let now = self.repo.dirty_paths()?;
+        let (before, fresh): (Vec<String>, Vec<String>) = now
+            .into_iter()
+            .partition(|path| self.dirty.contains_key(path));
+        let skipped = self
+            .repo
+            .contents(&before)?
+            .into_iter()
+            .filter(|(path, blob)| self.dirty.get(path) != Some(blob))
+            .map(|(path, _)| path)
+            .collect();
+        Ok((fresh, skipped))
+    }
+}
+
+/// How `/undo` took an agent commit back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndoneCommit {
+    /// The commit was the unpushed tip of the branch and was dropped.
+    Reset,
+    /// A revert commit was added on top.
+    Reverted,
+}
+
+/// The git repository an auto-commit goes to.
+#[derive(Debug, Clone)]
+pub struct GitRepo {
+    root: PathBuf,
+}
+
+impl GitRepo {
+    /// The repository containing `dir`, if any.
+    pub fn discover(dir: &Path) -> Option<Self> {
+        let output = Command::new("git")
+            .args(["rev-parse", "--show-toplevel"])
+            .current_dir(dir)
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let root = String::from_utf8(output.stdout).ok()?;
+        Some(Self {
+            root: PathBuf::from(root.trim()),
+        })
+    }
+
+    /// Stage `paths`, relative to the repository root, and return them with
+    /// their diff, or `None` when none of them differs from `HEAD`.
+    pub fn stage(&self, paths: &[String]) -> Result<Option<StagedTurn>> {
+        if paths.is_empty() {
+            return Ok(None);
+        }
+        self.git_with(&["add", "-A", "--"], paths)?;
+        let diff = self.git_with(&["diff", "--cached", "--color=never", "--"], paths)?;
+        if diff.trim().is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(StagedTurn {
+            paths: paths.to_vec(),
+            diff,
+        }))
+    }
+
+    /// Commit the staged paths of a turn, leaving anything else the user has
+    /// staged alone, and return the new commit's hash. The repository's hooks
+    /// only run when `run_hooks` is set, since they run outside the sandbox.
+    pub fn commit(
+        &self,
+        staged: &StagedTurn,
+        message: &str,
+        session: &str,
+        run_hooks: bool,
+    ) -> Result<String> {
+        let message = format!("{}\n\n{SESSION_TRAILER}: {session}\n", message.trim());
+        let mut args = Vec::new();
+        if !run_hooks {
+            args.extend(["-c", "core.hooksPath=/dev/null"]);
+        }
+        args.extend(["commit", "--only", "-m", message.as_str()]);
+        if !run_hooks {
+            args.push("--no-verify");
+        }
+        args.push("--");
+        args.extend(staged.paths.iter().map(String::as_str));
+        args.push(EXCLUDE_DOGE_DIRS);
+        self.git(&args)?;
+        Ok(self.git(&["rev-parse", "HEAD"])?.trim().to_string())
+    }
+
+    /// Take back an agent commit: drop it when it is the unpushed tip of the
+    /// branch, otherwise revert it. Local changes are never overwritten; git
+    /// refuses instead and the error is returned.
+    pub fn undo_commit(&self, commit: &str) -> Result<UndoneCommit> {
+        let message = self.git(&["show", "-s", "--format=%B", commit])?;
+        let trailer = format!("{SESSION_TRAILER}: ");
+        if !message.lines().any(|line| line.starts_with(&trailer)) {
+            bail!("{commit} was not made by the agent");
+        }
+        let head = self.git(&["rev-parse", "HEAD"])?;
+        let pushed = !self
+            .git(&["branch", "-r", "--contains", commit])?
+            .trim()
+            .is_empty();
+        let has_parent = self
+            .git(&["rev-parse", "--verify", "-q", &format!("{commit}^")])
+            .is_ok();
+        if head.trim() == commit && !pushed && has_parent {
+            self.git(&["reset", "--keep", &format!("{commit}^")])?;
+            Ok(UndoneCommit::Reset)
+        } else {
+            self.git(&["revert", "--no-edit", commit])?;
+            Ok(UndoneCommit::Reverted)
+        }
+    }
+
+    /// Paths, relative to the repository root, that differ from `HEAD` or
+    /// are untracked and not ignored, outside `.doge` directories.
+    fn dirty_paths(&self) -> Result<Vec<String>> {
+        let status = self.git(&[
+            "status",
+            "--porcelain=v1",
+            "-z",
+            "--no-renames",
+            "--untracked-files=all",
+            "--",
+            EXCLUDE_DOGE_DIRS,
+        ])?;
+        Ok(status
+            .split('\0')
+            .filter_map(|entry| entry.get(3..))
+            .filter(|path| !path.is_empty())
+            .map(ToString::to_string)
+            .collect())
+    }
+
+    /// Blob hash of the working tree content of each of `paths`.
+    fn contents(&self, paths: &[String]) -> Result<BTreeMap<String, Option<String>>> {
+        let existing: Vec<String> = paths
+            .iter()
+            .filter(|path| self.root.join(path).symlink_metadata().is_ok())
+            .cloned()
+            .collect();
+        let hashes = if existing.is_empty() {
+            String::new()
+        } else {
+            self.git_with(&["hash-object", "--"], &existing)?
+        };
+        let mut contents: BTreeMap<_, _> = paths.iter().map(|p| (p.clone(), None)).collect();
+        for (path, hash) in existing.into_iter().zip(hashes.lines()) {
+            contents.insert(path, Some(hash.to_string()));
+        }
+        Ok(contents)
+    }
+
+    fn git_with(&self, args: &[&str], paths: &[String]) -> Result<String> {
+        let mut all = args.to_vec();
+        all.extend(paths.iter().map(String::as_str));
+        self.git(&all)
+    }
+
+    fn git(&self, args: &[&str]) -> Result<String> {
+        // The subcommand, after a `-c name=value` option
+        let name = match args {
+            ["-c", _, name, ..] => name,
+            _ => &args[0],
+        };
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(&self.root)
+            .output()
+            .with_context(|| format!("failed to run git {name}"))?;
+        if !output.status.success() {
+            bail!(
+                "git {name} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        String::from_utf8(output.stdout).with_context(|| format!("git {name} output"))
+    }
+}
+
+/// Commit what changed in the repository since `start` with a message
+/// generated from the diff and `prompt`. Files that already had uncommitted
+/// changes when the turn started are left out, so the user's own work is
+/// never committed or later undone with the agent's.
+pub async fn commit_turn(
+    client: &dyn LlmProvider,
+    model: &str,
+    fs: &FsTools,
+    start: &TurnStart,
+    prompt: &str,
+    session: &str,
+) -> Result<TurnCommit> {
+    let (paths, skipped) = start.changes()?;
+    let Some(staged) = start.repo.stage(&paths)? else {
+        return Ok(TurnCommit {
+            commit: None,
+            skipped,
+        });
+    };
+    let message = generate_message(client, model, prompt, &staged.diff).await;
+    let commit = start
+        .repo
+        .commit(&staged, &message, session, fs.config.auto_commit_hooks)?;
+    fs.checkpoints().record_commit(&commit)?;
+    let summary = message.lines().next().unwrap_or_default().to_string();
+    Ok(TurnCommit {
+        commit: Some((commit, summary)),
+        skipped,
+    })
+}
+
+/// Ask the model for a commit message describing `diff`, made in answer to
+/// `prompt`. Falls back to the first line of the prompt.
+pub async fn generate_message(
+    client: &dyn LlmProvider,
+    model: &str,
+    prompt: &str,
+    diff: &str,
+) -> String {
+    let diff = if diff.len() > MAX_DIFF_BYTES {
+        let cut = diff.floor_char_boundary(MAX_DIFF_BYTES);
+        format!("{}\n[... diff truncated ...]\n", &diff[..cut])
+    } else {
+        diff.to_string()
+    };
+    let messages = vec![
+        message("system", COMMIT_MESSAGE_PROMPT.to_string()),
+        message("user", format!("Request:\n{prompt}\n\nDiff:\n{diff}")),
+    ];
+    match client.chat_once(model, messages, None).await {
+        Ok(reply) if !clean_message(&reply.content).is_empty() => clean_message(&reply.content),
+        Ok(_) => fallback_message(prompt),
+        Err(e) => {
+            tracing::warn!(?e, "Failed to generate a commit message");
+            fallback_message(prompt)
+        }
+    }
+}
+
+fn message(role: &str, content: String) -> ChatMessage {
+    ChatMessage {
+        role: role.into(),
+        content: Some(content),
+        tool_calls: vec![],
+        tool_call_id: None,
+        reasoning: None,
+        images: vec![],
+    }
+}
+
+/// Drop code fences and surrounding blank lines the model may add.
+fn clean_message(raw: &str) -> String {
+    raw.lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+fn fallback_message(prompt: &str) -> String {
+    let line = prompt.lines().next().unwrap_or("").trim();
+    let cut = line.floor_char_boundary(72);
+    if line.is_empty() {
+        "Apply agent changes".to_string()
+    } else {
+        line[..cut].to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    fn init_repo() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        git(dir.path(), &["init", "-q"]);
+        git(dir.path(), &["config", "user.name", "Test User"]);
+        git(dir.path(), &["config", "user.email", "test@example.com"]);
+        git(dir.path(), &["config", "commit.gpgsign", "false"]);
+        fs::write(dir.path().join("README.md"), "readme\n").unwrap();
+        fs::write(dir.path().join(".gitignore"), "ignored.txt\n").unwrap();
+        git(dir.path(), &["add", "."]);
+        git(dir.path(), &["commit", "-q", "-m", "Initial commit"]);
+        dir
+    }
+
+    #[test]
+    fn commits_only_the_turn_changes_and_undo_resets_them() -> Result<()> {
+        let dir = init_repo();
+        let root = dir.path();
+        // A change the user made and staged themselves
+        fs::write(root.join(".gitignore"), "ignored.txt\ntarget/\n")?;
+        git(root, &["add", ".gitignore"]);
+        let start = TurnStart::capture(root)?.expect("a repository");
+        let repo = start.repo();
+
+        fs::write(root.join("README.md"), "changed\n")?;
+        fs::write(root.join("new.rs"), "fn main() {}\n")?;
+        fs::write(root.join("ignored.txt"), "scratch\n")?;
+        let (paths, skipped) = start.changes()?;
+        assert_eq!(paths, ["README.md", "new.rs"]);
+        assert!(skipped.is_empty());
+        let staged = repo.stage(&paths)?.expect("changes to commit");
+        assert!(staged.diff.contains("+changed"));
+
+        let commit = repo.commit(&staged, "Update the readme", "session-1", false)?;
+        let message = git(root, &["show", "-s", "--format=%B", "HEAD"]);
+        assert!(message.starts_with("Update the readme\n"));
+        assert!(message.contains("Doge-Session: session-1"));
+        let committed = git(root, &["show", "--name-only", "--format=", "HEAD"]);
+        assert_eq!(
+            committed.lines().collect::<Vec<_>>(),
+            ["README.md", "new.rs"]
+        );
+        // The user's staged change is still staged, not committed
+        assert_eq!(
+            git(root, &["diff", "--cached", "--name-only"]),
+            ".gitignore\n"
+        );
+
+        assert_eq!(repo.undo_commit(&commit)?, UndoneCommit::Reset);
+        assert_eq!(fs::read_to_string(root.join("README.md"))?, "readme\n");
+        assert!(!root.join("new.rs").exists());
+        assert_eq!(git(root, &["rev-list", "--count", "HEAD"]).trim(), "1");
+        assert!(repo.stage(&["README.md".to_string()])?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn files_dirty_before_the_turn_are_left_out_and_hooks_do_not_run() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = init_repo();
+        let root = dir.path();
+        let hook = root.join(".git/hooks/pre-commit");
+        fs::write(&hook, "#!/bin/sh\ntouch hook-ran\nexit 1\n")?;
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755))?;
+        fs::write(root.join("README.md"), "user edit\n")?;
+        let start = TurnStart::capture(root)?.expect("a repository");
+        let repo = start.repo();
+
+        // The agent edits the dirty file and a shell command generates another
+        fs::write(root.join("README.md"), "user edit\nagent edit\n")?;
+        fs::write(root.join("generated.rs"), "// generated\n")?;
+        let (paths, skipped) = start.changes()?;
+        assert_eq!(paths, ["generated.rs"]);
+        assert_eq!(skipped, ["README.md"]);
+
+        let staged = repo.stage(&paths)?.expect("changes to commit");
+        let commit = repo.commit(&staged, "Generate code", "session-1", false)?;
+        assert!(!root.join("hook-ran").exists());
+        let committed = git(root, &["show", "--name-only", "--format=", "HEAD"]);
+        assert_eq!(committed, "generated.rs\n");
+
+        assert_eq!(repo.undo_commit(&commit)?, UndoneCommit::Reset);
+        assert!(!root.join("generated.rs").exists());
+        assert_eq!(
+            fs::read_to_string(root.join("README.md"))?,
+            "user edit\nagent edit\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn checkpoints_and_sessions_are_not_committed() -> Result<()> {
+        let dir = init_repo();
+        let root = dir.path();
+        fs::create_dir_all(root.join(".doge/sessions"))?;
+        fs::write(root.join(".doge/sessions/old.json"), "{}")?;
+        let checkpoints = crate::tools::checkpoints::Checkpoints::new(root);
+        let start = TurnStart::capture(root)?.expect("a repository");
+
+        checkpoints.begin_turn("session-1", 0, "edit the readme")?;
+        checkpoints.snapshot(&root.join("README.md"))?;
+        fs::write(
+            root.join("README.md"),
+            "changed
+",
+        )?;
+        fs::write(root.join(".doge/sessions/old.json"), "{\"turns\": 1}")?;
+        let (paths, skipped) = start.changes()?;
+        assert_eq!(paths, ["README.md"]);
+        assert!(skipped.is_empty());
+
+        let staged = start.repo().stage(&paths)?.expect("changes to commit");
+        start
+            .repo()
+            .commit(&staged, "Edit the readme", "session-1", false)?;
+        let committed = git(root, &["show", "--name-only", "--format=", "HEAD"]);
+        assert_eq!(committed, "README.md\n");
+        Ok(())
+    }
+
+    #[test]
+    fn undo_reverts_commits_that_are_not_the_tip_and_skips_user_commits() -> Result<()> {
+        let dir = init_repo();
+        let root = dir.path();
+        let repo = GitRepo::discover(root).expect("a repository");
+        fs::write(root.join("README.md"), "agent\n")?;
+        let staged = repo.stage(&["README.md".to_string()])?.unwrap();
+        let commit = repo.commit(&staged, "Agent change", "session-1", false)?;
+        fs::write(root.join("other.txt"), "user\n")?;
+        git(root, &["add", "other.txt"]);
+        git(root, &["commit", "-q", "-m", "User change"]);
+
+        assert_eq!(repo.undo_commit(&commit)?, UndoneCommit::Reverted);
+        assert_eq!(fs::read_to_string(root.join("README.md"))?, "readme\n");
+        assert!(root.join("other.txt").exists());
+
+        let user_commit = git(root, &["rev-parse", "HEAD~1"]);
+        assert!(repo.undo_commit(user_commit.trim()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn messages_are_cleaned_up_or_fall_back_to_the_prompt() {
+        assert_eq!(
+            clean_message("```\nFix the parser\n\nHandle empty input.\n```\n"),
+            "Fix the parser\n\nHandle empty input."
+        );
+        assert_eq!(fallback_message("add a flag\nmore details"), "add a flag");
+        assert_eq!(fallback_message(""), "Apply agent changes");
+        assert_eq!(fallback_message(&"x".repeat(100)).len(), 72);
+    }
+}
@@ -1 +1,2 @@
+pub mod auto_commit;
 pub mod worktree_manager;
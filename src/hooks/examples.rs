@@ -65,6 +65,7 @@ impl InstructionHook for LoggingHook {
 #[derive(Clone)]
 pub struct SaveConversationHook {
     name: String,
+    #[allow(dead_code)]
     output_path: String,
 }
 
@@ -115,6 +116,7 @@ impl InstructionHook for SaveConversationHook {
 #[derive(Clone)]
 pub struct AnalysisHook {
     name: String,
+    #[allow(dead_code)]
     model: String,
 }
 
@@ -0,0 +1,733 @@
+//! Native Anthropic Messages API backend.
+//!
+//! Talks to `/v1/messages` directly instead of going through an OpenAI-compatible
+//! proxy so that tool_use/tool_result blocks, system prompts and usage (including
+//! cached prompt tokens) are handled with full fidelity. Models with extended
+//! thinking get a thinking budget; the signed thinking blocks returned alongside
+//! tool calls are cached per tool call id and sent back on the next request.
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::header::{CONTENT_TYPE, HeaderMap};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::config::{LlmConfig, PromptCacheMode};
+use crate::cost::{RequestUsage, UsageLog};
+use crate::llm::LlmErrorKind;
+use crate::llm::chat_with_tools::ChoiceMessageWithTools;
+use crate::llm::provider::{ChatStream, LlmProvider};
+use crate::llm::retry::{CircuitBreaker, Retrier, RetryPolicy, breaker_for};
+use crate::llm::types::{ChatMessage, ChoiceMessage, ToolDef};
+
+mod stream;
+mod wire;
+
+use wire::{
+    ContentBlock, MessagesRequest, MessagesResponse, ThinkingConfig, add_cache_breakpoints,
+    build_request, into_choice_message, thinking_carry_over,
+};
+
+pub const ANTHROPIC_VERSION: &str = "2023-06-01";
+pub const DEFAULT_MAX_TOKENS: u32 = 8192;
+pub const DEFAULT_THINKING_BUDGET: u32 = 4096;
+
+#[derive(Debug, Clone)]
+pub struct AnthropicClient {
+    pub base_url: String,
+    pub api_key: String,
+    pub(crate) inner: reqwest::Client,
+    pub llm_cfg: LlmConfig,
+    /// Upper bound for generated tokens; required by the Messages API
+    pub max_tokens: u32,
+    /// Thinking budget sent to models with extended thinking; `None` disables thinking
+    pub thinking_budget: Option<u32>,
+    /// Thinking blocks keyed by the id of the first tool call they preceded
+    thinking: Arc<Mutex<HashMap<String, Vec<ContentBlock>>>>,
+    /// Tracks total tokens used by this client
+    pub tokens_used: Arc<AtomicU32>,
+    /// Tracks prompt tokens used by this client (for header display)
+    pub prompt_tokens_used: Arc<AtomicU32>,
+    /// Per-request usage for cost accounting
+    pub usage_log: UsageLog,
+}
+
+impl AnthropicClient {
+    pub fn new(base_url: impl Into<String>, api_key: impl Into<String>) -> Result<Self> {
+        let inner = reqwest::Client::builder().build()?;
+        Ok(Self {
+            base_url: base_url.into(),
+            api_key: api_key.into(),
+            inner,
+            llm_cfg: LlmConfig::default(),
+            max_tokens: DEFAULT_MAX_TOKENS,
+            thinking_budget: Some(DEFAULT_THINKING_BUDGET),
+            thinking: Arc::new(Mutex::new(HashMap::new())),
+            tokens_used: Arc::new(AtomicU32::new(0)),
+            prompt_tokens_used: Arc::new(AtomicU32::new(0)),
+            usage_log: UsageLog::default(),
+        })
+    }
+
+    pub fn with_llm_config(mut self, cfg: LlmConfig) -> Self {
+        let builder = reqwest::Client::builder()
+            .connect_timeout(Duration::from_millis(cfg.connect_timeout_ms))
+            .timeout(Duration::from_millis(cfg.timeout_ms))
+            .read_timeout(Duration::from_millis(cfg.timeout_ms));
+        if let Ok(c) = builder.build() {
+            self.inner = c;
+        }
+        self.llm_cfg = cfg;
+        self
+    }
+
+    pub(crate) fn endpoint(&self) -> String {
+        let mut base = self.base_url.trim_end_matches('/').to_string();
+        if let Some(pos) = base.rfind("/v1") {
+            base.truncate(pos);
+            base = base.trim_end_matches('/').to_string();
+        }
+        format!("{base}/v1/messages")
+    }
+
+    fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+        headers.insert("anthropic-version", ANTHROPIC_VERSION.parse().unwrap());
+        if let Ok(key) = self.api_key.parse() {
+            headers.insert("x-api-key", key);
+        }
+        headers
+    }
+
+    fn record_usage(&self, model: &str, usage: &wire::AnthropicUsage) {
+        self.tokens_used
+            .store(usage.total_tokens(), Ordering::Relaxed);
+        self.prompt_tokens_used
+            .store(usage.prompt_tokens(), Ordering::Relaxed);
+        self.usage_log.record(RequestUsage {
+            model: model.to_string(),
+            input_tokens: usage.prompt_tokens(),
+            output_tokens: usage.output_tokens,
+            cached_tokens: usage.cache_read_input_tokens.unwrap_or(0),
+        });
+    }
+
+    fn build_request(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDef],
+    ) -> MessagesRequest {
+        let referenced: HashSet<String> = messages
+            .iter()
+            .flat_map(|m| m.tool_calls.iter().filter_map(|tc| tc.id.clone()))
+            .collect();
+        let mut thinking = self.thinking.lock().unwrap();
+        // Drop blocks for calls that are no longer in the history (e.g. after compaction)
+        thinking.retain(|call_id, _| referenced.contains(call_id));
+        // A tool-use turn whose thinking blocks are unknown (a restored session,
+        // a fallback model) has to be continued without thinking
+        let pending_call = messages
+            .iter()
+            .rev()
+            .find(|m| m.role == "assistant")
+            .and_then(|m| m.tool_calls.first());
+        let budget = self
+            .thinking_budget
+            .filter(|_| supports_thinking(model))
+            .filter(|_| {
+                pending_call
+                    .is_none_or(|tc| tc.id.as_ref().is_some_and(|id| thinking.contains_key(id)))
+            })
+            // The budget counts against max_tokens and must stay below it
+            .map(|budget| budget.min(self.max_tokens.saturating_sub(1)));
+        let none = HashMap::new();
+        let replayed = if budget.is_some() { &*thinking } else { &none };
+        let mut req = build_request(model, messages, tools, self.max_tokens, replayed);
+        drop(thinking);
+        req.thinking = budget.map(|budget_tokens| ThinkingConfig::Enabled { budget_tokens });
+        // The native API supports explicit caching for every model
+        if self.llm_cfg.prompt_cache != PromptCacheMode::Off {
+            add_cache_breakpoints(&mut req);
+        }
+        req
+    }
+
+    /// Cache the thinking blocks of a turn that called tools.
+    fn remember_thinking(&self, carry_over: Option<(String, Vec<ContentBlock>)>) {
+        if let Some((call_id, blocks)) = carry_over {
+            debug!(%call_id, blocks = blocks.len(), "caching anthropic thinking blocks");
+            self.thinking.lock().unwrap().insert(call_id, blocks);
+        }
+    }
+
+    /// Send a request and return the response once a success status is received.
+    async fn send(
+        &self,
+        req: &MessagesRequest,
+        cancel_token: &CancellationToken,
+    ) -> Result<reqwest::Response> {
+        let url = self.endpoint();
+        let timeout_duration = Duration::from_millis(self.llm_cfg.timeout_ms);
+        let fut = tokio::time::timeout(
+            timeout_duration,
+            self.inner
+                .post(&url)
+                .headers(self.headers())
+                .json(req)
+                .send(),
+        );
+
+        let resp = tokio::select! {
+            biased;
+            _ = cancel_token.cancelled() => {
+                warn!("anthropic request cancelled before send");
+                return Err(anyhow!(LlmErrorKind::Cancelled));
+            }
+            res = fut => match res {
+                Ok(Ok(resp)) => resp,
+                Ok(Err(e)) => return Err(anyhow::Error::new(e).context("send anthropic messages request")),
+                Err(_) => return Err(anyhow!(LlmErrorKind::Timeout)),
+            },
+        };
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let retry_after = crate::llm::retry::retry_after(resp.headers());
+            let text = resp.text().await.unwrap_or_default().trim().to_owned();
+            error!(status=%status.as_u16(), body=%text, "anthropic messages non-success status");
+            return Err(map_error_response(status, retry_after, &text));
+        }
+        Ok(resp)
+    }
+
+    /// Send one non-streaming request and decode the assistant message.
+    async fn attempt(
+        &self,
+        model: &str,
+        req: &MessagesRequest,
+        cancel_token: &CancellationToken,
+    ) -> Result<ChoiceMessageWithTools> {
+        let resp = self.send(req, cancel_token).await?;
+
+        let timeout_duration = Duration::from_millis(self.llm_cfg.timeout_ms);
+        let text = tokio::select! {
+            biased;
+            _ = cancel_token.cancelled() => {
+                warn!("anthropic request cancelled during body read");
+                return Err(anyhow!(LlmErrorKind::Cancelled));
+            }
+            res = tokio::time::timeout(timeout_duration, resp.text()) => match res {
+                Ok(Ok(text)) => text,
+                Ok(Err(e)) => return Err(anyhow::Error::new(e).context("read anthropic response body")),
+                Err(_) => return Err(anyhow!(LlmErrorKind::Timeout)),
+            },
+        };
+
+        debug!(response_body=%text, "anthropic messages response");
+        let body: MessagesResponse = serde_json::from_str(&text)?;
+        if let Some(usage) = &body.usage {
+            self.record_usage(model, usage);
+        }
+        debug!(id=?body.id, stop_reason=?body.stop_reason, "anthropic messages completed");
+        self.remember_thinking(thinking_carry_over(&body.content));
+        Ok(into_choice_message(body.content))
+    }
+}
+
+/// Models with extended thinking (Claude 3.7 and the Claude 4 families).
+fn supports_thinking(model: &str) -> bool {
+    let name = model.rsplit('/').next().unwrap_or(model);
+    let Some(rest) = name.strip_prefix("claude-") else {
+        return false;
+    };
+    rest.starts_with("3-7")
+        || rest
+            .split('-')
+            .find_map(|part| part.parse::<u32>().ok())
+            .is_some_and(|major| major >= 4)
+}
+
+/// Translate an error response body into an error the agent loop understands.
+fn map_error_response(
+    status: reqwest::StatusCode,
+    retry_after: Option<Duration>,
+    text: &str,
+) -> anyhow::Error {
+    if status.as_u16() == 400
+        && let Ok(json) = serde_json::from_str::<serde_json::Value>(text)
+        && let Some(message) = json
+            .get("error")
+            .and_then(|e| e.get("message"))
+            .and_then(|m| m.as_str())
+        && message.contains("prompt is too long")
+    {
+        return anyhow!(LlmErrorKind::ContextLengthExceeded);
+    }
+    crate::llm::status_error("anthropic messages error", status, retry_after, text)
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicClient {
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+
+    async fn chat_once(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChoiceMessage> {
+        let msg = self.chat_tools_once(model, messages, &[], cancel).await?;
+        Ok(ChoiceMessage {
+            role: msg.role,
+            content: msg.content.unwrap_or_default(),
+        })
+    }
+
+    async fn chat_tools_once(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDef],
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChoiceMessageWithTools> {
+        let cancel_token = cancel.unwrap_or_default();
+        let req = self.build_request(model, messages, tools);
+        Retrier::new(
+            RetryPolicy::from_config(&self.llm_cfg),
+            self.circuit_breaker(),
+            "anthropic_messages",
+        )
+        .run(&cancel_token, || self.attempt(model, &req, &cancel_token))
+        .await
+    }
+
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDef],
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChatStream> {
+        let cancel_token = cancel.unwrap_or_default();
+        let mut req = self.build_request(model, messages, tools);
+        req.stream = Some(true);
+        let resp = self.send(&req, &cancel_token).await?;
+
+        let mut byte_stream = resp.bytes_stream();
+        let client = self.clone();
+        let model = model.to_string();
+        let timeout_duration = Duration::from_millis(self.llm_cfg.timeout_ms);
+
+        let stream = async_stream::try_stream! {
+            let mut state = stream::StreamState::new();
+            let mut buf = Vec::<u8>::new();
+            loop {
+                let chunk = tokio::select! {
+                    biased;
+                    _ = cancel_token.cancelled() => {
+                        info!("anthropic stream cancelled");
+                        Err(anyhow!(LlmErrorKind::Cancelled))
+                    }
+                    res = tokio::time::timeout(timeout_duration, byte_stream.next()) => match res {
+                        Ok(Some(Ok(bytes))) => Ok(bytes),
+                        Ok(Some(Err(e))) => Err(anyhow::Error::new(e).context("byte stream read error")),
+                        Ok(None) => break,
+                        Err(_) => Err(anyhow!(LlmErrorKind::Timeout)),
+                    },
+                }?;
+
+                buf.extend_from_slice(&chunk);
+                while let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = buf.drain(..=pos).collect();
+                    let Ok(line) = std::str::from_utf8(&line) else {
+                        continue;
+                    };
+                    let Some(payload) = line.trim().strip_prefix("data:") else {
+                        continue;
+                    };
+                    for out in state.handle(payload.trim())? {
+                        yield out;
+                    }
+                }
+            }
+            client.record_usage(&model, &state.usage);
+            client.remember_thinking(state.thinking_carry_over());
+        };
+
+        Ok(Box::pin(stream))
+    }
+
+    fn with_reasoning_disabled(&self) -> Arc<dyn LlmProvider> {
+        let mut client = self.clone();
+        client.thinking_budget = None;
+        Arc::new(client)
+    }
+
+    fn get_tokens_used(&self) -> u32 {
+        self.tokens_used.load(Ordering::Relaxed)
+    }
+
+    fn set_tokens(&self, tokens: u32) {
+        self.tokens_used.store(tokens, Ordering::Relaxed);
+    }
+
+    fn get_prompt_tokens_used(&self) -> u32 {
+        self.prompt_tokens_used.load(Ordering::Relaxed)
+    }
+
+    fn set_prompt_tokens(&self, tokens: u32) {
+        self.prompt_tokens_used.store(tokens, Ordering::Relaxed);
+    }
+
+    fn usage_log(&self) -> &UsageLog {
+        &self.usage_log
+    }
+
+    fn circuit_breaker(&self) -> Arc<CircuitBreaker> {
+        breaker_for(&self.base_url, &self.llm_cfg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::types::{ToolDef, ToolFunctionDef};
+    use httptest::{Expectation, Server, matchers::*, responders::*};
+    use serde_json::json;
+
+    fn user(content: &str) -> ChatMessage {
+        ChatMessage {
+            role: "user".into(),
+            content: Some(content.into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn chat_tools_once_parses_tool_use() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/v1/messages"),
+                request::headers(contains(("x-api-key", "test-key"))),
+                request::headers(contains(("anthropic-version", ANTHROPIC_VERSION))),
+                request::body(json_decoded(all_of![
+                    // The system prompt carries a cache breakpoint
+                    matches_json_field(
+                        "system",
+                        json!([{"type":"text","text":"sys","cache_control":{"type":"ephemeral"}}])
+                    ),
+                    matches_json_field("max_tokens", json!(DEFAULT_MAX_TOKENS)),
+                ])),
+            ])
+            .respond_with(json_encoded(json!({
+                "id": "msg_1",
+                "type": "message",
+                "role": "assistant",
+                "content": [
+                    {"type":"text","text":"Listing."},
+                    {"type":"tool_use","id":"toolu_1","name":"fs_list","input":{"path":"."}}
+                ],
+                "stop_reason": "tool_use",
+                "usage": {"input_tokens": 20, "output_tokens": 4}
+            }))),
+        );
+
+        let client = AnthropicClient::new(server.url_str(""), "test-key").unwrap();
+        let tools = vec![ToolDef {
+            kind: "function".into(),
+            function: ToolFunctionDef {
+                name: "fs_list".into(),
+                description: "List".into(),
+                parameters: json!({"type":"object"}),
+                strict: None,
+            },
+        }];
+        let messages = vec![
+            ChatMessage {
+                role: "system".into(),
+                content: Some("sys".into()),
+                tool_calls: vec![],
+                tool_call_id: None,
+                reasoning: None,
+                images: vec![],
+            },
+            user("hi"),
+        ];
+        let msg = client
+            .chat_tools_once("claude-test", messages, &tools, None)
+            .await
+            .unwrap();
+        assert_eq!(msg.content.as_deref(), Some("Listing."));
+        assert_eq!(msg.tool_calls[0].function.name, "fs_list");
+        assert_eq!(client.get_prompt_tokens_used(), 20);
+        assert_eq!(client.get_tokens_used(), 24);
+    }
+
+    #[tokio::test]
+    async fn prompt_too_long_maps_to_context_length_exceeded() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/v1/messages")).respond_with(
+                status_code(400).body(
+                    json!({
+                        "type": "error",
+                        "error": {
+                            "type": "invalid_request_error",
+                            "message": "prompt is too long: 210000 tokens > 200000 maximum"
+                        }
+                    })
+                    .to_string(),
+                ),
+            ),
+        );
+        let client = AnthropicClient::new(server.url_str(""), "k").unwrap();
+        let err = client
+            .chat_once("claude-test", vec![user("hi")], None)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LlmErrorKind>(),
+            Some(&LlmErrorKind::ContextLengthExceeded)
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_are_retried() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/v1/messages"))
+                .times(2)
+                .respond_with(cycle![
+                    status_code(429).insert_header("retry-after", "0"),
+                    json_encoded(json!({
+                        "type": "message",
+                        "role": "assistant",
+                        "content": [{"type":"text","text":"ok"}],
+                        "usage": {"input_tokens": 3, "output_tokens": 1}
+                    })),
+                ]),
+        );
+        let client = AnthropicClient::new(server.url_str(""), "k").unwrap();
+        let msg = client
+            .chat_once("claude-test", vec![user("hi")], None)
+            .await
+            .unwrap();
+        assert_eq!(msg.content, "ok");
+    }
+
+    #[tokio::test]
+    async fn chat_stream_yields_text_and_tool_markers() {
+        let body = [
+            r#"{"type":"message_start","message":{"usage":{"input_tokens":8,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hel"}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"lo"}}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_2","name":"todo_read","input":{}}}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":3}}"#,
+            r#"{"type":"message_stop"}"#,
+        ]
+        .iter()
+        .map(|d| format!("event: x\ndata: {d}\n\n"))
+        .collect::<String>();
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/v1/messages"),
+                request::body(json_decoded(matches_json_field("stream", json!(true)))),
+            ])
+            .respond_with(
+                status_code(200)
+                    .append_header("content-type", "text/event-stream")
+                    .body(body),
+            ),
+        );
+
+        let client = AnthropicClient::new(server.url_str(""), "k").unwrap();
+        let mut stream = client
+            .chat_stream("claude-test", vec![user("hi")], &[], None)
+            .await
+            .unwrap();
+        let mut chunks = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            chunks.push(chunk.unwrap());
+        }
+        assert_eq!(chunks[0], "Hel");
+        assert_eq!(chunks[1], "lo");
+        assert!(chunks[2].starts_with("__TOOL_CALLS_DELTA__:"));
+        assert_eq!(client.get_tokens_used(), 11);
+    }
+
+    #[tokio::test]
+    async fn thinking_blocks_are_sent_back_with_tool_results() {
+        let thinking = json!({"type": "thinking", "thinking": "List first.", "signature": "sig_1"});
+        let tool_use = json!({"type": "tool_use", "id": "toolu_1", "name": "fs_list", "input": {}});
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/v1/messages"),
+                request::body(json_decoded(matches_json_field(
+                    "thinking",
+                    json!({"type": "enabled", "budget_tokens": DEFAULT_THINKING_BUDGET})
+                ))),
+            ])
+            .respond_with(json_encoded(json!({
+                "content": [thinking.clone(), tool_use.clone()],
+                "stop_reason": "tool_use"
+            }))),
+        );
+        let client = AnthropicClient::new(server.url_str(""), "k").unwrap();
+        let msg = client
+            .chat_tools_once("claude-sonnet-4-5", vec![user("hi")], &[], None)
+            .await
+            .unwrap();
+        assert_eq!(msg.reasoning.as_deref(), Some("List first."));
+
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/v1/messages"),
+                request::body(json_decoded(matches_json_field(
+                    "messages",
+                    json!([
+                        {"role": "user", "content": [{"type": "text", "text": "hi"}]},
+                        {"role": "assistant", "content": [thinking, tool_use]},
+                        {"role": "user", "content": [{
+                            "type": "tool_result",
+                            "tool_use_id": "toolu_1",
+                            "content": "[]",
+                            "cache_control": {"type": "ephemeral"}
+                        }]}
+                    ])
+                ))),
+            ])
+            .respond_with(json_encoded(json!({
+                "content": [{"type": "text", "text": "Empty."}]
+            }))),
+        );
+        let history = vec![
+            user("hi"),
+            ChatMessage {
+                role: "assistant".into(),
+                content: None,
+                tool_calls: msg.tool_calls,
+                tool_call_id: None,
+                reasoning: msg.reasoning,
+                images: vec![],
+            },
+            ChatMessage {
+                role: "tool".into(),
+                content: Some("[]".into()),
+                tool_calls: vec![],
+                tool_call_id: Some("toolu_1".into()),
+                reasoning: None,
+                images: vec![],
+            },
+        ];
+        let msg = client
+            .chat_tools_once("claude-sonnet-4-5", history, &[], None)
+            .await
+            .unwrap();
+        assert_eq!(msg.content.as_deref(), Some("Empty."));
+    }
+
+    #[test]
+    fn thinking_is_requested_only_where_it_can_be_kept() {
+        let client = AnthropicClient::new("http://localhost", "k").unwrap();
+        let enabled = Some(ThinkingConfig::Enabled {
+            budget_tokens: DEFAULT_THINKING_BUDGET,
+        });
+        let hi = || vec![user("hi")];
+        assert_eq!(
+            client.build_request("claude-opus-4-1", hi(), &[]).thinking,
+            enabled
+        );
+        assert_eq!(
+            client
+                .build_request("claude-3-7-sonnet-latest", hi(), &[])
+                .thinking,
+            enabled
+        );
+        assert_eq!(
+            client
+                .build_request("claude-3-5-haiku-latest", hi(), &[])
+                .thinking,
+            None
+        );
+
+        let mut disabled = client.clone();
+        disabled.thinking_budget = None;
+        assert_eq!(
+            disabled
+                .build_request("claude-opus-4-1", hi(), &[])
+                .thinking,
+            None
+        );
+
+        // A pending tool call without its thinking blocks
+        let mut history = hi();
+        history.push(ChatMessage {
+            role: "assistant".into(),
+            content: None,
+            tool_calls: vec![crate::llm::types::ToolCall {
+                id: Some("toolu_old".into()),
+                r#type: "function".into(),
+                function: crate::llm::types::ToolCallFunction {
+                    name: "fs_list".into(),
+                    arguments: "{}".into(),
+                },
+            }],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        });
+        assert_eq!(
+            client
+                .build_request("claude-opus-4-1", history, &[])
+                .thinking,
+            None
+        );
+    }
+
+    #[test]
+    fn endpoint_normalization() {
+        let c = AnthropicClient::new("https://api.anthropic.com/v1/", "x").unwrap();
+        assert_eq!(c.endpoint(), "https://api.anthropic.com/v1/messages");
+        let c2 = AnthropicClient::new("https://api.anthropic.com", "x").unwrap();
+        assert_eq!(c2.endpoint(), "https://api.anthropic.com/v1/messages");
+    }
+
+    fn matches_json_field(
+        field: &'static str,
+        expected: serde_json::Value,
+    ) -> impl Matcher<serde_json::Value> {
+        FieldMatcher { field, expected }
+    }
+
+    #[derive(Debug)]
+    struct FieldMatcher {
+        field: &'static str,
+        expected: serde_json::Value,
+    }
+
+    impl Matcher<serde_json::Value> for FieldMatcher {
+        fn matches(&mut self, input: &serde_json::Value, _ctx: &mut ExecutionContext) -> bool {
+            input.get(self.field) == Some(&self.expected)
+        }
+
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "field {} == {}", self.field, self.expected)
+        }
+    }
+}
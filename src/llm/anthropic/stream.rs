@@ -0,0 +1,256 @@
+//! Incremental decoding of Anthropic Messages streaming events.
+
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+use super::wire::{AnthropicUsage, ContentBlock};
+use crate::llm::stream::{ToolCallDelta, ToolCallFunctionDelta};
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart {
+        message: MessageStart,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: ContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: BlockDelta,
+    },
+    MessageDelta {
+        #[serde(default)]
+        usage: Option<AnthropicUsage>,
+    },
+    Error {
+        error: Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageStart {
+    #[serde(default)]
+    usage: Option<AnthropicUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BlockDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    SignatureDelta {
+        signature: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Translates SSE `data:` payloads into the chunk strings produced by
+/// `LlmProvider::chat_stream` (plain text, `__TOOL_CALLS_DELTA__:` and
+/// `__REASONING_DELTA__:` markers).
+#[derive(Debug, Default)]
+pub struct StreamState {
+    /// Content block index -> tool call index (text blocks are not counted).
+    tool_indices: HashMap<usize, usize>,
+    /// Thinking blocks by content block index, rebuilt from their deltas
+    thinking: BTreeMap<usize, ContentBlock>,
+    first_tool_id: Option<String>,
+    pub usage: AnthropicUsage,
+}
+
+impl StreamState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle(&mut self, payload: &str) -> Result<Vec<String>> {
+        let event: StreamEvent = match serde_json::from_str(payload) {
+            Ok(ev) => ev,
+            Err(e) => {
+                tracing::warn!(payload, err = %e, "failed to parse anthropic stream event");
+                return Ok(vec![]);
+            }
+        };
+
+        let mut out = Vec::new();
+        match event {
+            StreamEvent::MessageStart { message } => {
+                if let Some(usage) = message.usage {
+                    self.usage = usage;
+                }
+            }
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => match content_block {
+                ContentBlock::ToolUse { id, name, .. } => {
+                    self.first_tool_id.get_or_insert_with(|| id.clone());
+                    let tool_index = self.tool_indices.len();
+                    self.tool_indices.insert(index, tool_index);
+                    out.push(tool_delta_marker(ToolCallDelta {
+                        index: Some(tool_index),
+                        id: Some(id),
+                        kind: Some("function".into()),
+                        function: Some(ToolCallFunctionDelta {
+                            name,
+                            arguments: String::new(),
+                        }),
+                    }));
+                }
+                ContentBlock::Text { text, .. } if !text.is_empty() => out.push(text),
+                block @ (ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. }) => {
+                    self.thinking.insert(index, block);
+                }
+                _ => {}
+            },
+            StreamEvent::ContentBlockDelta { index, delta } => match delta {
+                BlockDelta::TextDelta { text } if !text.is_empty() => out.push(text),
+                BlockDelta::ThinkingDelta { thinking } if !thinking.is_empty() => {
+                    if let Some(ContentBlock::Thinking { thinking: text, .. }) =
+                        self.thinking.get_mut(&index)
+                    {
+                        text.push_str(&thinking);
+                    }
+                    out.push(format!("__REASONING_DELTA__:{thinking}"))
+                }
+                BlockDelta::SignatureDelta { signature } => {
+                    if let Some(ContentBlock::Thinking { signature: sig, .. }) =
+                        self.thinking.get_mut(&index)
+                    {
+                        sig.push_str(&signature);
+                    }
+                }
+                BlockDelta::InputJsonDelta { partial_json } => {
+                    if let Some(&tool_index) = self.tool_indices.get(&index) {
+                        out.push(tool_delta_marker(ToolCallDelta {
+                            index: Some(tool_index),
+                            id: None,
+                            kind: None,
+                            function: Some(ToolCallFunctionDelta {
+                                name: String::new(),
+                                arguments: partial_json,
+                            }),
+                        }));
+                    }
+                }
+                _ => {}
+            },
+            StreamEvent::MessageDelta { usage } => {
+                if let Some(usage) = usage {
+                    self.usage.output_tokens = usage.output_tokens;
+                }
+            }
+            StreamEvent::Error { error } => {
+                return Err(anyhow!("anthropic stream error: {error}"));
+            }
+            StreamEvent::Other => {}
+        }
+        Ok(out)
+    }
+
+    /// Thinking blocks to replay before the first tool call of this turn.
+    pub fn thinking_carry_over(&self) -> Option<(String, Vec<ContentBlock>)> {
+        let call_id = self.first_tool_id.clone()?;
+        (!self.thinking.is_empty()).then(|| (call_id, self.thinking.values().cloned().collect()))
+    }
+}
+
+fn tool_delta_marker(delta: ToolCallDelta) -> String {
+    format!(
+        "__TOOL_CALLS_DELTA__:{}",
+        serde_json::to_string(&[delta]).unwrap_or_else(|_| "[]".into())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_text_and_tool_use_events() {
+        let mut state = StreamState::new();
+        let events = [
+            r#"{"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":12,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hi"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"fs_read","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"path\":"}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"\"a\"}"}}"#,
+            r#"{"type":"ping"}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":7}}"#,
+            r#"{"type":"message_stop"}"#,
+        ];
+        let chunks: Vec<String> = events
+            .iter()
+            .flat_map(|e| state.handle(e).unwrap())
+            .collect();
+
+        assert_eq!(chunks[0], "Hi");
+        assert!(chunks[1].contains(r#""id":"toolu_1""#));
+        assert!(chunks[1].contains(r#""name":"fs_read""#));
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(state.usage.input_tokens, 12);
+        assert_eq!(state.usage.output_tokens, 7);
+    }
+
+    #[test]
+    fn thinking_blocks_are_rebuilt_for_replay() {
+        let mut state = StreamState::new();
+        let events = [
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":"","signature":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"Read "}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"it."}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"sig"}}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"fs_read","input":{}}}"#,
+        ];
+        for event in events {
+            state.handle(event).unwrap();
+        }
+        assert_eq!(
+            state.thinking_carry_over(),
+            Some((
+                "toolu_1".to_string(),
+                vec![ContentBlock::Thinking {
+                    thinking: "Read it.".into(),
+                    signature: "sig".into(),
+                }]
+            ))
+        );
+    }
+
+    #[test]
+    fn error_event_is_surfaced() {
+        let mut state = StreamState::new();
+        let err = state
+            .handle(
+                r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("overloaded_error"));
+    }
+
+    #[test]
+    fn thinking_deltas_become_reasoning_markers() {
+        let mut state = StreamState::new();
+        let chunks = state
+            .handle(
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"Check the tests first."}}"#,
+            )
+            .unwrap();
+        assert_eq!(chunks, vec!["__REASONING_DELTA__:Check the tests first."]);
+    }
+}
@@ -0,0 +1,578 @@
+//! Anthropic Messages API wire types and conversion from the OpenAI-shaped
+//! message types used by the rest of the crate.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+
+use crate::llm::chat_with_tools::ChoiceMessageWithTools;
+use crate::llm::prompt_cache::CacheControl;
+use crate::llm::types::{ChatMessage, ImagePart, ToolCall, ToolCallFunction, ToolDef};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MessagesRequest {
+    pub model: String,
+    pub max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<SystemPrompt>,
+    pub messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolSpec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<ThinkingConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+}
+
+/// Extended thinking request; `budget_tokens` must stay below `max_tokens`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ThinkingConfig {
+    Enabled { budget_tokens: u32 },
+}
+
+/// Top-level system prompt; sent as text blocks when it carries a cache breakpoint.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum SystemPrompt {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    pub role: String, // "user" | "assistant"
+    pub content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    Text {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Thinking {
+        thinking: String,
+        #[serde(default)]
+        signature: String,
+    },
+    RedactedThinking {
+        data: String,
+    },
+    Image {
+        source: ImageSource,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageSource {
+    #[serde(rename = "type")]
+    pub kind: String, // "base64"
+    pub media_type: String,
+    pub data: String,
+}
+
+impl From<ImagePart> for ContentBlock {
+    fn from(image: ImagePart) -> Self {
+        Self::Image {
+            source: ImageSource {
+                kind: "base64".into(),
+                media_type: image.media_type,
+                data: image.data,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    pub input_schema: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AnthropicUsage {
+    #[serde(default)]
+    pub input_tokens: u32,
+    #[serde(default)]
+    pub output_tokens: u32,
+    #[serde(default)]
+    pub cache_creation_input_tokens: Option<u32>,
+    #[serde(default)]
+    pub cache_read_input_tokens: Option<u32>,
+}
+
+impl AnthropicUsage {
+    /// Everything the model had to read, including cached prefix tokens.
+    pub fn prompt_tokens(&self) -> u32 {
+        self.input_tokens
+            + self.cache_creation_input_tokens.unwrap_or(0)
+            + self.cache_read_input_tokens.unwrap_or(0)
+    }
+
+    pub fn total_tokens(&self) -> u32 {
+        self.prompt_tokens() + self.output_tokens
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessagesResponse {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub content: Vec<ContentBlock>,
+    #[serde(default)]
+    pub stop_reason: Option<String>,
+    #[serde(default)]
+    pub usage: Option<AnthropicUsage>,
+}
+
+impl From<&ToolDef> for ToolSpec {
+    fn from(def: &ToolDef) -> Self {
+        Self {
+            name: def.function.name.clone(),
+            description: def.function.description.clone(),
+            input_schema: def.function.parameters.clone(),
+            cache_control: None,
+        }
+    }
+}
+
+/// Build a Messages API request from OpenAI-shaped chat messages.
+///
+/// System messages are hoisted into the top-level `system` field, assistant
+/// tool calls become `tool_use` blocks and `tool` messages become `tool_result`
+/// blocks on a user turn. Consecutive turns with the same role are merged since
+/// the API requires strict user/assistant alternation. Thinking blocks in
+/// `thinking` (keyed by the id of the first tool call they preceded) are
+/// replayed at the start of that assistant turn.
+pub fn build_request(
+    model: &str,
+    messages: Vec<ChatMessage>,
+    tools: &[ToolDef],
+    max_tokens: u32,
+    thinking: &HashMap<String, Vec<ContentBlock>>,
+) -> MessagesRequest {
+    let mut system_parts = Vec::new();
+    let mut out: Vec<Message> = Vec::new();
+    // Tool calls without an id still need to be paired with their results.
+    let mut pending_ids: VecDeque<String> = VecDeque::new();
+    let mut generated = 0usize;
+
+    for msg in messages {
+        let (role, blocks) = match msg.role.as_str() {
+            "system" => {
+                if let Some(text) = msg.content
+                    && !text.is_empty()
+                {
+                    system_parts.push(text);
+                }
+                continue;
+            }
+            "assistant" => {
+                let mut blocks = Vec::new();
+                if let Some(replayed) = msg
+                    .tool_calls
+                    .first()
+                    .and_then(|tc| tc.id.as_ref())
+                    .and_then(|id| thinking.get(id))
+                {
+                    blocks.extend(replayed.iter().cloned());
+                }
+                if let Some(text) = msg.content
+                    && !text.trim().is_empty()
+                {
+                    blocks.push(ContentBlock::Text {
+                        text,
+                        cache_control: None,
+                    });
+                }
+                for tc in msg.tool_calls {
+                    let id = tc.id.unwrap_or_else(|| {
+                        generated += 1;
+                        let id = format!("toolu_doge_{generated}");
+                        pending_ids.push_back(id.clone());
+                        id
+                    });
+                    let input = serde_json::from_str::<Value>(&tc.function.arguments)
+                        .ok()
+                        .filter(Value::is_object)
+                        .unwrap_or_else(|| serde_json::json!({}));
+                    blocks.push(ContentBlock::ToolUse {
+                        id,
+                        name: tc.function.name,
+                        input,
+                    });
+                }
+                ("assistant", blocks)
+            }
+            "tool" => {
+                let tool_use_id = msg
+                    .tool_call_id
+                    .or_else(|| pending_ids.pop_front())
+                    .unwrap_or_default();
+                (
+                    "user",
+                    vec![ContentBlock::ToolResult {
+                        tool_use_id,
+                        content: msg.content.unwrap_or_default(),
+                        cache_control: None,
+                    }],
+                )
+            }
+            _ => {
+                let mut blocks = Vec::new();
+                if let Some(text) = msg.content
+                    && !text.is_empty()
+                {
+                    blocks.push(ContentBlock::Text {
+                        text,
+                        cache_control: None,
+                    });
+                }
+                blocks.extend(msg.images.into_iter().map(ContentBlock::from));
+                ("user", blocks)
+            }
+        };
+
+        if blocks.is_empty() {
+            continue;
+        }
+        match out.last_mut() {
+            Some(last) if last.role == role => last.content.extend(blocks),
+            _ => out.push(Message {
+                role: role.to_string(),
+                content: blocks,
+            }),
+        }
+    }
+
+    MessagesRequest {
+        model: model.to_string(),
+        max_tokens,
+        system: if system_parts.is_empty() {
+            None
+        } else {
+            Some(SystemPrompt::Text(system_parts.join("\n\n")))
+        },
+        messages: out,
+        tools: tools.iter().map(ToolSpec::from).collect(),
+        thinking: None,
+        temperature: None,
+        stream: None,
+    }
+}
+
+/// Mark the system prompt, the last tool and the last message as cache breakpoints.
+pub fn add_cache_breakpoints(req: &mut MessagesRequest) {
+    if let Some(SystemPrompt::Text(text)) = req.system.take() {
+        req.system = Some(SystemPrompt::Blocks(vec![ContentBlock::Text {
+            text,
+            cache_control: Some(CacheControl::default()),
+        }]));
+    }
+    if let Some(tool) = req.tools.last_mut() {
+        tool.cache_control = Some(CacheControl::default());
+    }
+    if let Some(
+        ContentBlock::Text { cache_control, .. } | ContentBlock::ToolResult { cache_control, .. },
+    ) = req.messages.last_mut().and_then(|m| m.content.last_mut())
+    {
+        *cache_control = Some(CacheControl::default());
+    }
+}
+
+/// Thinking blocks of a response that calls tools, keyed by the id of its
+/// first tool call; the API wants them back, signatures intact, on the next
+/// request.
+pub fn thinking_carry_over(content: &[ContentBlock]) -> Option<(String, Vec<ContentBlock>)> {
+    let call_id = content.iter().find_map(|block| match block {
+        ContentBlock::ToolUse { id, .. } => Some(id.clone()),
+        _ => None,
+    })?;
+    let blocks: Vec<ContentBlock> = content
+        .iter()
+        .filter(|block| {
+            matches!(
+                block,
+                ContentBlock::Thinking { .. } | ContentBlock::RedactedThinking { .. }
+            )
+        })
+        .cloned()
+        .collect();
+    (!blocks.is_empty()).then_some((call_id, blocks))
+}
+
+/// Map response content blocks back onto the OpenAI-shaped assistant message.
+pub fn into_choice_message(content: Vec<ContentBlock>) -> ChoiceMessageWithTools {
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut tool_calls = Vec::new();
+    for block in content {
+        match block {
+            ContentBlock::Text { text: t, .. } => text.push_str(&t),
+            ContentBlock::Thinking { thinking, .. } => reasoning.push_str(&thinking),
+            ContentBlock::ToolUse { id, name, input } => tool_calls.push(ToolCall {
+                id: Some(id),
+                r#type: "function".into(),
+                function: ToolCallFunction {
+                    name,
+                    arguments: input.to_string(),
+                },
+            }),
+            _ => {}
+        }
+    }
+    ChoiceMessageWithTools {
+        role: "assistant".into(),
+        content: if text.is_empty() { None } else { Some(text) },
+        tool_calls,
+        reasoning: if reasoning.is_empty() {
+            None
+        } else {
+            Some(reasoning)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::types::ToolFunctionDef;
+    use serde_json::json;
+
+    fn msg(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.into(),
+            content: Some(content.into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        }
+    }
+
+    #[test]
+    fn hoists_system_and_maps_tool_round_trip() {
+        let messages = vec![
+            msg("system", "be helpful"),
+            msg("user", "list files"),
+            ChatMessage {
+                role: "assistant".into(),
+                content: Some("Let me look.".into()),
+                tool_calls: vec![ToolCall {
+                    id: Some("toolu_1".into()),
+                    r#type: "function".into(),
+                    function: ToolCallFunction {
+                        name: "fs_list".into(),
+                        arguments: r#"{"path":"."}"#.into(),
+                    },
+                }],
+                tool_call_id: None,
+                reasoning: None,
+                images: vec![],
+            },
+            ChatMessage {
+                role: "tool".into(),
+                content: Some(r#"{"ok":true}"#.into()),
+                tool_calls: vec![],
+                tool_call_id: Some("toolu_1".into()),
+                reasoning: None,
+                images: vec![],
+            },
+            msg("user", "thanks"),
+        ];
+        let tools = vec![ToolDef {
+            kind: "function".into(),
+            function: ToolFunctionDef {
+                name: "fs_list".into(),
+                description: "List files".into(),
+                parameters: json!({"type":"object"}),
+                strict: None,
+            },
+        }];
+
+        let req = build_request("claude-test", messages, &tools, 1024, &HashMap::new());
+        assert_eq!(req.system, Some(SystemPrompt::Text("be helpful".into())));
+        assert_eq!(req.tools[0].input_schema, json!({"type":"object"}));
+
+        let body = serde_json::to_value(&req).unwrap();
+        assert_eq!(
+            body["messages"],
+            json!([
+                {"role":"user","content":[{"type":"text","text":"list files"}]},
+                {"role":"assistant","content":[
+                    {"type":"text","text":"Let me look."},
+                    {"type":"tool_use","id":"toolu_1","name":"fs_list","input":{"path":"."}}
+                ]},
+                {"role":"user","content":[
+                    {"type":"tool_result","tool_use_id":"toolu_1","content":"{\"ok\":true}"},
+                    {"type":"text","text":"thanks"}
+                ]}
+            ])
+        );
+    }
+
+    #[test]
+    fn pairs_tool_results_when_ids_are_missing() {
+        let messages = vec![
+            msg("user", "go"),
+            ChatMessage {
+                role: "assistant".into(),
+                content: None,
+                tool_calls: vec![ToolCall {
+                    id: None,
+                    r#type: "function".into(),
+                    function: ToolCallFunction {
+                        name: "todo_read".into(),
+                        arguments: "not json".into(),
+                    },
+                }],
+                tool_call_id: None,
+                reasoning: None,
+                images: vec![],
+            },
+            msg("tool", "[]"),
+        ];
+        let req = build_request("m", messages, &[], 16, &HashMap::new());
+        let ContentBlock::ToolUse { id, input, .. } = &req.messages[1].content[0] else {
+            panic!("expected tool_use block");
+        };
+        assert_eq!(input, &json!({}));
+        let ContentBlock::ToolResult { tool_use_id, .. } = &req.messages[2].content[0] else {
+            panic!("expected tool_result block");
+        };
+        assert_eq!(tool_use_id, id);
+    }
+
+    #[test]
+    fn user_images_become_base64_blocks() {
+        let mut user = msg("user", "what is this?");
+        user.images.push(ImagePart {
+            media_type: "image/png".into(),
+            data: "iVBORw0KGgo=".into(),
+        });
+        let req = build_request("m", vec![user], &[], 16, &HashMap::new());
+        let body = serde_json::to_value(&req.messages[0]).unwrap();
+        assert_eq!(body["content"][0]["text"], "what is this?");
+        assert_eq!(
+            body["content"][1],
+            json!({
+                "type": "image",
+                "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}
+            })
+        );
+    }
+
+    #[test]
+    fn response_blocks_map_to_tool_calls() {
+        let resp: MessagesResponse = serde_json::from_value(json!({
+            "id": "msg_1",
+            "content": [
+                {"type":"thinking","thinking":"hmm","signature":"sig"},
+                {"type":"text","text":"Reading."},
+                {"type":"tool_use","id":"toolu_9","name":"fs_read","input":{"path":"a.rs"}}
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 10, "output_tokens": 5, "cache_read_input_tokens": 90}
+        }))
+        .unwrap();
+        let usage = resp.usage.clone().unwrap();
+        assert_eq!(usage.prompt_tokens(), 100);
+        assert_eq!(usage.total_tokens(), 105);
+
+        let msg = into_choice_message(resp.content);
+        assert_eq!(msg.content.as_deref(), Some("Reading."));
+        assert_eq!(msg.reasoning.as_deref(), Some("hmm"));
+        assert_eq!(msg.tool_calls.len(), 1);
+        assert_eq!(msg.tool_calls[0].id.as_deref(), Some("toolu_9"));
+        assert_eq!(msg.tool_calls[0].function.arguments, r#"{"path":"a.rs"}"#);
+    }
+
+    #[test]
+    fn thinking_blocks_are_replayed_before_their_tool_use() {
+        let content: Vec<ContentBlock> = serde_json::from_value(json!([
+            {"type":"thinking","thinking":"hmm","signature":"sig"},
+            {"type":"redacted_thinking","data":"opaque"},
+            {"type":"tool_use","id":"toolu_9","name":"fs_read","input":{}}
+        ]))
+        .unwrap();
+        let (call_id, blocks) = thinking_carry_over(&content).unwrap();
+        assert_eq!(call_id, "toolu_9");
+        assert!(thinking_carry_over(&content[..2]).is_none());
+
+        let mut assistant = msg("assistant", "");
+        assistant.tool_calls = into_choice_message(content).tool_calls;
+        let req = build_request(
+            "m",
+            vec![msg("user", "go"), assistant, msg("tool", "ok")],
+            &[],
+            16,
+            &HashMap::from([(call_id, blocks)]),
+        );
+        let body = serde_json::to_value(&req.messages[1]).unwrap();
+        assert_eq!(
+            body["content"],
+            json!([
+                {"type":"thinking","thinking":"hmm","signature":"sig"},
+                {"type":"redacted_thinking","data":"opaque"},
+                {"type":"tool_use","id":"toolu_9","name":"fs_read","input":{}}
+            ])
+        );
+    }
+
+    #[test]
+    fn cache_breakpoints_mark_system_tools_and_last_message() {
+        let tools = vec![ToolDef {
+            kind: "function".into(),
+            function: ToolFunctionDef {
+                name: "fs_read".into(),
+                description: "Read".into(),
+                parameters: json!({"type":"object"}),
+                strict: None,
+            },
+        }];
+        let mut req = build_request(
+            "claude-test",
+            vec![
+                msg("system", "sys"),
+                msg("user", "hi"),
+                msg("user", "again"),
+            ],
+            &tools,
+            1024,
+            &HashMap::new(),
+        );
+        add_cache_breakpoints(&mut req);
+
+        let body = serde_json::to_value(&req).unwrap();
+        let marker = json!({"type": "ephemeral"});
+        assert_eq!(body["system"][0]["text"], "sys");
+        assert_eq!(body["system"][0]["cache_control"], marker);
+        assert_eq!(body["tools"][0]["cache_control"], marker);
+        // Only the last block of the last message is marked
+        let blocks = &body["messages"][0]["content"];
+        assert!(blocks[0].get("cache_control").is_none());
+        assert_eq!(blocks[1]["cache_control"], marker);
+    }
+}
@@ -0,0 +1,225 @@
+//! Startup probing of model capabilities for local inference servers.
+//!
+//! Ollama and llama.cpp expose the loaded model's context window and chat
+//! template, which tells us how early to compact and whether the model can be
+//! offered native tools at all.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::config::AppConfig;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelCapabilities {
+    /// Context window reported by the server, in tokens
+    pub context_length: Option<u32>,
+    /// Whether the model's chat template understands native tool calls
+    pub supports_tools: bool,
+    /// Whether the model accepts images, when the server reports it
+    #[serde(default)]
+    pub supports_vision: Option<bool>,
+}
+
+/// Probe the configured model when the provider is a local inference server.
+///
+/// Returns `Ok(None)` for hosted providers, which do not expose this information.
+pub async fn probe_model_capabilities(cfg: &AppConfig) -> Result<Option<ModelCapabilities>> {
+    let client = reqwest::Client::builder()
+        .connect_timeout(Duration::from_millis(cfg.llm.connect_timeout_ms))
+        .timeout(Duration::from_millis(cfg.llm.request_timeout_ms))
+        .build()?;
+    let base = server_root(&cfg.base_url);
+
+    let caps = match cfg.provider.as_str() {
+        "ollama" => {
+            let body: Value = client
+                .post(format!("{base}/api/show"))
+                .json(&json!({ "model": cfg.model }))
+                .send()
+                .await
+                .context("probe ollama /api/show")?
+                .error_for_status()?
+                .json()
+                .await
+                .context("parse ollama /api/show response")?;
+            parse_ollama_show(&body)
+        }
+        "llamacpp" => {
+            let body: Value = client
+                .get(format!("{base}/props"))
+                .send()
+                .await
+                .context("probe llama.cpp /props")?
+                .error_for_status()?
+                .json()
+                .await
+                .context("parse llama.cpp /props response")?;
+            parse_llamacpp_props(&body)
+        }
+        _ => return Ok(None),
+    };
+
+    info!(provider = %cfg.provider, model = %cfg.model, ?caps, "probed model capabilities");
+    Ok(Some(caps))
+}
+
+/// Strip a trailing `/v1` (and slashes) so native endpoints can be appended.
+pub(crate) fn server_root(base_url: &str) -> String {
+    let mut base = base_url.trim_end_matches('/').to_string();
+    if let Some(stripped) = base.strip_suffix("/v1") {
+        base = stripped.trim_end_matches('/').to_string();
+    }
+    base
+}
+
+fn parse_ollama_show(body: &Value) -> ModelCapabilities {
+    // model_info keys are prefixed with the architecture, e.g. "llama.context_length"
+    let context_length = body
+        .get("model_info")
+        .and_then(Value::as_object)
+        .and_then(|info| {
+            info.iter()
+                .find(|(k, _)| k.ends_with(".context_length"))
+                .and_then(|(_, v)| v.as_u64())
+        })
+        .map(|n| n.min(u32::MAX as u64) as u32);
+
+    let reported = body.get("capabilities").and_then(Value::as_array);
+    let supports_vision = reported.map(|caps| caps.iter().any(|c| c.as_str() == Some("vision")));
+    let supports_tools = match reported {
+        Some(caps) => caps.iter().any(|c| c.as_str() == Some("tools")),
+        // Older servers do not report capabilities; fall back to the template
+        None => body
+            .get("template")
+            .and_then(Value::as_str)
+            .is_some_and(|t| t.contains(".Tools")),
+    };
+    debug!(
+        ?context_length,
+        supports_tools,
+        ?supports_vision,
+        "parsed ollama /api/show"
+    );
+
+    ModelCapabilities {
+        context_length,
+        supports_tools,
+        supports_vision,
+    }
+}
+
+fn parse_llamacpp_props(body: &Value) -> ModelCapabilities {
+    let context_length = body
+        .pointer("/default_generation_settings/n_ctx")
+        .or_else(|| body.get("n_ctx"))
+        .and_then(Value::as_u64)
+        .map(|n| n.min(u32::MAX as u64) as u32);
+
+    let supports_tools = body
+        .pointer("/chat_template_caps/supports_tools")
+        .and_then(Value::as_bool)
+        .unwrap_or_else(|| {
+            body.get("chat_template")
+                .and_then(Value::as_str)
+                .is_some_and(|t| t.contains("tools"))
+        });
+    let supports_vision = body.pointer("/modalities/vision").and_then(Value::as_bool);
+    debug!(
+        ?context_length,
+        supports_tools,
+        ?supports_vision,
+        "parsed llama.cpp /props"
+    );
+
+    ModelCapabilities {
+        context_length,
+        supports_tools,
+        supports_vision,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httptest::{Expectation, Server, matchers::*, responders::*};
+
+    fn cfg_for(provider: &str, server: &Server) -> AppConfig {
+        AppConfig {
+            provider: provider.into(),
+            base_url: server.url_str("/v1"),
+            model: "qwen2.5-coder:7b".into(),
+            ..AppConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn probes_ollama_show() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/api/show"),
+                request::body(json_decoded(eq(json!({"model": "qwen2.5-coder:7b"})))),
+            ])
+            .respond_with(json_encoded(json!({
+                "template": "{{ .Prompt }}",
+                "capabilities": ["completion", "tools", "vision"],
+                "model_info": {
+                    "general.architecture": "qwen2",
+                    "qwen2.context_length": 32768
+                }
+            }))),
+        );
+
+        let caps = probe_model_capabilities(&cfg_for("ollama", &server))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            caps,
+            ModelCapabilities {
+                context_length: Some(32768),
+                supports_tools: true,
+                supports_vision: Some(true),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn probes_llamacpp_props() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/props")).respond_with(
+                json_encoded(json!({
+                    "default_generation_settings": {"n_ctx": 8192},
+                    "chat_template": "{% for message in messages %}{{ message.content }}{% endfor %}"
+                })),
+            ),
+        );
+
+        let caps = probe_model_capabilities(&cfg_for("llamacpp", &server))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(caps.context_length, Some(8192));
+        assert!(!caps.supports_tools);
+    }
+
+    #[tokio::test]
+    async fn hosted_providers_are_not_probed() {
+        let cfg = AppConfig::default();
+        assert!(probe_model_capabilities(&cfg).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn ollama_without_capabilities_uses_template() {
+        let caps = parse_ollama_show(&json!({
+            "template": "{{- if .Tools }}tools{{ end }}",
+            "model_info": {}
+        }));
+        assert!(caps.supports_tools);
+        assert_eq!(caps.context_length, None);
+    }
+}
@@ -0,0 +1,481 @@
+//! Record/replay of LLM HTTP traffic.
+//!
+//! In record mode every request is forwarded to the server and the exchange,
+//! including complete SSE streams, is appended to a cassette file. In replay
+//! mode no network is used: each request is answered with the first unused
+//! recorded response whose method, path and normalized body match. API keys
+//! and other request headers are never written to the cassette; of the
+//! response headers only those the client acts on are kept.
+
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderValue};
+use reqwest::{Response, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, warn};
+
+/// Request fields that vary between otherwise identical runs, at any depth.
+const VOLATILE_FIELDS: &[&str] = &["stream_options", "user", "metadata"];
+
+/// Response headers kept in the cassette, besides `x-ratelimit-*`.
+const RECORDED_HEADERS: &[&str] = &["content-type", "retry-after", "retry-after-ms"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+/// Cassette selected on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CassetteConfig {
+    pub mode: CassetteMode,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct CassetteFile {
+    #[serde(default)]
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub body: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    /// Headers with lowercase names, filtered by [`is_recorded_header`]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Raw body; SSE streams are stored as the full event text
+    pub body: String,
+}
+
+#[derive(Debug)]
+struct State {
+    interactions: Vec<Interaction>,
+    used: Vec<bool>,
+}
+
+/// Cassette shared by all clones of a client.
+#[derive(Debug)]
+pub struct Cassette {
+    mode: CassetteMode,
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl Cassette {
+    /// Open `cfg.path`, starting an empty cassette when recording.
+    pub fn open(cfg: &CassetteConfig) -> Result<Arc<Self>> {
+        let interactions = match cfg.mode {
+            CassetteMode::Record => Vec::new(),
+            CassetteMode::Replay => {
+                let text = std::fs::read_to_string(&cfg.path)
+                    .with_context(|| format!("failed to read cassette {}", cfg.path.display()))?;
+                serde_json::from_str::<CassetteFile>(&text)
+                    .with_context(|| format!("invalid cassette {}", cfg.path.display()))?
+                    .interactions
+            }
+        };
+        Ok(Arc::new(Self::new(
+            cfg.mode,
+            cfg.path.clone(),
+            interactions,
+        )))
+    }
+
+    fn new(mode: CassetteMode, path: PathBuf, interactions: Vec<Interaction>) -> Self {
+        let used = vec![false; interactions.len()];
+        Self {
+            mode,
+            path,
+            state: Mutex::new(State { interactions, used }),
+        }
+    }
+
+    /// Send a JSON POST through the cassette.
+    pub async fn post_json<T: Serialize + ?Sized>(
+        self: &Arc<Self>,
+        client: &reqwest::Client,
+        url: &str,
+        headers: HeaderMap,
+        body: &T,
+    ) -> reqwest::Result<Response> {
+        let request = RecordedRequest {
+            method: "POST".into(),
+            path: url_path(url),
+            body: serde_json::to_value(body).unwrap_or(Value::Null),
+        };
+        match self.mode {
+            CassetteMode::Replay => Ok(self.replay(&request)),
+            CassetteMode::Record => {
+                let resp = client.post(url).headers(headers).json(body).send().await?;
+                Ok(self.record(request, resp))
+            }
+        }
+    }
+
+    fn replay(&self, request: &RecordedRequest) -> Response {
+        let wanted = normalize(&request.body);
+        let mut state = self.state.lock().unwrap();
+        let State { interactions, used } = &mut *state;
+        let found = interactions.iter().enumerate().position(|(i, rec)| {
+            !used[i]
+                && rec.request.method == request.method
+                && rec.request.path == request.path
+                && normalize(&rec.request.body) == wanted
+        });
+        let Some(i) = found else {
+            warn!(path = %request.path, "no recorded interaction matches request");
+            return build_response(
+                StatusCode::NOT_FOUND.as_u16(),
+                &BTreeMap::from([(CONTENT_TYPE.to_string(), "text/plain".to_string())]),
+                format!(
+                    "cassette {}: no recorded interaction matches request to {}",
+                    self.path.display(),
+                    request.path
+                ),
+            );
+        };
+        used[i] = true;
+        debug!(index = i, path = %request.path, "replaying recorded interaction");
+        let recorded = &interactions[i].response;
+        build_response(recorded.status, &recorded.headers, recorded.body.clone())
+    }
+
+    /// Pass the response through while capturing its body; the interaction is
+    /// saved once the body has been read to the end.
+    fn record(self: &Arc<Self>, request: RecordedRequest, resp: Response) -> Response {
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let recorded_headers: BTreeMap<String, String> = headers
+            .iter()
+            .filter(|(name, _)| is_recorded_header(name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let cassette = Arc::clone(self);
+        let mut upstream = resp.bytes_stream();
+        let body = async_stream::stream! {
+            let mut captured = Vec::new();
+            while let Some(chunk) = upstream.next().await {
+                if let Ok(bytes) = &chunk {
+                    captured.extend_from_slice(bytes);
+                }
+                yield chunk;
+            }
+            cassette.push(Interaction {
+                request,
+                response: RecordedResponse {
+                    status: status.as_u16(),
+                    headers: recorded_headers,
+                    body: String::from_utf8_lossy(&captured).into_owned(),
+                },
+            });
+        };
+
+        let mut builder = http::Response::builder().status(status);
+        for (name, value) in &headers {
+            builder = builder.header(name, value);
+        }
+        let resp = builder
+            .body(reqwest::Body::wrap_stream(body))
+            .expect("status and headers come from a valid response");
+        Response::from(resp)
+    }
+
+    fn push(&self, interaction: Interaction) {
+        let mut state = self.state.lock().unwrap();
+        state.interactions.push(interaction);
+        state.used.push(true);
+        if let Err(e) = save(&self.path, &state.interactions) {
+            warn!(error = %e, path = %self.path.display(), "failed to write cassette");
+        }
+    }
+}
+
+fn save(path: &Path, interactions: &[Interaction]) -> Result<()> {
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = CassetteFile {
+        interactions: interactions.to_vec(),
+    };
+    std::fs::write(path, serde_json::to_string_pretty(&file)?)?;
+    Ok(())
+}
+
+fn url_path(url: &str) -> String {
+    Url::parse(url)
+        .map(|u| u.path().to_string())
+        .unwrap_or_else(|_| url.to_string())
+}
+
+/// Request body with volatile fields removed, used for matching.
+fn normalize(body: &Value) -> Value {
+    match body {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(key, _)| !VOLATILE_FIELDS.contains(&key.as_str()))
+                .map(|(key, value)| (key.clone(), normalize(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(normalize).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Whether a response header (lowercase name) is kept in the cassette.
+fn is_recorded_header(name: &str) -> bool {
+    RECORDED_HEADERS.contains(&name) || name.starts_with("x-ratelimit-")
+}
+
+fn build_response(status: u16, headers: &BTreeMap<String, String>, body: String) -> Response {
+    let mut builder = http::Response::builder().status(status);
+    for (name, value) in headers {
+        if let Ok(value) = HeaderValue::from_str(value) {
+            builder = builder.header(name.as_str(), value);
+        }
+    }
+    let resp = builder
+        .body(body)
+        .unwrap_or_else(|_| http::Response::new(String::new()));
+    Response::from(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httptest::{Expectation, Server, matchers::*, responders::*};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn records_then_replays_without_network() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/v1/chat/completions"))
+                .times(1)
+                .respond_with(
+                    status_code(200)
+                        .insert_header("content-type", "text/event-stream")
+                        .body("data: {\"a\":1}\n\ndata: [DONE]\n\n"),
+                ),
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        let url = server.url_str("/v1/chat/completions");
+        let client = reqwest::Client::new();
+        let body = json!({
+            "model": "m",
+            "stream_options": {"include_usage": true},
+            "messages": [{"role": "user", "content": "hi", "metadata": {"ts": 1}}],
+        });
+
+        let recorder = Cassette::open(&CassetteConfig {
+            mode: CassetteMode::Record,
+            path: path.clone(),
+        })
+        .unwrap();
+        let resp = recorder
+            .post_json(&client, &url, HeaderMap::new(), &body)
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.text().await.unwrap(),
+            "data: {\"a\":1}\n\ndata: [DONE]\n\n"
+        );
+
+        let player = Cassette::open(&CassetteConfig {
+            mode: CassetteMode::Replay,
+            path,
+        })
+        .unwrap();
+        // Volatile fields are ignored when matching, nested ones too
+        let unchanged = json!({"model": "m", "messages": [{"role": "user", "content": "hi"}]});
+        let resp = player
+            .post_json(&client, &url, HeaderMap::new(), &unchanged)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers().get(CONTENT_TYPE).unwrap(),
+            "text/event-stream"
+        );
+        assert_eq!(
+            resp.text().await.unwrap(),
+            "data: {\"a\":1}\n\ndata: [DONE]\n\n"
+        );
+
+        // Each recording is replayed once
+        let resp = player
+            .post_json(&client, &url, HeaderMap::new(), &unchanged)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_headers_are_replayed() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/v1/chat/completions"))
+                .times(1)
+                .respond_with(
+                    status_code(429)
+                        .insert_header("retry-after", "7")
+                        .insert_header("x-ratelimit-remaining-requests", "0")
+                        .insert_header("set-cookie", "session=secret")
+                        .body("{}"),
+                ),
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        let url = server.url_str("/v1/chat/completions");
+        let client = reqwest::Client::new();
+        let body = json!({"model": "m"});
+
+        let recorder = Cassette::open(&CassetteConfig {
+            mode: CassetteMode::Record,
+            path: path.clone(),
+        })
+        .unwrap();
+        let resp = recorder
+            .post_json(&client, &url, HeaderMap::new(), &body)
+            .await
+            .unwrap();
+        resp.bytes().await.unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("secret"));
+
+        let player = Cassette::open(&CassetteConfig {
+            mode: CassetteMode::Replay,
+            path,
+        })
+        .unwrap();
+        let resp = player
+            .post_json(&client, &url, HeaderMap::new(), &body)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 429);
+        assert_eq!(
+            crate::llm::retry::retry_after(resp.headers()),
+            Some(std::time::Duration::from_secs(7))
+        );
+        assert_eq!(
+            resp.headers()
+                .get("x-ratelimit-remaining-requests")
+                .unwrap(),
+            "0"
+        );
+        assert!(resp.headers().get("set-cookie").is_none());
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_tool_loop() {
+        use crate::config::AppConfig;
+        use crate::llm::{ChatMessage, LlmProvider, OpenAIClient, run_agent_loop};
+        use crate::tools::FsTools;
+        use tokio::sync::RwLock;
+
+        let project = tempfile::tempdir().unwrap();
+        let notes = project.path().join("notes.txt");
+        std::fs::write(&notes, "remember the milk").unwrap();
+        let cfg = AppConfig {
+            project_root: project.path().to_path_buf(),
+            ..Default::default()
+        };
+        let fs = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg.clone()));
+        let prompt = vec![ChatMessage {
+            role: "user".into(),
+            content: Some("What do my notes say?".into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        }];
+
+        let server = Server::run();
+        let read_call = json!({"choices": [{"index": 0, "delta": {"tool_calls": [{
+            "index": 0, "id": "call_1", "type": "function",
+            "function": {"name": "fs_read", "arguments": json!({"path": notes}).to_string()}
+        }]}}]});
+        server.expect(
+            Expectation::matching(request::body(not(matches("tool_call_id"))))
+                .times(1)
+                .respond_with(
+                    status_code(200)
+                        .insert_header("content-type", "text/event-stream")
+                        .body(format!("data: {read_call}\n\ndata: [DONE]\n\n")),
+                ),
+        );
+        server.expect(
+            Expectation::matching(request::body(matches("remember the milk")))
+                .times(1)
+                .respond_with(
+                    status_code(200)
+                        .insert_header("content-type", "text/event-stream")
+                        .body(
+                            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Buy milk.\"}}]}\n\ndata: [DONE]\n\n",
+                        ),
+                ),
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tool-loop.json");
+        let open = |mode| {
+            Cassette::open(&CassetteConfig {
+                mode,
+                path: path.clone(),
+            })
+            .unwrap()
+        };
+
+        let recorder = OpenAIClient::new(server.url_str("/v1"), "secret-key")
+            .unwrap()
+            .with_cassette(open(CassetteMode::Record));
+        let client: &dyn LlmProvider = &recorder;
+        let (recorded, _) = run_agent_loop(
+            client,
+            "gpt-4o",
+            &fs,
+            prompt.clone(),
+            None,
+            None,
+            &cfg,
+            None,
+        )
+        .await
+        .unwrap();
+        let file = std::fs::read_to_string(&path).unwrap();
+        assert!(!file.contains("secret-key"));
+
+        // The replaying client points at a dead address; everything comes from the cassette
+        let player = OpenAIClient::new("http://127.0.0.1:9/v1", "")
+            .unwrap()
+            .with_cassette(open(CassetteMode::Replay));
+        let client: &dyn LlmProvider = &player;
+        let (replayed, last) =
+            run_agent_loop(client, "gpt-4o", &fs, prompt, None, None, &cfg, None)
+                .await
+                .unwrap();
+        assert_eq!(last.content, "Buy milk.");
+        assert_eq!(replayed.len(), recorded.len());
+        assert_eq!(replayed[1].tool_calls[0].function.name, "fs_read");
+    }
+}
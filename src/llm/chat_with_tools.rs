@@ -16,6 +16,24 @@ pub struct ChatRequestWithTools {
     pub reasoning_effort: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reasoning: Option<Reasoning>, // OpenRouter reasoning parameter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<serde_json::Value>, // {"include_usage":true}
+    /// Add prompt cache breakpoints when serializing the body
+    #[serde(skip)]
+    pub prompt_cache: bool,
+}
+
+impl ChatRequestWithTools {
+    /// JSON body sent to the chat completions endpoint.
+    pub fn to_body(&self) -> serde_json::Value {
+        let mut body = serde_json::to_value(self).unwrap_or_default();
+        if self.prompt_cache {
+            crate::llm::prompt_cache::mark_chat_completions_body(&mut body);
+        }
+        body
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,11 +47,43 @@ pub struct Reasoning {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "WireChoiceMessage")]
 pub struct ChoiceMessageWithTools {
     pub role: String,
     pub content: Option<String>,
     #[serde(default)]
     pub tool_calls: Vec<ToolCall>,
+    /// Reasoning returned alongside the reply, when the backend exposes it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+}
+
+/// Assistant message as returned on the wire. OpenRouter and vLLM use
+/// `reasoning`, DeepSeek and others `reasoning_content`; some send both.
+#[derive(Deserialize)]
+struct WireChoiceMessage {
+    role: String,
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCall>,
+    #[serde(default)]
+    reasoning: Option<String>,
+    #[serde(default)]
+    reasoning_content: Option<String>,
+}
+
+impl From<WireChoiceMessage> for ChoiceMessageWithTools {
+    fn from(wire: WireChoiceMessage) -> Self {
+        Self {
+            role: wire.role,
+            content: wire.content,
+            tool_calls: wire.tool_calls,
+            reasoning: wire
+                .reasoning
+                .or(wire.reasoning_content)
+                .filter(|r| !r.trim().is_empty()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,3 +106,34 @@ pub struct ToolMessagePayload {
     pub tool_call_id: Option<String>,
     pub name: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reasoning_is_read_from_either_field() {
+        let parse = |v: serde_json::Value| {
+            serde_json::from_value::<ChoiceMessageWithTools>(v)
+                .unwrap()
+                .reasoning
+        };
+        assert_eq!(
+            parse(serde_json::json!({"role": "assistant", "content": "hi", "reasoning": "r1"})),
+            Some("r1".into())
+        );
+        assert_eq!(
+            parse(serde_json::json!({
+                "role": "assistant",
+                "content": null,
+                "reasoning": null,
+                "reasoning_content": "r2"
+            })),
+            Some("r2".into())
+        );
+        assert_eq!(
+            parse(serde_json::json!({"role": "assistant", "content": "hi", "reasoning": " "})),
+            None
+        );
+    }
+}
@@ -1,12 +1,20 @@
-use anyhow::Result;
+use anyhow::{Result, anyhow};
+use reqwest::header::HeaderMap;
+use serde_json::Value;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::Duration;
 use tokio_util::sync::CancellationToken;
+use tracing::warn;
 
 use crate::config::LlmConfig;
-use crate::llm::LlmErrorKind;
-use crate::llm::types::{ChatMessage, ChoiceMessage};
+use crate::cost::{RequestUsage, UsageLog};
+use crate::llm::cassette::Cassette;
+use crate::llm::chat_with_tools::{ChatRequestWithTools, Reasoning};
+use crate::llm::prompt_cache;
+use crate::llm::retry::{CircuitBreaker, breaker_for};
+use crate::llm::structured::{ResponseFormat, ResponseSchema, chat_via_forced_tool, extract_json};
+use crate::llm::types::{ChatMessage, ChoiceMessage, ToolDef, Usage};
 
 mod network;
 
@@ -20,7 +28,11 @@ pub struct OpenAIClient {
     pub tokens_used: Arc<AtomicU32>,
     /// Tracks prompt tokens used by this client (for header display)
     pub prompt_tokens_used: Arc<AtomicU32>,
+    /// Per-request usage for cost accounting
+    pub usage_log: UsageLog,
     pub reason_enable: bool,
+    /// Records or replays HTTP exchanges instead of plain network calls
+    pub(crate) cassette: Option<Arc<Cassette>>,
 }
 
 impl OpenAIClient {
@@ -39,7 +51,9 @@ impl OpenAIClient {
             llm_cfg: LlmConfig::default(),
             tokens_used: Arc::new(AtomicU32::new(0)),
             prompt_tokens_used: Arc::new(AtomicU32::new(0)),
+            usage_log: UsageLog::default(),
             reason_enable,
+            cassette: None,
         })
     }
 
@@ -57,6 +71,87 @@ impl OpenAIClient {
         self
     }
 
+    pub fn with_cassette(mut self, cassette: Arc<Cassette>) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    /// POST a JSON body, through the cassette when one is attached.
+    pub(crate) async fn send_json<T: serde::Serialize + ?Sized>(
+        &self,
+        url: &str,
+        headers: HeaderMap,
+        body: &T,
+    ) -> reqwest::Result<reqwest::Response> {
+        match &self.cassette {
+            Some(cassette) => cassette.post_json(&self.inner, url, headers, body).await,
+            None => {
+                self.inner
+                    .post(url)
+                    .headers(headers)
+                    .json(body)
+                    .send()
+                    .await
+            }
+        }
+    }
+
+    /// Chat completions request advertising `tools`, with the reasoning
+    /// parameters this client's backend expects.
+    pub(crate) fn tools_request(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDef],
+    ) -> ChatRequestWithTools {
+        let reasoning_effort = if self.reason_enable {
+            Some("high".to_owned())
+        } else {
+            None
+        };
+
+        let reasoning = if model.contains("grok-4-fast") {
+            Some(Reasoning {
+                effort: None,
+                max_tokens: None,
+                enabled: Some(true),
+            })
+        } else {
+            None
+        };
+        ChatRequestWithTools {
+            model: model.to_string(),
+            messages: self.outgoing_messages(messages),
+            temperature: None,
+            tools: if tools.is_empty() {
+                None
+            } else {
+                Some(tools.to_vec())
+            },
+            tool_choice: None,
+            reasoning_effort,
+            reasoning,
+            stream: None,
+            stream_options: None,
+            prompt_cache: prompt_cache::is_enabled(self.llm_cfg.prompt_cache, model),
+        }
+    }
+
+    /// Drop captured reasoning from history unless it should be resent.
+    pub(crate) fn outgoing_messages(&self, mut messages: Vec<ChatMessage>) -> Vec<ChatMessage> {
+        if !self.llm_cfg.resend_reasoning {
+            for msg in &mut messages {
+                msg.reasoning = None;
+            }
+        }
+        messages
+    }
+
+    /// Circuit breaker shared by clients of this endpoint.
+    pub(crate) fn circuit_breaker(&self) -> Arc<CircuitBreaker> {
+        breaker_for(&self.base_url, &self.llm_cfg)
+    }
+
     pub(crate) fn endpoint(&self) -> String {
         let mut base = self.base_url.trim_end_matches('/').to_string();
         if let Some(pos) = base.rfind("/v1") {
@@ -94,6 +189,18 @@ impl OpenAIClient {
         self.prompt_tokens_used.store(tokens, Ordering::Relaxed);
     }
 
+    /// Update the token counters and log the request for cost accounting.
+    pub(crate) fn record_usage(&self, model: &str, usage: &Usage) {
+        self.set_tokens(usage.total_tokens);
+        self.set_prompt_tokens(usage.prompt_tokens);
+        self.usage_log.record(RequestUsage {
+            model: model.to_string(),
+            input_tokens: usage.prompt_tokens,
+            output_tokens: usage.completion_tokens,
+            cached_tokens: usage.cached_tokens(),
+        });
+    }
+
     #[allow(dead_code)]
     pub async fn chat_once(
         &self,
@@ -102,36 +209,39 @@ impl OpenAIClient {
         cancel: Option<CancellationToken>,
     ) -> Result<ChoiceMessage> {
         // Delegate to network module implementation for clarity and to keep this file small
-        crate::llm::client_core::network::chat_once(self, model, messages, cancel).await
+        crate::llm::client_core::network::chat_once(self, model, messages, None, cancel).await
     }
 
-    #[allow(dead_code)]
-    fn should_retry(&self, kind: LlmErrorKind) -> bool {
-        // borrow kind to avoid move
-        matches!(
-            kind,
-            LlmErrorKind::RateLimited
-                | LlmErrorKind::Server
-                | LlmErrorKind::Network
-                | LlmErrorKind::Timeout
-        )
-    }
-
-    pub(crate) fn backoff_delay(&self, attempt: usize, retry_after_secs: Option<u64>) -> Duration {
-        if self.llm_cfg.respect_retry_after
-            && let Some(secs) = retry_after_secs
+    /// Chat request constrained by a `json_schema` response format.
+    ///
+    /// Servers that reject `response_format` get the forced tool call instead.
+    pub async fn chat_structured(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        schema: &ResponseSchema,
+        cancel: Option<CancellationToken>,
+    ) -> Result<Value> {
+        let format = ResponseFormat::json_schema(schema.clone());
+        match network::chat_once(self, model, messages.clone(), Some(format), cancel.clone()).await
         {
-            return Duration::from_secs(secs);
+            Ok(msg) => extract_json(&msg.content)
+                .ok_or_else(|| anyhow!("model did not return a `{}` result", schema.name)),
+            Err(e) if is_unsupported_response_format(&e) => {
+                warn!(err = %e, "response_format rejected; falling back to a forced tool call");
+                chat_via_forced_tool(self, model, messages, schema, cancel).await
+            }
+            Err(e) => Err(e),
         }
-        let base = self.llm_cfg.retry_base_ms;
-        let exp = base.saturating_mul(1u64 << (attempt as u32 - 1));
-        let jitter = self.llm_cfg.retry_jitter_ms as i64;
-        let half = jitter / 2;
-        let rnd = fastrand::i64(-half..=half).max(0) as u64;
-        Duration::from_millis(exp.saturating_add(rnd))
     }
 }
 
+/// Whether a failed request was rejected for its `response_format`.
+fn is_unsupported_response_format(err: &anyhow::Error) -> bool {
+    let msg = err.to_string();
+    msg.contains("400") && (msg.contains("response_format") || msg.contains("json_schema"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -163,6 +273,8 @@ mod tests {
                     content: Some("hi".into()),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
                 }],
                 None,
             )
@@ -171,6 +283,72 @@ mod tests {
         assert_eq!(msg.content, "hello");
     }
 
+    #[tokio::test]
+    async fn chat_structured_sends_response_format_and_falls_back() {
+        let schema = ResponseSchema {
+            name: "answer".into(),
+            description: None,
+            schema: serde_json::json!({"type": "object"}),
+            strict: true,
+        };
+        let user = vec![ChatMessage {
+            role: "user".into(),
+            content: Some("hi".into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        }];
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/v1/chat/completions"),
+                request::body(matches(r#""response_format":\{"type":"json_schema""#)),
+            ])
+            .respond_with(json_encoded(serde_json::json!({
+                "choices": [
+                    {"index":0, "message": {"role":"assistant","content":"{\"value\": 1}"}}
+                ]
+            }))),
+        );
+        let client = OpenAIClient::new(format!("{}/", server.url_str("")), "k").unwrap();
+        let payload = client
+            .chat_structured("gpt-test", user.clone(), &schema, None)
+            .await
+            .unwrap();
+        assert_eq!(payload, serde_json::json!({"value": 1}));
+
+        // A server rejecting response_format gets the schema as a tool instead
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::body(matches("response_format")))
+                .respond_with(status_code(400).body("unknown field: response_format")),
+        );
+        server.expect(
+            Expectation::matching(request::body(matches(r#""tools":\[\{"#))).respond_with(
+                json_encoded(serde_json::json!({
+                    "choices": [{"index":0, "message": {
+                        "role":"assistant",
+                        "content":null,
+                        "tool_calls":[{"id":"c1","type":"function","function":{"name":"answer","arguments":"{\"value\":2}"}}]
+                    }}]
+                })),
+            ),
+        );
+        let client = OpenAIClient::new(format!("{}/", server.url_str("")), "k")
+            .unwrap()
+            .with_llm_config(LlmConfig {
+                max_retries: 0,
+                ..LlmConfig::default()
+            });
+        let payload = client
+            .chat_structured("gpt-test", user, &schema, None)
+            .await
+            .unwrap();
+        assert_eq!(payload, serde_json::json!({"value": 2}));
+    }
+
     #[tokio::test]
     #[ignore]
     async fn chat_once_retries_on_500_then_succeeds() {
@@ -203,6 +381,8 @@ mod tests {
                     content: Some("hi".into()),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
                 }],
                 None,
             )
@@ -239,6 +419,8 @@ mod tests {
                     content: Some("hi".into()),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
                 }],
                 None,
             )
@@ -273,6 +455,8 @@ mod tests {
                     content: Some("hi".into()),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
                 }],
                 None,
             )
@@ -313,6 +497,8 @@ mod tests {
                     content: Some("hi".into()),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
                 }],
                 None,
             )
@@ -358,6 +544,8 @@ mod tests {
                     content: Some("hi".into()),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
                 }],
                 None,
             )
@@ -375,7 +563,9 @@ mod tests {
             llm_cfg: LlmConfig::default(),
             tokens_used: Arc::new(AtomicU32::new(0)),
             prompt_tokens_used: Arc::new(AtomicU32::new(0)),
+            usage_log: UsageLog::default(),
             reason_enable: false,
+            cassette: None,
         };
         assert_eq!(c.endpoint(), "https://api.example.com/v1/chat/completions");
         let c2 = OpenAIClient {
@@ -385,7 +575,9 @@ mod tests {
             llm_cfg: LlmConfig::default(),
             tokens_used: Arc::new(AtomicU32::new(0)),
             prompt_tokens_used: Arc::new(AtomicU32::new(0)),
+            usage_log: UsageLog::default(),
             reason_enable: false,
+            cassette: None,
         };
         assert_eq!(c2.endpoint(), "https://api.example.com/v1/chat/completions");
     }
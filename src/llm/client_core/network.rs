@@ -1,26 +1,29 @@
 use anyhow::Result;
-use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap, RETRY_AFTER};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap};
 use serde_json;
-use std::time::Duration;
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, info};
 
 use super::OpenAIClient;
 use crate::llm::LlmErrorKind;
+use crate::llm::retry::{self, Retrier, RetryPolicy};
+use crate::llm::structured::ResponseFormat;
 use crate::llm::types::{ChatMessage, ChatRequest, ChatResponse, ChoiceMessage};
 
 pub async fn chat_once(
     client: &OpenAIClient,
     model: &str,
     messages: Vec<ChatMessage>,
+    response_format: Option<ResponseFormat>,
     cancel: Option<CancellationToken>,
 ) -> Result<ChoiceMessage> {
     let url = client.endpoint();
     let req = ChatRequest {
         model: model.to_string(),
-        messages,
+        messages: client.outgoing_messages(messages),
         temperature: None,
         stream: None,
+        response_format,
     };
 
     let mut headers = HeaderMap::new();
@@ -39,195 +42,78 @@ pub async fn chat_once(
         debug!(payload=%payload, endpoint=%url, "sending chat.completions payload");
     }
 
-    let max_attempts = client.llm_cfg.max_retries.saturating_add(1);
-    let mut last_err: Option<anyhow::Error> = None;
-
     let cancel_token = cancel.unwrap_or_default();
+    Retrier::new(
+        RetryPolicy::from_config(&client.llm_cfg),
+        client.circuit_breaker(),
+        "chat_once",
+    )
+    .run(&cancel_token, || {
+        attempt(client, model, &url, &headers, &req, &cancel_token)
+    })
+    .await
+}
 
-    for attempt in 1..=max_attempts {
-        let req_builder = client.inner.post(&url).headers(headers.clone()).json(&req);
-
-        let resp_res = tokio::select! {
-            biased;
-            _ = cancel_token.cancelled() => {
-                info!("chat_once cancelled before send");
-                return Err(anyhow::anyhow!(LlmErrorKind::Cancelled));
-            }
-            res = req_builder.send() => res,
-        };
-
-        match resp_res {
-            Err(e) => {
-                error!(attempt, err=%e, "llm chat_once send error");
-                last_err = Some(anyhow::Error::new(e).context("send chat request"));
-            }
-            Ok(resp) => {
-                if !resp.status().is_success() {
-                    let status = resp.status();
-                    let retry_after = resp
-                        .headers()
-                        .get(RETRY_AFTER)
-                        .and_then(|h| h.to_str().ok())
-                        .and_then(|s| s.parse::<u64>().ok());
-
-                    let text = tokio::select! {
-                        biased;
-                        _ = cancel_token.cancelled() => {
-                            info!("chat_once cancelled during error body read");
-                            return Err(anyhow::anyhow!(LlmErrorKind::Cancelled));
-                        }
-                        res = resp.text() => res.unwrap_or_default(),
-                    };
-
-                    error!(attempt, status=%status.as_u16(), body=%text, "llm chat_once non-success status");
-                    let e = anyhow::anyhow!("chat error: {} - {}", status, text);
-                    let kind = crate::llm::classify_error(Some(status), &e);
-                    // Retry even in case of timeout
-                    if (should_retry(kind.clone()) || matches!(kind, LlmErrorKind::Timeout))
-                        && attempt < max_attempts
-                    {
-                        let wait = backoff_delay(client, attempt, retry_after);
-                        info!(attempt, kind=?kind, wait_ms=%wait.as_millis(), "retrying chat_once");
-                        tokio::select! {
-                            biased;
-                            _ = cancel_token.cancelled() => {
-                                info!("chat_once cancelled during retry sleep");
-                                return Err(anyhow::anyhow!(LlmErrorKind::Cancelled));
-                            }
-                            _ = tokio::time::sleep(wait) => {}
-                        }
-                        continue;
-                    } else {
-                        return Err(e);
-                    }
-                }
-
-                let response_text = match tokio::select! {
-                    biased;
-                    _ = cancel_token.cancelled() => {
-                        info!("chat_once cancelled during body read");
-                        return Err(anyhow::anyhow!(LlmErrorKind::Cancelled));
-                    }
-                    res = resp.text() => res
-                } {
-                    Ok(text) => text,
-                    Err(e) => {
-                        error!(attempt, err=%e, "llm chat_once read body error");
-                        last_err = Some(anyhow::Error::new(e).context("read chat response body"));
-                        let kind = crate::llm::classify_error(None, last_err.as_ref().unwrap());
-                        // Retry even in case of timeout
-                        if (should_retry(kind.clone()) || matches!(kind, LlmErrorKind::Timeout))
-                            && attempt < max_attempts
-                        {
-                            let wait = backoff_delay(client, attempt, None);
-                            warn!(attempt, kind=?kind, "retrying after body read error");
-                            tokio::select! {
-                                biased;
-                                _ = cancel_token.cancelled() => {
-                                    info!("chat_once cancelled during retry sleep");
-                                    return Err(anyhow::anyhow!(LlmErrorKind::Cancelled));
-                                }
-                                _ = tokio::time::sleep(wait) => {}
-                            }
-                            continue;
-                        } else {
-                            return Err(last_err.unwrap());
-                        }
-                    }
-                };
-
-                debug!("llm chat_once response");
-
-                let body: Result<ChatResponse, _> = serde_json::from_str(&response_text);
-                match body {
-                    Ok(body) => {
-                        // Track token usage if available
-                        if let Some(usage) = &body.usage {
-                            client.set_tokens(usage.total_tokens);
-                            // Also track prompt tokens for non-streaming path so UI can display header info
-                            client.set_prompt_tokens(usage.prompt_tokens);
-                        }
-
-                        if let Some(msg) = body.choices.into_iter().next().map(|c| c.message) {
-                            return Ok(msg);
-                        } else {
-                            let e = anyhow::anyhow!("no choices returned");
-                            return Err(e);
-                        }
-                    }
-                    Err(e) => {
-                        let kind = LlmErrorKind::Deserialize;
-                        error!(attempt, err=%e, "llm chat_once deserialize error");
-                        // Retry even in case of timeout
-                        if (should_retry(kind.clone()) || matches!(kind, LlmErrorKind::Timeout))
-                            && attempt < max_attempts
-                        {
-                            let wait = backoff_delay(client, attempt, None);
-                            warn!(attempt, kind=?kind, "retrying after deserialize error");
-                            tokio::select! {
-                                biased;
-                                _ = cancel_token.cancelled() => {
-                                    info!("chat_once cancelled during retry sleep");
-                                    return Err(anyhow::anyhow!(LlmErrorKind::Cancelled));
-                                }
-                                _ = tokio::time::sleep(wait) => {}
-                            }
-                            last_err = Some(anyhow::Error::new(e).context("parse chat response"));
-                            continue;
-                        } else {
-                            return Err(anyhow::Error::new(e).context("parse chat response"));
-                        }
-                    }
-                }
-            }
+async fn attempt(
+    client: &OpenAIClient,
+    model: &str,
+    url: &str,
+    headers: &HeaderMap,
+    req: &ChatRequest,
+    cancel_token: &CancellationToken,
+) -> Result<ChoiceMessage> {
+    let send = client.send_json(url, headers.clone(), req);
+    let resp = tokio::select! {
+        biased;
+        _ = cancel_token.cancelled() => {
+            info!("chat_once cancelled before send");
+            return Err(anyhow::anyhow!(LlmErrorKind::Cancelled));
         }
+        res = send => res.map_err(|e| {
+            error!(err=%e, "llm chat_once send error");
+            anyhow::Error::new(e).context("send chat request")
+        })?,
+    };
 
-        if attempt < max_attempts {
-            let kind = crate::llm::classify_error(None, last_err.as_ref().unwrap());
-            // Retry even in case of timeout
-            if should_retry(kind.clone()) || matches!(kind, LlmErrorKind::Timeout) {
-                let wait = backoff_delay(client, attempt, None);
-                info!(attempt, kind=?kind, wait_ms=%wait.as_millis(), "retrying chat_once");
-                tokio::select! {
-                    biased;
-                    _ = cancel_token.cancelled() => {
-                        info!("chat_once cancelled during retry sleep");
-                        return Err(anyhow::anyhow!(LlmErrorKind::Cancelled));
-                    }
-                    _ = tokio::time::sleep(wait) => {}
-                }
-                continue;
-            }
+    let status = resp.status();
+    let retry_after = retry::retry_after(resp.headers());
+    let text = tokio::select! {
+        biased;
+        _ = cancel_token.cancelled() => {
+            info!("chat_once cancelled during body read");
+            return Err(anyhow::anyhow!(LlmErrorKind::Cancelled));
         }
-    }
-
-    Err(last_err.unwrap_or_else(|| anyhow::anyhow!("unknown error")))
-}
+        res = resp.text() => res,
+    };
 
-pub(crate) fn should_retry(kind: LlmErrorKind) -> bool {
-    matches!(
-        kind,
-        LlmErrorKind::RateLimited
-            | LlmErrorKind::Server
-            | LlmErrorKind::Network
-            | LlmErrorKind::Timeout
-    )
-}
+    if !status.is_success() {
+        let text = text.unwrap_or_default();
+        error!(status=%status.as_u16(), body=%text, "llm chat_once non-success status");
+        return Err(crate::llm::status_error(
+            "chat error",
+            status,
+            retry_after,
+            &text,
+        ));
+    }
 
-pub(crate) fn backoff_delay(
-    client: &OpenAIClient,
-    attempt: usize,
-    retry_after_secs: Option<u64>,
-) -> Duration {
-    if client.llm_cfg.respect_retry_after
-        && let Some(secs) = retry_after_secs
-    {
-        return Duration::from_secs(secs);
+    let response_text = text.map_err(|e| {
+        error!(err=%e, "llm chat_once read body error");
+        anyhow::Error::new(e).context("read chat response body")
+    })?;
+    debug!("llm chat_once response");
+
+    let body: ChatResponse = serde_json::from_str(&response_text).map_err(|e| {
+        error!(err=%e, "llm chat_once deserialize error");
+        anyhow::Error::new(LlmErrorKind::Deserialize).context(format!("parse chat response: {e}"))
+    })?;
+    // Track token usage if available
+    if let Some(usage) = &body.usage {
+        client.record_usage(model, usage);
     }
-    let base = client.llm_cfg.retry_base_ms;
-    let exp = base.saturating_mul(1u64 << (attempt as u32 - 1));
-    let jitter = client.llm_cfg.retry_jitter_ms as i64;
-    let half = jitter / 2;
-    let rnd = fastrand::i64(-half..=half).max(0) as u64;
-    Duration::from_millis(exp.saturating_add(rnd))
+    body.choices
+        .into_iter()
+        .next()
+        .map(|c| c.message)
+        .ok_or_else(|| anyhow::Error::new(LlmErrorKind::Deserialize).context("no choices returned"))
 }
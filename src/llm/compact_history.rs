@@ -5,9 +5,16 @@
 //! token usage for future LLM interactions.
 
 use crate::config::AppConfig;
-use crate::llm::{self, OpenAIClient};
+use crate::llm::structured::{ResponseSchema, from_payload};
+use crate::llm::tokenizer::count_tokens;
+use crate::llm::types::ChatMessage;
+use crate::llm::{self, LlmProvider};
 use crate::tools::FsTools;
-use anyhow::Result;
+use anyhow::{Result, bail};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use tracing::warn;
 
 /// The prompt used for compacting conversation history
 pub const COMPACT_PROMPT: &str = r#"You are the component that summarizes internal chat history into a given structure.
@@ -58,10 +65,163 @@ The structure MUST be as follows:
     
 </state_snapshot>"#;
 
+/// Structured form of the `<state_snapshot>` requested by [`COMPACT_PROMPT`].
+#[derive(Debug, Deserialize)]
+struct StateSnapshot {
+    overall_goal: String,
+    #[serde(default)]
+    key_knowledge: Vec<String>,
+    #[serde(default)]
+    file_system_state: Vec<String>,
+    #[serde(default)]
+    recent_actions: Vec<String>,
+}
+
+impl StateSnapshot {
+    fn schema() -> ResponseSchema {
+        let list = |description: &str| json!({"type": "array", "items": {"type": "string"}, "description": description});
+        ResponseSchema {
+            name: "state_snapshot".to_string(),
+            description: Some("Submit the state snapshot of the conversation.".to_string()),
+            schema: json!({
+                "type": "object",
+                "properties": {
+                    "overall_goal": {
+                        "type": "string",
+                        "description": "A single, concise sentence describing the user's high-level objective"
+                    },
+                    "key_knowledge": list("Crucial facts, conventions and constraints the agent must remember"),
+                    "file_system_state": list("Files created, read, modified or deleted, with their status"),
+                    "recent_actions": list("The last few significant agent actions and their outcomes")
+                },
+                "required": ["overall_goal", "key_knowledge", "file_system_state", "recent_actions"],
+                "additionalProperties": false
+            }),
+            strict: true,
+        }
+    }
+
+    /// Render in the XML layout of [`COMPACT_PROMPT`].
+    fn to_xml(&self) -> String {
+        let section = |tag: &str, items: &[String]| {
+            let body: String = items
+                .iter()
+                .map(|item| format!("         - {}\n", item.trim()))
+                .collect();
+            format!("    <{tag}>\n{body}    </{tag}>\n")
+        };
+        format!(
+            "<state_snapshot>\n    <overall_goal>\n        {}\n    </overall_goal>\n{}{}{}</state_snapshot>",
+            self.overall_goal.trim(),
+            section("key_knowledge", &self.key_knowledge),
+            section("file_system_state", &self.file_system_state),
+            section("recent_actions", &self.recent_actions),
+        )
+    }
+}
+
+/// Appended to a snapshot that replaces the history in the middle of a task.
+const CONTINUE_FROM_SNAPSHOT: &str = "The conversation was compacted to fit the context window. Continue the task from this snapshot.";
+
+/// Summarize the history of an agent loop that no longer fits the context
+/// window, keeping its system prompt. The full `messages` are summarized when
+/// they fit `budget`, else `trimmed` (the same history with old tool output
+/// replaced); when even that is over, the oldest messages are left out.
+pub async fn compact_to_fit(
+    client: &dyn LlmProvider,
+    model: &str,
+    messages: &[ChatMessage],
+    trimmed: &[ChatMessage],
+    budget: usize,
+) -> Result<Vec<ChatMessage>> {
+    let schema = StateSnapshot::schema();
+    let room = budget
+        .saturating_sub(count_tokens(COMPACT_PROMPT) + count_tokens(&schema.schema.to_string()));
+    let request = vec![
+        text_message("system", COMPACT_PROMPT.to_string()),
+        text_message("user", summary_transcript(messages, trimmed, room)?),
+    ];
+    let snapshot = client
+        .chat_structured(model, request, &schema, None)
+        .await
+        .and_then(from_payload::<StateSnapshot>)?;
+    let system = messages.first().filter(|m| m.role == "system");
+    let mut compacted: Vec<ChatMessage> = system.into_iter().cloned().collect();
+    compacted.push(text_message(
+        "user",
+        format!("{}\n\n{CONTINUE_FROM_SNAPSHOT}", snapshot.to_xml()),
+    ));
+    Ok(compacted)
+}
+
+/// Transcript to summarize within `room` tokens: all of `messages` if it fits,
+/// else the newest part of `trimmed`. System prompts are left out.
+fn summary_transcript(
+    messages: &[ChatMessage],
+    trimmed: &[ChatMessage],
+    room: usize,
+) -> Result<String> {
+    let newest_within = |history: &[ChatMessage]| {
+        let mut room = room;
+        let mut entries = Vec::new();
+        for entry in history
+            .iter()
+            .filter(|m| m.role != "system")
+            .rev()
+            .map(transcript_entry)
+        {
+            let tokens = count_tokens(&entry);
+            if tokens > room {
+                break;
+            }
+            room -= tokens;
+            entries.push(entry);
+        }
+        entries.reverse();
+        entries
+    };
+    let full = newest_within(messages);
+    if full.len() == messages.iter().filter(|m| m.role != "system").count() {
+        return Ok(full.join("\n"));
+    }
+    let entries = newest_within(trimmed);
+    if entries.is_empty() {
+        bail!("the latest message alone does not fit the context window");
+    }
+    Ok(entries.join("\n"))
+}
+
+/// One message of the history as plain text for the summarization request.
+fn transcript_entry(msg: &ChatMessage) -> String {
+    let mut entry = format!("[{}]\n", msg.role);
+    if let Some(content) = &msg.content {
+        entry.push_str(content);
+        entry.push('\n');
+    }
+    for tc in &msg.tool_calls {
+        entry.push_str(&format!(
+            "called {}({})\n",
+            tc.function.name, tc.function.arguments
+        ));
+    }
+    entry
+}
+
+fn text_message(role: &str, content: String) -> ChatMessage {
+    ChatMessage {
+        role: role.into(),
+        content: Some(content),
+        tool_calls: vec![],
+        tool_call_id: None,
+        reasoning: None,
+        images: vec![],
+    }
+}
+
 /// Parameters for compacting conversation history
 pub struct CompactParams {
     /// The LLM client to use for summarization
-    pub client: OpenAIClient,
+    pub client: Arc<dyn LlmProvider>,
     /// The model to use for summarization
     pub model: String,
     /// The file system tools
@@ -111,14 +271,43 @@ pub async fn compact_conversation_history(params: CompactParams) -> Result<Compa
         content: Some(COMPACT_PROMPT.to_string()),
         tool_calls: vec![],
         tool_call_id: None,
+        reasoning: None,
+        images: vec![],
     });
 
     // Add the conversation history to be summarized
     msgs.extend(params.history.clone());
 
+    // Prefer a typed snapshot; fall back to a free-text summary when the
+    // backend cannot produce one
+    let structured = params
+        .client
+        .chat_structured(&params.model, msgs.clone(), &StateSnapshot::schema(), None)
+        .await
+        .and_then(from_payload::<StateSnapshot>);
+    match structured {
+        Ok(snapshot) => {
+            return Ok(CompactResult {
+                compacted_message: llm::types::ChatMessage {
+                    role: "user".into(),
+                    content: Some(snapshot.to_xml()),
+                    tool_calls: vec![],
+                    tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
+                },
+                metadata: CompactMetadata {
+                    success: true,
+                    error_message: None,
+                },
+            });
+        }
+        Err(e) => warn!(error = %e, "structured compaction failed; using free-text summary"),
+    }
+
     // Send the summarization request to the LLM using run_agent_loop
     match llm::run_agent_loop(
-        &params.client,
+        params.client.as_ref(),
         &params.model,
         &params.fs_tools,
         msgs,
@@ -138,6 +327,8 @@ pub async fn compact_conversation_history(params: CompactParams) -> Result<Compa
                     content: Some(final_msg.content.clone()),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
                 };
 
                 Ok(CompactResult {
@@ -155,6 +346,8 @@ pub async fn compact_conversation_history(params: CompactParams) -> Result<Compa
                         content: Some("".to_string()),
                         tool_calls: vec![],
                         tool_call_id: None,
+                        reasoning: None,
+                        images: vec![],
                     },
                     metadata: CompactMetadata {
                         success: false,
@@ -173,6 +366,8 @@ pub async fn compact_conversation_history(params: CompactParams) -> Result<Compa
                     content: Some("".to_string()),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
                 },
                 metadata: CompactMetadata {
                     success: false,
@@ -204,6 +399,8 @@ mod tests {
             content: Some("test content".to_string()),
             tool_calls: vec![],
             tool_call_id: None,
+            reasoning: None,
+            images: vec![],
         };
 
         let metadata = CompactMetadata {
@@ -224,6 +421,26 @@ mod tests {
         assert!(result.metadata.success);
     }
 
+    #[test]
+    fn test_state_snapshot_renders_prompt_layout() {
+        let snapshot: StateSnapshot = from_payload(json!({
+            "overall_goal": "Add a cost report.",
+            "key_knowledge": ["Tests run with `cargo test`."],
+            "file_system_state": [],
+            "recent_actions": ["MODIFIED: `src/cost/mod.rs`"]
+        }))
+        .unwrap();
+        let xml = snapshot.to_xml();
+        assert!(
+            xml.starts_with("<state_snapshot>\n    <overall_goal>\n        Add a cost report.")
+        );
+        assert!(xml.contains(
+            "    <key_knowledge>\n         - Tests run with `cargo test`.\n    </key_knowledge>"
+        ));
+        assert!(xml.contains("    <file_system_state>\n    </file_system_state>"));
+        assert!(xml.ends_with("</recent_actions>\n</state_snapshot>"));
+    }
+
     #[test]
     fn test_compact_metadata_struct() {
         let metadata = CompactMetadata {
@@ -234,4 +451,28 @@ mod tests {
         assert!(!metadata.success);
         assert_eq!(metadata.error_message, Some("test error".to_string()));
     }
+
+    #[test]
+    fn summary_uses_the_untrimmed_history_when_it_fits() {
+        let message = |role: &str, content: &str| text_message(role, content.to_string());
+        let messages = vec![
+            message("system", "Be brief."),
+            message("tool", &"first output ".repeat(50)),
+            message("tool", "second output"),
+        ];
+        let mut trimmed = messages.clone();
+        trimmed[1].content = Some("trimmed".into());
+
+        let transcript = summary_transcript(&messages, &trimmed, 1_000).unwrap();
+        assert!(transcript.contains("first output"), "{transcript}");
+        assert!(!transcript.contains("Be brief."));
+
+        // Too large in full: the trimmed history is summarized instead
+        let room = count_tokens(&transcript) / 2;
+        let transcript = summary_transcript(&messages, &trimmed, room).unwrap();
+        assert!(transcript.contains("trimmed"), "{transcript}");
+        assert!(transcript.contains("second output"));
+
+        assert!(summary_transcript(&messages, &trimmed, 0).is_err());
+    }
 }
@@ -0,0 +1,90 @@
+//! Context window sizes of well-known models.
+
+/// Window assumed for models not in the registry.
+pub const DEFAULT_CONTEXT_WINDOW: u32 = 128_000;
+
+/// Known context windows keyed by model name prefix; the longest match wins.
+const KNOWN_CONTEXT_WINDOWS: &[(&str, u32)] = &[
+    // OpenAI
+    ("gpt-3.5-turbo", 16_385),
+    ("gpt-4", 8_192),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4o", 128_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-5", 400_000),
+    ("gpt-oss", 131_072),
+    ("o1", 200_000),
+    ("o1-mini", 128_000),
+    ("o3", 200_000),
+    ("o4-mini", 200_000),
+    ("codex-mini", 200_000),
+    // Anthropic
+    ("claude", 200_000),
+    // Google
+    ("gemini-1.5-pro", 2_097_152),
+    ("gemini-1.5-flash", 1_048_576),
+    ("gemini-2", 1_048_576),
+    // xAI
+    ("grok-3", 131_072),
+    ("grok-4", 256_000),
+    ("grok-code-fast", 256_000),
+    // Open-weight models
+    ("deepseek", 128_000),
+    ("qwen3-coder", 262_144),
+    ("qwen2.5-coder", 32_768),
+    ("glm-4.5", 131_072),
+    ("glm-4.6", 200_000),
+    ("kimi-k2", 131_072),
+    ("llama3", 8_192),
+    ("llama3.1", 131_072),
+    ("mistral", 32_768),
+];
+
+/// Context window of `model` from the registry, if known.
+///
+/// Routing prefixes such as `openai/` or `anthropic/` are ignored.
+pub fn known_context_window(model: &str) -> Option<u32> {
+    let name = model
+        .rsplit('/')
+        .next()
+        .unwrap_or(model)
+        .to_ascii_lowercase();
+    KNOWN_CONTEXT_WINDOWS
+        .iter()
+        .filter(|(prefix, _)| name.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, window)| *window)
+}
+
+/// Prompt tokens that fit in `window` while leaving room for the reply.
+pub fn prompt_budget(window: u32) -> u32 {
+    let reserve = (window / 10).clamp(1_024, 32_000);
+    window.saturating_sub(reserve)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_prefix_wins() {
+        assert_eq!(known_context_window("gpt-4"), Some(8_192));
+        assert_eq!(known_context_window("gpt-4o-mini"), Some(128_000));
+        assert_eq!(known_context_window("gpt-4.1-mini"), Some(1_047_576));
+        assert_eq!(known_context_window("o1-mini"), Some(128_000));
+        assert_eq!(known_context_window("claude-sonnet-4-5"), Some(200_000));
+    }
+
+    #[test]
+    fn routing_prefix_and_case_are_ignored() {
+        assert_eq!(known_context_window("openai/GPT-5-codex"), Some(400_000));
+        assert_eq!(known_context_window("my-finetune"), None);
+    }
+
+    #[test]
+    fn budget_reserves_room_for_the_reply() {
+        assert_eq!(prompt_budget(200_000), 180_000);
+        assert_eq!(prompt_budget(8_192), 7_168);
+        assert_eq!(prompt_budget(1_000_000), 968_000);
+    }
+}
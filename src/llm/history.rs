@@ -30,6 +30,8 @@ impl ChatHistory {
                     content: Some(sys),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
                 },
             );
             self.system_added = true;
@@ -43,6 +45,8 @@ impl ChatHistory {
             content: Some(content.into()),
             tool_calls: vec![],
             tool_call_id: None,
+            reasoning: None,
+            images: vec![],
         });
         self.trim_to_max();
     }
@@ -54,6 +58,8 @@ impl ChatHistory {
             content: Some(content.into()),
             tool_calls: vec![],
             tool_call_id: None,
+            reasoning: None,
+            images: vec![],
         });
         self.trim_to_max();
     }
@@ -1,29 +1,55 @@
+mod anthropic;
+mod capabilities;
+mod cassette;
 mod chat_with_tools;
 pub mod client_core;
 mod compact_history;
+mod context_window;
 mod history;
+mod ollama;
+mod prompt_cache;
+mod prompt_tools;
+pub mod provider;
+mod responses;
+pub mod retry;
 mod stream;
 mod stream_tools;
+mod structured;
 mod symbol_edit;
+mod tokenizer;
 mod tool_def;
 mod tool_execution;
 mod tool_runtime;
 pub mod types;
+mod vision;
 
 use reqwest::StatusCode;
 
+pub use anthropic::AnthropicClient;
+pub use capabilities::{ModelCapabilities, probe_model_capabilities};
+pub use cassette::{Cassette, CassetteConfig, CassetteMode};
 pub use chat_with_tools::*;
 pub use client_core::*;
+pub use context_window::{DEFAULT_CONTEXT_WINDOW, known_context_window, prompt_budget};
 pub use history::*;
+pub use ollama::OllamaClient;
+pub use provider::{ChatStream, LlmProvider, build_fallback_provider, build_provider};
+pub use responses::ResponsesClient;
+pub use structured::{ResponseFormat, ResponseSchema, from_payload};
 pub use symbol_edit::*;
+pub use tokenizer::{count_tokens, estimate_request_tokens};
 pub use tool_def::*;
 pub use types::*;
+pub use vision::{
+    ensure_vision, image_media_type, image_references, known_vision_support, load_image,
+    png_from_rgba,
+};
 
-pub use tool_execution::{run_agent_loop, run_agent_streaming_once};
+pub use tool_execution::run_agent_loop;
 
 // Re-export the compact_history module components
 pub use compact_history::{
-    CompactMetadata, CompactParams, CompactResult, compact_conversation_history,
+    CompactMetadata, CompactParams, CompactResult, compact_conversation_history, compact_to_fit,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
@@ -44,24 +70,69 @@ pub enum LlmErrorKind {
     Cancelled,
     #[error("context length exceeded")]
     ContextLengthExceeded,
+    #[error("provider circuit open")]
+    CircuitOpen,
     #[error("unknown error")]
     Unknown,
 }
 
-#[allow(dead_code)]
+impl LlmErrorKind {
+    /// Errors that outlast retries during a provider outage, where switching to
+    /// a fallback model can keep the task going.
+    pub fn warrants_fallback(&self) -> bool {
+        matches!(
+            self,
+            Self::RateLimited | Self::Server | Self::Timeout | Self::CircuitOpen
+        )
+    }
+}
+
+/// Error for a non-success HTTP response, tagged with its [`LlmErrorKind`] and
+/// any `Retry-After` hint.
+pub(crate) fn status_error(
+    label: &str,
+    status: StatusCode,
+    retry_after: Option<std::time::Duration>,
+    body: &str,
+) -> anyhow::Error {
+    // OpenAI-compatible servers reject oversized prompts with a plain 400
+    let context_length = status == StatusCode::BAD_REQUEST
+        && serde_json::from_str::<serde_json::Value>(body)
+            .is_ok_and(|json| json["error"]["code"].as_str() == Some("context_length_exceeded"));
+    let kind = if context_length {
+        LlmErrorKind::ContextLengthExceeded
+    } else {
+        classify_status(status)
+    };
+    let mut err = anyhow::Error::new(kind);
+    if let Some(wait) = retry_after {
+        err = err.context(retry::RetryAfter(wait));
+    }
+    err.context(format!("{label}: {status} - {body}"))
+}
+
+fn classify_status(status: StatusCode) -> LlmErrorKind {
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        LlmErrorKind::RateLimited
+    } else if status == StatusCode::REQUEST_TIMEOUT {
+        LlmErrorKind::Timeout
+    } else if status.is_server_error() {
+        LlmErrorKind::Server
+    } else if status.is_client_error() {
+        LlmErrorKind::Client
+    } else {
+        LlmErrorKind::Unknown
+    }
+}
+
 pub fn classify_error(status: Option<StatusCode>, err: &anyhow::Error) -> LlmErrorKind {
     if let Some(e) = err.downcast_ref::<LlmErrorKind>() {
         return e.clone();
     }
     if let Some(st) = status {
-        if st == StatusCode::TOO_MANY_REQUESTS {
-            return LlmErrorKind::RateLimited;
-        }
-        if st.is_server_error() {
-            return LlmErrorKind::Server;
-        }
-        if st.is_client_error() {
-            return LlmErrorKind::Client;
+        let kind = classify_status(st);
+        if kind != LlmErrorKind::Unknown {
+            return kind;
         }
     }
     if let Some(e) = err.downcast_ref::<reqwest::Error>() {
@@ -0,0 +1,602 @@
+//! Native Ollama `/api/chat` backend.
+//!
+//! Ollama's OpenAI-compatible endpoint ignores `num_ctx`, which silently truncates
+//! long agent conversations to the server default context. The native endpoint lets
+//! us pass the probed context window and read exact prompt/eval token counts.
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::config::LlmConfig;
+use crate::cost::{RequestUsage, UsageLog};
+use crate::llm::LlmErrorKind;
+use crate::llm::capabilities::server_root;
+use crate::llm::chat_with_tools::ChoiceMessageWithTools;
+use crate::llm::provider::{ChatStream, LlmProvider};
+use crate::llm::retry::{CircuitBreaker, Retrier, RetryPolicy, breaker_for};
+use crate::llm::stream::{ToolCallDelta, ToolCallFunctionDelta};
+use crate::llm::structured::{ResponseSchema, extract_json};
+use crate::llm::types::{ChatMessage, ChoiceMessage, ToolCall, ToolCallFunction, ToolDef};
+
+#[derive(Debug, Clone)]
+pub struct OllamaClient {
+    pub base_url: String,
+    pub(crate) inner: reqwest::Client,
+    pub llm_cfg: LlmConfig,
+    /// Context window passed as `options.num_ctx`; server default when unset
+    pub num_ctx: Option<u32>,
+    /// Tracks total tokens used by this client
+    pub tokens_used: Arc<AtomicU32>,
+    /// Tracks prompt tokens used by this client (for header display)
+    pub prompt_tokens_used: Arc<AtomicU32>,
+    /// Per-request usage for cost accounting
+    pub usage_log: UsageLog,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct OllamaChatRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ToolDef>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<OllamaOptions>,
+    /// JSON schema the reply must follow
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct OllamaOptions {
+    num_ctx: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct OllamaMessage {
+    role: String,
+    #[serde(default)]
+    content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<OllamaToolCall>,
+    /// Base64-encoded images for vision models
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+    /// Reasoning of thinking models; never sent back
+    #[serde(default, skip_serializing)]
+    thinking: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OllamaToolCall {
+    function: OllamaToolFunction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OllamaToolFunction {
+    name: String,
+    /// Ollama sends and expects arguments as a JSON object, not a string
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct OllamaChatResponse {
+    #[serde(default)]
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    prompt_eval_count: Option<u32>,
+    #[serde(default)]
+    eval_count: Option<u32>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+impl OllamaClient {
+    pub fn new(base_url: impl Into<String>) -> Result<Self> {
+        let inner = reqwest::Client::builder().build()?;
+        Ok(Self {
+            base_url: base_url.into(),
+            inner,
+            llm_cfg: LlmConfig::default(),
+            num_ctx: None,
+            tokens_used: Arc::new(AtomicU32::new(0)),
+            prompt_tokens_used: Arc::new(AtomicU32::new(0)),
+            usage_log: UsageLog::default(),
+        })
+    }
+
+    pub fn with_llm_config(mut self, cfg: LlmConfig) -> Self {
+        let builder = reqwest::Client::builder()
+            .connect_timeout(Duration::from_millis(cfg.connect_timeout_ms))
+            .timeout(Duration::from_millis(cfg.timeout_ms))
+            .read_timeout(Duration::from_millis(cfg.timeout_ms));
+        if let Ok(c) = builder.build() {
+            self.inner = c;
+        }
+        self.llm_cfg = cfg;
+        self
+    }
+
+    pub fn with_num_ctx(mut self, num_ctx: Option<u32>) -> Self {
+        self.num_ctx = num_ctx;
+        self
+    }
+
+    /// Send a non-streaming request, retrying under the configured policy,
+    /// and decode the assistant message.
+    async fn complete(
+        &self,
+        model: &str,
+        req: &OllamaChatRequest,
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChoiceMessageWithTools> {
+        let cancel_token = cancel.unwrap_or_default();
+        Retrier::new(
+            RetryPolicy::from_config(&self.llm_cfg),
+            self.circuit_breaker(),
+            "ollama_chat",
+        )
+        .run(&cancel_token, || self.attempt(model, req, &cancel_token))
+        .await
+    }
+
+    async fn attempt(
+        &self,
+        model: &str,
+        req: &OllamaChatRequest,
+        cancel_token: &CancellationToken,
+    ) -> Result<ChoiceMessageWithTools> {
+        let resp = self.send(req, cancel_token).await?;
+        let text = tokio::select! {
+            biased;
+            _ = cancel_token.cancelled() => {
+                warn!("ollama request cancelled during body read");
+                return Err(anyhow!(LlmErrorKind::Cancelled));
+            }
+            res = resp.text() => res?,
+        };
+        debug!(response_body=%text, "ollama chat response");
+        let body: OllamaChatResponse = serde_json::from_str(&text)?;
+        if let Some(err) = body.error {
+            return Err(anyhow!("ollama chat error: {err}"));
+        }
+        self.record_usage(model, &body);
+        let msg = body.message.ok_or_else(|| anyhow!("no message"))?;
+        Ok(into_choice_message(msg))
+    }
+
+    pub(crate) fn endpoint(&self) -> String {
+        format!("{}/api/chat", server_root(&self.base_url))
+    }
+
+    fn build_request(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDef],
+        stream: bool,
+    ) -> OllamaChatRequest {
+        let messages = messages
+            .into_iter()
+            .map(|m| OllamaMessage {
+                role: m.role,
+                content: m.content.unwrap_or_default(),
+                tool_calls: m
+                    .tool_calls
+                    .into_iter()
+                    .map(|tc| OllamaToolCall {
+                        function: OllamaToolFunction {
+                            arguments: serde_json::from_str(&tc.function.arguments)
+                                .unwrap_or_else(|_| serde_json::json!({})),
+                            name: tc.function.name,
+                        },
+                    })
+                    .collect(),
+                images: m.images.into_iter().map(|image| image.data).collect(),
+                ..Default::default()
+            })
+            .collect();
+        OllamaChatRequest {
+            model: model.to_string(),
+            messages,
+            tools: tools.to_vec(),
+            stream,
+            options: self.num_ctx.map(|num_ctx| OllamaOptions { num_ctx }),
+            format: None,
+        }
+    }
+
+    fn record_usage(&self, model: &str, resp: &OllamaChatResponse) {
+        if let Some(prompt) = resp.prompt_eval_count {
+            let output = resp.eval_count.unwrap_or(0);
+            self.prompt_tokens_used.store(prompt, Ordering::Relaxed);
+            self.tokens_used.store(prompt + output, Ordering::Relaxed);
+            self.usage_log.record(RequestUsage {
+                model: model.to_string(),
+                input_tokens: prompt,
+                output_tokens: output,
+                cached_tokens: 0,
+            });
+        }
+    }
+
+    async fn send(
+        &self,
+        req: &OllamaChatRequest,
+        cancel_token: &CancellationToken,
+    ) -> Result<reqwest::Response> {
+        let timeout_duration = Duration::from_millis(self.llm_cfg.timeout_ms);
+        let fut = tokio::time::timeout(
+            timeout_duration,
+            self.inner.post(self.endpoint()).json(req).send(),
+        );
+        let resp = tokio::select! {
+            biased;
+            _ = cancel_token.cancelled() => {
+                warn!("ollama request cancelled before send");
+                return Err(anyhow!(LlmErrorKind::Cancelled));
+            }
+            res = fut => match res {
+                Ok(Ok(resp)) => resp,
+                Ok(Err(e)) => return Err(anyhow::Error::new(e).context("send ollama chat request")),
+                Err(_) => return Err(anyhow!(LlmErrorKind::Timeout)),
+            },
+        };
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let retry_after = crate::llm::retry::retry_after(resp.headers());
+            let text = resp.text().await.unwrap_or_default().trim().to_owned();
+            error!(status=%status.as_u16(), body=%text, "ollama chat non-success status");
+            return Err(crate::llm::status_error(
+                "ollama chat error",
+                status,
+                retry_after,
+                &text,
+            ));
+        }
+        Ok(resp)
+    }
+}
+
+fn into_choice_message(msg: OllamaMessage) -> ChoiceMessageWithTools {
+    ChoiceMessageWithTools {
+        role: "assistant".into(),
+        content: if msg.content.is_empty() {
+            None
+        } else {
+            Some(msg.content)
+        },
+        tool_calls: msg
+            .tool_calls
+            .into_iter()
+            .enumerate()
+            .map(|(i, tc)| ToolCall {
+                // Ollama does not assign call ids; synthesize stable ones per response
+                id: Some(format!("call_{i}")),
+                r#type: "function".into(),
+                function: ToolCallFunction {
+                    name: tc.function.name,
+                    arguments: tc.function.arguments.to_string(),
+                },
+            })
+            .collect(),
+        reasoning: if msg.thinking.is_empty() {
+            None
+        } else {
+            Some(msg.thinking)
+        },
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaClient {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    async fn chat_once(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChoiceMessage> {
+        let msg = self.chat_tools_once(model, messages, &[], cancel).await?;
+        Ok(ChoiceMessage {
+            role: msg.role,
+            content: msg.content.unwrap_or_default(),
+        })
+    }
+
+    async fn chat_tools_once(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDef],
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChoiceMessageWithTools> {
+        let req = self.build_request(model, messages, tools, false);
+        self.complete(model, &req, cancel).await
+    }
+
+    async fn chat_structured(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        schema: &ResponseSchema,
+        cancel: Option<CancellationToken>,
+    ) -> Result<Value> {
+        let mut req = self.build_request(model, messages, &[], false);
+        req.format = Some(schema.schema.clone());
+        let msg = self.complete(model, &req, cancel).await?;
+        msg.content
+            .as_deref()
+            .and_then(extract_json)
+            .ok_or_else(|| anyhow!("model did not return a `{}` result", schema.name))
+    }
+
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDef],
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChatStream> {
+        let cancel_token = cancel.unwrap_or_default();
+        let req = self.build_request(model, messages, tools, true);
+        let resp = self.send(&req, &cancel_token).await?;
+        let mut byte_stream = resp.bytes_stream();
+        let client = self.clone();
+        let model = model.to_string();
+
+        // Ollama streams newline-delimited JSON objects rather than SSE
+        let stream = async_stream::try_stream! {
+            let mut buf = Vec::<u8>::new();
+            let mut tool_index = 0usize;
+            loop {
+                let chunk = tokio::select! {
+                    biased;
+                    _ = cancel_token.cancelled() => {
+                        info!("ollama stream cancelled");
+                        Err(anyhow!(LlmErrorKind::Cancelled))
+                    }
+                    res = byte_stream.next() => match res {
+                        Some(Ok(bytes)) => Ok(bytes),
+                        Some(Err(e)) => Err(anyhow::Error::new(e).context("byte stream read error")),
+                        None => break,
+                    },
+                }?;
+                buf.extend_from_slice(&chunk);
+                while let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = buf.drain(..=pos).collect();
+                    let line = String::from_utf8_lossy(&line);
+                    let line = line.trim();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    let event: OllamaChatResponse = match serde_json::from_str(line) {
+                        Ok(ev) => ev,
+                        Err(e) => {
+                            warn!(line, err=%e, "failed to parse ollama stream line");
+                            continue;
+                        }
+                    };
+                    if let Some(err) = &event.error {
+                        Err(anyhow!("ollama chat error: {err}"))?;
+                    }
+                    if event.done {
+                        client.record_usage(&model, &event);
+                    }
+                    let Some(msg) = event.message else {
+                        continue;
+                    };
+                    if !msg.thinking.is_empty() {
+                        yield format!("__REASONING_DELTA__:{}", msg.thinking);
+                    }
+                    if !msg.content.is_empty() {
+                        yield msg.content;
+                    }
+                    // Tool calls arrive complete in a single chunk
+                    for tc in msg.tool_calls {
+                        let delta = ToolCallDelta {
+                            index: Some(tool_index),
+                            id: Some(format!("call_{tool_index}")),
+                            kind: Some("function".into()),
+                            function: Some(ToolCallFunctionDelta {
+                                name: tc.function.name,
+                                arguments: tc.function.arguments.to_string(),
+                            }),
+                        };
+                        tool_index += 1;
+                        yield format!(
+                            "__TOOL_CALLS_DELTA__:{}",
+                            serde_json::to_string(&[delta]).unwrap_or_else(|_| "[]".into())
+                        );
+                    }
+                }
+            }
+        };
+
+        Ok(Box::pin(stream))
+    }
+
+    fn with_reasoning_disabled(&self) -> Arc<dyn LlmProvider> {
+        Arc::new(self.clone())
+    }
+
+    fn get_tokens_used(&self) -> u32 {
+        self.tokens_used.load(Ordering::Relaxed)
+    }
+
+    fn set_tokens(&self, tokens: u32) {
+        self.tokens_used.store(tokens, Ordering::Relaxed);
+    }
+
+    fn get_prompt_tokens_used(&self) -> u32 {
+        self.prompt_tokens_used.load(Ordering::Relaxed)
+    }
+
+    fn set_prompt_tokens(&self, tokens: u32) {
+        self.prompt_tokens_used.store(tokens, Ordering::Relaxed);
+    }
+
+    fn usage_log(&self) -> &UsageLog {
+        &self.usage_log
+    }
+
+    fn circuit_breaker(&self) -> Arc<CircuitBreaker> {
+        breaker_for(&self.base_url, &self.llm_cfg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httptest::{Expectation, Server, matchers::*, responders::*};
+    use serde_json::json;
+
+    fn user(content: &str) -> ChatMessage {
+        ChatMessage {
+            role: "user".into(),
+            content: Some(content.into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn chat_tools_once_passes_num_ctx_and_maps_tool_calls() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/api/chat"),
+                request::body(json_decoded(eq(json!({
+                    "model": "llama3.1",
+                    "messages": [{"role": "user", "content": "hi"}],
+                    "stream": false,
+                    "options": {"num_ctx": 16384}
+                })))),
+            ])
+            .respond_with(json_encoded(json!({
+                "model": "llama3.1",
+                "message": {
+                    "role": "assistant",
+                    "content": "",
+                    "tool_calls": [{"function": {"name": "fs_read", "arguments": {"path": "a.rs"}}}]
+                },
+                "done": true,
+                "prompt_eval_count": 30,
+                "eval_count": 12
+            }))),
+        );
+
+        let client = OllamaClient::new(server.url_str("/v1"))
+            .unwrap()
+            .with_num_ctx(Some(16384));
+        let msg = client
+            .chat_tools_once("llama3.1", vec![user("hi")], &[], None)
+            .await
+            .unwrap();
+        assert!(msg.content.is_none());
+        assert_eq!(msg.tool_calls[0].id.as_deref(), Some("call_0"));
+        assert_eq!(msg.tool_calls[0].function.arguments, r#"{"path":"a.rs"}"#);
+        assert_eq!(client.get_prompt_tokens_used(), 30);
+        assert_eq!(client.get_tokens_used(), 42);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_are_retried() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/chat"))
+                .times(2)
+                .respond_with(cycle![
+                    status_code(429).insert_header("retry-after", "0"),
+                    json_encoded(json!({
+                        "model": "llama3.1",
+                        "message": {"role": "assistant", "content": "ok"},
+                        "done": true
+                    })),
+                ]),
+        );
+        let client = OllamaClient::new(server.url_str("")).unwrap();
+        let msg = client
+            .chat_once("llama3.1", vec![user("hi")], None)
+            .await
+            .unwrap();
+        assert_eq!(msg.content, "ok");
+    }
+
+    #[tokio::test]
+    async fn chat_stream_reads_ndjson() {
+        let body = [
+            json!({"message": {"role": "assistant", "content": "Hel"}, "done": false}),
+            json!({"message": {"role": "assistant", "content": "lo"}, "done": false}),
+            json!({"message": {"role": "assistant", "content": ""}, "done": true,
+                   "prompt_eval_count": 5, "eval_count": 2}),
+        ]
+        .iter()
+        .map(|v| format!("{v}\n"))
+        .collect::<String>();
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/api/chat"))
+                .respond_with(status_code(200).body(body)),
+        );
+
+        let client = OllamaClient::new(server.url_str("")).unwrap();
+        let mut stream = client
+            .chat_stream("llama3.1", vec![user("hi")], &[], None)
+            .await
+            .unwrap();
+        let mut text = String::new();
+        while let Some(chunk) = stream.next().await {
+            text.push_str(&chunk.unwrap());
+        }
+        assert_eq!(text, "Hello");
+        assert_eq!(client.get_tokens_used(), 7);
+    }
+
+    #[test]
+    fn assistant_tool_call_arguments_are_sent_as_objects() {
+        let client = OllamaClient::new("http://localhost:11434").unwrap();
+        let req = client.build_request(
+            "m",
+            vec![ChatMessage {
+                role: "assistant".into(),
+                content: None,
+                tool_calls: vec![ToolCall {
+                    id: Some("call_0".into()),
+                    r#type: "function".into(),
+                    function: ToolCallFunction {
+                        name: "fs_list".into(),
+                        arguments: r#"{"path":"."}"#.into(),
+                    },
+                }],
+                tool_call_id: None,
+                reasoning: None,
+                images: vec![],
+            }],
+            &[],
+            false,
+        );
+        let body = serde_json::to_value(&req).unwrap();
+        assert_eq!(
+            body["messages"][0]["tool_calls"][0]["function"]["arguments"],
+            json!({"path": "."})
+        );
+        assert_eq!(client.endpoint(), "http://localhost:11434/api/chat");
+    }
+}
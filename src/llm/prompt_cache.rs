@@ -0,0 +1,141 @@
+//! Explicit prompt cache breakpoints.
+//!
+//! Agent sessions resend the same system prompt and tool schemas on every turn.
+//! Backends with explicit caching (Anthropic, and proxies forwarding to it)
+//! only reuse a prefix that ends at a `cache_control` marker, so requests get
+//! up to three: on the system prompt, on the last tool definition and on the
+//! last message, which the next turn resends unchanged as its prefix.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::config::PromptCacheMode;
+
+/// `cache_control` marker attached to a block.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheControl {
+    #[serde(rename = "type")]
+    pub kind: CacheKind,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheKind {
+    #[default]
+    Ephemeral,
+}
+
+/// Whether a request for `model` should carry cache breakpoints.
+///
+/// In auto mode only Anthropic models are marked; OpenAI, Gemini and DeepSeek
+/// cache prefixes on their own and may reject unknown fields.
+pub fn is_enabled(mode: PromptCacheMode, model: &str) -> bool {
+    match mode {
+        PromptCacheMode::On => true,
+        PromptCacheMode::Off => false,
+        PromptCacheMode::Auto => model.to_ascii_lowercase().contains("claude"),
+    }
+}
+
+/// Add breakpoints to an OpenAI-shaped chat completions body.
+///
+/// String contents are rewritten as a single text part so the marker has a
+/// block to attach to.
+pub fn mark_chat_completions_body(body: &mut Value) {
+    if let Some(tool) = body
+        .get_mut("tools")
+        .and_then(Value::as_array_mut)
+        .and_then(|tools| tools.last_mut())
+        .and_then(Value::as_object_mut)
+    {
+        tool.insert("cache_control".into(), json!(CacheControl::default()));
+    }
+
+    let Some(messages) = body.get_mut("messages").and_then(Value::as_array_mut) else {
+        return;
+    };
+    let is_system = |m: &Value| m.get("role").and_then(Value::as_str) == Some("system");
+    if let Some(system) = messages.iter().rposition(is_system) {
+        mark_message(&mut messages[system]);
+    }
+    if let Some(last) = messages.iter().rposition(|m| !is_system(m) && has_text(m)) {
+        mark_message(&mut messages[last]);
+    }
+}
+
+fn has_text(msg: &Value) -> bool {
+    msg.get("content")
+        .and_then(Value::as_str)
+        .is_some_and(|s| !s.is_empty())
+}
+
+fn mark_message(msg: &mut Value) {
+    let Some(text) = msg.get("content").and_then(Value::as_str) else {
+        return;
+    };
+    let part = json!([{
+        "type": "text",
+        "text": text,
+        "cache_control": CacheControl::default(),
+    }]);
+    msg["content"] = part;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_mode_marks_anthropic_models_only() {
+        assert!(is_enabled(PromptCacheMode::Auto, "claude-sonnet-4-5"));
+        assert!(is_enabled(
+            PromptCacheMode::Auto,
+            "anthropic/Claude-3.7-sonnet"
+        ));
+        assert!(!is_enabled(PromptCacheMode::Auto, "gpt-4o"));
+        assert!(is_enabled(PromptCacheMode::On, "gpt-4o"));
+        assert!(!is_enabled(PromptCacheMode::Off, "claude-opus-4"));
+    }
+
+    #[test]
+    fn chat_completions_body_gets_three_breakpoints() {
+        let mut body = json!({
+            "model": "anthropic/claude-sonnet-4",
+            "messages": [
+                {"role": "system", "content": "sys"},
+                {"role": "user", "content": "hi"},
+                {"role": "assistant", "content": "", "tool_calls": [{"id": "1"}]},
+                {"role": "tool", "content": "result", "tool_call_id": "1"},
+                {"role": "assistant", "tool_calls": [{"id": "2"}]}
+            ],
+            "tools": [{"type": "function"}, {"type": "function"}]
+        });
+        mark_chat_completions_body(&mut body);
+
+        let marker = json!({"type": "ephemeral"});
+        assert_eq!(body["tools"][1]["cache_control"], marker);
+        assert!(body["tools"][0].get("cache_control").is_none());
+        assert_eq!(body["messages"][0]["content"][0]["text"], "sys");
+        assert_eq!(body["messages"][0]["content"][0]["cache_control"], marker);
+        assert_eq!(body["messages"][1]["content"], "hi");
+        assert_eq!(body["messages"][3]["content"][0]["cache_control"], marker);
+        assert!(body["messages"][4].get("content").is_none());
+    }
+
+    #[test]
+    fn cached_tokens_are_read_from_each_usage_shape() {
+        let usage = |v: Value| serde_json::from_value::<crate::llm::types::Usage>(v).unwrap();
+        let base = json!({"prompt_tokens": 100, "completion_tokens": 5, "total_tokens": 105});
+
+        assert_eq!(usage(base.clone()).cached_tokens(), 0);
+        let mut openai = base.clone();
+        openai["prompt_tokens_details"] = json!({"cached_tokens": 80});
+        assert_eq!(usage(openai).cached_tokens(), 80);
+        let mut proxy = base.clone();
+        proxy["cache_read_input_tokens"] = json!(60);
+        assert_eq!(usage(proxy).cached_tokens(), 60);
+        let mut deepseek = base;
+        deepseek["prompt_cache_hit_tokens"] = json!(40);
+        assert_eq!(usage(deepseek).cached_tokens(), 40);
+    }
+}
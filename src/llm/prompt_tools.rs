@@ -0,0 +1,618 @@
+//! Prompt-based tool calling for models that ignore the `tools` request field.
+//!
+//! Tool definitions are rendered into the system prompt and the model is asked
+//! to answer with `<tool_call>{"name": ..., "arguments": {...}}</tool_call>`
+//! blocks. Replies are parsed back into [`ToolCall`]s so the agent loop keeps
+//! working with the usual OpenAI-shaped history; that history is converted to
+//! plain text turns right before each request.
+
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use tracing::{debug, warn};
+
+use crate::config::ToolCallMode;
+use crate::llm::chat_with_tools::ChoiceMessageWithTools;
+use crate::llm::types::{ChatMessage, ToolCall, ToolCallFunction, ToolDef};
+
+const CALL_OPEN: &str = "<tool_call>";
+const CALL_CLOSE: &str = "</tool_call>";
+
+/// Phrases that announce an action when they end a reply.
+const INTENT_PHRASES: &[&str] = &[
+    "let me ",
+    "let's ",
+    "i'll ",
+    "i will ",
+    "i'm going to ",
+    "i am going to ",
+    "next, i ",
+    "now i ",
+];
+
+/// Tool call mode auto-detection settled on per model.
+static DETECTED_MODES: OnceLock<Mutex<HashMap<String, ToolCallMode>>> = OnceLock::new();
+
+fn detected_modes() -> &'static Mutex<HashMap<String, ToolCallMode>> {
+    DETECTED_MODES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Remember that `model` needs prompt-based tool calling for the rest of the process.
+pub fn mark_prompt_mode(model: &str) {
+    if let Ok(mut modes) = detected_modes().lock()
+        && modes.insert(model.to_string(), ToolCallMode::Prompt) != Some(ToolCallMode::Prompt)
+    {
+        warn!(
+            model,
+            "model does not use native tool calls; switching to prompt-based tool calling"
+        );
+    }
+}
+
+/// Remember that `model` answered with native tool calls, so its prose
+/// replies need no second look.
+fn mark_native_mode(model: &str) {
+    if let Ok(mut modes) = detected_modes().lock() {
+        modes
+            .entry(model.to_string())
+            .or_insert(ToolCallMode::Native);
+    }
+}
+
+/// Resolve the configured mode, taking earlier auto-detection into account.
+pub fn effective_mode(configured: ToolCallMode, model: &str) -> ToolCallMode {
+    if configured != ToolCallMode::Auto {
+        return configured;
+    }
+    detected_modes()
+        .lock()
+        .ok()
+        .and_then(|modes| modes.get(model).copied())
+        .unwrap_or(configured)
+}
+
+/// Messages and native tool definitions to send for `mode`.
+pub fn prepare_request(
+    messages: Vec<ChatMessage>,
+    tools: &[ToolDef],
+    mode: ToolCallMode,
+) -> (Vec<ChatMessage>, &[ToolDef]) {
+    if mode == ToolCallMode::Prompt && !tools.is_empty() {
+        (to_prompt_messages(&messages, tools), &[])
+    } else {
+        (messages, tools)
+    }
+}
+
+/// Read tool calls written as text into `msg.tool_calls`.
+///
+/// In auto mode a reply that carries such calls switches `mode` (and later
+/// loops for the same model) to prompt mode, while one with native tool calls
+/// settles the model on native mode.
+pub fn extract_text_tool_calls(
+    model: &str,
+    msg: &mut ChoiceMessageWithTools,
+    tools: &[ToolDef],
+    mode: &mut ToolCallMode,
+) {
+    if *mode == ToolCallMode::Auto && !msg.tool_calls.is_empty() {
+        mark_native_mode(model);
+    }
+    if *mode == ToolCallMode::Native || !msg.tool_calls.is_empty() || tools.is_empty() {
+        return;
+    }
+    let Some(text) = msg.content.as_deref() else {
+        return;
+    };
+    let (content, tool_calls) = parse_tool_calls(text, tools);
+    if *mode == ToolCallMode::Auto {
+        if tool_calls.is_empty() {
+            return;
+        }
+        mark_prompt_mode(model);
+        *mode = ToolCallMode::Prompt;
+    }
+    msg.content = content;
+    msg.tool_calls = tool_calls;
+}
+
+/// Whether an auto mode reply without tool calls reads as if the model meant to
+/// act, e.g. it names a tool or ends with "Let me read the file.", so the turn
+/// is worth asking again with prompt-based tool calling.
+pub fn looks_like_missed_tool_call(
+    msg: &ChoiceMessageWithTools,
+    tools: &[ToolDef],
+    mode: ToolCallMode,
+) -> bool {
+    if mode != ToolCallMode::Auto || tools.is_empty() || !msg.tool_calls.is_empty() {
+        return false;
+    }
+    let Some(text) = msg.content.as_deref().map(str::trim) else {
+        return false;
+    };
+    if tools.iter().any(|t| text.contains(&t.function.name)) {
+        return true;
+    }
+    let last = text.rsplit("\n\n").next().unwrap_or(text).to_lowercase();
+    !last.contains("let me know") && INTENT_PHRASES.iter().any(|p| last.contains(p))
+}
+
+/// Length of the prefix of `text` that can be shown while streaming in prompt
+/// mode: everything before the first `<tool_call>` tag, holding back a
+/// trailing fragment that may be the start of one.
+pub fn displayable_len(text: &str) -> usize {
+    if let Some(pos) = text.find(CALL_OPEN) {
+        return pos;
+    }
+    (1..CALL_OPEN.len())
+        .rev()
+        .find(|&n| text.ends_with(&CALL_OPEN[..n]))
+        .map_or(text.len(), |n| text.len() - n)
+}
+
+/// Render the tool calling convention and tool definitions for the system prompt.
+pub fn render_tools_prompt(tools: &[ToolDef]) -> String {
+    let mut out = String::from(
+        "# Tools\n\n\
+         You can call the tools listed below. To call a tool, write a block of exactly this form:\n\n\
+         <tool_call>\n\
+         {\"name\": \"<tool name>\", \"arguments\": {<arguments as a JSON object>}}\n\
+         </tool_call>\n\n\
+         You may write several <tool_call> blocks in one reply. Stop after your tool calls; \
+         their results are sent back to you inside <tool_result> blocks. Never write \
+         <tool_result> blocks yourself. When no tool is needed, answer normally without any \
+         <tool_call> block.\n\n\
+         ## Available tools\n",
+    );
+    for tool in tools {
+        let params = serde_json::to_string(&tool.function.parameters).unwrap_or_default();
+        out.push_str(&format!(
+            "\n### {}\n{}\nParameters (JSON Schema): {}\n",
+            tool.function.name,
+            tool.function.description.trim(),
+            params
+        ));
+    }
+    out
+}
+
+/// Convert OpenAI-shaped history into plain turns for a model without native tools.
+///
+/// The tool prompt is appended to the system message, assistant tool calls are
+/// written back as `<tool_call>` blocks and consecutive tool results are merged
+/// into a single user turn.
+pub fn to_prompt_messages(messages: &[ChatMessage], tools: &[ToolDef]) -> Vec<ChatMessage> {
+    let tools_prompt = render_tools_prompt(tools);
+    let mut out: Vec<ChatMessage> = Vec::with_capacity(messages.len() + 1);
+    let mut call_names: HashMap<String, String> = HashMap::new();
+    let mut injected = false;
+
+    for msg in messages {
+        match msg.role.as_str() {
+            "system" if !injected => {
+                let content = match msg.content.as_deref() {
+                    Some(c) if !c.is_empty() => format!("{c}\n\n{tools_prompt}"),
+                    _ => tools_prompt.clone(),
+                };
+                out.push(text_message("system", content));
+                injected = true;
+            }
+            "assistant" if !msg.tool_calls.is_empty() => {
+                let mut content = msg.content.clone().unwrap_or_default();
+                for tc in &msg.tool_calls {
+                    if let Some(id) = &tc.id {
+                        call_names.insert(id.clone(), tc.function.name.clone());
+                    }
+                    let arguments = serde_json::from_str::<Value>(&tc.function.arguments)
+                        .unwrap_or_else(|_| Value::String(tc.function.arguments.clone()));
+                    if !content.is_empty() {
+                        content.push('\n');
+                    }
+                    content.push_str(&format!(
+                        "{CALL_OPEN}\n{}\n{CALL_CLOSE}",
+                        json!({ "name": tc.function.name, "arguments": arguments })
+                    ));
+                }
+                out.push(text_message("assistant", content));
+            }
+            "tool" => {
+                let name = msg
+                    .tool_call_id
+                    .as_ref()
+                    .and_then(|id| call_names.get(id))
+                    .map(String::as_str)
+                    .unwrap_or("unknown");
+                let block = format!(
+                    "<tool_result name=\"{name}\">\n{}\n</tool_result>",
+                    msg.content.as_deref().unwrap_or_default()
+                );
+                // Merge with the previous result so user and assistant turns keep alternating
+                match out.last_mut() {
+                    Some(prev)
+                        if prev.role == "user"
+                            && prev
+                                .content
+                                .as_deref()
+                                .is_some_and(|c| c.ends_with("</tool_result>")) =>
+                    {
+                        let prev_content = prev.content.get_or_insert_with(String::new);
+                        prev_content.push('\n');
+                        prev_content.push_str(&block);
+                    }
+                    _ => out.push(text_message("user", block)),
+                }
+            }
+            _ => out.push(text_message(
+                &msg.role,
+                msg.content.clone().unwrap_or_default(),
+            )),
+        }
+    }
+
+    if !injected {
+        out.insert(0, text_message("system", tools_prompt));
+    }
+    out
+}
+
+/// Extract tool calls from assistant text.
+///
+/// `<tool_call>` blocks are always honoured. Without them, a reply that is
+/// nothing but a JSON call object (optionally fenced) is accepted when it names
+/// one of `tools`. Returns the remaining text and the parsed calls.
+pub fn parse_tool_calls(text: &str, tools: &[ToolDef]) -> (Option<String>, Vec<ToolCall>) {
+    let mut calls = Vec::new();
+    let mut rest = String::new();
+    let mut cursor = text;
+
+    while let Some(start) = cursor.find(CALL_OPEN) {
+        rest.push_str(&cursor[..start]);
+        let after = &cursor[start + CALL_OPEN.len()..];
+        // Tolerate a missing closing tag at the end of the reply
+        let (body, next) = match after.find(CALL_CLOSE) {
+            Some(end) => (&after[..end], &after[end + CALL_CLOSE.len()..]),
+            None => (after, ""),
+        };
+        match parse_call_body(body) {
+            Some(parsed) => calls.extend(parsed),
+            None => {
+                debug!(body, "could not parse <tool_call> block");
+                rest.push_str(&cursor[start..cursor.len() - next.len()]);
+            }
+        }
+        cursor = next;
+    }
+    rest.push_str(cursor);
+
+    if calls.is_empty()
+        && let Some(parsed) = parse_call_body(text)
+        && parsed
+            .iter()
+            .all(|(name, _)| tools.iter().any(|t| &t.function.name == name))
+    {
+        calls = parsed;
+        rest.clear();
+    }
+
+    let tool_calls = calls
+        .into_iter()
+        .enumerate()
+        .map(|(i, (name, arguments))| ToolCall {
+            id: Some(format!("call_{i}")),
+            r#type: "function".into(),
+            function: ToolCallFunction { name, arguments },
+        })
+        .collect();
+
+    let rest = rest.trim();
+    let content = (!rest.is_empty()).then(|| rest.to_string());
+    (content, tool_calls)
+}
+
+/// Parse a call object (or an array of them) into `(name, arguments JSON)` pairs.
+fn parse_call_body(body: &str) -> Option<Vec<(String, String)>> {
+    let body = strip_code_fence(body.trim());
+    let value: Value = serde_json::from_str(body).ok()?;
+    let items = match value {
+        Value::Array(items) => items,
+        other => vec![other],
+    };
+    if items.is_empty() {
+        return None;
+    }
+
+    items
+        .into_iter()
+        .map(|item| {
+            let name = item.get("name")?.as_str()?.to_string();
+            let arguments = match item.get("arguments").or_else(|| item.get("parameters")) {
+                Some(Value::String(s)) => s.clone(),
+                Some(v) => v.to_string(),
+                None => "{}".to_string(),
+            };
+            Some((name, arguments))
+        })
+        .collect()
+}
+
+fn strip_code_fence(s: &str) -> &str {
+    let Some(inner) = s.strip_prefix("```") else {
+        return s;
+    };
+    let inner = inner.strip_suffix("```").unwrap_or(inner);
+    // Drop the language tag on the opening fence line
+    match inner.find('\n') {
+        Some(nl) => inner[nl + 1..].trim(),
+        None => inner.trim(),
+    }
+}
+
+fn text_message(role: &str, content: String) -> ChatMessage {
+    ChatMessage {
+        role: role.to_string(),
+        content: Some(content),
+        tool_calls: vec![],
+        tool_call_id: None,
+        reasoning: None,
+        images: vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::types::ToolFunctionDef;
+
+    fn tool(name: &str) -> ToolDef {
+        ToolDef {
+            kind: "function".into(),
+            function: ToolFunctionDef {
+                name: name.into(),
+                description: format!("{name} description"),
+                parameters: json!({"type": "object", "properties": {"path": {"type": "string"}}}),
+                strict: None,
+            },
+        }
+    }
+
+    #[test]
+    fn renders_tools_into_prompt() {
+        let prompt = render_tools_prompt(&[tool("fs_read"), tool("mcp_fetch")]);
+        assert!(prompt.contains("<tool_call>"));
+        assert!(prompt.contains("### fs_read\nfs_read description"));
+        assert!(prompt.contains("### mcp_fetch"));
+        assert!(prompt.contains(r#""path":{"type":"string"}"#));
+    }
+
+    #[test]
+    fn parses_tagged_calls_and_keeps_text() {
+        let text = "Let me look.\n<tool_call>\n{\"name\": \"fs_read\", \"arguments\": {\"path\": \"a.rs\"}}\n</tool_call>\n<tool_call>{\"name\": \"fs_list\", \"arguments\": \"{}\"}</tool_call>";
+        let (content, calls) = parse_tool_calls(text, &[]);
+        assert_eq!(content.as_deref(), Some("Let me look."));
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].function.name, "fs_read");
+        assert_eq!(calls[0].function.arguments, r#"{"path":"a.rs"}"#);
+        assert_eq!(calls[0].id.as_deref(), Some("call_0"));
+        assert_eq!(calls[1].function.name, "fs_list");
+        assert_eq!(calls[1].function.arguments, "{}");
+    }
+
+    #[test]
+    fn tolerates_unclosed_tag_and_fences() {
+        let text =
+            "<tool_call>\n```json\n{\"name\": \"fs_read\", \"parameters\": {\"path\": \"b\"}}\n```";
+        let (content, calls) = parse_tool_calls(text, &[]);
+        assert!(content.is_none());
+        assert_eq!(calls[0].function.arguments, r#"{"path":"b"}"#);
+    }
+
+    #[test]
+    fn bare_json_requires_known_tool() {
+        let text = r#"{"name": "fs_read", "arguments": {"path": "c"}}"#;
+        let (_, calls) = parse_tool_calls(text, &[tool("fs_read")]);
+        assert_eq!(calls.len(), 1);
+
+        let (content, calls) = parse_tool_calls(text, &[tool("fs_write")]);
+        assert!(calls.is_empty());
+        assert_eq!(content.as_deref(), Some(text));
+    }
+
+    #[test]
+    fn invalid_block_is_left_as_text() {
+        let text = "<tool_call>not json</tool_call> done";
+        let (content, calls) = parse_tool_calls(text, &[]);
+        assert!(calls.is_empty());
+        assert_eq!(content.as_deref(), Some(text));
+    }
+
+    #[test]
+    fn converts_history_to_plain_turns() {
+        let messages = vec![
+            text_message("system", "You are helpful.".into()),
+            text_message("user", "read a and b".into()),
+            ChatMessage {
+                role: "assistant".into(),
+                content: None,
+                tool_calls: vec![
+                    ToolCall {
+                        id: Some("x1".into()),
+                        r#type: "function".into(),
+                        function: ToolCallFunction {
+                            name: "fs_read".into(),
+                            arguments: r#"{"path":"a"}"#.into(),
+                        },
+                    },
+                    ToolCall {
+                        id: Some("x2".into()),
+                        r#type: "function".into(),
+                        function: ToolCallFunction {
+                            name: "fs_list".into(),
+                            arguments: "{}".into(),
+                        },
+                    },
+                ],
+                tool_call_id: None,
+                reasoning: None,
+                images: vec![],
+            },
+            ChatMessage {
+                role: "tool".into(),
+                content: Some("A".into()),
+                tool_calls: vec![],
+                tool_call_id: Some("x1".into()),
+                reasoning: None,
+                images: vec![],
+            },
+            ChatMessage {
+                role: "tool".into(),
+                content: Some("B".into()),
+                tool_calls: vec![],
+                tool_call_id: Some("x2".into()),
+                reasoning: None,
+                images: vec![],
+            },
+        ];
+
+        let out = to_prompt_messages(&messages, &[tool("fs_read")]);
+        assert_eq!(out.len(), 4);
+        assert!(
+            out[0]
+                .content
+                .as_deref()
+                .unwrap()
+                .starts_with("You are helpful.\n\n# Tools")
+        );
+        let assistant = out[2].content.as_deref().unwrap();
+        assert!(assistant.contains(r#"{"arguments":{"path":"a"},"name":"fs_read"}"#));
+        assert!(out[2].tool_calls.is_empty());
+        assert_eq!(out[3].role, "user");
+        assert_eq!(
+            out[3].content.as_deref().unwrap(),
+            "<tool_result name=\"fs_read\">\nA\n</tool_result>\n<tool_result name=\"fs_list\">\nB\n</tool_result>"
+        );
+        assert!(out.iter().all(|m| m.tool_call_id.is_none()));
+    }
+
+    #[test]
+    fn inserts_system_prompt_when_missing() {
+        let out = to_prompt_messages(&[text_message("user", "hi".into())], &[tool("fs_read")]);
+        assert_eq!(out[0].role, "system");
+        assert_eq!(out[1].role, "user");
+    }
+
+    #[test]
+    fn extracts_calls_depending_on_mode() {
+        let reply = || ChoiceMessageWithTools {
+            role: "assistant".into(),
+            content: Some(
+                "<tool_call>{\"name\": \"fs_read\", \"arguments\": {}}</tool_call>".into(),
+            ),
+            tool_calls: vec![],
+            reasoning: None,
+        };
+        let tools = [tool("fs_read")];
+
+        let mut msg = reply();
+        let mut mode = ToolCallMode::Native;
+        extract_text_tool_calls("extract-test-model", &mut msg, &tools, &mut mode);
+        assert!(msg.tool_calls.is_empty());
+
+        let mut msg = reply();
+        let mut mode = ToolCallMode::Auto;
+        extract_text_tool_calls("extract-test-model", &mut msg, &tools, &mut mode);
+        assert_eq!(msg.tool_calls.len(), 1);
+        assert!(msg.content.is_none());
+        assert_eq!(mode, ToolCallMode::Prompt);
+    }
+
+    #[test]
+    fn displayable_len_holds_back_tags() {
+        assert_eq!(displayable_len("hello"), 5);
+        assert_eq!(displayable_len("hello <tool"), 6);
+        assert_eq!(displayable_len("hi <tool_call>{}"), 3);
+        assert_eq!(displayable_len("a < b"), 5);
+    }
+
+    #[test]
+    fn spots_replies_that_meant_to_call_a_tool() {
+        let reply = |text: &str| ChoiceMessageWithTools {
+            role: "assistant".into(),
+            content: Some(text.into()),
+            tool_calls: vec![],
+            reasoning: None,
+        };
+        let tools = [tool("fs_read")];
+        let auto = ToolCallMode::Auto;
+
+        assert!(looks_like_missed_tool_call(
+            &reply("I'll open main.rs."),
+            &tools,
+            auto
+        ));
+        assert!(looks_like_missed_tool_call(
+            &reply("Calling fs_read on it."),
+            &tools,
+            auto
+        ));
+        assert!(!looks_like_missed_tool_call(
+            &reply("It prints 42."),
+            &tools,
+            auto
+        ));
+        assert!(!looks_like_missed_tool_call(
+            &reply("Done.\n\nLet me know if you need more."),
+            &tools,
+            auto
+        ));
+        assert!(!looks_like_missed_tool_call(
+            &reply("I'll open it."),
+            &[],
+            auto
+        ));
+        assert!(!looks_like_missed_tool_call(
+            &reply("I'll open it."),
+            &tools,
+            ToolCallMode::Native
+        ));
+    }
+
+    #[test]
+    fn native_tool_calls_settle_auto_mode() {
+        let mut msg = ChoiceMessageWithTools {
+            role: "assistant".into(),
+            content: None,
+            tool_calls: vec![ToolCall {
+                id: Some("call_1".into()),
+                r#type: "function".into(),
+                function: ToolCallFunction {
+                    name: "fs_read".into(),
+                    arguments: "{}".into(),
+                },
+            }],
+            reasoning: None,
+        };
+        let mut mode = ToolCallMode::Auto;
+        extract_text_tool_calls("native-test-model", &mut msg, &[tool("fs_read")], &mut mode);
+        assert_eq!(
+            effective_mode(ToolCallMode::Auto, "native-test-model"),
+            ToolCallMode::Native
+        );
+    }
+
+    #[test]
+    fn auto_mode_remembers_detection() {
+        assert_eq!(
+            effective_mode(ToolCallMode::Auto, "detect-test-model"),
+            ToolCallMode::Auto
+        );
+        mark_prompt_mode("detect-test-model");
+        assert_eq!(
+            effective_mode(ToolCallMode::Auto, "detect-test-model"),
+            ToolCallMode::Prompt
+        );
+        assert_eq!(
+            effective_mode(ToolCallMode::Native, "detect-test-model"),
+            ToolCallMode::Native
+        );
+    }
+}
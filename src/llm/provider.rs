@@ -0,0 +1,297 @@
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use futures::Stream;
+use serde_json::Value;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::AppConfig;
+use crate::cost::UsageLog;
+use crate::llm::anthropic::AnthropicClient;
+use crate::llm::cassette::{Cassette, CassetteMode};
+use crate::llm::chat_with_tools::ChoiceMessageWithTools;
+use crate::llm::client_core::OpenAIClient;
+use crate::llm::ollama::OllamaClient;
+use crate::llm::responses::ResponsesClient;
+use crate::llm::retry::CircuitBreaker;
+use crate::llm::structured::{self, ResponseSchema};
+use crate::llm::types::{ChatMessage, ChoiceMessage, ToolDef};
+
+/// Stream of text deltas produced by [`LlmProvider::chat_stream`].
+///
+/// Tool call fragments are emitted in the OpenAI delta shape behind the
+/// `__TOOL_CALLS_DELTA__:` marker so every backend can share the same
+/// reconstruction logic. Reasoning text is emitted behind the
+/// `__REASONING_DELTA__:` marker.
+pub type ChatStream = Pin<Box<dyn Stream<Item = Result<String>> + Send>>;
+
+/// Wire-level backend used by the agent loop, compaction, watch and rewrite paths.
+///
+/// Implementations translate the OpenAI-shaped message types used throughout the
+/// crate into their own request format and back.
+#[async_trait]
+pub trait LlmProvider: std::fmt::Debug + Send + Sync {
+    /// Short identifier of the backend (e.g. "openai", "anthropic").
+    fn name(&self) -> &'static str;
+
+    /// Plain chat completion without tools.
+    async fn chat_once(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChoiceMessage>;
+
+    /// Chat request advertising `tools`, retried under the configured policy.
+    async fn chat_tools_once(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDef],
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChoiceMessageWithTools>;
+
+    /// Streaming chat request advertising `tools` (may be empty).
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDef],
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChatStream>;
+
+    /// Chat request whose reply is a JSON object matching `schema`.
+    ///
+    /// Backends without native structured output offer the schema as a tool
+    /// and ask the model to call it.
+    async fn chat_structured(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        schema: &ResponseSchema,
+        cancel: Option<CancellationToken>,
+    ) -> Result<Value> {
+        structured::chat_via_forced_tool(self, model, messages, schema, cancel).await
+    }
+
+    /// Copy of this provider with reasoning/thinking requests turned off.
+    fn with_reasoning_disabled(&self) -> Arc<dyn LlmProvider>;
+
+    fn get_tokens_used(&self) -> u32;
+    fn set_tokens(&self, tokens: u32);
+    fn get_prompt_tokens_used(&self) -> u32;
+    fn set_prompt_tokens(&self, tokens: u32);
+
+    /// Per-request usage recorded since the log was last drained.
+    fn usage_log(&self) -> &UsageLog;
+
+    /// Breaker tracking the health of this provider's endpoint. Backends
+    /// without a shared endpoint get one that never opens.
+    fn circuit_breaker(&self) -> Arc<CircuitBreaker> {
+        Arc::new(CircuitBreaker::new(0, Duration::ZERO))
+    }
+}
+
+/// Build the provider selected by `cfg.provider`.
+///
+/// Returns `Ok(None)` when a hosted provider has no API key configured. Local
+/// inference servers ("ollama", "llamacpp") do not require a key.
+pub fn build_provider(cfg: &AppConfig) -> Result<Option<Arc<dyn LlmProvider>>> {
+    let key = cfg.api_key.clone();
+    if cfg.cassette.is_some() && !matches!(cfg.provider.as_str(), "openai" | "llamacpp") {
+        bail!(
+            "--record/--replay are only supported for the openai and llamacpp providers, not {}",
+            cfg.provider
+        );
+    }
+    let provider: Arc<dyn LlmProvider> = match cfg.provider.as_str() {
+        "openai" => {
+            // Replayed sessions never reach the server, so no key is needed
+            let replaying = cfg
+                .cassette
+                .as_ref()
+                .is_some_and(|c| c.mode == CassetteMode::Replay);
+            let Some(key) = key.or_else(|| replaying.then(String::new)) else {
+                return Ok(None);
+            };
+            Arc::new(openai_client(cfg, key)?)
+        }
+        "openai-responses" => {
+            let Some(key) = key else {
+                return Ok(None);
+            };
+            Arc::new(
+                ResponsesClient::new(cfg.base_url.clone(), key)?.with_llm_config(cfg.llm.clone()),
+            )
+        }
+        "anthropic" => {
+            let Some(key) = key else {
+                return Ok(None);
+            };
+            Arc::new(
+                AnthropicClient::new(cfg.base_url.clone(), key)?.with_llm_config(cfg.llm.clone()),
+            )
+        }
+        "ollama" => Arc::new(
+            OllamaClient::new(cfg.base_url.clone())?
+                .with_llm_config(cfg.llm.clone())
+                .with_num_ctx(
+                    cfg.model_capabilities
+                        .as_ref()
+                        .and_then(|caps| caps.context_length),
+                ),
+        ),
+        // llama.cpp's server speaks the OpenAI chat completions protocol
+        "llamacpp" => Arc::new(openai_client(cfg, key.unwrap_or_default())?),
+        other => bail!("unknown LLM provider: {other}"),
+    };
+    Ok(Some(provider))
+}
+
+/// Provider for the `index`th entry of the configured fallback chain.
+///
+/// Returns `None` when the entry needs an API key that is not configured.
+pub fn build_fallback_provider(
+    cfg: &AppConfig,
+    index: usize,
+) -> Result<Option<(Arc<dyn LlmProvider>, String)>> {
+    let Some(fallback) = cfg.fallback_models.get(index) else {
+        return Ok(None);
+    };
+    let fallback_cfg = AppConfig {
+        provider: fallback.provider.clone(),
+        base_url: fallback.base_url.clone(),
+        model: fallback.model.clone(),
+        api_key: fallback.api_key.clone(),
+        // Recordings belong to the primary model
+        cassette: None,
+        ..cfg.clone()
+    };
+    Ok(build_provider(&fallback_cfg)?.map(|provider| (provider, fallback.model.clone())))
+}
+
+fn openai_client(cfg: &AppConfig, key: String) -> Result<OpenAIClient> {
+    let mut client = OpenAIClient::new(cfg.base_url.clone(), key)?.with_llm_config(cfg.llm.clone());
+    if let Some(cassette) = &cfg.cassette {
+        client = client.with_cassette(Cassette::open(cassette)?);
+    }
+    Ok(client)
+}
+
+#[async_trait]
+impl LlmProvider for OpenAIClient {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    async fn chat_once(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChoiceMessage> {
+        OpenAIClient::chat_once(self, model, messages, cancel).await
+    }
+
+    async fn chat_tools_once(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDef],
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChoiceMessageWithTools> {
+        crate::llm::tool_execution::chat_tools_once_openai(self, model, messages, tools, cancel)
+            .await
+    }
+
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDef],
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChatStream> {
+        OpenAIClient::chat_stream(self, model, messages, tools, cancel).await
+    }
+
+    async fn chat_structured(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        schema: &ResponseSchema,
+        cancel: Option<CancellationToken>,
+    ) -> Result<Value> {
+        OpenAIClient::chat_structured(self, model, messages, schema, cancel).await
+    }
+
+    fn with_reasoning_disabled(&self) -> Arc<dyn LlmProvider> {
+        let mut client = self.clone();
+        client.reason_enable = false;
+        Arc::new(client)
+    }
+
+    fn get_tokens_used(&self) -> u32 {
+        OpenAIClient::get_tokens_used(self)
+    }
+
+    fn set_tokens(&self, tokens: u32) {
+        OpenAIClient::set_tokens(self, tokens)
+    }
+
+    fn get_prompt_tokens_used(&self) -> u32 {
+        OpenAIClient::get_prompt_tokens_used(self)
+    }
+
+    fn set_prompt_tokens(&self, tokens: u32) {
+        OpenAIClient::set_prompt_tokens(self, tokens)
+    }
+
+    fn usage_log(&self) -> &UsageLog {
+        &self.usage_log
+    }
+
+    fn circuit_breaker(&self) -> Arc<CircuitBreaker> {
+        OpenAIClient::circuit_breaker(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_provider_without_key_returns_none() {
+        let cfg = AppConfig::default();
+        assert!(build_provider(&cfg).unwrap().is_none());
+    }
+
+    #[test]
+    fn build_provider_selects_backend() {
+        let mut cfg = AppConfig {
+            api_key: Some("k".into()),
+            ..AppConfig::default()
+        };
+        assert_eq!(build_provider(&cfg).unwrap().unwrap().name(), "openai");
+
+        cfg.provider = "openai-responses".into();
+        assert_eq!(
+            build_provider(&cfg).unwrap().unwrap().name(),
+            "openai-responses"
+        );
+
+        cfg.provider = "anthropic".into();
+        assert_eq!(build_provider(&cfg).unwrap().unwrap().name(), "anthropic");
+
+        cfg.provider = "llamacpp".into();
+        cfg.api_key = None;
+        assert_eq!(build_provider(&cfg).unwrap().unwrap().name(), "openai");
+
+        cfg.provider = "ollama".into();
+        assert_eq!(build_provider(&cfg).unwrap().unwrap().name(), "ollama");
+
+        cfg.provider = "nope".into();
+        assert!(build_provider(&cfg).is_err());
+    }
+}
@@ -0,0 +1,656 @@
+//! OpenAI Responses API backend.
+//!
+//! Talks to `/v1/responses` so reasoning models keep their reasoning between
+//! turns of the agent loop. Requests are sent with `store: false`; the
+//! encrypted reasoning items returned alongside function calls are cached per
+//! `call_id` and replayed in front of those calls on the next request.
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::config::LlmConfig;
+use crate::cost::{RequestUsage, UsageLog};
+use crate::llm::LlmErrorKind;
+use crate::llm::chat_with_tools::ChoiceMessageWithTools;
+use crate::llm::provider::{ChatStream, LlmProvider};
+use crate::llm::retry::{CircuitBreaker, Retrier, RetryPolicy, breaker_for};
+use crate::llm::types::{ChatMessage, ChoiceMessage, ToolDef};
+
+mod stream;
+mod wire;
+
+use wire::{
+    FunctionTool, ReasoningParam, ResponsesRequest, ResponsesResponse, ResponsesUsage, build_input,
+    parse_output,
+};
+
+pub const DEFAULT_REASONING_EFFORT: &str = "high";
+
+#[derive(Debug, Clone)]
+pub struct ResponsesClient {
+    pub base_url: String,
+    pub api_key: String,
+    pub(crate) inner: reqwest::Client,
+    pub llm_cfg: LlmConfig,
+    /// Reasoning effort sent to reasoning models; `None` disables reasoning requests
+    pub reasoning_effort: Option<String>,
+    /// Reasoning and built-in tool call items keyed by the `call_id` they preceded
+    carry_over: Arc<Mutex<HashMap<String, Vec<Value>>>>,
+    /// Tracks total tokens used by this client
+    pub tokens_used: Arc<AtomicU32>,
+    /// Tracks prompt tokens used by this client (for header display)
+    pub prompt_tokens_used: Arc<AtomicU32>,
+    /// Per-request usage for cost accounting
+    pub usage_log: UsageLog,
+}
+
+impl ResponsesClient {
+    pub fn new(base_url: impl Into<String>, api_key: impl Into<String>) -> Result<Self> {
+        let inner = reqwest::Client::builder().build()?;
+        Ok(Self {
+            base_url: base_url.into(),
+            api_key: api_key.into(),
+            inner,
+            llm_cfg: LlmConfig::default(),
+            reasoning_effort: Some(DEFAULT_REASONING_EFFORT.to_string()),
+            carry_over: Arc::new(Mutex::new(HashMap::new())),
+            tokens_used: Arc::new(AtomicU32::new(0)),
+            prompt_tokens_used: Arc::new(AtomicU32::new(0)),
+            usage_log: UsageLog::default(),
+        })
+    }
+
+    pub fn with_llm_config(mut self, cfg: LlmConfig) -> Self {
+        let builder = reqwest::Client::builder()
+            .connect_timeout(Duration::from_millis(cfg.connect_timeout_ms))
+            .timeout(Duration::from_millis(cfg.timeout_ms))
+            .read_timeout(Duration::from_millis(cfg.timeout_ms));
+        if let Ok(c) = builder.build() {
+            self.inner = c;
+        }
+        self.llm_cfg = cfg;
+        self
+    }
+
+    pub(crate) fn endpoint(&self) -> String {
+        let mut base = self.base_url.trim_end_matches('/').to_string();
+        if let Some(pos) = base.rfind("/v1") {
+            base.truncate(pos);
+            base = base.trim_end_matches('/').to_string();
+        }
+        format!("{base}/v1/responses")
+    }
+
+    fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+        if let Ok(value) = format!("Bearer {}", self.api_key).parse() {
+            headers.insert(AUTHORIZATION, value);
+        }
+        headers
+    }
+
+    fn record_usage(&self, model: &str, usage: &ResponsesUsage) {
+        self.tokens_used
+            .store(usage.total_tokens, Ordering::Relaxed);
+        self.prompt_tokens_used
+            .store(usage.input_tokens, Ordering::Relaxed);
+        self.usage_log.record(RequestUsage {
+            model: model.to_string(),
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cached_tokens: usage.cached_tokens(),
+        });
+    }
+
+    fn build_request(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDef],
+    ) -> ResponsesRequest {
+        let referenced: HashSet<String> = messages
+            .iter()
+            .flat_map(|m| m.tool_calls.iter().filter_map(|tc| tc.id.clone()))
+            .collect();
+        let input = {
+            let mut carry_over = self.carry_over.lock().unwrap();
+            // Drop items for calls that are no longer in the history (e.g. after compaction)
+            carry_over.retain(|call_id, _| referenced.contains(call_id));
+            build_input(messages, &carry_over)
+        };
+
+        let reasoning = self
+            .reasoning_effort
+            .as_ref()
+            .filter(|_| is_reasoning_model(model))
+            .map(|effort| ReasoningParam {
+                effort: effort.clone(),
+                summary: Some("auto".into()),
+            });
+        let include = if reasoning.is_some() {
+            vec!["reasoning.encrypted_content".to_string()]
+        } else {
+            vec![]
+        };
+
+        ResponsesRequest {
+            model: model.to_string(),
+            input,
+            tools: tools.iter().map(FunctionTool::from).collect(),
+            reasoning,
+            include,
+            store: false,
+            stream: None,
+        }
+    }
+
+    /// Record usage and cache carry-over items of a finished response.
+    fn finish_response(&self, model: &str, body: &ResponsesResponse) -> ChoiceMessageWithTools {
+        if let Some(usage) = &body.usage {
+            self.record_usage(model, usage);
+        }
+        let parsed = parse_output(&body.output);
+        if let Some((call_id, items)) = parsed.carry_over {
+            debug!(%call_id, items = items.len(), "caching responses carry-over items");
+            self.carry_over.lock().unwrap().insert(call_id, items);
+        }
+        debug!(id = ?body.id, status = ?body.status, "responses request completed");
+        parsed.message
+    }
+
+    /// Send a request and return the response once a success status is received.
+    async fn send(
+        &self,
+        req: &ResponsesRequest,
+        cancel_token: &CancellationToken,
+    ) -> Result<reqwest::Response> {
+        let url = self.endpoint();
+        let timeout_duration = Duration::from_millis(self.llm_cfg.timeout_ms);
+        let fut = tokio::time::timeout(
+            timeout_duration,
+            self.inner
+                .post(&url)
+                .headers(self.headers())
+                .json(req)
+                .send(),
+        );
+
+        let resp = tokio::select! {
+            biased;
+            _ = cancel_token.cancelled() => {
+                warn!("responses request cancelled before send");
+                return Err(anyhow!(LlmErrorKind::Cancelled));
+            }
+            res = fut => match res {
+                Ok(Ok(resp)) => resp,
+                Ok(Err(e)) => return Err(anyhow::Error::new(e).context("send responses request")),
+                Err(_) => return Err(anyhow!(LlmErrorKind::Timeout)),
+            },
+        };
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let retry_after = crate::llm::retry::retry_after(resp.headers());
+            let text = resp.text().await.unwrap_or_default().trim().to_owned();
+            error!(status=%status.as_u16(), body=%text, "responses non-success status");
+            return Err(map_error_response(status, retry_after, &text));
+        }
+        Ok(resp)
+    }
+
+    /// Send one non-streaming request and decode the assistant message.
+    async fn attempt(
+        &self,
+        model: &str,
+        req: &ResponsesRequest,
+        cancel_token: &CancellationToken,
+    ) -> Result<ChoiceMessageWithTools> {
+        let resp = self.send(req, cancel_token).await?;
+
+        let timeout_duration = Duration::from_millis(self.llm_cfg.timeout_ms);
+        let text = tokio::select! {
+            biased;
+            _ = cancel_token.cancelled() => {
+                warn!("responses request cancelled during body read");
+                return Err(anyhow!(LlmErrorKind::Cancelled));
+            }
+            res = tokio::time::timeout(timeout_duration, resp.text()) => match res {
+                Ok(Ok(text)) => text,
+                Ok(Err(e)) => return Err(anyhow::Error::new(e).context("read responses body")),
+                Err(_) => return Err(anyhow!(LlmErrorKind::Timeout)),
+            },
+        };
+
+        debug!(response_body=%text, "responses response");
+        let body: ResponsesResponse = serde_json::from_str(&text)?;
+        if let Some(err) = &body.error
+            && !err.is_null()
+        {
+            return Err(anyhow!("responses error: {err}"));
+        }
+        Ok(self.finish_response(model, &body))
+    }
+}
+
+/// Models that accept the `reasoning` parameter (o-series and gpt-5 families).
+fn is_reasoning_model(model: &str) -> bool {
+    let name = model.rsplit('/').next().unwrap_or(model);
+    let mut chars = name.chars();
+    (chars.next() == Some('o') && chars.next().is_some_and(|c| c.is_ascii_digit()))
+        || name.starts_with("gpt-5")
+        || name.contains("codex")
+}
+
+/// Translate an error response body into an error the agent loop understands.
+fn map_error_response(
+    status: reqwest::StatusCode,
+    retry_after: Option<Duration>,
+    text: &str,
+) -> anyhow::Error {
+    if status.as_u16() == 400
+        && let Ok(json) = serde_json::from_str::<Value>(text)
+        && let Some(code) = json
+            .get("error")
+            .and_then(|e| e.get("code"))
+            .and_then(|c| c.as_str())
+        && code == "context_length_exceeded"
+    {
+        return anyhow!(LlmErrorKind::ContextLengthExceeded);
+    }
+    crate::llm::status_error("responses error", status, retry_after, text)
+}
+
+#[async_trait]
+impl LlmProvider for ResponsesClient {
+    fn name(&self) -> &'static str {
+        "openai-responses"
+    }
+
+    async fn chat_once(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChoiceMessage> {
+        let msg = self.chat_tools_once(model, messages, &[], cancel).await?;
+        Ok(ChoiceMessage {
+            role: msg.role,
+            content: msg.content.unwrap_or_default(),
+        })
+    }
+
+    async fn chat_tools_once(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDef],
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChoiceMessageWithTools> {
+        let cancel_token = cancel.unwrap_or_default();
+        let req = self.build_request(model, messages, tools);
+        Retrier::new(
+            RetryPolicy::from_config(&self.llm_cfg),
+            self.circuit_breaker(),
+            "responses",
+        )
+        .run(&cancel_token, || self.attempt(model, &req, &cancel_token))
+        .await
+    }
+
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        tools: &[ToolDef],
+        cancel: Option<CancellationToken>,
+    ) -> Result<ChatStream> {
+        let cancel_token = cancel.unwrap_or_default();
+        let mut req = self.build_request(model, messages, tools);
+        req.stream = Some(true);
+        let resp = self.send(&req, &cancel_token).await?;
+
+        let mut byte_stream = resp.bytes_stream();
+        let client = self.clone();
+        let model = model.to_string();
+        let timeout_duration = Duration::from_millis(self.llm_cfg.timeout_ms);
+
+        let stream = async_stream::try_stream! {
+            let mut state = stream::StreamState::new();
+            let mut buf = Vec::<u8>::new();
+            loop {
+                let chunk = tokio::select! {
+                    biased;
+                    _ = cancel_token.cancelled() => {
+                        info!("responses stream cancelled");
+                        Err(anyhow!(LlmErrorKind::Cancelled))
+                    }
+                    res = tokio::time::timeout(timeout_duration, byte_stream.next()) => match res {
+                        Ok(Some(Ok(bytes))) => Ok(bytes),
+                        Ok(Some(Err(e))) => Err(anyhow::Error::new(e).context("byte stream read error")),
+                        Ok(None) => break,
+                        Err(_) => Err(anyhow!(LlmErrorKind::Timeout)),
+                    },
+                }?;
+
+                buf.extend_from_slice(&chunk);
+                while let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+                    let line: Vec<u8> = buf.drain(..=pos).collect();
+                    let Ok(line) = std::str::from_utf8(&line) else {
+                        continue;
+                    };
+                    let Some(payload) = line.trim().strip_prefix("data:") else {
+                        continue;
+                    };
+                    for out in state.handle(payload.trim())? {
+                        yield out;
+                    }
+                }
+            }
+            if let Some(body) = &state.completed {
+                client.finish_response(&model, body);
+            }
+        };
+
+        Ok(Box::pin(stream))
+    }
+
+    fn with_reasoning_disabled(&self) -> Arc<dyn LlmProvider> {
+        let mut client = self.clone();
+        client.reasoning_effort = None;
+        Arc::new(client)
+    }
+
+    fn get_tokens_used(&self) -> u32 {
+        self.tokens_used.load(Ordering::Relaxed)
+    }
+
+    fn set_tokens(&self, tokens: u32) {
+        self.tokens_used.store(tokens, Ordering::Relaxed);
+    }
+
+    fn get_prompt_tokens_used(&self) -> u32 {
+        self.prompt_tokens_used.load(Ordering::Relaxed)
+    }
+
+    fn set_prompt_tokens(&self, tokens: u32) {
+        self.prompt_tokens_used.store(tokens, Ordering::Relaxed);
+    }
+
+    fn usage_log(&self) -> &UsageLog {
+        &self.usage_log
+    }
+
+    fn circuit_breaker(&self) -> Arc<CircuitBreaker> {
+        breaker_for(&self.base_url, &self.llm_cfg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::types::{ToolCall, ToolCallFunction, ToolFunctionDef};
+    use httptest::{Expectation, Server, matchers::*, responders::*};
+    use serde_json::json;
+
+    fn user(content: &str) -> ChatMessage {
+        ChatMessage {
+            role: "user".into(),
+            content: Some(content.into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        }
+    }
+
+    fn fs_list_tool() -> ToolDef {
+        ToolDef {
+            kind: "function".into(),
+            function: ToolFunctionDef {
+                name: "fs_list".into(),
+                description: "List".into(),
+                parameters: json!({"type":"object"}),
+                strict: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn carries_reasoning_over_to_next_turn() {
+        let reasoning = json!({
+            "type": "reasoning",
+            "id": "rs_1",
+            "summary": [{"type": "summary_text", "text": "Need the listing"}],
+            "encrypted_content": "gAAAAenc"
+        });
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/v1/responses"),
+                request::headers(contains(("authorization", "Bearer test-key"))),
+                request::body(json_decoded(eq(json!({
+                    "model": "o4-mini",
+                    "input": [{"type": "message", "role": "user", "content": "hi"}],
+                    "tools": [{"type": "function", "name": "fs_list", "description": "List", "parameters": {"type": "object"}}],
+                    "reasoning": {"effort": "high", "summary": "auto"},
+                    "include": ["reasoning.encrypted_content"],
+                    "store": false
+                })))),
+            ])
+            .respond_with(json_encoded(json!({
+                "id": "resp_1",
+                "status": "completed",
+                "output": [
+                    reasoning.clone(),
+                    {"type": "function_call", "id": "fc_1", "call_id": "call_1", "name": "fs_list", "arguments": "{\"path\":\".\"}"}
+                ],
+                "usage": {"input_tokens": 20, "output_tokens": 30, "total_tokens": 50}
+            }))),
+        );
+
+        let client = ResponsesClient::new(server.url_str("/v1"), "test-key").unwrap();
+        let msg = client
+            .chat_tools_once("o4-mini", vec![user("hi")], &[fs_list_tool()], None)
+            .await
+            .unwrap();
+        assert!(msg.content.is_none());
+        assert_eq!(msg.tool_calls[0].id.as_deref(), Some("call_1"));
+        assert_eq!(client.get_prompt_tokens_used(), 20);
+        assert_eq!(client.get_tokens_used(), 50);
+
+        // The second turn replays the encrypted reasoning in front of the call
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/v1/responses"),
+                request::body(json_decoded(matches_input(json!([
+                    {"type": "message", "role": "user", "content": "hi"},
+                    reasoning,
+                    {"type": "function_call", "call_id": "call_1", "name": "fs_list", "arguments": "{\"path\":\".\"}"},
+                    {"type": "function_call_output", "call_id": "call_1", "output": "[\"a.rs\"]"}
+                ])))),
+            ])
+            .respond_with(json_encoded(json!({
+                "id": "resp_2",
+                "output": [{"type": "message", "role": "assistant", "content": [{"type": "output_text", "text": "One file."}]}]
+            }))),
+        );
+
+        let history = vec![
+            user("hi"),
+            ChatMessage {
+                role: "assistant".into(),
+                content: None,
+                tool_calls: vec![ToolCall {
+                    id: Some("call_1".into()),
+                    r#type: "function".into(),
+                    function: ToolCallFunction {
+                        name: "fs_list".into(),
+                        arguments: "{\"path\":\".\"}".into(),
+                    },
+                }],
+                tool_call_id: None,
+                reasoning: None,
+                images: vec![],
+            },
+            ChatMessage {
+                role: "tool".into(),
+                content: Some("[\"a.rs\"]".into()),
+                tool_calls: vec![],
+                tool_call_id: Some("call_1".into()),
+                reasoning: None,
+                images: vec![],
+            },
+        ];
+        let msg = client
+            .chat_tools_once("o4-mini", history, &[fs_list_tool()], None)
+            .await
+            .unwrap();
+        assert_eq!(msg.content.as_deref(), Some("One file."));
+    }
+
+    #[tokio::test]
+    async fn non_reasoning_models_skip_reasoning_params() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(all_of![
+                request::method_path("POST", "/v1/responses"),
+                request::body(json_decoded(eq(json!({
+                    "model": "gpt-4.1",
+                    "input": [{"type": "message", "role": "user", "content": "hi"}],
+                    "store": false
+                })))),
+            ])
+            .respond_with(json_encoded(json!({
+                "output": [{"type": "message", "role": "assistant", "content": [{"type": "output_text", "text": "hello"}]}]
+            }))),
+        );
+        let client = ResponsesClient::new(server.url_str(""), "k").unwrap();
+        let msg = client
+            .chat_once("gpt-4.1", vec![user("hi")], None)
+            .await
+            .unwrap();
+        assert_eq!(msg.content, "hello");
+    }
+
+    #[tokio::test]
+    async fn context_length_exceeded_is_mapped() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/v1/responses")).respond_with(
+                status_code(400).body(
+                    json!({
+                        "error": {
+                            "message": "Your input exceeds the context window of this model.",
+                            "type": "invalid_request_error",
+                            "code": "context_length_exceeded"
+                        }
+                    })
+                    .to_string(),
+                ),
+            ),
+        );
+        let client = ResponsesClient::new(server.url_str(""), "k").unwrap();
+        let err = client
+            .chat_once("o3", vec![user("hi")], None)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LlmErrorKind>(),
+            Some(&LlmErrorKind::ContextLengthExceeded)
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_are_retried() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/v1/responses"))
+                .times(2)
+                .respond_with(cycle![
+                    status_code(429).insert_header("retry-after", "0"),
+                    json_encoded(json!({
+                        "output": [{"type": "message", "role": "assistant", "content": [{"type": "output_text", "text": "ok"}]}]
+                    })),
+                ]),
+        );
+        let client = ResponsesClient::new(server.url_str(""), "k").unwrap();
+        let msg = client
+            .chat_once("gpt-4.1", vec![user("hi")], None)
+            .await
+            .unwrap();
+        assert_eq!(msg.content, "ok");
+    }
+
+    #[tokio::test]
+    async fn chat_stream_yields_text_and_records_usage() {
+        let body = [
+            r#"{"type":"response.output_text.delta","output_index":0,"content_index":0,"delta":"Hel"}"#,
+            r#"{"type":"response.output_text.delta","output_index":0,"content_index":0,"delta":"lo"}"#,
+            r#"{"type":"response.completed","response":{"id":"resp_1","output":[],"usage":{"input_tokens":4,"output_tokens":2,"total_tokens":6}}}"#,
+        ]
+        .iter()
+        .map(|d| format!("event: x\ndata: {d}\n\n"))
+        .collect::<String>();
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/v1/responses")).respond_with(
+                status_code(200)
+                    .append_header("content-type", "text/event-stream")
+                    .body(body),
+            ),
+        );
+
+        let client = ResponsesClient::new(server.url_str(""), "k").unwrap();
+        let mut stream = client
+            .chat_stream("gpt-5", vec![user("hi")], &[], None)
+            .await
+            .unwrap();
+        let mut chunks = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            chunks.push(chunk.unwrap());
+        }
+        assert_eq!(chunks, vec!["Hel", "lo"]);
+        assert_eq!(client.get_tokens_used(), 6);
+    }
+
+    #[test]
+    fn endpoint_and_model_detection() {
+        let c = ResponsesClient::new("https://api.openai.com/v1/", "x").unwrap();
+        assert_eq!(c.endpoint(), "https://api.openai.com/v1/responses");
+        assert!(is_reasoning_model("o3"));
+        assert!(is_reasoning_model("openai/o4-mini"));
+        assert!(is_reasoning_model("gpt-5-mini"));
+        assert!(!is_reasoning_model("gpt-4o"));
+    }
+
+    fn matches_input(expected: serde_json::Value) -> impl Matcher<serde_json::Value> {
+        InputMatcher { expected }
+    }
+
+    #[derive(Debug)]
+    struct InputMatcher {
+        expected: serde_json::Value,
+    }
+
+    impl Matcher<serde_json::Value> for InputMatcher {
+        fn matches(&mut self, input: &serde_json::Value, _ctx: &mut ExecutionContext) -> bool {
+            input.get("input") == Some(&self.expected)
+        }
+
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "input == {}", self.expected)
+        }
+    }
+}
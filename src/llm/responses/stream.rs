@@ -0,0 +1,170 @@
+//! Incremental decoding of OpenAI Responses API streaming events.
+
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::wire::ResponsesResponse;
+use crate::llm::stream::{ToolCallDelta, ToolCallFunctionDelta};
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum StreamEvent {
+    #[serde(rename = "response.output_text.delta")]
+    OutputTextDelta { delta: String },
+    #[serde(rename = "response.reasoning_summary_text.delta")]
+    ReasoningSummaryTextDelta { delta: String },
+    #[serde(rename = "response.output_item.added")]
+    OutputItemAdded { output_index: usize, item: Value },
+    #[serde(rename = "response.function_call_arguments.delta")]
+    FunctionCallArgumentsDelta { output_index: usize, delta: String },
+    #[serde(rename = "response.completed", alias = "response.incomplete")]
+    Completed { response: ResponsesResponse },
+    #[serde(rename = "response.failed")]
+    Failed { response: ResponsesResponse },
+    #[serde(rename = "error")]
+    Error {
+        #[serde(flatten)]
+        error: Value,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Translates SSE `data:` payloads into the chunk strings produced by
+/// `LlmProvider::chat_stream` (plain text, `__TOOL_CALLS_DELTA__:` and
+/// `__REASONING_DELTA__:` markers).
+#[derive(Debug, Default)]
+pub struct StreamState {
+    /// Output item index -> tool call index (non function items are not counted).
+    tool_indices: HashMap<usize, usize>,
+    /// Final response, carrying usage and the full output for reasoning carry-over
+    pub completed: Option<ResponsesResponse>,
+}
+
+impl StreamState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle(&mut self, payload: &str) -> Result<Vec<String>> {
+        let event: StreamEvent = match serde_json::from_str(payload) {
+            Ok(ev) => ev,
+            Err(e) => {
+                tracing::warn!(payload, err = %e, "failed to parse responses stream event");
+                return Ok(vec![]);
+            }
+        };
+
+        let mut out = Vec::new();
+        match event {
+            StreamEvent::OutputTextDelta { delta } if !delta.is_empty() => out.push(delta),
+            StreamEvent::ReasoningSummaryTextDelta { delta } if !delta.is_empty() => {
+                out.push(format!("__REASONING_DELTA__:{delta}"))
+            }
+            StreamEvent::OutputItemAdded { output_index, item }
+                if item.get("type").and_then(Value::as_str) == Some("function_call") =>
+            {
+                let tool_index = self.tool_indices.len();
+                self.tool_indices.insert(output_index, tool_index);
+                out.push(tool_delta_marker(ToolCallDelta {
+                    index: Some(tool_index),
+                    id: item
+                        .get("call_id")
+                        .and_then(Value::as_str)
+                        .map(str::to_string),
+                    kind: Some("function".into()),
+                    function: Some(ToolCallFunctionDelta {
+                        name: item
+                            .get("name")
+                            .and_then(Value::as_str)
+                            .unwrap_or_default()
+                            .to_string(),
+                        arguments: String::new(),
+                    }),
+                }));
+            }
+            StreamEvent::FunctionCallArgumentsDelta {
+                output_index,
+                delta,
+            } => {
+                if let Some(&tool_index) = self.tool_indices.get(&output_index) {
+                    out.push(tool_delta_marker(ToolCallDelta {
+                        index: Some(tool_index),
+                        id: None,
+                        kind: None,
+                        function: Some(ToolCallFunctionDelta {
+                            name: String::new(),
+                            arguments: delta,
+                        }),
+                    }));
+                }
+            }
+            StreamEvent::Completed { response } => self.completed = Some(response),
+            StreamEvent::Failed { response } => {
+                let error = response.error.unwrap_or(Value::Null);
+                return Err(anyhow!("responses stream failed: {error}"));
+            }
+            StreamEvent::Error { error } => {
+                return Err(anyhow!("responses stream error: {error}"));
+            }
+            _ => {}
+        }
+        Ok(out)
+    }
+}
+
+fn tool_delta_marker(delta: ToolCallDelta) -> String {
+    format!(
+        "__TOOL_CALLS_DELTA__:{}",
+        serde_json::to_string(&[delta]).unwrap_or_else(|_| "[]".into())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_text_and_function_call_events() {
+        let mut state = StreamState::new();
+        let events = [
+            r#"{"type":"response.created","response":{"id":"resp_1","output":[]}}"#,
+            r#"{"type":"response.output_item.added","output_index":0,"item":{"type":"reasoning","id":"rs_1","summary":[]}}"#,
+            r#"{"type":"response.reasoning_summary_text.delta","output_index":0,"summary_index":0,"delta":"Plan"}"#,
+            r#"{"type":"response.output_item.added","output_index":1,"item":{"type":"message","role":"assistant","content":[]}}"#,
+            r#"{"type":"response.output_text.delta","output_index":1,"content_index":0,"delta":"Hi"}"#,
+            r#"{"type":"response.output_item.added","output_index":2,"item":{"type":"function_call","id":"fc_1","call_id":"call_1","name":"fs_read","arguments":""}}"#,
+            r#"{"type":"response.function_call_arguments.delta","output_index":2,"delta":"{\"path\":\"a\"}"}"#,
+            r#"{"type":"response.completed","response":{"id":"resp_1","status":"completed","output":[],"usage":{"input_tokens":10,"output_tokens":5,"total_tokens":15}}}"#,
+        ];
+        let chunks: Vec<String> = events
+            .iter()
+            .flat_map(|e| state.handle(e).unwrap())
+            .collect();
+
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[0], "__REASONING_DELTA__:Plan");
+        assert_eq!(chunks[1], "Hi");
+        assert!(chunks[2].contains(r#""id":"call_1""#));
+        assert!(chunks[2].contains(r#""index":0"#));
+        assert!(chunks[3].contains(r#"{\"path\":\"a\"}"#));
+        let usage = state.completed.unwrap().usage.unwrap();
+        assert_eq!(usage.total_tokens, 15);
+    }
+
+    #[test]
+    fn failure_events_are_surfaced() {
+        let mut state = StreamState::new();
+        let err = state
+            .handle(r#"{"type":"response.failed","response":{"status":"failed","output":[],"error":{"code":"server_error","message":"boom"}}}"#)
+            .unwrap_err();
+        assert!(err.to_string().contains("server_error"));
+
+        let err = state
+            .handle(r#"{"type":"error","code":"rate_limit_exceeded","message":"slow down"}"#)
+            .unwrap_err();
+        assert!(err.to_string().contains("rate_limit_exceeded"));
+    }
+}
@@ -0,0 +1,449 @@
+//! OpenAI Responses API wire types and conversion from the OpenAI-shaped chat
+//! message types used by the rest of the crate.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use tracing::debug;
+
+use crate::llm::chat_with_tools::ChoiceMessageWithTools;
+use crate::llm::types::{ChatMessage, ToolCall, ToolCallFunction, ToolDef};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponsesRequest {
+    pub model: String,
+    pub input: Vec<InputItem>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<FunctionTool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<ReasoningParam>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Nothing is stored server-side; reasoning is carried over client-side instead
+    pub store: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum InputItem {
+    Typed(TypedInputItem),
+    /// Output item replayed verbatim (reasoning, built-in tool calls)
+    Raw(Value),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TypedInputItem {
+    Message {
+        role: String,
+        content: MessageContent,
+    },
+    FunctionCall {
+        call_id: String,
+        name: String,
+        arguments: String,
+    },
+    FunctionCallOutput {
+        call_id: String,
+        output: String,
+    },
+}
+
+/// Message text, or text and image parts when images are attached.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<InputPart>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputPart {
+    InputText { text: String },
+    InputImage { image_url: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionTool {
+    #[serde(rename = "type")]
+    pub kind: String, // "function"
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReasoningParam {
+    pub effort: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ResponsesUsage {
+    #[serde(default)]
+    pub input_tokens: u32,
+    #[serde(default)]
+    pub output_tokens: u32,
+    #[serde(default)]
+    pub total_tokens: u32,
+    #[serde(default)]
+    pub input_tokens_details: Option<InputTokensDetails>,
+}
+
+impl ResponsesUsage {
+    pub fn cached_tokens(&self) -> u32 {
+        self.input_tokens_details
+            .as_ref()
+            .map_or(0, |d| d.cached_tokens)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct InputTokensDetails {
+    #[serde(default)]
+    pub cached_tokens: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResponsesResponse {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub output: Vec<Value>,
+    #[serde(default)]
+    pub usage: Option<ResponsesUsage>,
+    #[serde(default)]
+    pub error: Option<Value>,
+}
+
+impl From<&ToolDef> for FunctionTool {
+    fn from(def: &ToolDef) -> Self {
+        Self {
+            kind: "function".into(),
+            name: def.function.name.clone(),
+            description: def.function.description.clone(),
+            parameters: def.function.parameters.clone(),
+            strict: def.function.strict,
+        }
+    }
+}
+
+/// Build the `input` item list from OpenAI-shaped chat messages.
+///
+/// Assistant tool calls become `function_call` items and `tool` messages become
+/// `function_call_output` items. Items in `carry_over` (reasoning and built-in
+/// tool calls from earlier responses, keyed by the `call_id` of the first
+/// function call they preceded) are replayed in front of that call.
+pub fn build_input(
+    messages: Vec<ChatMessage>,
+    carry_over: &HashMap<String, Vec<Value>>,
+) -> Vec<InputItem> {
+    let mut out = Vec::new();
+    // Tool calls without an id still need to be paired with their results.
+    let mut pending_ids: VecDeque<String> = VecDeque::new();
+    let mut generated = 0usize;
+
+    for msg in messages {
+        match msg.role.as_str() {
+            "tool" => {
+                let call_id = msg
+                    .tool_call_id
+                    .or_else(|| pending_ids.pop_front())
+                    .unwrap_or_default();
+                out.push(InputItem::Typed(TypedInputItem::FunctionCallOutput {
+                    call_id,
+                    output: msg.content.unwrap_or_default(),
+                }));
+            }
+            role => {
+                let text = msg.content.filter(|c| !c.is_empty());
+                let content = if msg.images.is_empty() {
+                    text.map(MessageContent::Text)
+                } else {
+                    let text = text.map(|text| InputPart::InputText { text });
+                    let images = msg.images.iter().map(|image| InputPart::InputImage {
+                        image_url: image.data_url(),
+                    });
+                    Some(MessageContent::Parts(
+                        text.into_iter().chain(images).collect(),
+                    ))
+                };
+                if let Some(content) = content {
+                    out.push(InputItem::Typed(TypedInputItem::Message {
+                        role: role.to_string(),
+                        content,
+                    }));
+                }
+                for tc in msg.tool_calls {
+                    let call_id = tc.id.unwrap_or_else(|| {
+                        generated += 1;
+                        let id = format!("call_doge_{generated}");
+                        pending_ids.push_back(id.clone());
+                        id
+                    });
+                    if let Some(items) = carry_over.get(&call_id) {
+                        out.extend(items.iter().cloned().map(InputItem::Raw));
+                    }
+                    out.push(InputItem::Typed(TypedInputItem::FunctionCall {
+                        call_id,
+                        name: tc.function.name,
+                        arguments: tc.function.arguments,
+                    }));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Assistant turn decoded from a response's `output` items.
+#[derive(Debug, Clone)]
+pub struct ParsedOutput {
+    pub message: ChoiceMessageWithTools,
+    /// Reasoning and built-in tool call items to replay before the first function call
+    pub carry_over: Option<(String, Vec<Value>)>,
+}
+
+/// Map response output items back onto the OpenAI-shaped assistant message.
+pub fn parse_output(output: &[Value]) -> ParsedOutput {
+    let mut text = String::new();
+    let mut reasoning = Vec::new();
+    let mut tool_calls = Vec::new();
+    let mut carried = Vec::new();
+
+    for item in output {
+        let kind = item.get("type").and_then(Value::as_str).unwrap_or_default();
+        match kind {
+            "message" => {
+                let parts = item.get("content").and_then(Value::as_array);
+                for part in parts.into_iter().flatten() {
+                    let part_text = match part.get("type").and_then(Value::as_str) {
+                        Some("output_text") => part.get("text"),
+                        Some("refusal") => part.get("refusal"),
+                        _ => None,
+                    };
+                    if let Some(t) = part_text.and_then(Value::as_str) {
+                        text.push_str(t);
+                    }
+                }
+            }
+            "function_call" => tool_calls.push(ToolCall {
+                id: item
+                    .get("call_id")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+                r#type: "function".into(),
+                function: ToolCallFunction {
+                    name: str_field(item, "name"),
+                    arguments: str_field(item, "arguments"),
+                },
+            }),
+            "reasoning" => {
+                let summary = reasoning_summary(item);
+                if !summary.is_empty() {
+                    debug!(summary = %summary, "responses reasoning summary");
+                    reasoning.push(summary);
+                }
+                carried.push(item.clone());
+            }
+            // Built-in tools (web_search_call, file_search_call, code_interpreter_call, ...)
+            // are executed server-side; they are only replayed for context.
+            other if other.ends_with("_call") => {
+                debug!(kind = other, status = ?item.get("status"), "responses built-in tool call");
+                carried.push(item.clone());
+            }
+            other => debug!(kind = other, "ignoring responses output item"),
+        }
+    }
+
+    let carry_over = match tool_calls.first().and_then(|tc| tc.id.clone()) {
+        Some(call_id) if !carried.is_empty() => Some((call_id, carried)),
+        _ => None,
+    };
+
+    ParsedOutput {
+        message: ChoiceMessageWithTools {
+            role: "assistant".into(),
+            content: if text.is_empty() { None } else { Some(text) },
+            tool_calls,
+            reasoning: if reasoning.is_empty() {
+                None
+            } else {
+                Some(reasoning.join("\n\n"))
+            },
+        },
+        carry_over,
+    }
+}
+
+/// Concatenated `summary_text` parts of a reasoning item.
+pub fn reasoning_summary(item: &Value) -> String {
+    item.get("summary")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|s| s.get("text").and_then(Value::as_str))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn str_field(item: &Value, field: &str) -> String {
+    item.get(field)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::types::ImagePart;
+    use serde_json::json;
+
+    fn msg(role: &str, content: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.into(),
+            content: Some(content.into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        }
+    }
+
+    #[test]
+    fn builds_input_with_carried_reasoning() {
+        let reasoning =
+            json!({"type": "reasoning", "id": "rs_1", "summary": [], "encrypted_content": "gAAA"});
+        let carry_over = HashMap::from([("call_1".to_string(), vec![reasoning.clone()])]);
+        let messages = vec![
+            msg("system", "be helpful"),
+            msg("user", "list files"),
+            ChatMessage {
+                role: "assistant".into(),
+                content: None,
+                tool_calls: vec![ToolCall {
+                    id: Some("call_1".into()),
+                    r#type: "function".into(),
+                    function: ToolCallFunction {
+                        name: "fs_list".into(),
+                        arguments: r#"{"path":"."}"#.into(),
+                    },
+                }],
+                tool_call_id: None,
+                reasoning: None,
+                images: vec![],
+            },
+            ChatMessage {
+                role: "tool".into(),
+                content: Some("[]".into()),
+                tool_calls: vec![],
+                tool_call_id: Some("call_1".into()),
+                reasoning: None,
+                images: vec![],
+            },
+        ];
+
+        let input = build_input(messages, &carry_over);
+        let json = serde_json::to_value(&input).unwrap();
+        assert_eq!(
+            json,
+            json!([
+                {"type": "message", "role": "system", "content": "be helpful"},
+                {"type": "message", "role": "user", "content": "list files"},
+                reasoning,
+                {"type": "function_call", "call_id": "call_1", "name": "fs_list", "arguments": "{\"path\":\".\"}"},
+                {"type": "function_call_output", "call_id": "call_1", "output": "[]"},
+            ])
+        );
+    }
+
+    #[test]
+    fn generates_ids_for_calls_without_one() {
+        let messages = vec![
+            ChatMessage {
+                role: "assistant".into(),
+                content: None,
+                tool_calls: vec![ToolCall {
+                    id: None,
+                    r#type: "function".into(),
+                    function: ToolCallFunction {
+                        name: "todo_read".into(),
+                        arguments: "{}".into(),
+                    },
+                }],
+                tool_call_id: None,
+                reasoning: None,
+                images: vec![],
+            },
+            msg("tool", "ok"),
+        ];
+        let input = build_input(messages, &HashMap::new());
+        assert_eq!(
+            input[1],
+            InputItem::Typed(TypedInputItem::FunctionCallOutput {
+                call_id: "call_doge_1".into(),
+                output: "ok".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn user_images_become_input_image_parts() {
+        let mut user = msg("user", "what is this?");
+        user.images.push(ImagePart {
+            media_type: "image/png".into(),
+            data: "iVBORw0KGgo=".into(),
+        });
+        let input = build_input(vec![user], &HashMap::new());
+        assert_eq!(
+            serde_json::to_value(&input).unwrap(),
+            json!([{
+                "type": "message",
+                "role": "user",
+                "content": [
+                    {"type": "input_text", "text": "what is this?"},
+                    {"type": "input_image", "image_url": "data:image/png;base64,iVBORw0KGgo="},
+                ],
+            }])
+        );
+    }
+
+    #[test]
+    fn parses_output_items() {
+        let output = vec![
+            json!({"type": "reasoning", "id": "rs_1", "summary": [{"type": "summary_text", "text": "Think"}], "encrypted_content": "gAAA"}),
+            json!({"type": "web_search_call", "id": "ws_1", "status": "completed"}),
+            json!({"type": "message", "role": "assistant", "content": [{"type": "output_text", "text": "Checking.", "annotations": []}]}),
+            json!({"type": "function_call", "id": "fc_1", "call_id": "call_9", "name": "fs_read", "arguments": "{\"path\":\"a\"}"}),
+        ];
+        let parsed = parse_output(&output);
+        assert_eq!(parsed.message.content.as_deref(), Some("Checking."));
+        assert_eq!(parsed.message.reasoning.as_deref(), Some("Think"));
+        assert_eq!(parsed.message.tool_calls[0].id.as_deref(), Some("call_9"));
+        assert_eq!(parsed.message.tool_calls[0].function.name, "fs_read");
+        let (call_id, items) = parsed.carry_over.unwrap();
+        assert_eq!(call_id, "call_9");
+        assert_eq!(items.len(), 2);
+        assert_eq!(reasoning_summary(&items[0]), "Think");
+    }
+
+    #[test]
+    fn final_answer_has_no_carry_over() {
+        let output = vec![
+            json!({"type": "reasoning", "id": "rs_1", "summary": []}),
+            json!({"type": "message", "role": "assistant", "content": [{"type": "output_text", "text": "Done"}]}),
+        ];
+        let parsed = parse_output(&output);
+        assert_eq!(parsed.message.content.as_deref(), Some("Done"));
+        assert!(parsed.carry_over.is_none());
+    }
+}
@@ -0,0 +1,453 @@
+//! Retry policy shared by every LLM request path.
+//!
+//! Whether and how often a failed request is retried depends on its
+//! [`LlmErrorKind`]. Backoff is exponential with jitter and a cap, `Retry-After`
+//! hints are honoured, and a request gives up once its total time budget is
+//! spent. Each endpoint has a circuit breaker: after a run of outage-type
+//! failures it opens and requests wait out the cooldown instead of hammering a
+//! provider that is down. Waits are reported to the UI when a channel is given.
+
+use anyhow::{Result, anyhow};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::config::LlmConfig;
+use crate::llm::{LlmErrorKind, classify_error};
+
+/// Server-requested wait attached to an error response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryAfter(pub Duration);
+
+impl std::fmt::Display for RetryAfter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "retry after {}s", self.0.as_secs())
+    }
+}
+
+/// `Retry-After` header given in seconds.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: Duration,
+    pub respect_retry_after: bool,
+    /// Total time a request may spend on attempts and waits
+    pub budget: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::from_config(&LlmConfig::default())
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_config(cfg: &LlmConfig) -> Self {
+        Self {
+            max_attempts: u32::try_from(cfg.max_retries)
+                .unwrap_or(u32::MAX)
+                .saturating_add(1),
+            base_delay: Duration::from_millis(cfg.retry_base_ms),
+            max_delay: Duration::from_millis(cfg.retry_max_delay_ms),
+            jitter: Duration::from_millis(cfg.retry_jitter_ms),
+            respect_retry_after: cfg.respect_retry_after,
+            budget: Duration::from_millis(cfg.retry_budget_ms),
+        }
+    }
+
+    /// Attempts allowed for a request failing with `kind`; 1 means no retry.
+    pub fn attempts_for(&self, kind: &LlmErrorKind) -> u32 {
+        match kind {
+            LlmErrorKind::RateLimited
+            | LlmErrorKind::Server
+            | LlmErrorKind::Network
+            | LlmErrorKind::Timeout
+            | LlmErrorKind::Unknown => self.max_attempts,
+            // A garbled body may be a one-off; a second one is not
+            LlmErrorKind::Deserialize => self.max_attempts.min(2),
+            LlmErrorKind::Client
+            | LlmErrorKind::Cancelled
+            | LlmErrorKind::ContextLengthExceeded
+            | LlmErrorKind::CircuitOpen => 1,
+        }
+    }
+
+    /// Wait before attempt `attempt + 1`.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if self.respect_retry_after
+            && let Some(wait) = retry_after
+        {
+            return wait;
+        }
+        let exp = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let backoff = self.base_delay.saturating_mul(exp).min(self.max_delay);
+        let jitter = fastrand::u64(0..=self.jitter.as_millis() as u64);
+        backoff + Duration::from_millis(jitter)
+    }
+}
+
+/// Failures that count against an endpoint's health.
+fn is_outage(kind: &LlmErrorKind) -> bool {
+    matches!(
+        kind,
+        LlmErrorKind::RateLimited
+            | LlmErrorKind::Server
+            | LlmErrorKind::Network
+            | LlmErrorKind::Timeout
+    )
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// A `threshold` of 0 disables the breaker.
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Time left before requests may be sent again, if the breaker is open.
+    pub fn open_for(&self) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap();
+        let until = state.open_until?;
+        let now = Instant::now();
+        if now < until {
+            return Some(until - now);
+        }
+        // Half-open: let one request through; a single failure reopens
+        state.open_until = None;
+        state.consecutive_failures = self.threshold.saturating_sub(1);
+        None
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.open_until = None;
+    }
+
+    pub fn record_failure(&self) {
+        if self.threshold == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= self.threshold {
+            warn!(
+                failures = state.consecutive_failures,
+                cooldown_ms = self.cooldown.as_millis() as u64,
+                "circuit breaker opened"
+            );
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+/// Breaker shared by all clients talking to `endpoint`.
+pub fn breaker_for(endpoint: &str, cfg: &LlmConfig) -> Arc<CircuitBreaker> {
+    static BREAKERS: OnceLock<Mutex<HashMap<String, Arc<CircuitBreaker>>>> = OnceLock::new();
+    let mut breakers = BREAKERS.get_or_init(Default::default).lock().unwrap();
+    breakers
+        .entry(endpoint.to_string())
+        .or_insert_with(|| {
+            Arc::new(CircuitBreaker::new(
+                cfg.circuit_breaker_threshold,
+                Duration::from_millis(cfg.circuit_breaker_cooldown_ms),
+            ))
+        })
+        .clone()
+}
+
+/// Retry state of one logical request.
+///
+/// Call [`Retrier::before_attempt`] before each attempt, then
+/// [`Retrier::succeeded`] or [`Retrier::after_failure`] with its outcome.
+pub struct Retrier<'a> {
+    policy: RetryPolicy,
+    breaker: Arc<CircuitBreaker>,
+    label: &'static str,
+    ui_tx: Option<&'a Sender<String>>,
+    started: Instant,
+    attempt: u32,
+}
+
+impl<'a> Retrier<'a> {
+    pub fn new(policy: RetryPolicy, breaker: Arc<CircuitBreaker>, label: &'static str) -> Self {
+        Self {
+            policy,
+            breaker,
+            label,
+            ui_tx: None,
+            started: Instant::now(),
+            attempt: 0,
+        }
+    }
+
+    /// Report waits to the UI as `[RETRY]` log lines.
+    pub fn with_ui(mut self, ui_tx: Option<&'a Sender<String>>) -> Self {
+        self.ui_tx = ui_tx;
+        self
+    }
+
+    /// Wait out an open circuit, or fail fast if that would exceed the budget.
+    pub async fn before_attempt(&mut self, cancel: &CancellationToken) -> Result<()> {
+        self.attempt += 1;
+        let Some(wait) = self.breaker.open_for() else {
+            return Ok(());
+        };
+        if self.started.elapsed() + wait > self.policy.budget {
+            return Err(anyhow!(LlmErrorKind::CircuitOpen).context(format!(
+                "{}: provider circuit is open for another {}s",
+                self.label,
+                wait.as_secs()
+            )));
+        }
+        self.notify(&format!(
+            "provider unavailable (circuit open); retrying in {}s",
+            wait.as_secs().max(1)
+        ));
+        sleep_or_cancel(wait, cancel).await
+    }
+
+    pub fn succeeded(&self) {
+        self.breaker.record_success();
+    }
+
+    /// Run `attempt` until it succeeds, is cancelled or the policy gives up.
+    pub async fn run<T, F, Fut>(&mut self, cancel: &CancellationToken, mut attempt: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        loop {
+            self.before_attempt(cancel).await?;
+            match attempt().await {
+                Ok(value) => {
+                    self.succeeded();
+                    return Ok(value);
+                }
+                Err(e) if matches!(e.downcast_ref(), Some(LlmErrorKind::Cancelled)) => {
+                    return Err(e);
+                }
+                Err(e) => self.after_failure(e, cancel).await?,
+            }
+        }
+    }
+
+    /// Sleep before the next attempt, or hand `err` back if the request
+    /// should give up.
+    pub async fn after_failure(
+        &mut self,
+        err: anyhow::Error,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        let kind = classify_error(None, &err);
+        if is_outage(&kind) {
+            self.breaker.record_failure();
+        }
+        let max = self.policy.attempts_for(&kind);
+        if self.attempt >= max {
+            if max > 1 {
+                warn!(label = self.label, attempts = self.attempt, error = %err, "giving up after retries");
+            }
+            return Err(err);
+        }
+        let hint = err.downcast_ref::<RetryAfter>().map(|r| r.0);
+        let wait = self.policy.delay(self.attempt, hint);
+        if self.started.elapsed() + wait > self.policy.budget {
+            warn!(label = self.label, attempts = self.attempt, error = %err, "retry budget exhausted");
+            return Err(err);
+        }
+        info!(
+            label = self.label,
+            attempt = self.attempt,
+            kind = ?kind,
+            wait_ms = wait.as_millis() as u64,
+            error = %err,
+            "retrying request"
+        );
+        self.notify(&format!(
+            "{kind}; retrying in {}s (attempt {}/{max})",
+            wait.as_secs_f64().round() as u64,
+            self.attempt + 1
+        ));
+        sleep_or_cancel(wait, cancel).await
+    }
+
+    fn notify(&self, text: &str) {
+        if let Some(tx) = self.ui_tx {
+            let _ = tx.send(format!("[RETRY] {text}"));
+        }
+    }
+}
+
+async fn sleep_or_cancel(wait: Duration, cancel: &CancellationToken) -> Result<()> {
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(anyhow!(LlmErrorKind::Cancelled)),
+        _ = tokio::time::sleep(wait) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(250),
+            jitter: Duration::ZERO,
+            respect_retry_after: true,
+            budget: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn backoff_is_capped_and_honours_retry_after() {
+        let p = policy();
+        assert_eq!(p.delay(1, None), Duration::from_millis(100));
+        assert_eq!(p.delay(2, None), Duration::from_millis(200));
+        assert_eq!(p.delay(3, None), Duration::from_millis(250));
+        assert_eq!(p.delay(200, None), Duration::from_millis(250));
+        assert_eq!(
+            p.delay(1, Some(Duration::from_secs(7))),
+            Duration::from_secs(7)
+        );
+        assert_eq!(p.attempts_for(&LlmErrorKind::Client), 1);
+        assert_eq!(p.attempts_for(&LlmErrorKind::Deserialize), 2);
+        assert_eq!(p.attempts_for(&LlmErrorKind::RateLimited), 4);
+    }
+
+    #[test]
+    fn breaker_opens_after_threshold_and_half_opens() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(20));
+        breaker.record_failure();
+        assert!(breaker.open_for().is_none());
+        breaker.record_failure();
+        assert!(breaker.open_for().is_some());
+
+        std::thread::sleep(Duration::from_millis(25));
+        assert!(breaker.open_for().is_none());
+        // One more failure while half-open reopens it
+        breaker.record_failure();
+        assert!(breaker.open_for().is_some());
+        breaker.record_success();
+        assert!(breaker.open_for().is_none());
+    }
+
+    #[tokio::test]
+    async fn retrier_reports_waits_and_gives_up() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let breaker = Arc::new(CircuitBreaker::new(0, Duration::ZERO));
+        let cancel = CancellationToken::new();
+        let mut retrier = Retrier::new(
+            RetryPolicy {
+                base_delay: Duration::from_millis(1),
+                ..policy()
+            },
+            breaker,
+            "test",
+        )
+        .with_ui(Some(&tx));
+
+        let rate_limited = || {
+            crate::llm::status_error(
+                "chat error",
+                reqwest::StatusCode::TOO_MANY_REQUESTS,
+                Some(Duration::ZERO),
+                "slow down",
+            )
+        };
+        for _ in 0..3 {
+            retrier.before_attempt(&cancel).await.unwrap();
+            retrier
+                .after_failure(rate_limited(), &cancel)
+                .await
+                .unwrap();
+        }
+        retrier.before_attempt(&cancel).await.unwrap();
+        let err = retrier
+            .after_failure(rate_limited(), &cancel)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LlmErrorKind>(),
+            Some(&LlmErrorKind::RateLimited)
+        );
+        assert!(err.to_string().contains("slow down"));
+
+        let events: Vec<String> = rx.try_iter().collect();
+        assert_eq!(
+            events,
+            [
+                "[RETRY] rate limited; retrying in 0s (attempt 2/4)",
+                "[RETRY] rate limited; retrying in 0s (attempt 3/4)",
+                "[RETRY] rate limited; retrying in 0s (attempt 4/4)",
+            ]
+        );
+
+        // Client errors are not retried
+        let mut retrier = Retrier::new(
+            policy(),
+            Arc::new(CircuitBreaker::new(0, Duration::ZERO)),
+            "t",
+        );
+        retrier.before_attempt(&cancel).await.unwrap();
+        let client_err = crate::llm::status_error(
+            "chat error",
+            reqwest::StatusCode::UNAUTHORIZED,
+            None,
+            "bad key",
+        );
+        assert!(retrier.after_failure(client_err, &cancel).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn open_circuit_fails_fast_beyond_budget() {
+        let breaker = Arc::new(CircuitBreaker::new(1, Duration::from_secs(120)));
+        breaker.record_failure();
+        let mut retrier = Retrier::new(policy(), breaker, "t");
+        let err = retrier
+            .before_attempt(&CancellationToken::new())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LlmErrorKind>(),
+            Some(&LlmErrorKind::CircuitOpen)
+        );
+        assert!(LlmErrorKind::CircuitOpen.warrants_fallback());
+    }
+}
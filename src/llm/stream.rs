@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use futures::{Stream, StreamExt};
 use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap};
 use serde::{Deserialize, Serialize};
@@ -9,15 +9,20 @@ use tracing::{debug, info, warn};
 
 use crate::llm::LlmErrorKind;
 use crate::llm::client_core::OpenAIClient;
-use crate::llm::types::{ChatMessage, Usage};
+use crate::llm::types::{ChatMessage, ToolDef, Usage};
 
 // Stream types
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct StreamChoiceDelta {
     #[serde(default)]
-    pub content: String,
+    pub content: Option<String>,
     #[serde(default)]
     pub role: Option<String>,
+    // Reasoning tokens; the field name differs between backends
+    #[serde(default)]
+    pub reasoning: Option<String>,
+    #[serde(default)]
+    pub reasoning_content: Option<String>,
     // OpenAI-compatible tool_calls (streamed as incremental deltas)
     #[serde(default)]
     pub tool_calls: Vec<ToolCallDelta>,
@@ -26,6 +31,8 @@ pub struct StreamChoiceDelta {
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ToolCallDelta {
     pub index: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     #[serde(rename = "type")]
     pub kind: Option<String>, // "function"
     #[serde(default)]
@@ -60,17 +67,15 @@ impl OpenAIClient {
         &self,
         model: &str,
         messages: Vec<ChatMessage>,
+        tools: &[ToolDef],
         cancel: Option<CancellationToken>,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<String>> + Send>>> {
-        use crate::llm::types::ChatRequest;
-
         let url = self.endpoint();
-        let req = ChatRequest {
-            model: model.to_string(),
-            messages,
-            temperature: None,
-            stream: Some(true),
-        };
+        let mut req = self.tools_request(model, messages, tools);
+        req.stream = Some(true);
+        // Usage is only reported on the final chunk when explicitly requested
+        req.stream_options = Some(serde_json::json!({ "include_usage": true }));
+        let body = req.to_body();
 
         let mut headers = HeaderMap::new();
         headers.insert(
@@ -90,78 +95,32 @@ impl OpenAIClient {
 
         let cancel_token = cancel.unwrap_or_default();
 
-        // Only retry establishing the stream, not mid-stream reads
-        let mut attempt = 1usize;
-        let max_attempts = self.llm_cfg.max_retries.saturating_add(1);
-        let resp = loop {
-            let fut = self
-                .inner
-                .post(url.clone())
-                .headers(headers.clone())
-                .json(&req)
-                .send();
-
-            let resp_res = tokio::select! {
-                biased;
-                _ = cancel_token.cancelled() => {
-                    info!("chat_stream cancelled before send");
-                    return Err(anyhow::anyhow!(LlmErrorKind::Cancelled));
-                }
-                res = fut => res,
-            };
-
-            match resp_res {
-                Err(e) => {
-                    if attempt < max_attempts {
-                        let wait = self.backoff_delay(attempt, None);
-                        warn!(attempt, err=%e, wait_ms=%wait.as_millis(), "retrying stream establish after error");
-                        tokio::select! {
-                            biased;
-                            _ = cancel_token.cancelled() => {
-                                info!("chat_stream cancelled during retry sleep");
-                                return Err(anyhow::anyhow!(LlmErrorKind::Cancelled));
-                            }
-                            _ = tokio::time::sleep(wait) => {}
-                        }
-                        attempt += 1;
-                        continue;
-                    } else {
-                        return Err(anyhow::Error::new(e).context("send chat request (stream)"));
-                    }
-                }
-                Ok(resp) => {
-                    if !resp.status().is_success() {
-                        let status = resp.status();
-                        let text = resp.text().await.unwrap_or_default();
-                        // Retry even in case of timeout
-                        if attempt < max_attempts
-                            && (status.is_server_error()
-                                || status.as_u16() == 429
-                                || status.as_u16() == 408)
-                        {
-                            let wait = self.backoff_delay(attempt, None);
-                            info!(attempt, status=%status.as_u16(), wait_ms=%wait.as_millis(), "retrying stream establish after HTTP error");
-                            tokio::select! {
-                                biased;
-                                _ = cancel_token.cancelled() => {
-                                    info!("chat_stream cancelled during retry sleep");
-                                    return Err(anyhow::anyhow!(LlmErrorKind::Cancelled));
-                                }
-                                _ = tokio::time::sleep(wait) => {}
-                            }
-                            attempt += 1;
-                            continue;
-                        }
-                        anyhow::bail!("chat error: {} - {}", status, text);
-                    }
-                    break resp;
-                }
+        // Retries are left to the caller, which knows whether output was emitted
+        let send = self.send_json(&url, headers, &body);
+        let resp = tokio::select! {
+            biased;
+            _ = cancel_token.cancelled() => {
+                info!("chat_stream cancelled before send");
+                return Err(anyhow::anyhow!(LlmErrorKind::Cancelled));
             }
+            res = send => res.context("send chat request (stream)")?,
         };
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let retry_after = crate::llm::retry::retry_after(resp.headers());
+            let text = resp.text().await.unwrap_or_default();
+            return Err(crate::llm::status_error(
+                "chat error",
+                status,
+                retry_after,
+                &text,
+            ));
+        }
 
         let mut byte_stream = resp.bytes_stream();
         let mut buf = Vec::<u8>::new();
         let client = self.clone();
+        let model = model.to_string();
         let timeout_duration = Duration::from_millis(self.llm_cfg.timeout_ms);
 
         let stream = async_stream::try_stream! {
@@ -218,9 +177,7 @@ impl OpenAIClient {
 
                             if let Ok(json) = serde_json::from_str::<ChatStreamChunk>(payload) {
                                 if let Some(usage) = &json.usage {
-                                    client.set_tokens(usage.total_tokens);
-                                    // accumulate prompt tokens separately for header display
-                                    client.set_prompt_tokens(usage.prompt_tokens);
+                                    client.record_usage(&model, usage);
                                 }
 
                                 for ch in json.choices {
@@ -229,8 +186,15 @@ impl OpenAIClient {
                                     {
                                         continue;
                                     }
-                                    let delta = ch.delta.content;
-                                    if !delta.is_empty() {
+                                    if let Some(reasoning) = ch
+                                        .delta
+                                        .reasoning
+                                        .or(ch.delta.reasoning_content)
+                                        .filter(|r| !r.is_empty())
+                                    {
+                                        yield format!("__REASONING_DELTA__:{}", reasoning);
+                                    }
+                                    if let Some(delta) = ch.delta.content.filter(|c| !c.is_empty()) {
                                         yield delta;
                                     }
                                     if !ch.delta.tool_calls.is_empty()
@@ -1,10 +1,5 @@
-use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
-use serde_json::Value as JsonValue;
-use tracing::debug;
 
-use crate::llm::tool_execution::dispatch_tool_call as dispatch_sync_tool_call;
-use crate::llm::tool_runtime::ToolRuntime;
 use crate::llm::types::{ToolCall as SyncToolCall, ToolCallFunction};
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -63,38 +58,24 @@ impl ToolDeltaBuffer {
         }
     }
 
-    // Attempt to parse the arguments JSON for a given index, returning a structured SyncToolCall
-    pub fn finalize_sync_call(&self, index: usize) -> Result<SyncToolCall> {
-        let rc = self
-            .calls
-            .get(index)
-            .ok_or_else(|| anyhow!("no tool call at index {index}"))?;
-        // Validate minimal fields
-        if rc.name.is_empty() {
-            return Err(anyhow!("tool call missing name"));
-        }
-        // Ensure arguments is valid JSON string per OpenAI spec
-        let _parsed: JsonValue = serde_json::from_str(&rc.arguments)
-            .map_err(|e| anyhow!("invalid tool arguments JSON: {e}"))?;
-        Ok(SyncToolCall {
-            id: rc.id.clone(),
-            r#type: "function".to_string(),
-            function: ToolCallFunction {
-                name: rc.name.clone(),
-                arguments: rc.arguments.clone(),
-            },
-        })
+    /// Reconstructed calls in index order. Empty arguments become `{}`;
+    /// malformed JSON is left for the dispatcher to report back to the model.
+    pub fn into_tool_calls(self) -> Vec<SyncToolCall> {
+        self.calls
+            .into_iter()
+            .filter(|rc| !rc.name.is_empty())
+            .map(|rc| SyncToolCall {
+                id: rc.id,
+                r#type: "function".to_string(),
+                function: ToolCallFunction {
+                    name: rc.name,
+                    arguments: if rc.arguments.trim().is_empty() {
+                        "{}".to_string()
+                    } else {
+                        rc.arguments
+                    },
+                },
+            })
+            .collect()
     }
 }
-
-// Execute a reconstructed tool call via existing dispatcher
-pub async fn execute_tool_call(
-    runtime: &ToolRuntime<'_>,
-    index: usize,
-    buf: &ToolDeltaBuffer,
-) -> Result<serde_json::Value> {
-    let sc = buf.finalize_sync_call(index)?;
-    debug!("executing reconstructed tool call");
-    let res = dispatch_sync_tool_call(runtime, sc).await?;
-    Ok(res)
-}
@@ -0,0 +1,172 @@
+//! Structured (JSON-schema) outputs.
+//!
+//! Callers that need a typed payload describe it with a [`ResponseSchema`].
+//! Backends with native structured output constrain decoding to the schema;
+//! the others are offered a single tool whose parameters are the schema and
+//! asked to call it, which most tool-capable models follow reliably.
+
+use anyhow::{Result, anyhow};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+
+use crate::llm::provider::LlmProvider;
+use crate::llm::types::{ChatMessage, ToolDef, ToolFunctionDef};
+
+/// Named JSON schema a reply must conform to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseSchema {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub schema: Value,
+    /// Strict schemas must list every property as required and forbid extras
+    #[serde(default)]
+    pub strict: bool,
+}
+
+/// OpenAI `response_format` request field.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    JsonObject,
+    JsonSchema { json_schema: ResponseSchema },
+}
+
+impl ResponseFormat {
+    pub fn json_schema(schema: ResponseSchema) -> Self {
+        Self::JsonSchema {
+            json_schema: schema,
+        }
+    }
+
+    /// Schema carried by this format, if any.
+    pub fn schema(&self) -> Option<&ResponseSchema> {
+        match self {
+            Self::JsonSchema { json_schema } => Some(json_schema),
+            _ => None,
+        }
+    }
+}
+
+/// Deserialize a structured reply into `T`.
+pub fn from_payload<T: DeserializeOwned>(payload: Value) -> Result<T> {
+    serde_json::from_value(payload)
+        .map_err(|e| anyhow!("structured reply does not match schema: {e}"))
+}
+
+/// JSON object in a text reply: the whole text, a fenced block, or the
+/// outermost braces.
+pub fn extract_json(text: &str) -> Option<Value> {
+    let text = text.trim();
+    if let Ok(value @ Value::Object(_)) = serde_json::from_str(text) {
+        return Some(value);
+    }
+    if let Some(start) = text.find("```") {
+        let body = &text[start + 3..];
+        let body = body.strip_prefix("json").unwrap_or(body);
+        if let Some(end) = body.find("```")
+            && let Ok(value @ Value::Object(_)) = serde_json::from_str(body[..end].trim())
+        {
+            return Some(value);
+        }
+    }
+    let start = text.find('{')?;
+    let end = text.rfind('}')?;
+    match serde_json::from_str(text.get(start..=end)?) {
+        Ok(value @ Value::Object(_)) => Some(value),
+        _ => None,
+    }
+}
+
+/// Structured reply obtained by offering the schema as the only tool.
+///
+/// Used by backends without native structured output. A reply that ignores
+/// the tool but still contains a JSON object is accepted as well.
+pub async fn chat_via_forced_tool<P: LlmProvider + ?Sized>(
+    client: &P,
+    model: &str,
+    mut messages: Vec<ChatMessage>,
+    schema: &ResponseSchema,
+    cancel: Option<CancellationToken>,
+) -> Result<Value> {
+    let tool = ToolDef {
+        kind: "function".into(),
+        function: ToolFunctionDef {
+            name: schema.name.clone(),
+            description: schema
+                .description
+                .clone()
+                .unwrap_or_else(|| format!("Submit the {} result.", schema.name)),
+            parameters: schema.schema.clone(),
+            strict: None,
+        },
+    };
+    messages.push(ChatMessage {
+        role: "user".into(),
+        content: Some(format!(
+            "Respond only by calling the `{}` tool with your answer as its arguments.",
+            schema.name
+        )),
+        tool_calls: vec![],
+        tool_call_id: None,
+        reasoning: None,
+        images: vec![],
+    });
+
+    let msg = client
+        .chat_tools_once(model, messages, std::slice::from_ref(&tool), cancel)
+        .await?;
+    if let Some(call) = msg
+        .tool_calls
+        .iter()
+        .find(|tc| tc.function.name == schema.name)
+    {
+        return serde_json::from_str(&call.function.arguments)
+            .map_err(|e| anyhow!("invalid `{}` tool arguments: {e}", schema.name));
+    }
+    msg.content
+        .as_deref()
+        .and_then(extract_json)
+        .ok_or_else(|| anyhow!("model did not return a `{}` result", schema.name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn response_format_serializes_like_openai() {
+        let format = ResponseFormat::json_schema(ResponseSchema {
+            name: "answer".into(),
+            description: None,
+            schema: json!({"type": "object"}),
+            strict: true,
+        });
+        assert_eq!(
+            serde_json::to_value(&format).unwrap(),
+            json!({
+                "type": "json_schema",
+                "json_schema": {"name": "answer", "schema": {"type": "object"}, "strict": true}
+            })
+        );
+    }
+
+    #[test]
+    fn json_is_found_in_text_replies() {
+        assert_eq!(extract_json(r#"{"a": 1}"#), Some(json!({"a": 1})));
+        assert_eq!(
+            extract_json("Sure:\n```json\n{\"a\": 2}\n```"),
+            Some(json!({"a": 2}))
+        );
+        assert_eq!(
+            extract_json("Result: {\"a\": {\"b\": 3}} done"),
+            Some(json!({"a": {"b": 3}}))
+        );
+        assert_eq!(extract_json("no json here"), None);
+        assert_eq!(extract_json("[1, 2]"), None);
+    }
+}
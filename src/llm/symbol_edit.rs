@@ -1,8 +1,11 @@
 use std::path::Path;
 
 use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{Value, json};
 
 use crate::analysis::SymbolSpan;
+use crate::llm::structured::{ResponseFormat, ResponseSchema, from_payload};
 use crate::llm::{ChatMessage, ChatRequest};
 
 /// シンボル限定編集用のLLM入力を表すリクエスト。
@@ -22,10 +25,51 @@ pub struct SymbolEditResponse {
     pub patch: Option<String>,
     /// シンボル定義全体の置換案
     pub replacement: Option<String>,
+    /// 変更内容の説明
+    pub explanation: Option<String>,
     /// 生レスポンス（パース失敗時のデバッグ用）
     pub raw: String,
 }
 
+/// 構造化出力で受け取るシンボル編集のペイロード。
+#[derive(Debug, Deserialize)]
+struct SymbolEditPayload {
+    #[serde(default)]
+    patch: Option<String>,
+    #[serde(default)]
+    replacement: Option<String>,
+    #[serde(default)]
+    explanation: Option<String>,
+}
+
+/// シンボル編集応答の JSON スキーマ。
+pub fn symbol_edit_schema() -> ResponseSchema {
+    ResponseSchema {
+        name: "symbol_edit".to_string(),
+        description: Some("Submit the edit of the target symbol.".to_string()),
+        schema: json!({
+            "type": "object",
+            "properties": {
+                "patch": {
+                    "type": ["string", "null"],
+                    "description": "Unified diff of the change to this file, or null"
+                },
+                "replacement": {
+                    "type": ["string", "null"],
+                    "description": "Full new definition of the symbol, or null"
+                },
+                "explanation": {
+                    "type": "string",
+                    "description": "One or two sentences describing the change"
+                }
+            },
+            "required": ["patch", "replacement", "explanation"],
+            "additionalProperties": false
+        }),
+        strict: true,
+    }
+}
+
 /// SymbolEditRequest から ChatRequest を構築する（非ストリーミング想定）。
 /// 実際の送信は既存のクライアントに委譲する。
 pub fn build_symbol_edit_chat_request(req: &SymbolEditRequest) -> ChatRequest {
@@ -39,10 +83,12 @@ pub fn build_symbol_edit_chat_request(req: &SymbolEditRequest) -> ChatRequest {
         ),
         tool_calls: Vec::new(),
         tool_call_id: None,
+        reasoning: None,
+        images: vec![],
     };
 
     let user_content = format!(
-        "Target file: {file}\nTarget symbol: {name} ({kind}) lines {start}-{end}\n\nOriginal symbol code:\n```rust\n{code}\n```\n\nInstruction:\n{inst}\n\nOutput format:\n- Prefer unified diff starting with '```diff' and including only changes for this file; or\n- A full replacement of the symbol definition inside '```rust' code fences.\n- Do not include explanations outside code blocks.",
+        "Target file: {file}\nTarget symbol: {name} ({kind}) lines {start}-{end}\n\nOriginal symbol code:\n```rust\n{code}\n```\n\nInstruction:\n{inst}\n\nOutput format (JSON object):\n- `patch`: a unified diff including only changes for this file, or null; or\n- `replacement`: the full new definition of the symbol without code fences, or null.\n- `explanation`: a short description of the change.",
         file = req.symbol.file.display(),
         name = req.symbol.name,
         kind = format_symbol_kind(&req.symbol),
@@ -57,6 +103,8 @@ pub fn build_symbol_edit_chat_request(req: &SymbolEditRequest) -> ChatRequest {
         content: Some(user_content),
         tool_calls: Vec::new(),
         tool_call_id: None,
+        reasoning: None,
+        images: vec![],
     };
 
     ChatRequest {
@@ -64,6 +112,7 @@ pub fn build_symbol_edit_chat_request(req: &SymbolEditRequest) -> ChatRequest {
         messages: vec![system, user],
         temperature: Some(0.2),
         stream: Some(false),
+        response_format: Some(ResponseFormat::json_schema(symbol_edit_schema())),
     }
 }
 
@@ -83,34 +132,33 @@ fn format_symbol_kind(symbol: &SymbolSpan) -> &'static str {
     }
 }
 
-/// LLMレスポンス文字列から SymbolEditResponse を構築する簡易パーサ。
+/// 構造化出力のペイロードから SymbolEditResponse を構築する。
 ///
-/// 優先順位:
-/// 1. ```diff ... ``` ブロックを unified diff として採用
-/// 2. ```rust ... ``` または ``` ... ``` 内の単一コードブロックを replacement として採用
-pub fn parse_symbol_edit_response(raw: &str) -> Result<SymbolEditResponse> {
-    // diff ブロック検出
-    if let Some(patch) = extract_code_block(raw, "diff") {
-        return Ok(SymbolEditResponse {
-            patch: Some(patch),
-            replacement: None,
-            raw: raw.to_string(),
-        });
-    }
-
-    // rust または無指定コードブロックを replacement として扱う
-    if let Some(code) = extract_code_block(raw, "rust").or_else(|| extract_code_block(raw, "")) {
-        return Ok(SymbolEditResponse {
-            patch: None,
-            replacement: Some(code),
-            raw: raw.to_string(),
-        });
+/// patch と replacement の両方が空の場合はエラー。replacement がコードフェンスで
+/// 囲まれていた場合はフェンスを外す。
+pub fn parse_symbol_edit_payload(payload: Value) -> Result<SymbolEditResponse> {
+    let raw = payload.to_string();
+    let parsed: SymbolEditPayload = from_payload(payload)?;
+    let non_empty = |s: Option<String>| s.filter(|s| !s.trim().is_empty());
+
+    let patch = non_empty(parsed.patch);
+    let replacement = non_empty(parsed.replacement).map(|code| {
+        extract_code_block(&code, "rust")
+            .or_else(|| extract_code_block(&code, ""))
+            .unwrap_or(code)
+    });
+    if patch.is_none() && replacement.is_none() {
+        return Err(anyhow::anyhow!(
+            "symbol edit response contains neither a patch nor a replacement"
+        ));
     }
 
-    // コードブロックが見つからない場合は、そのまま返す（呼び出し側で再試行・確認用）
-    Err(anyhow::anyhow!(
-        "failed to parse symbol edit response: no recognizable code block"
-    ))
+    Ok(SymbolEditResponse {
+        patch,
+        replacement,
+        explanation: non_empty(parsed.explanation),
+        raw,
+    })
 }
 
 fn extract_code_block(src: &str, lang: &str) -> Option<String> {
@@ -158,19 +206,22 @@ mod tests {
     use super::*;
 
     #[test]
-    fn parse_symbol_edit_response_prefers_diff() {
-        let raw = "Here is patch:\n```diff\n- old\n+ new\n```";
-        let resp = parse_symbol_edit_response(raw).unwrap();
-        assert!(resp.patch.is_some());
+    fn parse_symbol_edit_payload_reads_patch() {
+        let payload = json!({"patch": "- old\n+ new", "replacement": null, "explanation": "x"});
+        let resp = parse_symbol_edit_payload(payload).unwrap();
+        assert_eq!(resp.patch.as_deref(), Some("- old\n+ new"));
         assert!(resp.replacement.is_none());
+        assert_eq!(resp.explanation.as_deref(), Some("x"));
     }
 
     #[test]
-    fn parse_symbol_edit_response_uses_rust_block_as_replacement() {
-        let raw = "```rust\nfn foo() {}\n```";
-        let resp = parse_symbol_edit_response(raw).unwrap();
+    fn parse_symbol_edit_payload_unwraps_fenced_replacement() {
+        let payload = json!({"patch": "", "replacement": "```rust\nfn foo() {}\n```"});
+        let resp = parse_symbol_edit_payload(payload).unwrap();
         assert!(resp.patch.is_none());
         assert_eq!(resp.replacement.as_deref(), Some("fn foo() {}"));
+
+        assert!(parse_symbol_edit_payload(json!({"explanation": "nothing"})).is_err());
     }
 
     #[test]
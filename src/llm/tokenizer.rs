@@ -0,0 +1,246 @@
+//! Offline token estimation for outgoing requests.
+//!
+//! Text is counted with the `o200k_base` encoding of OpenAI's recent models
+//! (from the MIT-licensed `tiktoken-rs` crate). Other providers tokenize
+//! differently, so counts are estimates.
+
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Mutex, OnceLock};
+
+use crate::llm::types::{ChatMessage, ImagePart, ToolDef};
+
+/// Per-message framing overhead (role markers and separators).
+const MESSAGE_OVERHEAD: usize = 4;
+
+/// Framing overhead of the request as a whole (reply priming).
+const REQUEST_OVERHEAD: usize = 3;
+
+/// Messages whose counts are remembered before the cache starts over.
+const CACHED_MESSAGES: usize = 4_096;
+
+/// Tokens of an image whose size cannot be read: the most any provider
+/// charges for one image after downscaling it.
+const MAX_IMAGE_TOKENS: usize = 1_600;
+
+/// Estimated number of tokens in `text`.
+pub fn count_tokens(text: &str) -> usize {
+    if text.is_empty() {
+        return 0;
+    }
+    tiktoken_rs::o200k_base_singleton()
+        .encode_ordinary(text)
+        .len()
+}
+
+/// Counts of messages seen before, keyed by a hash of the counted fields. The
+/// agent loop estimates the whole history before every request.
+fn message_cache() -> &'static Mutex<HashMap<u64, usize>> {
+    static CACHE: OnceLock<Mutex<HashMap<u64, usize>>> = OnceLock::new();
+    CACHE.get_or_init(Mutex::default)
+}
+
+fn cache_key(msg: &ChatMessage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    msg.role.hash(&mut hasher);
+    msg.content.hash(&mut hasher);
+    msg.reasoning.hash(&mut hasher);
+    for image in &msg.images {
+        image.media_type.hash(&mut hasher);
+        image.data.hash(&mut hasher);
+    }
+    for tc in &msg.tool_calls {
+        tc.function.name.hash(&mut hasher);
+        tc.function.arguments.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Estimated prompt tokens contributed by one message.
+pub fn estimate_message_tokens(msg: &ChatMessage) -> usize {
+    let key = cache_key(msg);
+    if let Some(&total) = message_cache().lock().unwrap().get(&key) {
+        return total;
+    }
+
+    let mut total = MESSAGE_OVERHEAD + count_tokens(&msg.role);
+    if let Some(content) = &msg.content {
+        total += count_tokens(content);
+    }
+    if let Some(reasoning) = &msg.reasoning {
+        total += count_tokens(reasoning);
+    }
+    total += msg.images.iter().map(estimate_image_tokens).sum::<usize>();
+    for tc in &msg.tool_calls {
+        total += count_tokens(&tc.function.name) + count_tokens(&tc.function.arguments);
+    }
+    let mut cache = message_cache().lock().unwrap();
+    if cache.len() >= CACHED_MESSAGES {
+        cache.clear();
+    }
+    cache.insert(key, total);
+    total
+}
+
+/// Estimated prompt tokens of one image: the larger of OpenAI's high detail
+/// charge (85 plus 170 per 512px tile once the image is scaled into
+/// 2048x2048 and its short side to 768) and Anthropic's (pixels / 750 once the
+/// long side is at most 1568).
+fn estimate_image_tokens(image: &ImagePart) -> usize {
+    let Some((width, height)) = image_size(image) else {
+        return MAX_IMAGE_TOKENS;
+    };
+    let (width, height) = (width.max(1) as f64, height.max(1) as f64);
+    let scale = |w: f64, h: f64, limit: f64| {
+        let factor = (limit / w.max(h)).min(1.0);
+        (w * factor, h * factor)
+    };
+
+    let (w, h) = scale(width, height, 2048.0);
+    let factor = (768.0 / w.min(h)).min(1.0);
+    let tiles = (w * factor / 512.0).ceil() * (h * factor / 512.0).ceil();
+    let openai = 85 + 170 * tiles as usize;
+
+    let (w, h) = scale(width, height, 1568.0);
+    let anthropic = (w * h / 750.0).ceil() as usize;
+
+    openai.max(anthropic).min(MAX_IMAGE_TOKENS)
+}
+
+/// Pixel size from the header of a PNG, GIF or JPEG image.
+fn image_size(image: &ImagePart) -> Option<(u32, u32)> {
+    use base64::Engine;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(&image.data)
+        .ok()?;
+    let be16 = |at: usize| Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+    let be32 = |at: usize| Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    if bytes.starts_with(b"\x89PNG") {
+        return Some((be32(16)?, be32(20)?));
+    }
+    if bytes.starts_with(b"GIF8") {
+        let le16 =
+            |at: usize| Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32);
+        return Some((le16(6)?, le16(8)?));
+    }
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        // Walk the segments up to the start-of-frame marker
+        let mut at = 2;
+        while *bytes.get(at)? == 0xFF {
+            let marker = *bytes.get(at + 1)?;
+            if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+                return Some((be16(at + 7)?, be16(at + 5)?));
+            }
+            at += 2 + be16(at + 2)? as usize;
+        }
+    }
+    None
+}
+
+/// Estimated prompt tokens spent on tool definitions.
+pub fn estimate_tools_tokens(tools: &[ToolDef]) -> usize {
+    tools
+        .iter()
+        .map(|tool| serde_json::to_string(&tool.function).map_or(0, |json| count_tokens(&json)))
+        .sum()
+}
+
+/// Estimated prompt size of a request sending `messages` and `tools`.
+pub fn estimate_request_tokens(messages: &[ChatMessage], tools: &[ToolDef]) -> usize {
+    REQUEST_OVERHEAD
+        + messages.iter().map(estimate_message_tokens).sum::<usize>()
+        + estimate_tools_tokens(tools)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_text_has_no_tokens() {
+        assert_eq!(count_tokens(""), 0);
+    }
+
+    #[test]
+    fn common_text_merges_into_few_tokens() {
+        // Frequent words and keywords are single tokens
+        assert_eq!(count_tokens(" the"), 1);
+        assert_eq!(count_tokens("fn"), 1);
+
+        let code = "pub fn main() -> Result<()> {\n    println!(\"hello\");\n    Ok(())\n}\n";
+        let tokens = count_tokens(code);
+        assert!(tokens > 10 && tokens < code.len() / 2, "{tokens}");
+    }
+
+    #[test]
+    fn unknown_bytes_fall_back_to_byte_tokens() {
+        // Every byte is at worst its own token
+        let text = "\u{1F415}\u{1F415}";
+        assert!(count_tokens(text) <= text.len());
+        assert!(count_tokens(text) > 0);
+    }
+
+    #[test]
+    fn request_estimate_includes_messages_and_tools() {
+        let msg = ChatMessage {
+            role: "user".into(),
+            content: Some("Read the README and summarize it.".into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        };
+        let tools = crate::llm::default_tools_def();
+        let without_tools = estimate_request_tokens(std::slice::from_ref(&msg), &[]);
+        let with_tools = estimate_request_tokens(&[msg], &tools);
+        assert!(without_tools > MESSAGE_OVERHEAD);
+        assert!(with_tools > without_tools + 500);
+    }
+
+    #[test]
+    fn message_counts_are_cached() {
+        let mut msg = ChatMessage {
+            role: "tool".into(),
+            content: Some("cached tool output ".repeat(50)),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        };
+        let total = estimate_message_tokens(&msg);
+        assert_eq!(
+            message_cache().lock().unwrap().get(&cache_key(&msg)),
+            Some(&total)
+        );
+        assert_eq!(estimate_message_tokens(&msg), total);
+
+        msg.content = Some("changed".into());
+        assert!(estimate_message_tokens(&msg) < total);
+
+        // Images and reasoning are part of the key
+        let text_only = estimate_message_tokens(&msg);
+        msg.images
+            .push(crate::llm::png_from_rgba(1, 1, &[0, 0, 0, 255]).unwrap());
+        assert!(estimate_message_tokens(&msg) > text_only);
+        msg.reasoning = Some("thinking it over ".repeat(20));
+        assert!(estimate_message_tokens(&msg) > text_only + 20);
+    }
+
+    #[test]
+    fn images_are_counted_by_size() {
+        let pixels = |w: u32, h: u32| vec![0u8; (w * h * 4) as usize];
+        let small = crate::llm::png_from_rgba(64, 64, &pixels(64, 64)).unwrap();
+        let large = crate::llm::png_from_rgba(1024, 1024, &pixels(1024, 1024)).unwrap();
+        assert_eq!(image_size(&large), Some((1024, 1024)));
+        // One tile for OpenAI beats Anthropic's 6 tokens
+        assert_eq!(estimate_image_tokens(&small), 255);
+        // 4 tiles (765 tokens) against 1_399 for Anthropic
+        assert_eq!(estimate_image_tokens(&large), 1_399);
+
+        let unknown = ImagePart {
+            media_type: "image/webp".into(),
+            data: "UklGRg==".into(),
+        };
+        assert_eq!(estimate_image_tokens(&unknown), MAX_IMAGE_TOKENS);
+    }
+}
@@ -1,8 +1,11 @@
 mod agent_loop;
+mod context;
 mod dispatch;
 mod requests;
 mod streaming;
+mod sub_agent;
 
 pub use agent_loop::run_agent_loop;
-pub use dispatch::dispatch_tool_call;
-pub use streaming::run_agent_streaming_once;
+pub(crate) use requests::chat_tools_once_openai;
+pub(crate) use streaming::stream_assistant_turn;
+pub(crate) use sub_agent::Delegate;
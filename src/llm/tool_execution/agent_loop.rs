@@ -1,8 +1,12 @@
 use crate::diff_review::DiffReviewPayload;
 use crate::llm::LlmErrorKind;
+use crate::llm::provider::LlmProvider;
+use crate::llm::retry::RetryPolicy;
+use crate::llm::tool_execution::Delegate;
 use crate::llm::tool_runtime::ToolRuntime;
 use crate::llm::types::{ChatMessage, ChoiceMessage};
 use crate::tools::FsTools;
+use crate::tools::submit_plan::Plan;
 use crate::tools::todo_write::TodoList;
 use anyhow::{Context, Result, anyhow};
 use chrono::{DateTime, FixedOffset, Utc};
@@ -10,6 +14,39 @@ use std::process::Command;
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, warn};
 
+/// Appended to the system prompt while in plan mode.
+const PLAN_MODE_INSTRUCTIONS: &str = "# Plan Mode\n\
+Plan mode is active: investigate and plan, but do not change anything. Tools that modify files \
+are unavailable and `execute_bash` only runs read-only commands. When you understand the task, \
+call `submit_plan` with a short summary and the ordered implementation steps. The user reviews \
+the plan before any edit is made.";
+
+/// Sent once when the model ends a plan mode turn without a plan.
+const PLAN_MODE_REMINDER: &str =
+    "Plan mode is active. Finish by calling `submit_plan` with the summary and the ordered steps.";
+
+/// Add the plan mode instructions to the leading system prompt.
+fn add_plan_mode_instructions(messages: &mut Vec<ChatMessage>) {
+    match messages.first_mut() {
+        Some(first) if first.role == "system" => {
+            let content = first.content.get_or_insert_with(String::new);
+            content.push_str("\n\n");
+            content.push_str(PLAN_MODE_INSTRUCTIONS);
+        }
+        _ => messages.insert(
+            0,
+            ChatMessage {
+                role: "system".into(),
+                content: Some(PLAN_MODE_INSTRUCTIONS.into()),
+                tool_calls: vec![],
+                tool_call_id: None,
+                reasoning: None,
+                images: vec![],
+            },
+        ),
+    }
+}
+
 fn collect_diff_review_payload() -> Result<Option<DiffReviewPayload>> {
     let tracked_diff = Command::new("git")
         .arg("diff")
@@ -94,22 +131,169 @@ fn collect_diff_review_payload() -> Result<Option<DiffReviewPayload>> {
     }))
 }
 
+/// Fallback model chain for one agent loop run.
+///
+/// Once the active model keeps failing with an outage-type error, the next
+/// configured fallback takes over for the rest of the run. Usage of fallback
+/// models is folded into the primary client's log so cost accounting still
+/// sees it, and the UI is told which model is answering.
+struct Fallback<'a> {
+    primary: &'a dyn LlmProvider,
+    primary_model: &'a str,
+    cfg: &'a crate::config::AppConfig,
+    ui_tx: Option<std::sync::mpsc::Sender<String>>,
+    active: Option<(std::sync::Arc<dyn LlmProvider>, String)>,
+    next: usize,
+}
+
+impl<'a> Fallback<'a> {
+    fn new(
+        primary: &'a dyn LlmProvider,
+        primary_model: &'a str,
+        cfg: &'a crate::config::AppConfig,
+        ui_tx: Option<std::sync::mpsc::Sender<String>>,
+    ) -> Self {
+        Self {
+            primary,
+            primary_model,
+            cfg,
+            ui_tx,
+            active: None,
+            next: 0,
+        }
+    }
+
+    fn active(&self) -> (&dyn LlmProvider, &str) {
+        match &self.active {
+            Some((client, model)) => (client.as_ref(), model.as_str()),
+            None => (self.primary, self.primary_model),
+        }
+    }
+
+    fn collect_usage(&self) {
+        if let Some((client, _)) = &self.active {
+            for usage in client.usage_log().drain() {
+                self.primary.usage_log().record(usage);
+            }
+        }
+    }
+
+    /// Move to the next usable fallback if `err` warrants it.
+    fn switch(&mut self, err: &anyhow::Error, fs: &FsTools) -> Result<bool> {
+        let kind = crate::llm::classify_error(None, err);
+        if !kind.warrants_fallback() {
+            return Ok(false);
+        }
+        while self.next < self.cfg.fallback_models.len() {
+            let index = self.next;
+            self.next += 1;
+            let Some((client, model)) = crate::llm::build_fallback_provider(self.cfg, index)?
+            else {
+                warn!(index, "skipping fallback model without an API key");
+                continue;
+            };
+            let from = self.active().1.to_string();
+            let reason = kind.to_string();
+            warn!(%from, to = %model, %reason, "switching to fallback model");
+            if let Err(e) = fs.record_model_switch(&from, &model, &reason) {
+                warn!(error = %e, "failed to record model switch in session");
+            }
+            if let Some(tx) = &self.ui_tx {
+                let _ = tx.send(format!(
+                    "[WARN] {from} is unavailable ({reason}); switching to fallback model {model} for the rest of this turn."
+                ));
+                let _ = tx.send(format!("::model:{model}"));
+            }
+            self.active = Some((client, model));
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+impl Drop for Fallback<'_> {
+    fn drop(&mut self) {
+        // The next turn starts on the primary model again
+        if self.active.is_some()
+            && let Some(tx) = &self.ui_tx
+        {
+            let _ = tx.send(format!("::model:{}", self.primary_model));
+        }
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub async fn run_agent_loop(
-    client: &crate::llm::client_core::OpenAIClient,
+    client: &dyn LlmProvider,
     model: &str,
     fs: &FsTools,
     mut messages: Vec<ChatMessage>,
     ui_tx: Option<std::sync::mpsc::Sender<String>>,
     cancel: Option<CancellationToken>,
     cfg: &crate::config::AppConfig,
-    tui_executor: Option<&crate::tui::commands::core::TuiExecutor>,
+    _tui_executor: Option<&crate::tui::commands::core::TuiExecutor>,
 ) -> Result<(Vec<ChatMessage>, ChoiceMessage)> {
     debug!("run_agent_loop called");
-    let runtime = ToolRuntime::build(fs).await?;
-    let mut iters = 0usize;
     let cancel_token = cancel.unwrap_or_default();
+    let mut runtime = ToolRuntime::build(fs).await?;
+    if cfg.plan_mode {
+        runtime.enter_plan_mode();
+        add_plan_mode_instructions(&mut messages);
+    }
+    let runtime = runtime
+        .with_delegate(Delegate {
+            client,
+            model,
+            cfg,
+            cancel: cancel_token.clone(),
+            ui_tx: ui_tx.clone(),
+        })
+        .with_ui_tx(ui_tx.clone());
+    run_loop(client, model, &runtime, messages, ui_tx, cancel_token, cfg).await
+}
+
+/// History replacing `messages` once their `request` no longer fits the
+/// context window. A failed compaction still reports the context length as
+/// the cause.
+async fn compact(
+    client: &dyn LlmProvider,
+    model: &str,
+    messages: &[ChatMessage],
+    request: &[ChatMessage],
+    budget: usize,
+    ui_tx: Option<&std::sync::mpsc::Sender<String>>,
+) -> Result<Vec<ChatMessage>> {
+    if let Some(tx) = ui_tx {
+        let _ = tx.send("[INFO] Context window full. Compacting conversation history...".into());
+    }
+    crate::llm::compact_to_fit(client, model, messages, request, budget)
+        .await
+        .map_err(|e| {
+            warn!(error = %e, "compaction failed");
+            e.context(LlmErrorKind::ContextLengthExceeded)
+        })
+}
+
+/// The agent loop proper, with the tools and iteration budget of `runtime`.
+pub(super) async fn run_loop(
+    client: &dyn LlmProvider,
+    model: &str,
+    runtime: &ToolRuntime<'_>,
+    mut messages: Vec<ChatMessage>,
+    ui_tx: Option<std::sync::mpsc::Sender<String>>,
+    cancel_token: CancellationToken,
+    cfg: &crate::config::AppConfig,
+) -> Result<(Vec<ChatMessage>, ChoiceMessage)> {
+    let fs = runtime.fs;
+    let mut tool_mode =
+        crate::llm::prompt_tools::effective_mode(cfg.tool_call_mode_for_model(model), model);
+    let mut fallback = Fallback::new(client, model, cfg, ui_tx.clone());
+    let retry_policy = RetryPolicy::from_config(&cfg.llm);
+    let mut iters = 0usize;
     let mut file_was_written = false;
+    let mut plan_reminded = false;
+    // Set once the history was compacted, until a request succeeds again
+    let mut compacted = false;
 
     loop {
         iters += 1;
@@ -119,44 +303,95 @@ pub async fn run_agent_loop(
             return Err(anyhow!("max tool iterations reached"));
         }
 
-        let msg = tokio::select! {
-            biased;
-            _ = cancel_token.cancelled() => {
-                warn!("run_agent_loop cancelled before chat_tools_once");
-                return Err(anyhow!(LlmErrorKind::Cancelled));
+        // Keep the request inside the model's context window: trim old tool output
+        // first, and compact the history if that is not enough.
+        let (client, model) = fallback.active();
+        crate::llm::ensure_vision(cfg, model, &messages)?;
+        let window = cfg.context_window_for_model(model);
+        let budget = crate::llm::prompt_budget(window) as usize;
+        let (request, fit) = super::context::fit_to_budget(&messages, &runtime.tools, budget);
+        if let Some(tx) = &ui_tx {
+            if fit.trimmed > 0 {
+                let _ = tx.send(format!(
+                    "[INFO] Trimmed {} old tool results to fit the context window.",
+                    fit.trimmed
+                ));
             }
-            res = crate::llm::tool_execution::requests::chat_tools_once(
-                client,
-                model,
-                messages.clone(),
-                &runtime.tools,
-                Some(cancel_token.clone()),
-            ) => {
-                match res {
-                    Ok(msg) => msg,
-                    Err(e) => {
-                        // Check if the error is due to context length exceeded
-                        if let Some(LlmErrorKind::ContextLengthExceeded) = e.downcast_ref::<LlmErrorKind>()
-                            && let Some(_executor) = tui_executor {
-                                // Send a message to the UI to indicate that we are compacting
-                                if let Some(tx) = &ui_tx {
-                                    let _ = tx.send("[INFO] Context length exceeded. Compacting conversation history...".to_string());
-                                }
+            let _ = tx.send(format!("::context:used:{},window:{}", fit.estimate, window));
+        }
+        if fit.estimate > budget {
+            warn!(
+                estimate = fit.estimate,
+                budget, "request does not fit the context window"
+            );
+            if compacted {
+                return Err(anyhow!(LlmErrorKind::ContextLengthExceeded));
+            }
+            messages = compact(client, model, &messages, &request, budget, ui_tx.as_ref()).await?;
+            compacted = true;
+            continue;
+        }
 
-                                // Call the compact command
-                                // Since we don't have access to TuiApp here, we'll need to find a way to trigger the compact command.
-                                // One approach is to send a special message to the UI to trigger the compact command.
-                                // For now, we'll just return an error to indicate that the operation should be retried after compacting.
-                                // A better approach would be to have a callback or a channel to notify the TUI to run the compact command.
-                                // For now, we'll return the error to let the caller handle it.
-                                return Err(anyhow!(LlmErrorKind::ContextLengthExceeded));
-                            }
-                        return Err(e);
-                    }
-                }
-            },
+        let res = crate::llm::tool_execution::stream_assistant_turn(
+            client,
+            model,
+            &request,
+            &runtime.tools,
+            &mut tool_mode,
+            ui_tx.as_ref(),
+            &cancel_token,
+            &retry_policy,
+        )
+        .await;
+        fallback.collect_usage();
+        let msg = match res {
+            Ok(msg) => {
+                compacted = false;
+                msg
+            }
+            // The provider counts differently than the local estimate
+            Err(e)
+                if !compacted
+                    && e.downcast_ref::<LlmErrorKind>()
+                        == Some(&LlmErrorKind::ContextLengthExceeded) =>
+            {
+                messages =
+                    compact(client, model, &messages, &request, budget, ui_tx.as_ref()).await?;
+                compacted = true;
+                continue;
+            }
+            Err(e) if fallback.switch(&e, fs)? => {
+                tool_mode = crate::llm::prompt_tools::effective_mode(
+                    cfg.tool_call_mode_for_model(fallback.active().1),
+                    fallback.active().1,
+                );
+                continue;
+            }
+            Err(e) => return Err(e),
         };
 
+        // A plan mode turn has to end with a plan; remind the model once.
+        if msg.tool_calls.is_empty() && runtime.plan_mode() && !plan_reminded {
+            plan_reminded = true;
+            messages.push(ChatMessage {
+                role: "assistant".into(),
+                content: msg.content.clone(),
+                tool_calls: vec![],
+                tool_call_id: None,
+                reasoning: msg.reasoning.clone(),
+                images: vec![],
+            });
+            messages.push(ChatMessage {
+                role: "user".into(),
+                content: Some(PLAN_MODE_REMINDER.into()),
+                tool_calls: vec![],
+                tool_call_id: None,
+                reasoning: None,
+                images: vec![],
+            });
+            continue;
+        }
+
         // If assistant returned final content without tool calls, we are done.
         if msg.tool_calls.is_empty() {
             // Send final assistant content to UI (if present)
@@ -173,6 +408,8 @@ pub async fn run_agent_loop(
                 content: msg.content.clone(),
                 tool_calls: msg.tool_calls.clone(),
                 tool_call_id: None,
+                reasoning: msg.reasoning.clone(),
+                images: vec![],
             });
 
             // If files were written during tool execution, compute and send git diff
@@ -219,76 +456,109 @@ pub async fn run_agent_loop(
             ));
         }
 
-        // There are tool calls to process. Send intermediate content if available.
-        if let Some(content) = &msg.content
-            && !content.is_empty()
-            && let Some(tx) = &ui_tx
-        {
-            debug!(response_content = ?content, "Sending intermediate LLM response content.");
-            let _ = tx.send(content.clone());
-        }
-
+        // There are tool calls to process. Any intermediate content has already been
+        // streamed to the UI and is finalized by the processing status below.
         messages.push(ChatMessage {
             role: "assistant".into(),
             content: msg.content.clone(),
             tool_calls: msg.tool_calls.clone(),
             tool_call_id: None,
+            reasoning: msg.reasoning.clone(),
+            images: vec![],
         });
 
-        for tc in msg.tool_calls {
+        // Images returned by tools, sent together after the tool results
+        let mut tool_images = Vec::new();
+        let mut submitted_plan = None;
+        let mut pending = msg.tool_calls.into_iter().peekable();
+        while let Some(first) = pending.next() {
+            // Consecutive read-only calls run as one concurrent batch; anything
+            // else runs on its own so side effects keep their order.
+            let mut batch = vec![first];
+            if runtime.is_read_only(&batch[0].function.name) {
+                while let Some(tc) = pending.next_if(|tc| runtime.is_read_only(&tc.function.name)) {
+                    batch.push(tc);
+                }
+            }
+
             // Always send processing status to UI if available
             if let Some(tx) = &ui_tx {
                 let _ = tx.send("::status:processing".into());
             }
 
-            // Prepare and sanitize arguments for logging
-            let args_str = tc.function.arguments.clone();
-            if let Ok(mut args_val) = serde_json::from_str::<serde_json::Value>(&args_str) {
-                if let Some(obj) = args_val.as_object_mut() {
-                    if tc.function.name == "fs_write" {
-                        obj.remove("content");
-                    }
+            let results = tokio::select! {
+                biased;
+                _ = cancel_token.cancelled() => {
+                    warn!("run_agent_loop cancelled before dispatch_tool_calls");
+                    return Err(anyhow!(LlmErrorKind::Cancelled));
+                }
+                res = crate::llm::tool_execution::dispatch::dispatch_tool_calls(
+                    runtime,
+                    &batch,
+                    cfg.max_parallel_tools,
+                ) => res,
+            };
 
-                    for key in ["path", "paths", "file_path", "filename"].iter() {
-                        if let Some(value) = obj.get_mut(*key) {
-                            if value.is_string() {
-                                if let Some(path_str) = value.as_str() {
-                                    // Convert to relative path from project root
-                                    let project_root = std::env::current_dir()
-                                        .unwrap_or_else(|_| std::path::PathBuf::from("."));
-                                    if let Ok(relative_path) =
-                                        std::path::Path::new(path_str).strip_prefix(&project_root)
-                                    {
-                                        *value = format!("@{}", relative_path.display()).into();
-                                    } else {
-                                        // If we can't get a relative path, at least show the file name
-                                        if let Some(file_name) = std::path::Path::new(path_str)
-                                            .file_name()
-                                            .and_then(|s| s.to_str())
-                                        {
-                                            *value = file_name.to_string().into();
-                                        }
-                                    }
-                                }
-                            } else if value.is_array()
-                                && let Some(arr) = value.as_array_mut()
-                            {
-                                for item in arr.iter_mut() {
-                                    if let Some(path_str) = item.as_str() {
+            // Results are reported and fed back in the original call order
+            for (tc, mut res) in batch.into_iter().zip(results) {
+                if let Ok(value) = &mut res
+                    && let Some(image) = super::dispatch::take_image_attachment(value)
+                {
+                    tool_images.push(image);
+                }
+
+                // Prepare and sanitize arguments for logging
+                let args_str = tc.function.arguments.clone();
+                if let Ok(mut args_val) = serde_json::from_str::<serde_json::Value>(&args_str) {
+                    if let Some(obj) = args_val.as_object_mut() {
+                        if tc.function.name == "fs_write" {
+                            obj.remove("content");
+                        }
+
+                        for key in ["path", "paths", "file_path", "filename"].iter() {
+                            if let Some(value) = obj.get_mut(*key) {
+                                if value.is_string() {
+                                    if let Some(path_str) = value.as_str() {
                                         // Convert to relative path from project root
                                         let project_root = std::env::current_dir()
                                             .unwrap_or_else(|_| std::path::PathBuf::from("."));
                                         if let Ok(relative_path) = std::path::Path::new(path_str)
                                             .strip_prefix(&project_root)
                                         {
-                                            *item = format!("@{}", relative_path.display()).into();
+                                            *value = format!("@{}", relative_path.display()).into();
                                         } else {
                                             // If we can't get a relative path, at least show the file name
                                             if let Some(file_name) = std::path::Path::new(path_str)
                                                 .file_name()
                                                 .and_then(|s| s.to_str())
                                             {
-                                                *item = file_name.to_string().into();
+                                                *value = file_name.to_string().into();
+                                            }
+                                        }
+                                    }
+                                } else if value.is_array()
+                                    && let Some(arr) = value.as_array_mut()
+                                {
+                                    for item in arr.iter_mut() {
+                                        if let Some(path_str) = item.as_str() {
+                                            // Convert to relative path from project root
+                                            let project_root = std::env::current_dir()
+                                                .unwrap_or_else(|_| std::path::PathBuf::from("."));
+                                            if let Ok(relative_path) =
+                                                std::path::Path::new(path_str)
+                                                    .strip_prefix(&project_root)
+                                            {
+                                                *item =
+                                                    format!("@{}", relative_path.display()).into();
+                                            } else {
+                                                // If we can't get a relative path, at least show the file name
+                                                if let Some(file_name) =
+                                                    std::path::Path::new(path_str)
+                                                        .file_name()
+                                                        .and_then(|s| s.to_str())
+                                                {
+                                                    *item = file_name.to_string().into();
+                                                }
                                             }
                                         }
                                     }
@@ -296,142 +566,560 @@ pub async fn run_agent_loop(
                             }
                         }
                     }
-                }
 
-                let _ = serde_json::to_string(&args_val);
-            }
+                    let _ = serde_json::to_string(&args_val);
+                }
 
-            let mut args_str_truncated = args_str;
-            const MAX_ARG_LEN: usize = 120;
-            if args_str_truncated.len() > MAX_ARG_LEN {
-                args_str_truncated = format!(
-                    "{}...",
-                    args_str_truncated
-                        .chars()
-                        .take(MAX_ARG_LEN - 3)
-                        .collect::<String>()
-                );
-            }
+                let mut args_str_truncated = args_str;
+                const MAX_ARG_LEN: usize = 120;
+                if args_str_truncated.len() > MAX_ARG_LEN {
+                    args_str_truncated = format!(
+                        "{}...",
+                        args_str_truncated
+                            .chars()
+                            .take(MAX_ARG_LEN - 3)
+                            .collect::<String>()
+                    );
+                }
 
-            // Currently args_str_truncated is only used for potential future logging.
-            let _ = &args_str_truncated;
-            let res = tokio::select! {
-                biased;
-                _ = cancel_token.cancelled() => {
-                    warn!("run_agent_loop cancelled before dispatch_tool_call");
-                    return Err(anyhow!(LlmErrorKind::Cancelled));
+                // Currently args_str_truncated is only used for potential future logging.
+                let _ = &args_str_truncated;
+                // Set file_was_written flag for tools that modify files
+                if (tc.function.name == "fs_write"
+                    || tc.function.name == "edit"
+                    || tc.function.name == "apply_patch")
+                    && res.is_ok()
+                {
+                    file_was_written = true;
                 }
-                res = crate::llm::tool_execution::dispatch::dispatch_tool_call(&runtime, tc.clone()) => res,
-            };
 
-            // Set file_was_written flag for tools that modify files
-            if (tc.function.name == "fs_write"
-                || tc.function.name == "edit"
-                || tc.function.name == "apply_patch")
-                && res.is_ok()
-            {
-                file_was_written = true;
-            }
+                // Build tool message content (full JSON) for feeding back to the LLM
+                let tool_message_content = match &res {
+                    Ok(value) => serde_json::to_string(value).unwrap_or_else(|_e| {
+                        "{\"error\":\"failed to serialize tool result\"}".to_string()
+                    }),
+                    Err(e) => {
+                        error!(error = %e, "tool execution failed");
+                        serde_json::to_string(&serde_json::json!({ "error": e.to_string() }))
+                            .unwrap_or_else(|_e| {
+                                "{\"error\":\"failed to serialize error\"}".to_string()
+                            })
+                    }
+                };
 
-            // Build tool message content (full JSON) for feeding back to the LLM
-            let tool_message_content = match &res {
-                Ok(value) => serde_json::to_string(value).unwrap_or_else(|_e| {
-                    "{\"error\":\"failed to serialize tool result\"}".to_string()
-                }),
-                Err(e) => {
-                    error!(error = %e, "tool execution failed");
-                    serde_json::to_string(&serde_json::json!({ "error": e.to_string() }))
-                        .unwrap_or_else(|_e| {
-                            "{\"error\":\"failed to serialize error\"}".to_string()
-                        })
+                // Prepare a short result summary for UI log and truncate if necessary
+                let mut result_summary = tool_message_content.clone();
+                const MAX_RESULT_LEN: usize = 200;
+                if result_summary.len() > MAX_RESULT_LEN {
+                    let mut t = result_summary
+                        .chars()
+                        .take(MAX_RESULT_LEN - 3)
+                        .collect::<String>();
+                    t.push_str("...");
+                    result_summary = t;
                 }
-            };
 
-            // Prepare a short result summary for UI log and truncate if necessary
-            let mut result_summary = tool_message_content.clone();
-            const MAX_RESULT_LEN: usize = 200;
-            if result_summary.len() > MAX_RESULT_LEN {
-                let mut t = result_summary
-                    .chars()
-                    .take(MAX_RESULT_LEN - 3)
-                    .collect::<String>();
-                t.push_str("...");
-                result_summary = t;
-            }
+                // Send a more visually appealing multi-line tool execution display
+                if let Some(tx) = &ui_tx {
+                    let success = res.is_ok();
+                    let status_text = if success { "✅ SUCCESS" } else { "❌ FAILED" };
 
-            // Send a more visually appealing multi-line tool execution display
-            if let Some(tx) = &ui_tx {
-                let success = res.is_ok();
-                let status_text = if success { "✅ SUCCESS" } else { "❌ FAILED" };
-
-                let tool_name = tc.function.name.as_str();
-
-                // Map tool names to appropriate icons
-                let tool_icon = match tool_name {
-                    "fs_list" => "🗂️",
-                    "fs_read" => "📖",
-                    "fs_read_many_files" => "📚",
-                    "fs_write" => "📝",
-                    "search_text" => "🔍",
-                    "execute_bash" => "🔧",
-                    "find_file" => "📁",
-                    "search_repomap" => "🗺️",
-                    "edit" => "✏️",
-                    "apply_patch" => "🧩",
-                    "todo_write" => "📋",
-                    "todo_read" => "📋",
-                    _ => "🔧", // default icon
-                };
+                    let tool_name = tc.function.name.as_str();
 
-                let start_time = std::time::SystemTime::now();
-                let utc_datetime: DateTime<Utc> = start_time.into();
-                let jst_offset = FixedOffset::east_opt(9 * 3600).unwrap(); // JST is UTC+9
-                let jst_datetime = utc_datetime.with_timezone(&jst_offset);
-                let timestamp_short = jst_datetime.format("%H:%M:%S").to_string(); // HH:MM:SS format in JST
+                    // Map tool names to appropriate icons
+                    let tool_icon = match tool_name {
+                        "fs_list" => "🗂️",
+                        "fs_read" => "📖",
+                        "fs_read_many_files" => "📚",
+                        "fs_write" => "📝",
+                        "search_text" => "🔍",
+                        "execute_bash" => "🔧",
+                        "bash_output" => "📟",
+                        "bash_kill" => "🛑",
+                        "find_file" => "📁",
+                        "search_repomap" => "🗺️",
+                        "edit" => "✏️",
+                        "apply_patch" => "🧩",
+                        "todo_write" => "📋",
+                        "todo_read" => "📋",
+                        "spawn_agent" => "🤖",
+                        "submit_plan" => "🗒️",
+                        _ => "🔧", // default icon
+                    };
 
-                // Send indented lines to create a visually distinct tool execution display
-                let header_line =
-                    format!("🛠️  [{timestamp_short}] {tool_icon} {tool_name} => {status_text}");
-                let _ = tx.send(header_line);
+                    let start_time = std::time::SystemTime::now();
+                    let utc_datetime: DateTime<Utc> = start_time.into();
+                    let jst_offset = FixedOffset::east_opt(9 * 3600).unwrap(); // JST is UTC+9
+                    let jst_datetime = utc_datetime.with_timezone(&jst_offset);
+                    let timestamp_short = jst_datetime.format("%H:%M:%S").to_string(); // HH:MM:SS format in JST
 
-                // Tool arguments and results are intentionally not displayed in the TUI to avoid leaking sensitive data.
+                    // Send indented lines to create a visually distinct tool execution display
+                    let header_line =
+                        format!("🛠️  [{timestamp_short}] {tool_icon} {tool_name} => {status_text}");
+                    let _ = tx.send(header_line);
 
-                let _ = tx.send("".to_string()); // Extra blank line for spacing
-            }
+                    // Tool arguments and results are intentionally not displayed in the TUI to avoid leaking sensitive data.
 
-            // Also emit structured debug/error logs (include truncated result summary for debugging)
-            match &res {
-                Ok(_) => debug!("[tool] {} succeeded: {}", tc.function.name, result_summary),
-                Err(e) => error!("[tool] {} failed: {}", tc.function.name, e),
-            }
+                    let _ = tx.send("".to_string()); // Extra blank line for spacing
+                }
 
-            // Inform the UI that tool processing is complete and we are waiting for the LLM
-            if let Some(tx) = &ui_tx {
-                let _ = tx.send("::status:waiting".into());
-            }
+                // Also emit structured debug/error logs (include truncated result summary for debugging)
+                match &res {
+                    Ok(_) => debug!("[tool] {} succeeded: {}", tc.function.name, result_summary),
+                    Err(e) => error!("[tool] {} failed: {}", tc.function.name, e),
+                }
 
-            // Check if the tool call is todo_write and update the todo list in the UI
-            if tc.function.name == "todo_write"
-                && let Ok(tool_result) = &res
-                && let Ok(todo_list) = serde_json::from_value::<TodoList>(tool_result.clone())
-            {
-                debug!(?todo_list, "Updated todo list from todo_write tool");
-                // Send the todo list to the UI
+                // Inform the UI that tool processing is complete and we are waiting for the LLM
                 if let Some(tx) = &ui_tx {
-                    // Serialize the todo list to JSON and send it to the UI
-                    if let Ok(todo_list_json) = serde_json::to_string(&todo_list.todos) {
-                        let _ = tx.send(format!("::todo_list:{}", todo_list_json));
+                    let _ = tx.send("::status:waiting".into());
+                }
+
+                // Check if the tool call is todo_write and update the todo list in the UI
+                if tc.function.name == "todo_write"
+                    && let Ok(tool_result) = &res
+                    && let Ok(todo_list) = serde_json::from_value::<TodoList>(tool_result.clone())
+                {
+                    debug!(?todo_list, "Updated todo list from todo_write tool");
+                    // Send the todo list to the UI
+                    if let Some(tx) = &ui_tx {
+                        // Serialize the todo list to JSON and send it to the UI
+                        if let Ok(todo_list_json) = serde_json::to_string(&todo_list.todos) {
+                            let _ = tx.send(format!("::todo_list:{}", todo_list_json));
+                        }
                     }
                 }
+
+                if tc.function.name == "submit_plan"
+                    && let Ok(value) = &res
+                {
+                    submitted_plan = serde_json::from_value::<Plan>(value.clone()).ok();
+                }
+
+                // tool message to feed back to the LLM
+                messages.push(ChatMessage {
+                    role: "tool".into(),
+                    content: Some(tool_message_content),
+                    tool_calls: vec![],
+                    tool_call_id: tc.id,
+                    reasoning: None,
+                    images: vec![],
+                });
             }
+        }
+
+        if !tool_images.is_empty() {
+            messages.push(ChatMessage {
+                role: "user".into(),
+                content: Some("Images read by the tool calls above:".into()),
+                tool_calls: vec![],
+                tool_call_id: None,
+                reasoning: None,
+                images: tool_images,
+            });
+        }
 
-            // tool message to feed back to the LLM
+        // A submitted plan ends the turn; the user reviews it before anything else happens
+        if let Some(plan) = submitted_plan {
+            let content = plan.to_markdown();
+            if let Some(tx) = &ui_tx {
+                let _ = tx.send(format!("::status:done:{content}"));
+                if let Ok(json) = serde_json::to_string(&plan) {
+                    let _ = tx.send(format!("::plan:{json}"));
+                }
+            }
             messages.push(ChatMessage {
-                role: "tool".into(),
-                content: Some(tool_message_content),
+                role: "assistant".into(),
+                content: Some(content.clone()),
                 tool_calls: vec![],
-                tool_call_id: tc.id,
+                tool_call_id: None,
+                reasoning: None,
+                images: vec![],
             });
+            return Ok((
+                messages,
+                ChoiceMessage {
+                    role: "assistant".into(),
+                    content,
+                },
+            ));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AppConfig, FallbackModel, LlmConfig};
+    use crate::llm::OpenAIClient;
+    use crate::mock_llm::{Scenario, router};
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    async fn serve(scenario: &str) -> String {
+        let scenario: Scenario = toml::from_str(scenario).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(scenario)).await });
+        format!("http://{addr}/v1")
+    }
+
+    #[tokio::test]
+    async fn persistent_outage_switches_to_fallback_model() {
+        let primary_url = serve(
+            r#"
+            repeat = true
+            [[steps]]
+            type = "error"
+            status = 503
+            "#,
+        )
+        .await;
+        let fallback_url = serve(
+            r#"
+            [[steps]]
+            type = "text"
+            content = "Answered by the backup."
+            "#,
+        )
+        .await;
+
+        let project = tempfile::tempdir().unwrap();
+        let llm = LlmConfig {
+            max_retries: 0,
+            ..Default::default()
+        };
+        let cfg = AppConfig {
+            project_root: project.path().to_path_buf(),
+            llm: llm.clone(),
+            fallback_models: vec![FallbackModel {
+                provider: "openai".into(),
+                model: "backup".into(),
+                base_url: fallback_url,
+                api_key: Some("k".into()),
+            }],
+            ..Default::default()
+        };
+        let fs = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg.clone()));
+        let client = OpenAIClient::new(primary_url, "k")
+            .unwrap()
+            .with_llm_config(llm);
+        let prompt = vec![ChatMessage {
+            role: "user".into(),
+            content: Some("hello".into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        }];
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (_, last) = run_agent_loop(&client, "primary", &fs, prompt, Some(tx), None, &cfg, None)
+            .await
+            .unwrap();
+        assert_eq!(last.content, "Answered by the backup.");
+
+        let events: Vec<String> = rx.try_iter().collect();
+        let models: Vec<&str> = events
+            .iter()
+            .filter_map(|e| e.strip_prefix("::model:"))
+            .collect();
+        assert_eq!(models, ["backup", "primary"]);
+        assert!(
+            events
+                .iter()
+                .any(|e| e.starts_with("[WARN] primary is unavailable (server error)"))
+        );
+        // Fallback usage is accounted to the primary client
+        assert!(
+            client
+                .usage_log()
+                .drain()
+                .iter()
+                .any(|u| u.model == "backup")
+        );
+    }
+
+    #[tokio::test]
+    async fn history_is_compacted_when_the_context_is_exceeded() {
+        let url = serve(
+            r#"
+            [[steps]]
+            type = "context_length"
+            [[steps]]
+            type = "text"
+            content = '{"overall_goal": "Say hello.", "key_knowledge": [], "file_system_state": [], "recent_actions": []}'
+            [[steps]]
+            type = "text"
+            content = "Hello."
+            "#,
+        )
+        .await;
+
+        let project = tempfile::tempdir().unwrap();
+        let cfg = AppConfig {
+            project_root: project.path().to_path_buf(),
+            ..Default::default()
+        };
+        let fs = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg.clone()));
+        let client = OpenAIClient::new(url, "k").unwrap();
+        let message = |role: &str, content: &str| ChatMessage {
+            role: role.into(),
+            content: Some(content.into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        };
+        let prompt = vec![message("system", "Be brief."), message("user", "hello")];
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (messages, last) =
+            run_agent_loop(&client, "model", &fs, prompt, Some(tx), None, &cfg, None)
+                .await
+                .unwrap();
+        assert_eq!(last.content, "Hello.");
+        assert_eq!(messages[0].content.as_deref(), Some("Be brief."));
+        let snapshot = messages[1].content.as_deref().unwrap();
+        assert!(snapshot.contains("Say hello."), "{snapshot}");
+        assert_eq!(messages.len(), 3);
+        assert!(
+            rx.try_iter()
+                .any(|e| e.starts_with("[INFO] Context window full"))
+        );
+    }
+
+    #[tokio::test]
+    async fn fs_read_sends_images_to_vision_models() {
+        let project = tempfile::tempdir().unwrap();
+        let shot = project.path().join("shot.png");
+        let image = crate::llm::png_from_rgba(1, 1, &[255, 0, 0, 255]).unwrap();
+        std::fs::write(
+            &shot,
+            base64::Engine::decode(&base64::engine::general_purpose::STANDARD, &image.data)
+                .unwrap(),
+        )
+        .unwrap();
+        let url = serve(&format!(
+            r#"
+            [[steps]]
+            type = "tool_calls"
+            calls = [{{ name = "fs_read", arguments = {{ path = "{}" }} }}]
+            [[steps]]
+            type = "text"
+            content = "A red pixel."
+            "#,
+            shot.display()
+        ))
+        .await;
+
+        let cfg = AppConfig {
+            project_root: project.path().to_path_buf(),
+            model: "gpt-4o".into(),
+            ..Default::default()
+        };
+        let fs = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg.clone()));
+        let client = OpenAIClient::new(url, "k").unwrap();
+        let prompt = vec![ChatMessage {
+            role: "user".into(),
+            content: Some("what is in shot.png?".into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        }];
+
+        let (messages, last) = run_agent_loop(
+            &client,
+            "gpt-4o",
+            &fs,
+            prompt.clone(),
+            None,
+            None,
+            &cfg,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(last.content, "A red pixel.");
+        let tool = messages.iter().find(|m| m.role == "tool").unwrap();
+        assert!(!tool.content.as_ref().unwrap().contains(&image.data));
+        let attached = messages.iter().find(|m| !m.images.is_empty()).unwrap();
+        assert_eq!(attached.role, "user");
+        assert_eq!(attached.images, vec![image.clone()]);
+
+        // Models without vision get a clear error instead of a dropped image
+        let mut prompt = prompt;
+        prompt[0].images.push(image);
+        let err = run_agent_loop(
+            &client,
+            "deepseek-chat",
+            &fs,
+            prompt,
+            None,
+            None,
+            &cfg,
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("does not accept image input"));
+    }
+
+    #[tokio::test]
+    async fn spawn_agent_returns_only_the_summary() {
+        let project = tempfile::tempdir().unwrap();
+        let notes = project.path().join("notes.txt");
+        std::fs::write(&notes, "the secret is 42").unwrap();
+        // The parent and the sub-agent share the client, so the steps interleave
+        let url = serve(&format!(
+            r#"
+            [[steps]]
+            type = "tool_calls"
+            calls = [{{ name = "spawn_agent", arguments = {{ task = "Find the secret in the notes" }} }}]
+            [[steps]]
+            type = "tool_calls"
+            calls = [{{ name = "fs_read", arguments = {{ path = "{}" }} }}]
+            [[steps]]
+            type = "tool_calls"
+            calls = [{{ name = "fs_write", arguments = {{ path = "{}", content = "gone" }} }}]
+            [[steps]]
+            type = "text"
+            content = "The secret is 42 (notes.txt:1)."
+            [[steps]]
+            type = "text"
+            content = "Done."
+            "#,
+            notes.display(),
+            notes.display()
+        ))
+        .await;
+
+        let cfg = AppConfig {
+            project_root: project.path().to_path_buf(),
+            ..Default::default()
+        };
+        let store = crate::session::SessionStore::new(project.path().join("sessions")).unwrap();
+        let mut manager = crate::session::SessionManager {
+            store,
+            current_session: None,
+        };
+        manager.create_session(None).unwrap();
+        let manager = Arc::new(std::sync::Mutex::new(manager));
+        let fs = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg.clone()))
+            .with_session_manager(manager.clone());
+        let client = OpenAIClient::new(url, "k").unwrap();
+        let prompt = vec![ChatMessage {
+            role: "user".into(),
+            content: Some("what is the secret?".into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        }];
+
+        let (messages, last) =
+            run_agent_loop(&client, "model", &fs, prompt, None, None, &cfg, None)
+                .await
+                .unwrap();
+        assert_eq!(last.content, "Done.");
+        let tools: Vec<&str> = messages
+            .iter()
+            .filter(|m| m.role == "tool")
+            .filter_map(|m| m.content.as_deref())
+            .collect();
+        assert_eq!(tools.len(), 1);
+        assert!(tools[0].contains("The secret is 42 (notes.txt:1)."));
+        assert!(!tools[0].contains("the secret is 42"));
+        // The read-only sub-agent could not write
+        assert_eq!(std::fs::read_to_string(&notes).unwrap(), "the secret is 42");
+
+        let manager = manager.lock().unwrap();
+        let session = manager.current_session.as_ref().unwrap();
+        assert_eq!(session.sub_agents.len(), 1);
+        let run = &session.sub_agents[0];
+        assert_eq!(run.task, "Find the secret in the notes");
+        assert_eq!(run.tool_calls, 2);
+        assert!(run.tokens > 0);
+        assert!(run.succeeded);
+    }
+
+    #[tokio::test]
+    async fn plan_mode_withholds_edits_and_ends_with_a_plan() {
+        let project = tempfile::tempdir().unwrap();
+        let target = project.path().join("lib.rs");
+        let url = serve(&format!(
+            r#"
+            [[steps]]
+            type = "tool_calls"
+            calls = [
+                {{ name = "fs_write", arguments = {{ path = "{path}", content = "x" }} }},
+                {{ name = "execute_bash", arguments = {{ command = "touch {path}" }} }},
+                {{ name = "execute_bash", arguments = {{ command = "ls" }} }},
+            ]
+            [[steps]]
+            type = "text"
+            content = "I would add a function."
+            [[steps]]
+            type = "tool_calls"
+            calls = [{{ name = "submit_plan", arguments = {{ summary = "Add lib.rs.", steps = ["Create lib.rs", "Test it"] }} }}]
+            "#,
+            path = target.display()
+        ))
+        .await;
+
+        let mut cfg = AppConfig {
+            project_root: project.path().to_path_buf(),
+            plan_mode: true,
+            ..Default::default()
+        };
+        cfg.sandbox.enabled = false;
+        let fs = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg.clone()));
+        let client = OpenAIClient::new(url, "k").unwrap();
+        let prompt = vec![ChatMessage {
+            role: "system".into(),
+            content: Some("You are helpful.".into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        }];
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (messages, last) =
+            run_agent_loop(&client, "model", &fs, prompt, Some(tx), None, &cfg, None)
+                .await
+                .unwrap();
+        assert_eq!(
+            last.content,
+            "Add lib.rs.\n\n1. Create lib.rs\n2. Test it\n"
+        );
+        assert!(!target.exists());
+        assert!(
+            messages[0]
+                .content
+                .as_deref()
+                .unwrap()
+                .contains("# Plan Mode")
+        );
+        let tool_results: Vec<&str> = messages
+            .iter()
+            .filter(|m| m.role == "tool")
+            .filter_map(|m| m.content.as_deref())
+            .collect();
+        assert!(tool_results[0].contains("tool not available: fs_write"));
+        assert!(tool_results[1].contains("plan mode only allows read-only commands"));
+        assert!(tool_results[2].contains("\"ok\":true"));
+        assert!(
+            messages
+                .iter()
+                .any(|m| m.content.as_deref() == Some(PLAN_MODE_REMINDER))
+        );
+
+        let plan = rx
+            .try_iter()
+            .find_map(|e| e.strip_prefix("::plan:").map(String::from))
+            .unwrap();
+        let plan: Plan = serde_json::from_str(&plan).unwrap();
+        assert_eq!(plan.steps, ["Create lib.rs", "Test it"]);
+    }
+}
@@ -0,0 +1,132 @@
+use std::borrow::Cow;
+
+use crate::llm::tokenizer::{estimate_message_tokens, estimate_request_tokens};
+use crate::llm::types::{ChatMessage, ToolDef};
+
+/// Placeholder left in place of tool output dropped to fit the context window.
+const TRIMMED_TOOL_RESULT: &str =
+    r#"{"trimmed":"tool output removed to fit the context window; call the tool again if needed"}"#;
+
+/// Most recent tool results, which are never trimmed.
+const KEEP_RECENT_TOOL_RESULTS: usize = 4;
+
+/// Outcome of fitting a request into the prompt budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextFit {
+    /// Estimated prompt tokens after trimming
+    pub estimate: usize,
+    /// Number of tool results replaced by a placeholder
+    pub trimmed: usize,
+}
+
+/// The messages to send for `messages`, with the oldest tool results replaced
+/// by a placeholder until the request fits `budget` and the most recent ones
+/// kept intact. The history itself is left untouched.
+pub fn fit_to_budget<'a>(
+    messages: &'a [ChatMessage],
+    tools: &[ToolDef],
+    budget: usize,
+) -> (Cow<'a, [ChatMessage]>, ContextFit) {
+    let mut estimate = estimate_request_tokens(messages, tools);
+    let mut trimmed = 0;
+    if estimate <= budget {
+        return (Cow::Borrowed(messages), ContextFit { estimate, trimmed });
+    }
+    let mut request = messages.to_vec();
+
+    let tool_results: Vec<usize> = request
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| m.role == "tool")
+        .map(|(i, _)| i)
+        .collect();
+    let trimmable = tool_results.len().saturating_sub(KEEP_RECENT_TOOL_RESULTS);
+
+    for &i in &tool_results[..trimmable] {
+        if estimate <= budget {
+            break;
+        }
+        let msg = &mut request[i];
+        if msg
+            .content
+            .as_deref()
+            .is_none_or(|c| c.len() <= TRIMMED_TOOL_RESULT.len())
+        {
+            continue;
+        }
+        let before = estimate_message_tokens(msg);
+        msg.content = Some(TRIMMED_TOOL_RESULT.to_string());
+        estimate = estimate - before + estimate_message_tokens(msg);
+        trimmed += 1;
+    }
+
+    (Cow::Owned(request), ContextFit { estimate, trimmed })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(role: &str, content: String) -> ChatMessage {
+        ChatMessage {
+            role: role.into(),
+            content: Some(content),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        }
+    }
+
+    fn history(tool_results: usize) -> Vec<ChatMessage> {
+        let mut messages = vec![msg("system", "You are helpful.".into())];
+        for i in 0..tool_results {
+            let body = format!("line {i} of a large file\n").repeat(200);
+            messages.push(msg("tool", body));
+        }
+        messages
+    }
+
+    #[test]
+    fn fitting_request_is_left_alone() {
+        let messages = history(2);
+        let (request, fit) = fit_to_budget(&messages, &[], 1_000_000);
+        assert!(matches!(request, Cow::Borrowed(_)));
+        assert_eq!(fit.trimmed, 0);
+        assert_eq!(fit.estimate, estimate_request_tokens(&messages, &[]));
+    }
+
+    #[test]
+    fn oldest_tool_results_are_trimmed_first() {
+        let messages = history(8);
+        let full = estimate_request_tokens(&messages, &[]);
+        let budget = full * 2 / 3;
+        let (request, fit) = fit_to_budget(&messages, &[], budget);
+
+        assert_eq!(fit.trimmed, 3);
+        assert!(fit.estimate <= budget);
+        assert_eq!(fit.estimate, estimate_request_tokens(&request, &[]));
+        assert_eq!(request[1].content.as_deref(), Some(TRIMMED_TOOL_RESULT));
+        assert_ne!(request[8].content.as_deref(), Some(TRIMMED_TOOL_RESULT));
+        // Only the outgoing request is trimmed
+        assert!(
+            messages
+                .iter()
+                .all(|m| m.content.as_deref() != Some(TRIMMED_TOOL_RESULT))
+        );
+    }
+
+    #[test]
+    fn recent_tool_results_are_kept_even_when_over_budget() {
+        let messages = history(5);
+        let (request, fit) = fit_to_budget(&messages, &[], 10);
+
+        assert_eq!(fit.trimmed, 1);
+        assert!(fit.estimate > 10);
+        assert!(
+            request[2..]
+                .iter()
+                .all(|m| m.content.as_deref() != Some(TRIMMED_TOOL_RESULT))
+        );
+    }
+}
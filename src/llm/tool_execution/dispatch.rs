@@ -1,12 +1,23 @@
 use crate::llm::tool_runtime::ToolRuntime;
-use crate::llm::types::ToolCall;
+use crate::llm::types::{ImagePart, ToolCall};
 use anyhow::{Result, anyhow};
+use futures::{StreamExt, stream};
 use tracing::debug;
 
 mod analysis;
 mod fs;
 mod tools;
 
+/// Result field carrying an image for the model; it is moved into a user
+/// message since tool messages can only hold text.
+pub(crate) const IMAGE_ATTACHMENT_KEY: &str = "image_attachment";
+
+/// Remove the image attached to a tool result, if any.
+pub fn take_image_attachment(result: &mut serde_json::Value) -> Option<ImagePart> {
+    let image = result.as_object_mut()?.remove(IMAGE_ATTACHMENT_KEY)?;
+    serde_json::from_value(image).ok()
+}
+
 pub async fn dispatch_tool_call(
     runtime: &ToolRuntime<'_>,
     call: ToolCall,
@@ -16,8 +27,13 @@ pub async fn dispatch_tool_call(
         return Err(anyhow!("unsupported tool type: {}", call.r#type));
     }
     let name = call.function.name.as_str();
+    runtime.count_call();
+    if !runtime.offers(name) {
+        return Err(anyhow!("tool not available: {name}"));
+    }
     let args_val: serde_json::Value = serde_json::from_str(&call.function.arguments)
         .map_err(|e| anyhow!("invalid tool args: {e}"))?;
+    runtime.fs.permissions().check(name, &args_val).await?;
 
     match name {
         // FS-related
@@ -33,9 +49,13 @@ pub async fn dispatch_tool_call(
 
         // Tools and helpers
         "execute_bash" => tools::execute_bash(runtime, &args_val).await,
+        "bash_output" => tools::bash_output(runtime, &args_val).await,
+        "bash_kill" => tools::bash_kill(runtime, &args_val).await,
         "edit" => tools::edit(runtime, &args_val).await,
         "apply_patch" => tools::apply_patch(runtime, &args_val).await,
         "todo_write" => tools::todo_write(runtime, &args_val).await,
+        "spawn_agent" => super::sub_agent::spawn_agent(runtime, &args_val).await,
+        "submit_plan" => tools::submit_plan(runtime, &args_val).await,
 
         other => {
             if let Some(result) = runtime.fs.call_remote_tool(other, &args_val).await? {
@@ -46,3 +66,71 @@ pub async fn dispatch_tool_call(
         }
     }
 }
+
+/// Run `calls` with at most `limit` in flight, returning results in call order.
+pub async fn dispatch_tool_calls(
+    runtime: &ToolRuntime<'_>,
+    calls: &[ToolCall],
+    limit: usize,
+) -> Vec<Result<serde_json::Value>> {
+    stream::iter(calls.iter().cloned())
+        .map(|call| dispatch_tool_call(runtime, call))
+        .buffered(limit.max(1))
+        .collect()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+    use crate::llm::types::ToolCallFunction;
+    use crate::tools::FsTools;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+    use tokio::sync::RwLock;
+
+    fn call(name: &str, args: serde_json::Value) -> ToolCall {
+        ToolCall {
+            id: None,
+            r#type: "function".into(),
+            function: ToolCallFunction {
+                name: name.into(),
+                arguments: args.to_string(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn batched_calls_keep_their_order() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path().to_path_buf();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            std::fs::write(root.join(name), format!("contents of {name}"))?;
+        }
+        let cfg = AppConfig {
+            project_root: root.clone(),
+            mcp_servers: vec![],
+            ..Default::default()
+        };
+        let fs = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg));
+        let runtime = ToolRuntime::build(&fs).await?;
+        assert!(runtime.is_read_only("fs_read"));
+        assert!(!runtime.is_read_only("fs_write"));
+
+        let calls: Vec<ToolCall> = ["a.txt", "b.txt", "c.txt"]
+            .iter()
+            .map(|name| call("fs_read", serde_json::json!({ "path": root.join(name) })))
+            .chain(std::iter::once(call("no_such_tool", serde_json::json!({}))))
+            .collect();
+
+        let results = dispatch_tool_calls(&runtime, &calls, 2).await;
+        assert_eq!(results.len(), 4);
+        for (res, name) in results.iter().zip(["a.txt", "b.txt", "c.txt"]) {
+            let value = res.as_ref().expect("fs_read succeeds");
+            assert!(value.to_string().contains(&format!("contents of {name}")));
+        }
+        assert!(results[3].is_err());
+        Ok(())
+    }
+}
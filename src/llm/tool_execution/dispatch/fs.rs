@@ -1,9 +1,12 @@
+use super::IMAGE_ATTACHMENT_KEY;
+use crate::llm::image_media_type;
 use crate::llm::tool_runtime::ToolRuntime;
 use crate::tools::list::{FsListMode, FsListOptions};
 use crate::tools::read::{FsReadMode, FsReadOptions};
 use crate::tools::read_many::FsReadManyOptions;
 use anyhow::{Result, anyhow};
 use serde_json::json;
+use std::path::Path;
 
 pub async fn fs_list(
     runtime: &ToolRuntime<'_>,
@@ -46,6 +49,14 @@ pub async fn fs_read(
     args: &serde_json::Value,
 ) -> Result<serde_json::Value> {
     let path = args.get("path").and_then(|v| v.as_str()).unwrap_or("");
+    if let Some(media_type) = image_media_type(Path::new(path)) {
+        let image = runtime.fs.fs_read_image(path).map_err(|e| anyhow!("{e}"))?;
+        return Ok(json!({
+            "ok": true,
+            "result": { "path": path, "media_type": media_type, "note": "The image is attached to the next message." },
+            IMAGE_ATTACHMENT_KEY: image,
+        }));
+    }
     let start_line = args
         .get("start_line")
         .and_then(|v| v.as_u64())
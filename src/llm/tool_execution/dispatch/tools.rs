@@ -1,28 +1,84 @@
 use crate::llm::tool_runtime::ToolRuntime;
+use crate::tools::execute::{BashOptions, DEFAULT_TIMEOUT_SECS, MAX_TIMEOUT_SECS};
 use anyhow::{Result, anyhow};
 use serde_json::json;
+use std::time::Duration;
 
 pub async fn execute_bash(
     runtime: &ToolRuntime<'_>,
     args: &serde_json::Value,
 ) -> Result<serde_json::Value> {
     let command = args.get("command").and_then(|v| v.as_str()).unwrap_or("");
-    match runtime.fs.execute_bash(command).await {
+    if runtime.plan_mode() && !crate::tools::read_only::is_read_only_command(command) {
+        return Err(anyhow!(
+            "plan mode only allows read-only commands; `{command}` may modify the workspace"
+        ));
+    }
+    if args
+        .get("run_in_background")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+    {
+        let job_id = runtime.fs.start_bash_job(command)?;
+        return Ok(json!({
+            "ok": true,
+            "job_id": job_id,
+            "message": "Started in the background. Poll it with bash_output and stop it with bash_kill."
+        }));
+    }
+    if args
+        .get("reset_session")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+    {
+        runtime.fs.reset_shell();
+    }
+    let timeout_secs = args
+        .get("timeout_secs")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(DEFAULT_TIMEOUT_SECS)
+        .clamp(1, MAX_TIMEOUT_SECS);
+    let options = BashOptions {
+        timeout: Duration::from_secs(timeout_secs),
+        live_output: runtime.ui_tx().cloned(),
+    };
+    match runtime.fs.execute_bash_with(command, &options).await {
         Ok(output) => Ok(json!({ "ok": true, "stdout": output })),
         Err(e) => Err(anyhow!("{e}")),
     }
 }
 
+pub async fn bash_output(
+    runtime: &ToolRuntime<'_>,
+    args: &serde_json::Value,
+) -> Result<serde_json::Value> {
+    runtime.fs.bash_jobs().output(job_id(args)?)
+}
+
+pub async fn bash_kill(
+    runtime: &ToolRuntime<'_>,
+    args: &serde_json::Value,
+) -> Result<serde_json::Value> {
+    runtime.fs.bash_jobs().kill(job_id(args)?).await
+}
+
+fn job_id(args: &serde_json::Value) -> Result<&str> {
+    args.get("job_id")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("job_id is required"))
+}
+
 pub async fn edit(
     runtime: &ToolRuntime<'_>,
     args: &serde_json::Value,
 ) -> Result<serde_json::Value> {
-    let params = serde_json::from_value(args.clone())?;
+    let params: crate::tools::edit::EditParams = serde_json::from_value(args.clone())?;
 
     // Count the tool call attempt
     if let Err(e) = runtime.fs.update_session_with_tool_call_count() {
         tracing::error!(?e, "Failed to update session with tool call count");
     }
+    runtime.fs.checkpoint_file(&params.file_path)?;
 
     match crate::tools::edit::edit(params, &runtime.fs.config).await {
         Ok(res) => {
@@ -61,12 +117,13 @@ pub async fn apply_patch(
     runtime: &ToolRuntime<'_>,
     args: &serde_json::Value,
 ) -> Result<serde_json::Value> {
-    let params = serde_json::from_value(args.clone())?;
+    let params: crate::tools::apply_patch::ApplyPatchParams = serde_json::from_value(args.clone())?;
 
     // Count the tool call attempt
     if let Err(e) = runtime.fs.update_session_with_tool_call_count() {
         tracing::error!(?e, "Failed to update session with tool call count");
     }
+    runtime.fs.checkpoint_file(&params.file_path)?;
 
     match crate::tools::apply_patch::apply_patch(params, &runtime.fs.config).await {
         Ok(res) => {
@@ -139,3 +196,14 @@ pub async fn todo_write(
         }
     }
 }
+
+pub async fn submit_plan(
+    _runtime: &ToolRuntime<'_>,
+    args: &serde_json::Value,
+) -> Result<serde_json::Value> {
+    let plan: crate::tools::submit_plan::Plan = serde_json::from_value(args.clone())?;
+    if plan.steps.iter().all(|step| step.trim().is_empty()) {
+        return Err(anyhow!("the plan has no steps"));
+    }
+    Ok(serde_json::to_value(plan)?)
+}
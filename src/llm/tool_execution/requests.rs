@@ -1,101 +1,25 @@
 use crate::llm::LlmErrorKind;
-use crate::llm::chat_with_tools::{
-    ChatRequestWithTools, ChatResponseWithTools, ChoiceMessageWithTools, Reasoning,
-};
+use crate::llm::chat_with_tools::{ChatResponseWithTools, ChoiceMessageWithTools};
 use crate::llm::client_core::OpenAIClient;
-use crate::llm::types::ChatMessage;
+use crate::llm::retry::{Retrier, RetryPolicy};
+use crate::llm::types::{ChatMessage, ToolDef};
 use anyhow::{Result, anyhow};
-use std::ops::Mul;
-use tokio::time::{Duration, sleep};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap};
+use serde_json::Value;
+use tokio::time::Duration;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, warn};
 
-pub async fn chat_tools_once(
+/// Single OpenAI-compatible `/v1/chat/completions` request advertising tools.
+pub(crate) async fn chat_tools_once_openai(
     client: &OpenAIClient,
     model: &str,
     messages: Vec<ChatMessage>,
-    tools: &[crate::llm::types::ToolDef],
-    cancel: Option<tokio_util::sync::CancellationToken>,
+    tools: &[ToolDef],
+    cancel: Option<CancellationToken>,
 ) -> Result<ChoiceMessageWithTools> {
-    const MAX_RETRIES: u64 = 100;
-    const MAX_TIMEOUT_RETRIES: u64 = 20;
-    let mut last_error = anyhow!("Failed after {} retries", MAX_RETRIES);
-    let mut timeout_retries = 0u64;
-
-    for attempt in 1..=MAX_RETRIES {
-        match chat_tools_once_inner(client, model, messages.clone(), tools, cancel.clone()).await {
-            Ok(result) => return Ok(result),
-            Err(e) => {
-                last_error = e;
-                // Check if the error is a timeout
-                let is_timeout = last_error.to_string().contains("timed out")
-                    || matches!(
-                        last_error.downcast_ref::<LlmErrorKind>(),
-                        Some(LlmErrorKind::Timeout)
-                    );
-
-                // If it's a timeout, limit retries
-                if is_timeout {
-                    timeout_retries += 1;
-                    if timeout_retries > MAX_TIMEOUT_RETRIES {
-                        error!("Timeout error occurred: {:?}", &last_error);
-                        break;
-                    }
-                } else if attempt >= MAX_RETRIES {
-                    error!("Error occurred: {:?}", &last_error);
-                    break;
-                }
-
-                // Exponential backoff with jitter
-                let delay_ms = (2_u64.mul(attempt) * 1000).min(60_000);
-                let jitter = rand::random::<u64>() % 5000;
-                let total_delay = Duration::from_millis(delay_ms + jitter);
-                warn!(
-                    attempt = attempt,
-                    delay_ms = delay_ms + jitter,
-                    "Retrying chat_tools_once after error"
-                );
-                sleep(total_delay).await;
-            }
-        }
-    }
-
-    Err(last_error)
-}
-
-async fn chat_tools_once_inner(
-    client: &OpenAIClient,
-    model: &str,
-    messages: Vec<ChatMessage>,
-    tools: &[crate::llm::types::ToolDef],
-    cancel: Option<tokio_util::sync::CancellationToken>,
-) -> Result<ChoiceMessageWithTools> {
-    use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap};
-
     let url = client.endpoint();
-    let reasoning_effort = if client.reason_enable {
-        Some("high".to_owned())
-    } else {
-        None
-    };
-
-    let reasoning = if model.contains("grok-4-fast") {
-        Some(Reasoning {
-            effort: None,
-            max_tokens: None,
-            enabled: Some(true),
-        })
-    } else {
-        None
-    };
-    let req = ChatRequestWithTools {
-        model: model.to_string(),
-        messages,
-        temperature: None,
-        tools: Some(tools.to_vec()),
-        tool_choice: None,
-        reasoning_effort,
-        reasoning,
-    };
+    let req = client.tools_request(model, messages, tools);
 
     let mut headers = HeaderMap::new();
     headers.insert(
@@ -117,11 +41,31 @@ async fn chat_tools_once_inner(
     // }
 
     let cancel_token = cancel.unwrap_or_default();
-    let req_builder = client.inner.post(&url).headers(headers).json(&req);
+    let body = req.to_body();
+    Retrier::new(
+        RetryPolicy::from_config(&client.llm_cfg),
+        client.circuit_breaker(),
+        "chat_tools_once",
+    )
+    .run(&cancel_token, || {
+        attempt(client, model, &url, &headers, &body, &cancel_token)
+    })
+    .await
+}
+
+async fn attempt(
+    client: &OpenAIClient,
+    model: &str,
+    url: &str,
+    headers: &HeaderMap,
+    body: &Value,
+    cancel_token: &CancellationToken,
+) -> Result<ChoiceMessageWithTools> {
+    let send = client.send_json(url, headers.clone(), body);
 
     // Set timeout for the request
     let timeout_duration = Duration::from_millis(client.llm_cfg.timeout_ms);
-    let resp_fut = tokio::time::timeout(timeout_duration, req_builder.send());
+    let resp_fut = tokio::time::timeout(timeout_duration, send);
 
     let resp_result = tokio::select! {
         biased;
@@ -145,22 +89,16 @@ async fn chat_tools_once_inner(
 
     if !resp.status().is_success() {
         let status = resp.status();
+        let retry_after = crate::llm::retry::retry_after(resp.headers());
         let text = resp.text().await.unwrap_or_default().trim().to_owned();
         error!(status=%status.as_u16(), body=%text, "llm chat_tools_once non-success status");
 
-        // Check if the error is due to context length exceeded
-        if status.as_u16() == 400
-            && let Ok(json) = serde_json::from_str::<serde_json::Value>(&text)
-            && let Some(code) = json
-                .get("error")
-                .and_then(|e| e.get("code"))
-                .and_then(|c| c.as_str())
-            && code == "context_length_exceeded"
-        {
-            return Err(anyhow!(LlmErrorKind::ContextLengthExceeded));
-        }
-
-        return Err(anyhow!("chat (tools) error: {} - {}", status, text));
+        return Err(crate::llm::status_error(
+            "chat (tools) error",
+            status,
+            retry_after,
+            &text,
+        ));
     }
 
     // Set timeout for reading the response body
@@ -191,9 +129,7 @@ async fn chat_tools_once_inner(
 
     // Track token usage if available
     if let Some(usage) = &body.usage {
-        client.set_tokens(usage.total_tokens);
-        // Also track prompt tokens for non-streaming tools path
-        client.set_prompt_tokens(usage.prompt_tokens);
+        client.record_usage(model, usage);
     }
 
     let msg = body
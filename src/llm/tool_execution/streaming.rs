@@ -1,127 +1,509 @@
+use crate::config::ToolCallMode;
 use crate::llm::LlmErrorKind;
-use crate::llm::stream_tools::{ToolDeltaBuffer, execute_tool_call};
-use crate::llm::tool_runtime::ToolRuntime;
-use crate::llm::types::{ChatMessage, ChoiceMessage};
-use crate::session::SessionManager;
-use crate::tools::FsTools;
+use crate::llm::chat_with_tools::ChoiceMessageWithTools;
+use crate::llm::prompt_tools;
+use crate::llm::provider::LlmProvider;
+use crate::llm::retry::{Retrier, RetryPolicy};
+use crate::llm::stream::ToolCallDelta;
+use crate::llm::stream_tools::ToolDeltaBuffer;
+use crate::llm::types::{ChatMessage, ToolDef};
 use anyhow::{Result, anyhow};
 use futures::StreamExt;
-use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Sender;
 use tokio_util::sync::CancellationToken;
-use tracing::warn;
+use tracing::{debug, error, warn};
 
-pub async fn run_agent_streaming_once(
-    client: &crate::llm::client_core::OpenAIClient,
+/// Stream the next assistant turn, offering `tools` the way `mode` says.
+///
+/// Assistant text is forwarded to the UI as `::append:` events and reasoning as
+/// `::reasoning:` events as they arrive; tool calls are rebuilt from the
+/// streamed deltas. Failures are retried under `policy`, with each wait
+/// reported to the UI, as long as nothing has been shown to the user yet.
+#[allow(clippy::too_many_arguments)]
+pub async fn stream_assistant_turn(
+    client: &dyn LlmProvider,
     model: &str,
-    fs: &FsTools,
-    mut messages: Vec<ChatMessage>,
-    cancel: Option<CancellationToken>,
-    session_manager: Option<Arc<Mutex<SessionManager>>>,
-) -> Result<(Vec<ChatMessage>, Option<ChoiceMessage>)> {
-    let cancel_token = cancel.unwrap_or_default();
-
-    // Start stream
-    let mut stream = client
-        .chat_stream(model, messages.clone(), Some(cancel_token.clone()))
-        .await?;
-    let runtime = ToolRuntime::build(fs).await?;
+    messages: &[ChatMessage],
+    tools: &[ToolDef],
+    mode: &mut ToolCallMode,
+    ui_tx: Option<&Sender<String>>,
+    cancel: &CancellationToken,
+    policy: &RetryPolicy,
+) -> Result<ChoiceMessageWithTools> {
+    let mut retrier =
+        Retrier::new(policy.clone(), client.circuit_breaker(), "chat_stream").with_ui(ui_tx);
+
+    loop {
+        retrier.before_attempt(cancel).await?;
+        let (req_messages, req_tools) =
+            prompt_tools::prepare_request(messages.to_vec(), tools, *mode);
+        let mut emitted = false;
+
+        let err = match stream_once(
+            client,
+            model,
+            req_messages,
+            req_tools,
+            *mode,
+            ui_tx,
+            cancel,
+            &mut emitted,
+        )
+        .await
+        {
+            Ok(mut msg) => {
+                retrier.succeeded();
+                prompt_tools::extract_text_tool_calls(model, &mut msg, tools, mode);
+                if prompt_tools::looks_like_missed_tool_call(&msg, tools, *mode) {
+                    return resend_in_prompt_mode(
+                        client, model, messages, tools, mode, ui_tx, cancel, policy,
+                    )
+                    .await;
+                }
+                return Ok(msg);
+            }
+            Err(e) => e,
+        };
+
+        // Text already on screen cannot be taken back, and cancellation and
+        // context overflow are handled by the caller.
+        if emitted
+            || matches!(
+                err.downcast_ref::<LlmErrorKind>(),
+                Some(LlmErrorKind::Cancelled | LlmErrorKind::ContextLengthExceeded)
+            )
+        {
+            return Err(err);
+        }
+        if let Err(err) = retrier.after_failure(err, cancel).await {
+            error!("Error occurred: {:?}", &err);
+            return Err(err);
+        }
+    }
+}
+
+/// Ask for the turn again with tools described in the prompt, after an auto
+/// mode reply announced an action without making a native tool call.
+///
+/// The model is switched to prompt mode for good only if that reply does
+/// call a tool.
+#[allow(clippy::too_many_arguments)]
+async fn resend_in_prompt_mode(
+    client: &dyn LlmProvider,
+    model: &str,
+    messages: &[ChatMessage],
+    tools: &[ToolDef],
+    mode: &mut ToolCallMode,
+    ui_tx: Option<&Sender<String>>,
+    cancel: &CancellationToken,
+    policy: &RetryPolicy,
+) -> Result<ChoiceMessageWithTools> {
+    warn!(
+        model,
+        "reply announced an action without a tool call; retrying in prompt mode"
+    );
+    if let Some(tx) = ui_tx {
+        let _ = tx.send(
+            "[INFO] The model did not call a tool; asking again with tools described in the prompt."
+                .into(),
+        );
+    }
+    let mut prompt_mode = ToolCallMode::Prompt;
+    let msg = Box::pin(stream_assistant_turn(
+        client,
+        model,
+        messages,
+        tools,
+        &mut prompt_mode,
+        ui_tx,
+        cancel,
+        policy,
+    ))
+    .await?;
+    if !msg.tool_calls.is_empty() {
+        prompt_tools::mark_prompt_mode(model);
+        *mode = ToolCallMode::Prompt;
+    }
+    Ok(msg)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn stream_once(
+    client: &dyn LlmProvider,
+    model: &str,
+    messages: Vec<ChatMessage>,
+    tools: &[ToolDef],
+    mode: ToolCallMode,
+    ui_tx: Option<&Sender<String>>,
+    cancel: &CancellationToken,
+    emitted: &mut bool,
+) -> Result<ChoiceMessageWithTools> {
+    let mut stream = tokio::select! {
+        biased;
+        _ = cancel.cancelled() => {
+            warn!("stream_assistant_turn cancelled before chat_stream");
+            return Err(anyhow!(LlmErrorKind::Cancelled));
+        }
+        res = client.chat_stream(model, messages, tools, Some(cancel.clone())) => res?,
+    };
+
     let mut buf = ToolDeltaBuffer::new();
-    let mut acc_text = String::new();
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut shown = 0usize;
 
     loop {
         let chunk = tokio::select! {
             biased;
-            _ = cancel_token.cancelled() => {
-                warn!("run_agent_streaming_once cancelled");
+            _ = cancel.cancelled() => {
+                warn!("stream_assistant_turn cancelled mid-stream");
                 return Err(anyhow!(LlmErrorKind::Cancelled));
             }
             chunk = stream.next() => chunk,
         };
+        let Some(chunk) = chunk else {
+            break;
+        };
+        let delta = chunk?;
 
-        match chunk {
-            Some(Ok(delta)) => {
-                if delta.is_empty() {
-                    break;
-                }
-                if let Some(rest) = delta.strip_prefix("__TOOL_CALLS_DELTA__:") {
-                    // Parse synthetic tool_calls marker and feed into buffer.
-                    if let Ok(deltas) =
-                        serde_json::from_str::<Vec<crate::llm::stream::ToolCallDelta>>(rest)
-                    {
-                        for d in deltas {
-                            let idx = d.index.unwrap_or(0);
-                            let (name_delta, args_delta) = if let Some(f) = d.function {
-                                (Some(f.name), Some(f.arguments))
-                            } else {
-                                (None, None)
-                            };
-                            buf.push_delta(idx, name_delta.as_deref(), args_delta.as_deref(), None);
-                            // Try finalize and execute immediately when JSON becomes valid
-                            if buf.finalize_sync_call(idx).is_ok() {
-                                // Update session with tool call count if session manager is available
-                                if let Some(ref sm) = session_manager {
-                                    let mut session_mgr = sm.lock().unwrap();
-                                    if let Err(e) =
-                                        session_mgr.update_current_session_with_tool_call_count()
-                                    {
-                                        tracing::error!(
-                                            ?e,
-                                            "Failed to update session with tool call count"
-                                        );
-                                    }
-                                }
-
-                                let exec = execute_tool_call(&runtime, idx, &buf).await;
-                                if let Ok(val) = exec {
-                                    messages.push(ChatMessage {
-                                        role: "tool".into(),
-                                        content: Some(
-                                            serde_json::to_string(&val)
-                                                .unwrap_or_else(|_| "{\"ok\":false}".to_string()),
-                                        ),
-                                        tool_calls: vec![],
-                                        tool_call_id: None,
-                                    });
-                                    // One tool exec per streaming round (minimal policy)
-                                    return Ok((messages, None));
-                                }
-                            }
-                        }
+        if let Some(rest) = delta.strip_prefix("__TOOL_CALLS_DELTA__:") {
+            match serde_json::from_str::<Vec<ToolCallDelta>>(rest) {
+                Ok(deltas) => {
+                    for d in deltas {
+                        let (name, args) = match &d.function {
+                            Some(f) => (Some(f.name.as_str()), Some(f.arguments.as_str())),
+                            None => (None, None),
+                        };
+                        buf.push_delta(d.index.unwrap_or(0), name, args, d.id.as_deref());
                     }
-                } else {
-                    // Accumulate plain text
-                    acc_text.push_str(&delta);
                 }
+                Err(e) => warn!(error = %e, "failed to parse tool call delta"),
             }
-            Some(Err(e)) => {
-                if let Some(kind) = e.downcast_ref::<LlmErrorKind>()
-                    && kind == &LlmErrorKind::Cancelled
-                {
-                    warn!("run_agent_streaming_once stream cancelled");
-                    return Err(anyhow!(LlmErrorKind::Cancelled));
+            continue;
+        }
+
+        if let Some(rest) = delta.strip_prefix("__REASONING_DELTA__:") {
+            reasoning.push_str(rest);
+            if let Some(tx) = ui_tx {
+                if !*emitted {
+                    let _ = tx.send("::status:streaming".into());
+                    *emitted = true;
                 }
-                return Err(e);
+                let _ = tx.send(format!("::reasoning:{rest}"));
+            }
+            continue;
+        }
+
+        text.push_str(&delta);
+        // Tool calls written as text are not echoed while they stream in
+        let visible = if mode == ToolCallMode::Prompt {
+            prompt_tools::displayable_len(&text)
+        } else {
+            text.len()
+        };
+        if visible > shown
+            && let Some(tx) = ui_tx
+        {
+            if !*emitted {
+                let _ = tx.send("::status:streaming".into());
+                *emitted = true;
             }
-            None => break, // End of stream
+            let _ = tx.send(format!("::append:{}", &text[shown..visible]));
+            shown = visible;
         }
     }
 
-    // If we have accumulated content and no tool call executed, return it as assistant message
-    if !acc_text.is_empty() {
-        messages.push(ChatMessage {
-            role: "assistant".into(),
-            content: Some(acc_text.clone()),
-            tool_calls: vec![],
-            tool_call_id: None,
-        });
-        return Ok((
-            messages,
-            Some(ChoiceMessage {
-                role: "assistant".into(),
-                content: acc_text,
-            }),
-        ));
+    let tool_calls = buf.into_tool_calls();
+    debug!(
+        text_len = text.len(),
+        reasoning_len = reasoning.len(),
+        tool_calls = tool_calls.len(),
+        "streamed assistant turn complete"
+    );
+    Ok(ChoiceMessageWithTools {
+        role: "assistant".into(),
+        content: if text.is_empty() { None } else { Some(text) },
+        tool_calls,
+        reasoning: if reasoning.trim().is_empty() {
+            None
+        } else {
+            Some(reasoning)
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cost::UsageLog;
+    use crate::llm::chat_with_tools::ChoiceMessageWithTools;
+    use crate::llm::provider::ChatStream;
+    use crate::llm::types::ChoiceMessage;
+    use async_trait::async_trait;
+    use std::sync::Arc;
+
+    /// Provider replaying a fixed list of stream chunks.
+    #[derive(Debug, Default)]
+    struct ScriptedProvider {
+        chunks: Vec<&'static str>,
+        /// Chunks for every request after the first, if different
+        then: Option<Vec<&'static str>>,
+        /// Number of native tools offered by each request
+        offered_tools: std::sync::Mutex<Vec<usize>>,
+        usage_log: UsageLog,
     }
 
-    Ok((messages, None))
+    #[async_trait]
+    impl LlmProvider for ScriptedProvider {
+        fn name(&self) -> &'static str {
+            "scripted"
+        }
+
+        async fn chat_once(
+            &self,
+            _model: &str,
+            _messages: Vec<ChatMessage>,
+            _cancel: Option<CancellationToken>,
+        ) -> Result<ChoiceMessage> {
+            Err(anyhow!("not scripted"))
+        }
+
+        async fn chat_tools_once(
+            &self,
+            _model: &str,
+            _messages: Vec<ChatMessage>,
+            _tools: &[ToolDef],
+            _cancel: Option<CancellationToken>,
+        ) -> Result<ChoiceMessageWithTools> {
+            Err(anyhow!("not scripted"))
+        }
+
+        async fn chat_stream(
+            &self,
+            _model: &str,
+            _messages: Vec<ChatMessage>,
+            tools: &[ToolDef],
+            _cancel: Option<CancellationToken>,
+        ) -> Result<ChatStream> {
+            let mut offered = self.offered_tools.lock().unwrap();
+            let chunks = match &self.then {
+                Some(then) if !offered.is_empty() => then,
+                _ => &self.chunks,
+            };
+            offered.push(tools.len());
+            let chunks: Vec<Result<String>> = chunks.iter().map(|c| Ok(c.to_string())).collect();
+            Ok(Box::pin(futures::stream::iter(chunks)))
+        }
+
+        fn with_reasoning_disabled(&self) -> Arc<dyn LlmProvider> {
+            Arc::new(ScriptedProvider {
+                chunks: self.chunks.clone(),
+                then: self.then.clone(),
+                usage_log: self.usage_log.clone(),
+                ..Default::default()
+            })
+        }
+
+        fn get_tokens_used(&self) -> u32 {
+            0
+        }
+        fn set_tokens(&self, _tokens: u32) {}
+        fn get_prompt_tokens_used(&self) -> u32 {
+            0
+        }
+        fn set_prompt_tokens(&self, _tokens: u32) {}
+        fn usage_log(&self) -> &UsageLog {
+            &self.usage_log
+        }
+    }
+
+    #[tokio::test]
+    async fn streams_text_and_rebuilds_tool_calls() {
+        let client = ScriptedProvider {
+            chunks: vec![
+                "Let me ",
+                "look.",
+                r#"__TOOL_CALLS_DELTA__:[{"index":0,"id":"call_1","type":"function","function":{"name":"fs_read","arguments":"{\"pa"}}]"#,
+                r#"__TOOL_CALLS_DELTA__:[{"index":0,"function":{"name":"","arguments":"th\":\"a\"}"}}]"#,
+            ],
+            ..Default::default()
+        };
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut mode = ToolCallMode::Native;
+        let msg = stream_assistant_turn(
+            &client,
+            "m",
+            &[],
+            &[],
+            &mut mode,
+            Some(&tx),
+            &CancellationToken::new(),
+            &RetryPolicy::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(msg.content.as_deref(), Some("Let me look."));
+        assert_eq!(msg.tool_calls.len(), 1);
+        assert_eq!(msg.tool_calls[0].id.as_deref(), Some("call_1"));
+        assert_eq!(msg.tool_calls[0].function.arguments, r#"{"path":"a"}"#);
+
+        drop(tx);
+        let events: Vec<String> = rx.iter().collect();
+        assert_eq!(
+            events,
+            vec!["::status:streaming", "::append:Let me ", "::append:look."]
+        );
+    }
+
+    #[tokio::test]
+    async fn reasoning_is_collected_and_forwarded() {
+        let client = ScriptedProvider {
+            chunks: vec![
+                "__REASONING_DELTA__:The user wants ",
+                "__REASONING_DELTA__:a greeting.",
+                "Hello!",
+            ],
+            ..Default::default()
+        };
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut mode = ToolCallMode::Native;
+        let msg = stream_assistant_turn(
+            &client,
+            "m",
+            &[],
+            &[],
+            &mut mode,
+            Some(&tx),
+            &CancellationToken::new(),
+            &RetryPolicy::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(msg.content.as_deref(), Some("Hello!"));
+        assert_eq!(msg.reasoning.as_deref(), Some("The user wants a greeting."));
+        drop(tx);
+        let events: Vec<String> = rx.iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                "::status:streaming",
+                "::reasoning:The user wants ",
+                "::reasoning:a greeting.",
+                "::append:Hello!"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn prompt_mode_hides_tool_call_text() {
+        let client = ScriptedProvider {
+            chunks: vec![
+                "Reading.\n<tool",
+                "_call>{\"name\": \"fs_read\", \"arguments\": {}}</tool_call>",
+            ],
+            ..Default::default()
+        };
+        let tools = [ToolDef {
+            kind: "function".into(),
+            function: crate::llm::types::ToolFunctionDef {
+                name: "fs_read".into(),
+                description: "Read".into(),
+                parameters: serde_json::json!({"type": "object"}),
+                strict: None,
+            },
+        }];
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut mode = ToolCallMode::Prompt;
+        let msg = stream_assistant_turn(
+            &client,
+            "m",
+            &[],
+            &tools,
+            &mut mode,
+            Some(&tx),
+            &CancellationToken::new(),
+            &RetryPolicy::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(msg.content.as_deref(), Some("Reading."));
+        assert_eq!(msg.tool_calls[0].function.name, "fs_read");
+        drop(tx);
+        let appended: String = rx
+            .iter()
+            .filter_map(|e| e.strip_prefix("::append:").map(str::to_string))
+            .collect();
+        assert_eq!(appended, "Reading.\n");
+    }
+
+    #[tokio::test]
+    async fn announced_actions_are_asked_again_in_prompt_mode() {
+        let client = ScriptedProvider {
+            chunks: vec!["Let me read the file."],
+            then: Some(vec![
+                "<tool_call>{\"name\": \"fs_read\", \"arguments\": {}}</tool_call>",
+            ]),
+            ..Default::default()
+        };
+        let tools = [ToolDef {
+            kind: "function".into(),
+            function: crate::llm::types::ToolFunctionDef {
+                name: "fs_read".into(),
+                description: "Read".into(),
+                parameters: serde_json::json!({"type": "object"}),
+                strict: None,
+            },
+        }];
+        let mut mode = ToolCallMode::Auto;
+        let msg = stream_assistant_turn(
+            &client,
+            "announce-test-model",
+            &[],
+            &tools,
+            &mut mode,
+            None,
+            &CancellationToken::new(),
+            &RetryPolicy::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(msg.tool_calls[0].function.name, "fs_read");
+        assert_eq!(*client.offered_tools.lock().unwrap(), [1, 0]);
+        assert_eq!(mode, ToolCallMode::Prompt);
+        assert_eq!(
+            prompt_tools::effective_mode(ToolCallMode::Auto, "announce-test-model"),
+            ToolCallMode::Prompt
+        );
+    }
+
+    #[tokio::test]
+    async fn cancellation_is_reported() {
+        let client = ScriptedProvider {
+            chunks: vec!["never"],
+            ..Default::default()
+        };
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let mut mode = ToolCallMode::Native;
+        let err = stream_assistant_turn(
+            &client,
+            "m",
+            &[],
+            &[],
+            &mut mode,
+            None,
+            &cancel,
+            &RetryPolicy::default(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LlmErrorKind>(),
+            Some(&LlmErrorKind::Cancelled)
+        );
+    }
 }
@@ -0,0 +1,125 @@
+//! `spawn_agent`: delegate a task to a nested agent loop.
+//!
+//! The sub-agent starts from a fresh context with its own tool subset and
+//! iteration budget, and only its final message goes back to the parent. It
+//! shares the parent's client and `FsTools`, so its requests, cost and tool
+//! statistics land in the parent session; the run itself is recorded as a
+//! [`SubAgentRun`].
+
+use crate::config::AppConfig;
+use crate::llm::provider::LlmProvider;
+use crate::llm::tool_runtime::ToolRuntime;
+use crate::llm::types::ChatMessage;
+use crate::session::SubAgentRun;
+use crate::tools::spawn_agent::{SpawnAgentArgs, SubAgentTools};
+use anyhow::{Result, anyhow};
+use serde_json::json;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::Sender;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+const SUB_AGENT_INSTRUCTIONS: &str = "# Sub-agent\n\
+You are a sub-agent working on a single task delegated by another agent, which sees none of \
+your tool calls. Work autonomously; you cannot ask questions. When you are done, reply with a \
+self-contained summary of your findings or changes, citing file paths and line numbers. That \
+reply is the only thing returned.";
+
+/// Context of the parent loop that sub-agents run in.
+pub(crate) struct Delegate<'a> {
+    pub client: &'a dyn LlmProvider,
+    pub model: &'a str,
+    pub cfg: &'a AppConfig,
+    pub cancel: CancellationToken,
+    pub ui_tx: Option<Sender<String>>,
+}
+
+type ToolFuture<'a> = Pin<Box<dyn Future<Output = Result<serde_json::Value>> + Send + 'a>>;
+
+/// Boxed to break the recursion between the agent loop and tool dispatch.
+pub fn spawn_agent<'a>(
+    runtime: &'a ToolRuntime<'_>,
+    args: &'a serde_json::Value,
+) -> ToolFuture<'a> {
+    Box::pin(run_sub_agent(runtime, args))
+}
+
+async fn run_sub_agent(
+    runtime: &ToolRuntime<'_>,
+    args: &serde_json::Value,
+) -> Result<serde_json::Value> {
+    let Some(parent) = &runtime.delegate else {
+        return Err(anyhow!("sub-agents cannot spawn further agents"));
+    };
+    let params: SpawnAgentArgs = serde_json::from_value(args.clone())?;
+
+    let mut sub = ToolRuntime::build(runtime.fs).await?;
+    // Nothing may change the workspace while planning
+    if params.tools == SubAgentTools::ReadOnly || parent.cfg.plan_mode {
+        sub.restrict_to_read_only();
+    }
+    sub.max_iters = params.iteration_budget();
+
+    let system_prompt = format!(
+        "{}\n\n{SUB_AGENT_INSTRUCTIONS}",
+        crate::tui::commands::prompt::build_system_prompt(parent.cfg)
+    );
+    let messages = [("system", system_prompt), ("user", params.task.clone())]
+        .into_iter()
+        .map(|(role, content)| ChatMessage {
+            role: role.into(),
+            content: Some(content),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        })
+        .collect();
+
+    if let Some(tx) = &parent.ui_tx {
+        let _ = tx.send(format!("[INFO] Sub-agent started: {}", params.task));
+    }
+    let usage_start = parent.client.usage_log().recorded();
+    let res = super::agent_loop::run_loop(
+        parent.client,
+        parent.model,
+        &sub,
+        messages,
+        None,
+        parent.cancel.clone(),
+        parent.cfg,
+    )
+    .await;
+
+    let tokens = parent
+        .client
+        .usage_log()
+        .recorded_since(usage_start)
+        .iter()
+        .map(|u| u64::from(u.input_tokens) + u64::from(u.output_tokens))
+        .sum();
+    let tool_calls = sub.calls() as u64;
+    if let Err(e) = runtime.fs.record_sub_agent(SubAgentRun {
+        at: String::new(),
+        task: params.task,
+        tool_calls,
+        tokens,
+        succeeded: res.is_ok(),
+    }) {
+        warn!(error = %e, "failed to record sub-agent run in session");
+    }
+    if let Some(tx) = &parent.ui_tx {
+        let outcome = if res.is_ok() { "finished" } else { "failed" };
+        let _ = tx.send(format!(
+            "[INFO] Sub-agent {outcome} after {tool_calls} tool calls ({tokens} tokens)."
+        ));
+    }
+
+    let (_, last) = res.map_err(|e| anyhow!("sub-agent failed: {e}"))?;
+    Ok(json!({
+        "ok": true,
+        "summary": last.content,
+        "tool_calls": tool_calls,
+    }))
+}
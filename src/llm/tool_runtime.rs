@@ -1,16 +1,40 @@
 use crate::llm::tool_def::default_tools_def;
+use crate::llm::tool_execution::Delegate;
 use crate::llm::types::{ToolDef, ToolFunctionDef};
 use crate::tools::{FsTools, RemoteToolInfo};
 use anyhow::Result;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use tracing::debug;
 
 const MAX_ITERS: usize = 256;
 
+/// Built-in tools without side effects, safe to run concurrently.
+const READ_ONLY_TOOLS: &[&str] = &[
+    "fs_read",
+    "fs_read_many_files",
+    "search_text",
+    "search_repomap",
+    "find_file",
+    "fs_list",
+    "bash_output",
+];
+
 pub struct ToolRuntime<'a> {
     pub tools: Vec<ToolDef>,
     pub fs: &'a FsTools,
     // repomap is delegated to FsTools, removed here
     pub max_iters: usize,
+    // Names of tools that only read state (built-in and read-only MCP tools)
+    read_only: HashSet<String>,
+    // Context for `spawn_agent`; sub-agents have none and cannot spawn agents
+    pub(crate) delegate: Option<Delegate<'a>>,
+    // Tool calls dispatched through this runtime
+    calls: AtomicUsize,
+    // Plan mode: nothing may change the workspace
+    plan_mode: bool,
+    // TUI inbox for live command output; sub-agents run silently
+    ui_tx: Option<std::sync::mpsc::Sender<String>>,
 }
 
 impl<'a> ToolRuntime<'a> {
@@ -19,6 +43,13 @@ impl<'a> ToolRuntime<'a> {
         let remote_tools = fs.remote_tools_snapshot().await;
 
         let mut tools = default_tools_def();
+        if fs.config.persistent_shell
+            && let Some(bash) = tools
+                .iter_mut()
+                .find(|tool| tool.function.name == "execute_bash")
+        {
+            *bash = crate::tools::execute::session_tool_def();
+        }
         append_remote_tools(&mut tools, &remote_tools);
 
         debug!(
@@ -26,12 +57,86 @@ impl<'a> ToolRuntime<'a> {
             "ToolRuntime registered remote MCP tools"
         );
 
+        let read_only = READ_ONLY_TOOLS
+            .iter()
+            .map(|name| name.to_string())
+            .chain(
+                remote_tools
+                    .iter()
+                    .filter(|info| info.read_only)
+                    .map(|info| info.alias.clone()),
+            )
+            .collect();
+
         Ok(Self {
             tools,
             fs,
             max_iters: MAX_ITERS,
+            read_only,
+            delegate: None,
+            calls: AtomicUsize::new(0),
+            plan_mode: false,
+            ui_tx: None,
         })
     }
+
+    /// Offer `spawn_agent`, running sub-agents in `delegate`'s context.
+    pub(crate) fn with_delegate(mut self, delegate: Delegate<'a>) -> Self {
+        self.tools.push(crate::tools::spawn_agent::tool_def());
+        self.delegate = Some(delegate);
+        self
+    }
+
+    /// Stream command output to the TUI through `ui_tx`.
+    pub fn with_ui_tx(mut self, ui_tx: Option<std::sync::mpsc::Sender<String>>) -> Self {
+        self.ui_tx = ui_tx;
+        self
+    }
+
+    pub fn ui_tx(&self) -> Option<&std::sync::mpsc::Sender<String>> {
+        self.ui_tx.as_ref()
+    }
+
+    /// Drop every tool that is not read-only.
+    pub fn restrict_to_read_only(&mut self) {
+        let read_only = &self.read_only;
+        self.tools
+            .retain(|tool| read_only.contains(&tool.function.name));
+    }
+
+    /// Keep only tools that cannot change the workspace and offer
+    /// `submit_plan`; `execute_bash` stays for read-only commands.
+    pub fn enter_plan_mode(&mut self) {
+        let read_only = &self.read_only;
+        self.tools.retain(|tool| {
+            read_only.contains(&tool.function.name) || tool.function.name == "execute_bash"
+        });
+        self.tools.push(crate::tools::submit_plan::tool_def());
+        self.plan_mode = true;
+    }
+
+    pub fn plan_mode(&self) -> bool {
+        self.plan_mode
+    }
+
+    /// Whether `name` is one of the tools offered to the model.
+    pub fn offers(&self, name: &str) -> bool {
+        self.tools.iter().any(|tool| tool.function.name == name)
+    }
+
+    /// Whether `name` can run alongside other read-only tool calls.
+    pub fn is_read_only(&self, name: &str) -> bool {
+        self.read_only.contains(name)
+    }
+
+    pub(crate) fn count_call(&self) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of tool calls dispatched so far.
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::Relaxed)
+    }
 }
 
 fn append_remote_tools(tools: &mut Vec<ToolDef>, remote: &[RemoteToolInfo]) {
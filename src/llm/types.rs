@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::llm::structured::ResponseFormat;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolFunctionDef {
     pub name: String,
@@ -29,16 +31,129 @@ pub struct ToolCall {
     pub function: ToolCallFunction,
 }
 
+/// Image attached to a message, carried inline as base64.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImagePart {
+    /// MIME type such as `image/png`
+    pub media_type: String,
+    /// Base64-encoded image bytes
+    pub data: String,
+}
+
+impl ImagePart {
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+
+    pub fn from_data_url(url: &str) -> Option<Self> {
+        let (media_type, data) = url.strip_prefix("data:")?.split_once(";base64,")?;
+        Some(Self {
+            media_type: media_type.to_string(),
+            data: data.to_string(),
+        })
+    }
+}
+
+/// Chat message in the OpenAI shape.
+///
+/// Messages with images serialize their content as a list of text and
+/// `image_url` parts; plain messages keep string content.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "WireChatMessage", from = "WireChatMessage")]
 pub struct ChatMessage {
     pub role: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<String>,
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    #[serde(default)]
     pub tool_calls: Vec<ToolCall>,
-    #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_call_id: Option<String>,
+    /// Reasoning the model produced before this (assistant) message
+    pub reasoning: Option<String>,
+    /// Images sent along with the text (user messages only)
+    pub images: Vec<ImagePart>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireChatMessage {
+    role: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content: Option<WireContent>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<ToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reasoning: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum WireContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Serialize, Deserialize)]
+struct ImageUrl {
+    url: String,
+}
+
+impl From<ChatMessage> for WireChatMessage {
+    fn from(msg: ChatMessage) -> Self {
+        let content = if msg.images.is_empty() {
+            msg.content.map(WireContent::Text)
+        } else {
+            let text = msg.content.map(|text| ContentPart::Text { text });
+            let images = msg.images.iter().map(|image| ContentPart::ImageUrl {
+                image_url: ImageUrl {
+                    url: image.data_url(),
+                },
+            });
+            Some(WireContent::Parts(text.into_iter().chain(images).collect()))
+        };
+        Self {
+            role: msg.role,
+            content,
+            tool_calls: msg.tool_calls,
+            tool_call_id: msg.tool_call_id,
+            reasoning: msg.reasoning,
+        }
+    }
+}
+
+impl From<WireChatMessage> for ChatMessage {
+    fn from(wire: WireChatMessage) -> Self {
+        let mut images = Vec::new();
+        let content = match wire.content {
+            None => None,
+            Some(WireContent::Text(text)) => Some(text),
+            Some(WireContent::Parts(parts)) => {
+                let mut texts = Vec::new();
+                for part in parts {
+                    match part {
+                        ContentPart::Text { text } => texts.push(text),
+                        ContentPart::ImageUrl { image_url } => {
+                            images.extend(ImagePart::from_data_url(&image_url.url))
+                        }
+                    }
+                }
+                (!texts.is_empty()).then(|| texts.join("\n"))
+            }
+        };
+        Self {
+            role: wire.role,
+            content,
+            tool_calls: wire.tool_calls,
+            tool_call_id: wire.tool_call_id,
+            reasoning: wire.reasoning,
+            images,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,6 +164,8 @@ pub struct ChatRequest {
     pub temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,6 +185,33 @@ pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+    /// Cache reads as reported by Anthropic-compatible proxies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_read_input_tokens: Option<u32>,
+    /// Cache hits as reported by DeepSeek
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_cache_hit_tokens: Option<u32>,
+}
+
+impl Usage {
+    /// Prompt tokens served from the provider's cache.
+    pub fn cached_tokens(&self) -> u32 {
+        self.prompt_tokens_details
+            .as_ref()
+            .map(|d| d.cached_tokens)
+            .filter(|&n| n > 0)
+            .or(self.cache_read_input_tokens)
+            .or(self.prompt_cache_hit_tokens)
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PromptTokensDetails {
+    #[serde(default)]
+    pub cached_tokens: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -76,3 +220,44 @@ pub struct ChatResponse {
     pub choices: Vec<Choice>,
     pub usage: Option<Usage>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn images_serialize_as_content_parts_and_round_trip() {
+        let msg = ChatMessage {
+            role: "user".into(),
+            content: Some("what is this?".into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![ImagePart {
+                media_type: "image/png".into(),
+                data: "iVBORw0KGgo=".into(),
+            }],
+        };
+        let value = serde_json::to_value(&msg).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "what is this?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}},
+                ],
+            })
+        );
+        let back: ChatMessage = serde_json::from_value(value).unwrap();
+        assert_eq!(back.content, msg.content);
+        assert_eq!(back.images, msg.images);
+
+        // Plain messages keep string content
+        let plain: ChatMessage =
+            serde_json::from_value(json!({"role": "user", "content": "hi"})).unwrap();
+        assert!(plain.images.is_empty());
+        assert_eq!(serde_json::to_value(&plain).unwrap()["content"], "hi");
+    }
+}
@@ -0,0 +1,182 @@
+//! Image input: loading attachments and knowing which models accept them.
+
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use std::path::{Path, PathBuf};
+
+use crate::config::AppConfig;
+use crate::llm::types::{ChatMessage, ImagePart};
+
+/// Largest image file accepted as an attachment.
+pub const MAX_IMAGE_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Vision support keyed by model name prefix; the longest match wins.
+const KNOWN_VISION_MODELS: &[(&str, bool)] = &[
+    // OpenAI
+    ("gpt-3.5", false),
+    ("gpt-4", false),
+    ("gpt-4-turbo", true),
+    ("gpt-4o", true),
+    ("gpt-4.1", true),
+    ("gpt-5", true),
+    ("gpt-oss", false),
+    ("o1", true),
+    ("o1-mini", false),
+    ("o3", true),
+    ("o3-mini", false),
+    ("o4-mini", true),
+    // Anthropic
+    ("claude", true),
+    // Google
+    ("gemini", true),
+    ("gemma3", true),
+    // xAI
+    ("grok-4", true),
+    ("grok-code-fast", false),
+    // Open-weight models
+    ("llava", true),
+    ("pixtral", true),
+    ("mistral-small3.1", true),
+    ("qwen2.5vl", true),
+];
+
+/// Name fragments of open-weight vision variants (e.g. `llama3.2-vision`).
+const VISION_MARKERS: &[&str] = &["vision", "-vl"];
+
+/// Whether `model` accepts image input according to the built-in registry.
+///
+/// Routing prefixes such as `openai/` are ignored.
+pub fn known_vision_support(model: &str) -> bool {
+    let name = model
+        .rsplit('/')
+        .next()
+        .unwrap_or(model)
+        .to_ascii_lowercase();
+    KNOWN_VISION_MODELS
+        .iter()
+        .filter(|(prefix, _)| name.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, vision)| *vision)
+        .unwrap_or_else(|| VISION_MARKERS.iter().any(|m| name.contains(m)))
+}
+
+/// Fail if `messages` carry images that `model` cannot see.
+pub fn ensure_vision(cfg: &AppConfig, model: &str, messages: &[ChatMessage]) -> Result<()> {
+    if messages.iter().any(|m| !m.images.is_empty()) && !cfg.supports_vision(model) {
+        bail!(
+            "{model} does not accept image input; remove the image or switch to a vision-capable \
+             model (add `\"{model}\" = true` under [vision] in the config if it does support images)"
+        );
+    }
+    Ok(())
+}
+
+/// MIME type of an image file, judged by its extension.
+pub fn image_media_type(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+pub fn load_image(path: &Path) -> Result<ImagePart> {
+    let Some(media_type) = image_media_type(path) else {
+        bail!("unsupported image type: {}", path.display());
+    };
+    let size = std::fs::metadata(path)
+        .with_context(|| format!("metadata {}", path.display()))?
+        .len();
+    if size > MAX_IMAGE_BYTES {
+        bail!(
+            "image {} is {size} bytes; the limit is {MAX_IMAGE_BYTES}",
+            path.display()
+        );
+    }
+    let bytes = std::fs::read(path).with_context(|| format!("read {}", path.display()))?;
+    Ok(ImagePart {
+        media_type: media_type.to_string(),
+        data: STANDARD.encode(bytes),
+    })
+}
+
+/// Encode raw RGBA pixels, as read from the clipboard, as a PNG attachment.
+pub fn png_from_rgba(width: u32, height: u32, rgba: &[u8]) -> Result<ImagePart> {
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().context("encode png header")?;
+    writer.write_image_data(rgba).context("encode png data")?;
+    writer.finish().context("finish png")?;
+    Ok(ImagePart {
+        media_type: "image/png".to_string(),
+        data: STANDARD.encode(out),
+    })
+}
+
+/// Image files referenced as `@path` in `text`, resolved against `root`.
+pub fn image_references(text: &str, root: &Path) -> Vec<PathBuf> {
+    text.split_whitespace()
+        .filter_map(|word| word.strip_prefix('@'))
+        .map(|path| path.trim_end_matches([',', '.', ';', ':', ')', '!', '?']))
+        .filter(|path| image_media_type(Path::new(path)).is_some())
+        .map(|path| root.join(path))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_knows_vision_models() {
+        assert!(known_vision_support("gpt-4o-mini"));
+        assert!(known_vision_support("anthropic/claude-sonnet-4-5"));
+        assert!(known_vision_support("llama3.2-vision:11b"));
+        assert!(known_vision_support("qwen2.5-vl-7b-instruct"));
+        assert!(!known_vision_support("o3-mini"));
+        assert!(!known_vision_support("qwen2.5-coder:7b"));
+        assert!(!known_vision_support("deepseek-chat"));
+    }
+
+    #[test]
+    fn loads_referenced_images_and_encodes_clipboard_pixels() {
+        let dir = tempfile::tempdir().unwrap();
+        let png = png_from_rgba(1, 1, &[255, 0, 0, 255]).unwrap();
+        let bytes = STANDARD.decode(&png.data).unwrap();
+        assert!(bytes.starts_with(b"\x89PNG"));
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        std::fs::write(dir.path().join("docs/shot.png"), &bytes).unwrap();
+
+        let refs = image_references(
+            "what is wrong in @docs/shot.png, and @src/main.rs? see @missing.png",
+            dir.path(),
+        );
+        assert_eq!(refs, vec![dir.path().join("docs/shot.png")]);
+        let image = load_image(&refs[0]).unwrap();
+        assert_eq!(image, png);
+        assert!(load_image(&dir.path().join("notes.txt")).is_err());
+    }
+
+    #[test]
+    fn rejects_images_for_text_only_models() {
+        let cfg = AppConfig::default();
+        let msg = ChatMessage {
+            role: "user".into(),
+            content: Some("look".into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![png_from_rgba(1, 1, &[0, 0, 0, 255]).unwrap()],
+        };
+        assert!(ensure_vision(&cfg, "gpt-4o", std::slice::from_ref(&msg)).is_ok());
+        let err = ensure_vision(&cfg, "deepseek-chat", &[msg]).unwrap_err();
+        assert!(err.to_string().contains("does not accept image input"));
+    }
+}
@@ -1,6 +1,7 @@
 pub mod analysis;
 pub mod assets;
 pub mod config;
+pub mod cost;
 pub mod diff_review;
 pub mod exec;
 pub mod features;
@@ -8,6 +9,7 @@ pub mod hooks;
 pub mod llm;
 pub mod logging;
 pub mod mcp;
+pub mod mock_llm;
 pub mod session;
 pub mod tools;
 mod tui;
@@ -29,6 +31,10 @@ use dotenvy::dotenv;
     about = "Interactive AI coding agent (TUI)"
 )]
 pub struct Cli {
+    /// LLM provider: "openai", "openai-responses", "anthropic", "ollama" or "llamacpp" (falls back to env DOGE_PROVIDER or config file)
+    #[arg(long, default_value = "")]
+    pub provider: String,
+
     /// OpenAI-compatible API base URL (no default; falls back to env OPENAI_BASE_URL or config file)
     #[arg(long, default_value = "")]
     pub base_url: String,
@@ -53,6 +59,14 @@ pub struct Cli {
     #[arg(short, long, default_value_t = false)]
     pub resume: bool,
 
+    /// Record all LLM HTTP traffic to a cassette file (OpenAI-compatible providers)
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    pub record: Option<std::path::PathBuf>,
+
+    /// Replay LLM responses from a cassette file instead of the network
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<std::path::PathBuf>,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
@@ -100,6 +114,17 @@ pub enum Commands {
         #[arg(long, default_value_t = false)]
         json: bool,
     },
+
+    /// Serve a scripted OpenAI-compatible endpoint for end-to-end tests
+    #[command()]
+    MockLlm {
+        /// Scenario file (TOML, YAML or JSON) listing the replies to send, in order
+        #[arg(long, value_name = "FILE")]
+        script: std::path::PathBuf,
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8089")]
+        address: String,
+    },
 }
 
 #[tokio::main]
@@ -108,9 +133,21 @@ async fn main() -> Result<()> {
     let cli = Cli::parse();
     logging::init_logging()?;
 
-    let cfg = AppConfig::from_cli(cli.clone())?;
+    // The mock server needs neither project config nor a repomap
+    if let Some(Commands::MockLlm { script, address }) = &cli.command {
+        return mock_llm::run(script, address).await;
+    }
+
+    let mut cfg = AppConfig::from_cli(cli.clone())?;
     // info!(?cfg, "app config");
 
+    // Local inference servers report context length and tool support for the loaded model
+    match crate::llm::probe_model_capabilities(&cfg).await {
+        Ok(Some(caps)) => cfg.apply_model_capabilities(caps),
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Failed to probe model capabilities: {:?}", e),
+    }
+
     // Initialize repomap
     let (repomap, status_rx) = if !cfg.no_repomap {
         let repomap = std::sync::Arc::new(tokio::sync::RwLock::new(None));
@@ -191,6 +228,7 @@ async fn main() -> Result<()> {
             mcp::server::start_mcp_server(&config, repomap.clone());
             Ok(())
         }
+        Some(Commands::MockLlm { .. }) => unreachable!("handled before config loading"),
     }
 }
 
@@ -207,6 +245,8 @@ async fn run_tui(
     // Set auto-compact threshold in the UI from configuration
     app.auto_compact_prompt_token_threshold =
         cfg.auto_compact_prompt_token_threshold_for_current_model();
+    app.sandbox = crate::tools::sandbox::Sandbox::from_config(&cfg);
+    app.command_policy = crate::tools::command_policy::CommandPolicy::new(&cfg);
 
     // app.push_log("Welcome to doge-code TUI");
     // app.push_log("Initializing repomap...");
@@ -0,0 +1,471 @@
+//! Scriptable OpenAI-compatible mock server (`dgc mock-llm`).
+//!
+//! Answers chat completions requests from a scenario file, one step per
+//! request, so `exec`, `watch` and custom commands can run end to end without
+//! a real model. Scenarios are TOML, YAML (`.yaml`/`.yml`) or JSON, chosen by
+//! the file extension:
+//!
+//! ```toml
+//! [[steps]]
+//! type = "tool_calls"
+//! calls = [{ name = "fs_read", arguments = { path = "README.md" } }]
+//!
+//! [[steps]]
+//! type = "rate_limit"
+//! retry_after = 1
+//!
+//! [[steps]]
+//! type = "text"
+//! content = "The README describes the project."
+//! ```
+
+use anyhow::{Context, Result};
+use axum::Json;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{StatusCode, header};
+use axum::response::Response;
+use axum::routing::post;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::info;
+
+use crate::llm::count_tokens;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Scenario {
+    /// Model name echoed in responses
+    #[serde(default = "default_model")]
+    pub model: String,
+    /// Start over from the first step once the script is exhausted
+    #[serde(default)]
+    pub repeat: bool,
+    #[serde(default)]
+    pub steps: Vec<Step>,
+}
+
+fn default_model() -> String {
+    "mock-llm".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Step {
+    /// Wait this long before answering (to exercise client timeouts)
+    #[serde(default)]
+    pub delay_ms: u64,
+    #[serde(flatten)]
+    pub reply: Reply,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Reply {
+    Text {
+        content: String,
+    },
+    ToolCalls {
+        #[serde(default)]
+        content: Option<String>,
+        calls: Vec<ScriptedCall>,
+    },
+    /// Arbitrary HTTP error with an OpenAI-shaped error body
+    Error {
+        status: u16,
+        #[serde(default = "default_error_message")]
+        message: String,
+    },
+    /// 429 with a `Retry-After` header (seconds)
+    RateLimit {
+        #[serde(default)]
+        retry_after: u64,
+    },
+    ContextLength,
+}
+
+fn default_error_message() -> String {
+    "scripted error".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScriptedCall {
+    pub name: String,
+    /// Tool arguments as an object, or a raw (possibly malformed) string
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read scenario {}", path.display()))?;
+        let scenario = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&text).map_err(anyhow::Error::from),
+            Some("yaml" | "yml") => serde_yaml_ng::from_str(&text).map_err(anyhow::Error::from),
+            _ => serde_json::from_str(&text).map_err(anyhow::Error::from),
+        };
+        scenario.with_context(|| format!("invalid scenario {}", path.display()))
+    }
+}
+
+struct MockState {
+    scenario: Scenario,
+    next: Mutex<usize>,
+}
+
+impl MockState {
+    fn next_step(&self) -> Option<Step> {
+        let mut next = self.next.lock().unwrap();
+        let steps = &self.scenario.steps;
+        if *next >= steps.len() && self.scenario.repeat && !steps.is_empty() {
+            *next = 0;
+        }
+        let step = steps.get(*next).cloned();
+        *next += 1;
+        step
+    }
+}
+
+/// Router serving `POST /v1/chat/completions` from `scenario`.
+pub fn router(scenario: Scenario) -> axum::Router {
+    let state = Arc::new(MockState {
+        scenario,
+        next: Mutex::new(0),
+    });
+    axum::Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/chat/completions", post(chat_completions))
+        .with_state(state)
+}
+
+/// Serve `scenario` on `address` until Ctrl-C.
+pub async fn run(script: &Path, address: &str) -> Result<()> {
+    let scenario = Scenario::load(script)?;
+    let steps = scenario.steps.len();
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    let addr = listener.local_addr()?;
+    println!("mock-llm serving {steps} steps at http://{addr}/v1");
+    axum::serve(listener, router(scenario))
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+    Ok(())
+}
+
+async fn chat_completions(State(state): State<Arc<MockState>>, Json(req): Json<Value>) -> Response {
+    let stream = req.get("stream").and_then(Value::as_bool).unwrap_or(false);
+    let prompt_tokens = req
+        .get("messages")
+        .map_or(0, |m| count_tokens(&m.to_string()) as u32);
+
+    let Some(step) = state.next_step() else {
+        info!("mock-llm script exhausted");
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "mock-llm script exhausted",
+            "invalid_request_error",
+            None,
+        );
+    };
+    info!(reply = ?step.reply, stream, "mock-llm answering");
+    if step.delay_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(step.delay_ms)).await;
+    }
+
+    let model = &state.scenario.model;
+    match step.reply {
+        Reply::Text { content } => completion(model, Some(content), vec![], prompt_tokens, stream),
+        Reply::ToolCalls { content, calls } => {
+            completion(model, content, calls, prompt_tokens, stream)
+        }
+        Reply::Error { status, message } => error_response(
+            StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            &message,
+            "server_error",
+            None,
+        ),
+        Reply::RateLimit { retry_after } => {
+            let mut resp = error_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                "Rate limit reached",
+                "rate_limit_exceeded",
+                Some("rate_limit_exceeded"),
+            );
+            resp.headers_mut()
+                .insert(header::RETRY_AFTER, retry_after.into());
+            resp
+        }
+        Reply::ContextLength => error_response(
+            StatusCode::BAD_REQUEST,
+            "This model's maximum context length has been exceeded.",
+            "invalid_request_error",
+            Some("context_length_exceeded"),
+        ),
+    }
+}
+
+fn completion(
+    model: &str,
+    content: Option<String>,
+    calls: Vec<ScriptedCall>,
+    prompt_tokens: u32,
+    stream: bool,
+) -> Response {
+    let tool_calls: Vec<Value> = calls
+        .iter()
+        .enumerate()
+        .map(|(i, call)| {
+            let arguments = match &call.arguments {
+                Value::String(raw) => raw.clone(),
+                Value::Null => "{}".to_string(),
+                other => other.to_string(),
+            };
+            json!({
+                "index": i,
+                "id": format!("call_{}", i + 1),
+                "type": "function",
+                "function": {"name": call.name, "arguments": arguments}
+            })
+        })
+        .collect();
+    let finish_reason = if tool_calls.is_empty() {
+        "stop"
+    } else {
+        "tool_calls"
+    };
+    let completion_tokens = content.as_deref().map_or(0, count_tokens) as u32
+        + tool_calls
+            .iter()
+            .map(|tc| count_tokens(&tc["function"].to_string()) as u32)
+            .sum::<u32>();
+    let usage = json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    });
+
+    if !stream {
+        let mut message = json!({"role": "assistant", "content": content});
+        if !tool_calls.is_empty() {
+            message["tool_calls"] = Value::Array(tool_calls);
+        }
+        let body = json!({
+            "id": "chatcmpl-mock",
+            "object": "chat.completion",
+            "model": model,
+            "choices": [{"index": 0, "message": message, "finish_reason": finish_reason}],
+            "usage": usage,
+        });
+        return Response::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .expect("valid response");
+    }
+
+    let chunk = |delta: Value, finish: Option<&str>| {
+        json!({
+            "id": "chatcmpl-mock",
+            "object": "chat.completion.chunk",
+            "model": model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish}],
+        })
+    };
+    let mut events = vec![chunk(json!({"role": "assistant"}), None)];
+    // Stream text word by word so clients see incremental deltas
+    for word in content.as_deref().unwrap_or_default().split_inclusive(' ') {
+        events.push(chunk(json!({"content": word}), None));
+    }
+    if !tool_calls.is_empty() {
+        events.push(chunk(json!({"tool_calls": tool_calls}), None));
+    }
+    events.push(chunk(json!({}), Some(finish_reason)));
+    events.push(json!({"id": "chatcmpl-mock", "choices": [], "usage": usage}));
+
+    let mut body: String = events
+        .iter()
+        .map(|event| format!("data: {event}\n\n"))
+        .collect();
+    body.push_str("data: [DONE]\n\n");
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .body(Body::from(body))
+        .expect("valid response")
+}
+
+fn error_response(status: StatusCode, message: &str, kind: &str, code: Option<&str>) -> Response {
+    let body = json!({"error": {"message": message, "type": kind, "code": code}});
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("valid response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LlmConfig;
+    use crate::llm::{ChatMessage, LlmErrorKind, LlmProvider, OpenAIClient};
+    use futures::StreamExt;
+
+    async fn spawn(scenario: &str) -> OpenAIClient {
+        let scenario: Scenario = toml::from_str(scenario).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(scenario)).await });
+        let cfg = LlmConfig {
+            max_retries: 2,
+            ..Default::default()
+        };
+        OpenAIClient::new(format!("http://{addr}/v1"), "k")
+            .unwrap()
+            .with_llm_config(cfg)
+    }
+
+    fn user(text: &str) -> Vec<ChatMessage> {
+        vec![ChatMessage {
+            role: "user".into(),
+            content: Some(text.into()),
+            tool_calls: vec![],
+            tool_call_id: None,
+            reasoning: None,
+            images: vec![],
+        }]
+    }
+
+    #[tokio::test]
+    async fn serves_scripted_tool_calls_and_text() {
+        let client = spawn(
+            r#"
+            [[steps]]
+            type = "tool_calls"
+            calls = [{ name = "fs_read", arguments = { path = "README.md" } }]
+
+            [[steps]]
+            type = "text"
+            content = "All done."
+            "#,
+        )
+        .await;
+
+        let msg = client
+            .chat_tools_once("m", user("read it"), &[], None)
+            .await
+            .unwrap();
+        assert_eq!(msg.tool_calls[0].function.name, "fs_read");
+        assert_eq!(
+            msg.tool_calls[0].function.arguments,
+            r#"{"path":"README.md"}"#
+        );
+
+        let mut stream = client
+            .chat_stream("m", user("and?"), &[], None)
+            .await
+            .unwrap();
+        let mut text = String::new();
+        while let Some(delta) = stream.next().await {
+            text.push_str(&delta.unwrap());
+        }
+        assert_eq!(text, "All done.");
+        assert!(client.get_tokens_used() > 0);
+    }
+
+    #[tokio::test]
+    async fn rate_limits_are_retried_and_context_errors_surface() {
+        let client = spawn(
+            r#"
+            [[steps]]
+            type = "rate_limit"
+            retry_after = 0
+
+            [[steps]]
+            type = "text"
+            content = "ok"
+
+            [[steps]]
+            type = "context_length"
+            "#,
+        )
+        .await;
+
+        let msg = client.chat_once("m", user("hi"), None).await.unwrap();
+        assert_eq!(msg.content, "ok");
+
+        let err = client
+            .chat_tools_once("m", user("long"), &[], None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LlmErrorKind>(),
+            Some(LlmErrorKind::ContextLengthExceeded)
+        ));
+
+        // Script exhausted
+        assert!(client.chat_once("m", user("more"), None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn tool_requests_are_retried() {
+        let client = spawn(
+            r#"
+            [[steps]]
+            type = "rate_limit"
+            retry_after = 0
+
+            [[steps]]
+            type = "tool_calls"
+            calls = [{ name = "fs_read", arguments = { path = "a.rs" } }]
+            "#,
+        )
+        .await;
+
+        let msg = client
+            .chat_tools_once("m", user("read it"), &[], None)
+            .await
+            .unwrap();
+        assert_eq!(msg.tool_calls[0].function.name, "fs_read");
+    }
+
+    #[test]
+    fn loads_block_style_yaml() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("scenario.yaml");
+        std::fs::write(
+            &path,
+            r#"
+model: scripted
+steps:
+  - type: tool_calls
+    delay_ms: 5
+    calls:
+      - name: fs_read
+        arguments:
+          path: README.md
+  - type: rate_limit
+    retry_after: 1
+  - type: text
+    content: The README describes the project.
+"#,
+        )
+        .unwrap();
+
+        let scenario = Scenario::load(&path).unwrap();
+        assert_eq!(scenario.model, "scripted");
+        assert_eq!(scenario.steps.len(), 3);
+        assert_eq!(scenario.steps[0].delay_ms, 5);
+        let Reply::ToolCalls { calls, .. } = &scenario.steps[0].reply else {
+            panic!("expected tool calls: {:?}", scenario.steps[0]);
+        };
+        assert_eq!(calls[0].arguments, json!({"path": "README.md"}));
+        assert!(matches!(
+            scenario.steps[1].reply,
+            Reply::RateLimit { retry_after: 1 }
+        ));
+        assert!(
+            matches!(&scenario.steps[2].reply, Reply::Text { content } if content.starts_with("The README"))
+        );
+    }
+}
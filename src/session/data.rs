@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
 
+use crate::cost::CostSummary;
+
 fn serialize_rfc3339<S>(val: &str, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
@@ -83,6 +85,31 @@ pub struct SessionMeta {
     pub title_is_default: bool,
 }
 
+/// Switch to a fallback model after the active one kept failing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ModelSwitch {
+    /// When the switch happened (RFC3339 string)
+    pub at: String,
+    pub from: String,
+    pub to: String,
+    pub reason: String,
+}
+
+/// Task delegated to a sub-agent through `spawn_agent`.
+///
+/// The sub-agent's requests and tool calls already count towards the session
+/// totals; this keeps the per-run breakdown.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SubAgentRun {
+    /// When the sub-agent finished (RFC3339 string)
+    pub at: String,
+    pub task: String,
+    pub tool_calls: u64,
+    /// Prompt and completion tokens of the sub-agent's requests
+    pub tokens: u64,
+    pub succeeded: bool,
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SessionData {
     pub meta: SessionMeta,
@@ -108,6 +135,15 @@ pub struct SessionData {
     pub tool_call_failures: HashMap<String, u64>,
     /// Changed files during the session for repomap update
     pub changed_files: Vec<String>,
+    /// Token usage and spend by model
+    #[serde(default)]
+    pub cost: CostSummary,
+    /// Fallback model switches, oldest first
+    #[serde(default)]
+    pub model_switches: Vec<ModelSwitch>,
+    /// Sub-agent runs, oldest first
+    #[serde(default)]
+    pub sub_agents: Vec<SubAgentRun>,
 }
 
 impl SessionData {
@@ -132,6 +168,9 @@ impl SessionData {
             tool_call_successes: HashMap::new(),
             tool_call_failures: HashMap::new(),
             changed_files: Vec::new(),
+            cost: CostSummary::default(),
+            model_switches: Vec::new(),
+            sub_agents: Vec::new(),
         }
     }
 
@@ -165,6 +204,32 @@ impl SessionData {
         self.timestamp = Utc::now().to_rfc3339(); // Update timestamp
     }
 
+    /// Add the usage and spend of a run to the session totals.
+    pub fn add_cost(&mut self, cost: &CostSummary) {
+        self.cost.merge(cost);
+        self.timestamp = Utc::now().to_rfc3339(); // Update timestamp
+    }
+
+    /// Record a switch to a fallback model.
+    pub fn record_model_switch(&mut self, from: &str, to: &str, reason: &str) {
+        let now = Utc::now().to_rfc3339();
+        self.model_switches.push(ModelSwitch {
+            at: now.clone(),
+            from: from.to_string(),
+            to: to.to_string(),
+            reason: reason.to_string(),
+        });
+        self.timestamp = now;
+    }
+
+    /// Record a finished sub-agent run.
+    pub fn record_sub_agent(&mut self, mut run: SubAgentRun) {
+        let now = Utc::now().to_rfc3339();
+        run.at = now.clone();
+        self.sub_agents.push(run);
+        self.timestamp = now;
+    }
+
     /// Increment lines edited count.
     pub fn increment_lines_edited(&mut self, count: u64) {
         self.lines_edited += count;
@@ -1,4 +1,5 @@
-use crate::session::{SessionData, SessionStore};
+use crate::cost::CostSummary;
+use crate::session::{SessionData, SessionStore, SubAgentRun};
 use anyhow::Result;
 use tracing::{debug, error as tracing_error};
 
@@ -143,6 +144,23 @@ impl SessionManager {
         Ok(())
     }
 
+    /// Add the usage and spend of a run to the current session
+    pub fn update_current_session_with_cost(&mut self, cost: &CostSummary) -> Result<()> {
+        if let Some(ref mut session) = self.current_session {
+            session.add_cost(cost);
+            if let Err(e) = self.store.save(session) {
+                tracing_error!(?e, "Failed to save session data");
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the usage and spend recorded for the current session
+    pub fn current_session_cost(&self) -> Option<&CostSummary> {
+        self.current_session.as_ref().map(|session| &session.cost)
+    }
+
     /// Update the current session with request count
     pub fn update_current_session_with_request_count(&mut self) -> Result<()> {
         if let Some(ref mut session) = self.current_session {
@@ -179,6 +197,30 @@ impl SessionManager {
         Ok(())
     }
 
+    /// Record a switch to a fallback model in the current session
+    pub fn record_model_switch(&mut self, from: &str, to: &str, reason: &str) -> Result<()> {
+        if let Some(ref mut session) = self.current_session {
+            session.record_model_switch(from, to, reason);
+            if let Err(e) = self.store.save(session) {
+                tracing_error!(?e, "Failed to save session data");
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Record a finished sub-agent run in the current session
+    pub fn record_sub_agent(&mut self, run: SubAgentRun) -> Result<()> {
+        if let Some(ref mut session) = self.current_session {
+            session.record_sub_agent(run);
+            if let Err(e) = self.store.save(session) {
+                tracing_error!(?e, "Failed to save session data");
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+
     /// Record a failed tool call in the current session
     pub fn record_tool_call_failure(&mut self, tool_name: &str) -> Result<()> {
         if let Some(ref mut session) = self.current_session {
@@ -298,6 +340,11 @@ impl SessionManager {
                 session.changed_files.len()
             );
 
+            if !session.cost.is_empty() {
+                stats.push_str("\n\nCost:\n");
+                stats.push_str(&session.cost.report());
+            }
+
             // Add tool call success statistics
             if !session.tool_call_successes.is_empty() {
                 stats.push_str("\n\nTool Call Successes:");
@@ -314,6 +361,30 @@ impl SessionManager {
                 }
             }
 
+            if !session.model_switches.is_empty() {
+                stats.push_str("\n\nFallback Model Switches:");
+                for switch in &session.model_switches {
+                    stats.push_str(&format!(
+                        "\n  {}: {} -> {} ({})",
+                        switch.at, switch.from, switch.to, switch.reason
+                    ));
+                }
+            }
+
+            if !session.sub_agents.is_empty() {
+                stats.push_str("\n\nSub-agents:");
+                for run in &session.sub_agents {
+                    stats.push_str(&format!(
+                        "\n  {}: {} ({} tool calls, {} tokens, {})",
+                        run.at,
+                        run.task,
+                        run.tool_calls,
+                        run.tokens,
+                        if run.succeeded { "completed" } else { "failed" }
+                    ));
+                }
+            }
+
             stats
         })
     }
@@ -454,4 +525,48 @@ mod tests {
             0
         );
     }
+
+    #[test]
+    fn test_update_current_session_with_cost() {
+        use crate::cost::{PriceTable, RequestUsage};
+
+        let dir = tempdir().expect("Failed to create temp directory");
+        let store = SessionStore::new(dir.path()).expect("Failed to create session store");
+        let mut session_manager = SessionManager {
+            store,
+            current_session: None,
+        };
+        session_manager
+            .create_session(None)
+            .expect("Failed to create session");
+
+        let usage = RequestUsage {
+            model: "gpt-4o".to_string(),
+            input_tokens: 1_000_000,
+            output_tokens: 0,
+            cached_tokens: 0,
+        };
+        let run = CostSummary::from_usages(&[usage], &PriceTable::default());
+        session_manager
+            .update_current_session_with_cost(&run)
+            .expect("Failed to update session with cost");
+        session_manager
+            .update_current_session_with_cost(&run)
+            .expect("Failed to update session with cost");
+
+        let cost = session_manager.current_session_cost().unwrap();
+        assert_eq!(cost.requests, 2);
+        assert!((cost.cost_usd - 5.0).abs() < 1e-9);
+
+        // The total survives a reload from disk
+        let id = session_manager.get_current_session_id().unwrap();
+        session_manager.load_session(&id).unwrap();
+        assert_eq!(session_manager.current_session_cost().unwrap().requests, 2);
+        assert!(
+            session_manager
+                .get_session_statistics()
+                .unwrap()
+                .contains("Total cost: $5.00")
+        );
+    }
 }
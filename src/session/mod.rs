@@ -5,6 +5,6 @@ pub mod store;
 #[cfg(test)]
 pub mod tests;
 
-pub use data::{SessionData, SessionMeta};
+pub use data::{ModelSwitch, SessionData, SessionMeta, SubAgentRun};
 pub use manager::SessionManager;
 pub use store::SessionStore;
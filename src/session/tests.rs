@@ -42,6 +42,8 @@ fn test_session_metric_tracking() {
         ),
         tool_calls: vec![],
         tool_call_id: None,
+        reasoning: None,
+        images: vec![],
     };
 
     session_manager
@@ -0,0 +1,399 @@
+use crate::config::AppConfig;
+use crate::llm::types::{ToolDef, ToolFunctionDef};
+use crate::tools::sandbox::Sandbox;
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
+use tokio::task::JoinHandle;
+
+/// Bytes of each stream kept per read; the middle of longer output is dropped.
+pub const MAX_OUTPUT_BYTES: usize = 30_000;
+
+/// How long a killed job gets to exit before its status is reported anyway.
+const KILL_GRACE: Duration = Duration::from_secs(2);
+
+/// Output of one stream since it was last taken, capped to
+/// [`MAX_OUTPUT_BYTES`] by keeping the beginning and the end.
+#[derive(Debug, Default)]
+pub struct OutputBuffer {
+    head: String,
+    tail: String,
+    dropped: usize,
+}
+
+impl OutputBuffer {
+    pub fn push(&mut self, mut text: &str) {
+        let half = MAX_OUTPUT_BYTES / 2;
+        if self.head.len() < half {
+            let take = text.floor_char_boundary(half - self.head.len());
+            self.head.push_str(&text[..take]);
+            text = &text[take..];
+        }
+        self.tail.push_str(text);
+        if self.tail.len() > half {
+            let cut = self.tail.ceil_char_boundary(self.tail.len() - half);
+            self.tail.drain(..cut);
+            self.dropped += cut;
+        }
+    }
+
+    /// Everything buffered since the last call.
+    pub fn take(&mut self) -> String {
+        let mut out = std::mem::take(&mut self.head);
+        if self.dropped > 0 {
+            out.push_str(&format!(
+                "\n[... {} bytes of output truncated ...]\n",
+                self.dropped
+            ));
+        }
+        out.push_str(&std::mem::take(&mut self.tail));
+        self.dropped = 0;
+        out
+    }
+}
+
+/// `bash -c` running in its own process group, with its output collected in
+/// the background. Dropping it kills the whole group, so cancelling a tool
+/// call also stops everything the command started.
+#[derive(Debug)]
+pub struct ShellProcess {
+    child: Child,
+    pgid: Option<i32>,
+    stdout: Arc<Mutex<OutputBuffer>>,
+    stderr: Arc<Mutex<OutputBuffer>>,
+    readers: Vec<JoinHandle<()>>,
+}
+
+impl ShellProcess {
+    /// Start `command` in the project root, inside the sandbox if one is
+    /// configured. Complete lines are sent to `live` as they arrive.
+    ///
+    /// A sandbox that cannot be set up is reported as a
+    /// [`SandboxViolation`](crate::tools::sandbox::SandboxViolation) error.
+    pub fn spawn(command: &str, config: &AppConfig, live: Option<Sender<String>>) -> Result<Self> {
+        let project_root = &config.project_root;
+        let mut cmd = Command::new("bash");
+        cmd.arg("-c")
+            .arg(command)
+            .current_dir(project_root)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0);
+        let sandbox = Sandbox::from_config(config);
+        if let Some(sandbox) = &sandbox {
+            sandbox
+                .confine(&mut cmd, project_root)
+                .map_err(|e| anyhow!(Sandbox::unavailable(e)))?;
+        }
+
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            // The namespaces are set up in the child, so their failures show up here
+            Err(e) if sandbox.is_some() => return Err(anyhow!(Sandbox::unavailable(e))),
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!(
+                        "Failed to execute command: {command} in directory: {}",
+                        project_root.display()
+                    )
+                });
+            }
+        };
+
+        let stdout = Arc::new(Mutex::new(OutputBuffer::default()));
+        let stderr = Arc::new(Mutex::new(OutputBuffer::default()));
+        let mut readers = Vec::new();
+        if let Some(pipe) = child.stdout.take() {
+            readers.push(tokio::spawn(collect(pipe, stdout.clone(), live.clone())));
+        }
+        if let Some(pipe) = child.stderr.take() {
+            readers.push(tokio::spawn(collect(pipe, stderr.clone(), live)));
+        }
+        Ok(Self {
+            pgid: child.id().map(|pid| pid as i32),
+            child,
+            stdout,
+            stderr,
+            readers,
+        })
+    }
+
+    /// Wait for the command to exit. Whatever it left running in its process
+    /// group is killed, so that all output has been collected on return.
+    pub async fn wait(&mut self) -> Result<ExitStatus> {
+        let status = self.child.wait().await?;
+        self.kill();
+        for reader in self.readers.drain(..) {
+            let _ = reader.await;
+        }
+        Ok(status)
+    }
+
+    /// Exit status, if the command has finished.
+    pub fn try_status(&mut self) -> Option<ExitStatus> {
+        self.child.try_wait().ok().flatten()
+    }
+
+    /// Send SIGKILL to the command's process group.
+    pub fn kill(&self) {
+        if let Some(pgid) = self.pgid {
+            // SAFETY: killpg has no memory-safety requirements
+            unsafe {
+                libc::killpg(pgid, libc::SIGKILL);
+            }
+        }
+    }
+
+    /// Stdout and stderr collected since the last call.
+    pub fn take_output(&self) -> (String, String) {
+        (
+            self.stdout.lock().unwrap().take(),
+            self.stderr.lock().unwrap().take(),
+        )
+    }
+}
+
+impl Drop for ShellProcess {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// Read `pipe` into `buffer` until it closes.
+async fn collect(
+    mut pipe: impl AsyncRead + Unpin,
+    buffer: Arc<Mutex<OutputBuffer>>,
+    live: Option<Sender<String>>,
+) {
+    let mut chunk = [0u8; 8192];
+    // Trailing bytes of an incomplete UTF-8 sequence
+    let mut pending = Vec::new();
+    // Incomplete line not yet streamed
+    let mut line = String::new();
+    loop {
+        let n = match pipe.read(&mut chunk).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        pending.extend_from_slice(&chunk[..n]);
+        let valid = match std::str::from_utf8(&pending) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            // Not UTF-8 at all; keep what can be shown
+            Err(_) => pending.len(),
+        };
+        let text = String::from_utf8_lossy(&pending[..valid]).into_owned();
+        pending.drain(..valid);
+
+        buffer.lock().unwrap().push(&text);
+        if let Some(tx) = &live {
+            line.push_str(&text);
+            while let Some(end) = line.find('\n') {
+                let _ = tx.send(format!("::shell_output:{}", &line[..end]));
+                line.drain(..=end);
+            }
+        }
+    }
+    if !pending.is_empty() {
+        buffer
+            .lock()
+            .unwrap()
+            .push(&String::from_utf8_lossy(&pending));
+    }
+    if let Some(tx) = &live
+        && !line.is_empty()
+    {
+        let _ = tx.send(format!("::shell_output:{line}"));
+    }
+}
+
+#[derive(Debug)]
+struct Job {
+    command: String,
+    started: Instant,
+    process: ShellProcess,
+}
+
+/// Commands started with `run_in_background`, polled with `bash_output`
+/// and stopped with `bash_kill`. Jobs still running when the registry is
+/// dropped are killed.
+#[derive(Debug, Default)]
+pub struct BashJobs {
+    next_id: AtomicUsize,
+    jobs: Mutex<HashMap<String, Job>>,
+}
+
+impl BashJobs {
+    /// Start `command` and return its job id.
+    pub fn start(&self, command: &str, config: &AppConfig) -> Result<String> {
+        let process = ShellProcess::spawn(command, config, None)?;
+        let id = format!("bash-{}", self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+        self.jobs.lock().unwrap().insert(
+            id.clone(),
+            Job {
+                command: command.to_string(),
+                started: Instant::now(),
+                process,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Output since the last poll and whether the job is still running. A
+    /// finished job is forgotten once its final output has been returned.
+    pub fn output(&self, id: &str) -> Result<Value> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs
+            .get_mut(id)
+            .ok_or_else(|| anyhow!("no background job with id {id}"))?;
+        let status = job.process.try_status();
+        let (stdout, stderr) = job.process.take_output();
+        let report = json!({
+            "job_id": id,
+            "command": job.command,
+            "running": status.is_none(),
+            "exit_code": status.and_then(|s| s.code()),
+            "elapsed_secs": job.started.elapsed().as_secs(),
+            "stdout": stdout,
+            "stderr": stderr,
+        });
+        if status.is_some() {
+            jobs.remove(id);
+        }
+        Ok(report)
+    }
+
+    /// Kill the job's process group and return its remaining output.
+    pub async fn kill(&self, id: &str) -> Result<Value> {
+        let mut job = self
+            .jobs
+            .lock()
+            .unwrap()
+            .remove(id)
+            .ok_or_else(|| anyhow!("no background job with id {id}"))?;
+        job.process.kill();
+        let status = tokio::time::timeout(KILL_GRACE, job.process.wait())
+            .await
+            .ok()
+            .and_then(Result::ok);
+        let (stdout, stderr) = job.process.take_output();
+        Ok(json!({
+            "job_id": id,
+            "killed": true,
+            "exit_code": status.and_then(|s| s.code()),
+            "stdout": stdout,
+            "stderr": stderr,
+        }))
+    }
+
+    /// Ids and commands of the jobs not yet reported as finished.
+    pub fn list(&self) -> Vec<(String, String)> {
+        let mut jobs: Vec<_> = self
+            .jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, job)| (id.clone(), job.command.clone()))
+            .collect();
+        jobs.sort();
+        jobs
+    }
+}
+
+pub fn bash_output_tool_def() -> ToolDef {
+    ToolDef {
+        kind: "function".to_string(),
+        function: ToolFunctionDef {
+            name: "bash_output".to_string(),
+            description: "Returns the output a background command (started with execute_bash and `run_in_background`) produced since the last call, and whether it is still running. Once it has exited, the exit code is reported and the job id is released.".to_string(),
+            strict: None,
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "job_id": {"type": "string", "description": "Id returned by execute_bash."}
+                },
+                "required": ["job_id"]
+            }),
+        },
+    }
+}
+
+pub fn bash_kill_tool_def() -> ToolDef {
+    ToolDef {
+        kind: "function".to_string(),
+        function: ToolFunctionDef {
+            name: "bash_kill".to_string(),
+            description: "Stops a background command started with execute_bash and `run_in_background`, together with every process it started, and returns its remaining output.".to_string(),
+            strict: None,
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "job_id": {"type": "string", "description": "Id returned by execute_bash."}
+                },
+                "required": ["job_id"]
+            }),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn output_keeps_head_and_tail() {
+        let mut buffer = OutputBuffer::default();
+        buffer.push("start\n");
+        for _ in 0..10_000 {
+            buffer.push("0123456789");
+        }
+        buffer.push("\nend");
+        let text = buffer.take();
+        assert!(text.len() < MAX_OUTPUT_BYTES + 100);
+        assert!(text.starts_with("start\n"));
+        assert!(text.ends_with("\nend"));
+        assert!(text.contains("bytes of output truncated"));
+        assert_eq!(buffer.take(), "");
+    }
+
+    #[tokio::test]
+    async fn background_jobs_can_be_polled_and_killed() -> Result<()> {
+        let dir = TempDir::new()?;
+        let mut config = AppConfig {
+            project_root: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        config.sandbox.enabled = false;
+        let jobs = BashJobs::default();
+
+        let quick = jobs.start("echo ready", &config)?;
+        let report = loop {
+            let report = jobs.output(&quick)?;
+            if report["running"] == false {
+                break report;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        };
+        assert_eq!(report["exit_code"], 0);
+        assert!(jobs.output(&quick).is_err(), "finished jobs are released");
+
+        let server = jobs.start("echo up; sleep 30 & wait", &config)?;
+        assert_eq!(
+            jobs.list(),
+            [(server.clone(), "echo up; sleep 30 & wait".to_string())]
+        );
+        let killed = jobs.kill(&server).await?;
+        assert_eq!(killed["killed"], true);
+        assert!(jobs.list().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,390 @@
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const MANIFEST: &str = "manifest.json";
+
+/// One user prompt and the pre-images of the files the agent changed while
+/// answering it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Turn {
+    pub turn: u32,
+    pub prompt: String,
+    /// Number of conversation messages before the prompt was sent.
+    pub history_len: usize,
+    pub created_at: String,
+    #[serde(default)]
+    pub files: Vec<FileCheckpoint>,
+    /// Auto-commit that recorded the turn's changes, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+/// The state of `path` before the turn first touched it. A missing `backup`
+/// means the file did not exist, so restoring it deletes the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileCheckpoint {
+    pub path: PathBuf,
+    pub backup: Option<String>,
+}
+
+#[derive(Debug)]
+struct ActiveTurn {
+    dir: PathBuf,
+    turn: Turn,
+    seen: HashSet<PathBuf>,
+}
+
+/// Per-session checkpoint store under `.doge/checkpoints/<session>/<turn>/`.
+///
+/// Every tool that writes a file calls [`Checkpoints::snapshot`] first, so
+/// each turn can be undone even for untracked files and projects without git.
+/// Changes made by shell commands are not tracked, and only files inside the
+/// project are ever copied or restored.
+#[derive(Debug)]
+pub struct Checkpoints {
+    project: PathBuf,
+    root: PathBuf,
+    current: Mutex<Option<ActiveTurn>>,
+}
+
+impl Checkpoints {
+    pub fn new(project_root: &Path) -> Self {
+        let project = project_root
+            .canonicalize()
+            .unwrap_or_else(|_| project_root.to_path_buf());
+        Self {
+            root: project.join(".doge").join("checkpoints"),
+            project,
+            current: Mutex::new(None),
+        }
+    }
+
+    /// Start recording a new turn of `session` and return its number.
+    pub fn begin_turn(&self, session: &str, history_len: usize, prompt: &str) -> Result<u32> {
+        let number = self.turns(session)?.last().map_or(1, |t| t.turn + 1);
+        let dir = self.session_dir(session).join(number.to_string());
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("create checkpoint dir {}", dir.display()))?;
+        let turn = Turn {
+            turn: number,
+            prompt: prompt.to_string(),
+            history_len,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            files: Vec::new(),
+            commit: None,
+        };
+        write_manifest(&dir, &turn)?;
+        *self.current.lock().unwrap() = Some(ActiveTurn {
+            dir,
+            turn,
+            seen: HashSet::new(),
+        });
+        Ok(number)
+    }
+
+    /// Save the pre-image of `path` unless this turn already has one. Does
+    /// nothing outside a turn or for paths that resolve outside the project.
+    pub fn snapshot(&self, path: &Path) -> Result<()> {
+        let mut current = self.current.lock().unwrap();
+        let Some(active) = current.as_mut() else {
+            return Ok(());
+        };
+        let Some(path) = self.resolve(path) else {
+            return Ok(());
+        };
+        let path = path.as_path();
+        if path.starts_with(&self.root) || !active.seen.insert(path.to_path_buf()) {
+            return Ok(());
+        }
+        let backup = if path.is_file() {
+            let name = active.turn.files.len().to_string();
+            fs::copy(path, active.dir.join(&name))
+                .with_context(|| format!("checkpoint {}", path.display()))?;
+            Some(name)
+        } else {
+            None
+        };
+        active.turn.files.push(FileCheckpoint {
+            path: path.to_path_buf(),
+            backup,
+        });
+        write_manifest(&active.dir, &active.turn)
+    }
+
+    /// All recorded turns of `session`, oldest first.
+    pub fn turns(&self, session: &str) -> Result<Vec<Turn>> {
+        let dir = self.session_dir(session);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("read {}", dir.display())),
+        };
+        let mut turns = Vec::new();
+        for entry in entries {
+            let path = entry?.path().join(MANIFEST);
+            if let Ok(raw) = fs::read_to_string(&path) {
+                let turn: Turn = serde_json::from_str(&raw)
+                    .with_context(|| format!("parse {}", path.display()))?;
+                turns.push(turn);
+            }
+        }
+        turns.sort_by_key(|t| t.turn);
+        Ok(turns)
+    }
+
+    /// Files written so far in the current turn.
+    pub fn current_files(&self) -> Vec<PathBuf> {
+        self.current
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|a| a.turn.files.iter().map(|f| f.path.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Remember the commit that recorded the current turn's changes.
+    pub fn record_commit(&self, commit: &str) -> Result<()> {
+        let mut current = self.current.lock().unwrap();
+        let Some(active) = current.as_mut() else {
+            return Ok(());
+        };
+        active.turn.commit = Some(commit.to_string());
+        write_manifest(&active.dir, &active.turn)
+    }
+
+    /// The latest turn of `session` whose file changes have not been undone.
+    pub fn last_changed_turn(&self, session: &str) -> Result<Option<Turn>> {
+        Ok(self
+            .turns(session)?
+            .into_iter()
+            .rev()
+            .find(|t| !t.files.is_empty()))
+    }
+
+    /// Revert the files changed by the latest turn of `session` that changed
+    /// any, and return that turn. The conversation is left as it is.
+    pub fn undo(&self, session: &str) -> Result<Option<Turn>> {
+        let Some(turn) = self.last_changed_turn(session)? else {
+            return Ok(None);
+        };
+        self.restore(&self.turn_dir(session, turn.turn), &turn)?;
+        self.discard(session, &turn)?;
+        Ok(Some(turn))
+    }
+
+    /// Mark the file changes of `turn` as undone. The turn itself is kept so
+    /// the conversation can still be rewound to it.
+    pub fn discard(&self, session: &str, turn: &Turn) -> Result<()> {
+        let mut current = self.current.lock().unwrap();
+        let dir = self.turn_dir(session, turn.turn);
+        let mut turn = turn.clone();
+        turn.files.clear();
+        turn.commit = None;
+        write_manifest(&dir, &turn)?;
+        if let Some(active) = current.as_mut().filter(|a| a.dir == dir) {
+            active.turn.files.clear();
+            active.turn.commit = None;
+            active.seen.clear();
+        }
+        Ok(())
+    }
+
+    /// Restore the files to their state before `turn` of `session` started,
+    /// drop that turn and every later one, and return it so the caller can
+    /// cut the conversation back to [`Turn::history_len`].
+    pub fn rewind(&self, session: &str, turn: u32) -> Result<Turn> {
+        let mut current = self.current.lock().unwrap();
+        let turns = self.turns(session)?;
+        let target = turns
+            .iter()
+            .find(|t| t.turn == turn)
+            .cloned()
+            .ok_or_else(|| anyhow!("no checkpoint for turn {turn}"))?;
+        for later in turns.iter().rev().filter(|t| t.turn >= turn) {
+            let dir = self.turn_dir(session, later.turn);
+            self.restore(&dir, later)?;
+            fs::remove_dir_all(&dir).with_context(|| format!("remove {}", dir.display()))?;
+        }
+        if current
+            .as_ref()
+            .is_some_and(|a| a.dir.starts_with(self.session_dir(session)) && a.turn.turn >= turn)
+        {
+            *current = None;
+        }
+        Ok(target)
+    }
+
+    /// Put every file of `turn` back the way it was before the turn. Fails
+    /// before touching anything if the manifest names a path outside the
+    /// project.
+    fn restore(&self, dir: &Path, turn: &Turn) -> Result<()> {
+        if let Some(file) = turn.files.iter().find(|f| self.resolve(&f.path).is_none()) {
+            bail!(
+                "checkpoint of turn {} names a path outside the project: {}",
+                turn.turn,
+                file.path.display()
+            );
+        }
+        for file in &turn.files {
+            match &file.backup {
+                Some(name) => {
+                    if let Some(parent) = file.path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::copy(dir.join(name), &file.path)
+                        .with_context(|| format!("restore {}", file.path.display()))?;
+                }
+                None => match fs::remove_file(&file.path) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(e).with_context(|| format!("remove {}", file.path.display()));
+                    }
+                    _ => {}
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// `path` with symlinks resolved, or `None` when it lies outside the
+    /// project. Missing files are resolved through their nearest existing
+    /// ancestor.
+    fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        let path = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.project.join(path)
+        };
+        let mut existing = path.as_path();
+        let mut missing = Vec::new();
+        let resolved = loop {
+            match existing.canonicalize() {
+                Ok(resolved) => break resolved,
+                Err(_) => {
+                    missing.push(existing.file_name()?);
+                    existing = existing.parent()?;
+                }
+            }
+        };
+        if missing.iter().any(|name| *name == "..") {
+            return None;
+        }
+        let resolved = missing.iter().rev().fold(resolved, |p, name| p.join(name));
+        resolved.starts_with(&self.project).then_some(resolved)
+    }
+
+    fn session_dir(&self, session: &str) -> PathBuf {
+        self.root.join(session)
+    }
+
+    fn turn_dir(&self, session: &str, turn: u32) -> PathBuf {
+        self.session_dir(session).join(turn.to_string())
+    }
+}
+
+fn write_manifest(dir: &Path, turn: &Turn) -> Result<()> {
+    let path = dir.join(MANIFEST);
+    fs::write(&path, serde_json::to_string_pretty(turn)?)
+        .with_context(|| format!("write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_restores_changed_files_and_removes_created_ones() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let existing = dir.path().join("a.txt");
+        let created = dir.path().join("new/b.txt");
+        fs::write(&existing, "before")?;
+        let checkpoints = Checkpoints::new(dir.path());
+
+        // Writes outside a turn are not recorded.
+        checkpoints.snapshot(&existing)?;
+        assert!(checkpoints.undo("s")?.is_none());
+
+        checkpoints.begin_turn("s", 0, "change things")?;
+        checkpoints.snapshot(&existing)?;
+        fs::write(&existing, "after")?;
+        checkpoints.snapshot(&existing)?;
+        fs::write(&existing, "after again")?;
+        checkpoints.snapshot(&created)?;
+        fs::create_dir_all(created.parent().unwrap())?;
+        fs::write(&created, "new")?;
+
+        let undone = checkpoints.undo("s")?.expect("a turn to undo");
+        assert_eq!(undone.prompt, "change things");
+        assert_eq!(undone.files.len(), 2);
+        assert_eq!(fs::read_to_string(&existing)?, "before");
+        assert!(!created.exists());
+        // The turn is kept for /rewind but has nothing left to undo.
+        assert_eq!(checkpoints.turns("s")?.len(), 1);
+        assert!(checkpoints.undo("s")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn rewind_restores_every_later_turn() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "v1")?;
+        let checkpoints = Checkpoints::new(dir.path());
+
+        for (history_len, content) in [(0, "v2"), (2, "v3"), (4, "v4")] {
+            checkpoints.begin_turn("s", history_len, content)?;
+            checkpoints.snapshot(&file)?;
+            fs::write(&file, content)?;
+        }
+        assert_eq!(
+            checkpoints
+                .turns("s")?
+                .iter()
+                .map(|t| t.turn)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+
+        let turn = checkpoints.rewind("s", 2)?;
+        assert_eq!(turn.history_len, 2);
+        assert_eq!(fs::read_to_string(&file)?, "v2");
+        assert_eq!(checkpoints.turns("s")?.len(), 1);
+        assert!(checkpoints.rewind("s", 3).is_err());
+        assert!(checkpoints.turns("other")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn paths_outside_the_project_are_not_checkpointed() -> Result<()> {
+        let outside = tempfile::tempdir()?;
+        let secret = outside.path().join("id_rsa");
+        fs::write(&secret, "secret")?;
+        let dir = tempfile::tempdir()?;
+        let project = dir.path().join("project");
+        fs::create_dir_all(&project)?;
+        std::os::unix::fs::symlink(&secret, project.join("link"))?;
+        let checkpoints = Checkpoints::new(&project);
+
+        checkpoints.begin_turn("s", 0, "escape")?;
+        checkpoints.snapshot(&secret)?;
+        checkpoints.snapshot(&project.join("../project/../id_rsa"))?;
+        checkpoints.snapshot(&project.join("missing/../../id_rsa"))?;
+        checkpoints.snapshot(&project.join("link"))?;
+        assert!(checkpoints.current_files().is_empty());
+        assert!(checkpoints.last_changed_turn("s")?.is_none());
+
+        // A tampered manifest is refused instead of writing outside the project.
+        let mut turn = checkpoints.turns("s")?.remove(0);
+        turn.files.push(FileCheckpoint {
+            path: secret.clone(),
+            backup: None,
+        });
+        write_manifest(&checkpoints.turn_dir("s", 1), &turn)?;
+        assert!(checkpoints.undo("s").is_err());
+        assert!(secret.exists());
+        Ok(())
+    }
+}
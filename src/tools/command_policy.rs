@@ -0,0 +1,703 @@
+use crate::config::{AppConfig, CommandRule};
+use regex::Regex;
+use tracing::warn;
+
+/// One program invocation out of a shell command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    /// `NAME=value` assignments made for this command only.
+    pub env: Vec<String>,
+}
+
+impl SimpleCommand {
+    /// Program name without its directory.
+    pub fn name(&self) -> &str {
+        self.program.rsplit('/').next().unwrap_or(&self.program)
+    }
+
+    /// First argument that is not an option.
+    fn subcommand(&self) -> Option<&str> {
+        self.args
+            .iter()
+            .map(String::as_str)
+            .find(|arg| !arg.starts_with('-'))
+    }
+
+    fn display(&self) -> String {
+        std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Why the policy refused a command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyViolation {
+    // The offending part of the command line
+    pub command: String,
+    pub reason: String,
+}
+
+impl std::fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}` {}", self.command, self.reason)
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+#[derive(Debug, Clone)]
+enum Matcher {
+    Prefix(SimpleCommand),
+    Match {
+        program: String,
+        subcommand: Option<String>,
+        args: Option<Regex>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    matcher: Matcher,
+    reason: Option<String>,
+}
+
+impl CompiledRule {
+    fn compile(rule: &CommandRule) -> Option<Self> {
+        match rule {
+            CommandRule::Prefix(text) => {
+                let command = parse(text).ok()?.into_iter().next()?;
+                Some(Self {
+                    matcher: Matcher::Prefix(command),
+                    reason: None,
+                })
+            }
+            CommandRule::Match {
+                program,
+                subcommand,
+                args,
+                reason,
+            } => {
+                let args = match args.as_deref().map(Regex::new).transpose() {
+                    Ok(args) => args,
+                    Err(e) => {
+                        warn!(error = %e, "Ignoring command rule with an invalid args pattern");
+                        return None;
+                    }
+                };
+                Some(Self {
+                    matcher: Matcher::Match {
+                        program: program.clone(),
+                        subcommand: subcommand.clone(),
+                        args,
+                    },
+                    reason: reason.clone(),
+                })
+            }
+        }
+    }
+
+    fn matches(&self, command: &SimpleCommand) -> bool {
+        match &self.matcher {
+            Matcher::Prefix(prefix) => {
+                prefix.name() == command.name() && command.args.starts_with(&prefix.args)
+            }
+            Matcher::Match {
+                program,
+                subcommand,
+                args,
+            } => {
+                (program == "*" || program == command.name())
+                    && subcommand
+                        .as_deref()
+                        .is_none_or(|sub| command.subcommand() == Some(sub))
+                    && args
+                        .as_ref()
+                        .is_none_or(|args| args.is_match(&command.args.join(" ")))
+            }
+        }
+    }
+}
+
+/// Allow and deny rules applied to every simple command of a command line,
+/// including those in pipelines, lists, subshells, command substitutions and
+/// wrappers such as `xargs`, `env` or `bash -c`.
+#[derive(Debug, Clone, Default)]
+pub struct CommandPolicy {
+    allow: Vec<CompiledRule>,
+    deny: Vec<CompiledRule>,
+}
+
+impl CommandPolicy {
+    /// Policy from `[command_policy]`, with `allowed_commands` as prefix
+    /// allow rules.
+    pub fn new(config: &AppConfig) -> Self {
+        let legacy = config
+            .allowed_commands
+            .iter()
+            .map(|command| CommandRule::Prefix(command.clone()))
+            .collect::<Vec<_>>();
+        let compile = |rules: &mut dyn Iterator<Item = &CommandRule>| {
+            rules.filter_map(CompiledRule::compile).collect()
+        };
+        Self {
+            allow: compile(&mut config.command_policy.allow.iter().chain(&legacy)),
+            deny: compile(&mut config.command_policy.deny.iter()),
+        }
+    }
+
+    /// Check every command `command_line` would run.
+    pub fn check(&self, command_line: &str) -> Result<(), PolicyViolation> {
+        if self.allow.is_empty() && self.deny.is_empty() {
+            return Ok(());
+        }
+        let commands = parse(command_line).map_err(|reason| PolicyViolation {
+            command: command_line.to_string(),
+            reason: format!("could not be parsed ({reason}), so it cannot be checked"),
+        })?;
+        for command in &commands {
+            if let Some(rule) = self.deny.iter().find(|rule| rule.matches(command)) {
+                return Err(PolicyViolation {
+                    command: command.display(),
+                    reason: rule
+                        .reason
+                        .clone()
+                        .unwrap_or_else(|| "is denied by the command policy".to_string()),
+                });
+            }
+            if !self.allow.is_empty() && !self.allow.iter().any(|rule| rule.matches(command)) {
+                return Err(PolicyViolation {
+                    command: command.display(),
+                    reason: format!(
+                        "is not allowed: `{}` matches no allow rule of the command policy",
+                        command.name()
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Programs that run the command given in their arguments.
+const WRAPPERS: &[&str] = &[
+    "sudo", "doas", "env", "nohup", "nice", "ionice", "timeout", "command", "exec", "builtin",
+    "stdbuf", "xargs", "setsid", "time", "chrt", "taskset",
+];
+
+/// Options of wrappers that take a separate value.
+fn takes_value(wrapper: &str, option: &str) -> bool {
+    let options: &[&str] = match wrapper {
+        "sudo" | "doas" => &["-u", "-g", "-C", "-D", "-h", "-p", "-r", "-t", "-U"],
+        "env" => &["-u", "-C", "--unset", "--chdir"],
+        "nice" => &["-n", "--adjustment"],
+        "ionice" => &["-c", "-n", "-p", "-P", "-u"],
+        "timeout" => &["-s", "-k", "--signal", "--kill-after"],
+        "stdbuf" => &["-i", "-o", "-e"],
+        "xargs" => &["-I", "-n", "-L", "-P", "-d", "-E", "-s", "-a"],
+        "chrt" => &["-p"],
+        _ => &[],
+    };
+    options.contains(&option)
+}
+
+/// Shells whose `-c` argument is a command line of its own.
+const SHELLS: &[&str] = &["bash", "sh", "zsh", "dash", "ksh"];
+
+/// Reserved words that may start a command without being its program.
+const LEADING_KEYWORDS: &[&str] = &[
+    "!", "{", "}", "if", "then", "else", "elif", "fi", "do", "done", "while", "until", "esac",
+];
+
+/// Split a bash command line into the simple commands it would run, most
+/// nested ones included. Words are unquoted; expansions inside them are kept
+/// as written.
+pub fn parse(command_line: &str) -> Result<Vec<SimpleCommand>, String> {
+    let mut parser = Parser {
+        chars: command_line.chars().collect(),
+        pos: 0,
+        commands: Vec::new(),
+    };
+    parser.list(false)?;
+    Ok(parser.commands)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    commands: Vec<SimpleCommand>,
+}
+
+impl Parser {
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    /// Commands up to the end, or up to the `)` closing a `$(` when
+    /// `nested`.
+    fn list(&mut self, nested: bool) -> Result<(), String> {
+        let mut words: Vec<String> = Vec::new();
+        let mut word: Option<String> = None;
+        let mut depth = 0usize;
+        let mut heredocs: Vec<(String, bool)> = Vec::new();
+        while let Some(c) = self.peek(0) {
+            match c {
+                ' ' | '\t' => {
+                    self.pos += 1;
+                    words.extend(word.take());
+                }
+                '\n' | ';' | '|' => {
+                    self.pos += 1;
+                    words.extend(word.take());
+                    self.finish(std::mem::take(&mut words));
+                    if c == '\n' {
+                        self.skip_heredocs(std::mem::take(&mut heredocs));
+                    }
+                }
+                '&' if self.peek(1) == Some('>') => {
+                    words.extend(word.take());
+                    self.redirection(&mut heredocs)?;
+                }
+                '&' => {
+                    self.pos += 1;
+                    words.extend(word.take());
+                    self.finish(std::mem::take(&mut words));
+                }
+                '>' | '<' if self.peek(1) == Some('(') => {
+                    // Process substitution
+                    self.pos += 2;
+                    self.list(true)?;
+                    word.get_or_insert_default().push_str("/dev/fd/0");
+                }
+                '>' | '<' => {
+                    // A number right before the operator is a file descriptor
+                    match word.take() {
+                        Some(w) if w.chars().all(|c| c.is_ascii_digit()) => {}
+                        w => words.extend(w),
+                    }
+                    self.redirection(&mut heredocs)?;
+                }
+                '(' => {
+                    self.pos += 1;
+                    depth += 1;
+                    words.extend(word.take());
+                    self.finish(std::mem::take(&mut words));
+                }
+                ')' => {
+                    self.pos += 1;
+                    words.extend(word.take());
+                    self.finish(std::mem::take(&mut words));
+                    if depth == 0 && nested {
+                        return Ok(());
+                    }
+                    depth = depth.saturating_sub(1);
+                }
+                '#' if word.is_none() => {
+                    while self.peek(0).is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => self.word_part(word.get_or_insert_default())?,
+            }
+        }
+        if nested {
+            return Err("unterminated `$(`".to_string());
+        }
+        words.extend(word);
+        self.finish(words);
+        Ok(())
+    }
+
+    /// Consume one piece of a word: a quoted string, an expansion or a
+    /// plain character.
+    fn word_part(&mut self, word: &mut String) -> Result<(), String> {
+        let c = self.peek(0).unwrap_or_default();
+        self.pos += 1;
+        match c {
+            '\'' => loop {
+                match self.peek(0) {
+                    Some('\'') => {
+                        self.pos += 1;
+                        return Ok(());
+                    }
+                    Some(c) => {
+                        word.push(c);
+                        self.pos += 1;
+                    }
+                    None => return Err("unterminated single quote".to_string()),
+                }
+            },
+            '"' => loop {
+                match self.peek(0) {
+                    Some('"') => {
+                        self.pos += 1;
+                        return Ok(());
+                    }
+                    Some('\\') => {
+                        let next = self.peek(1).ok_or("unterminated double quote")?;
+                        if !matches!(next, '"' | '\\' | '$' | '`' | '\n') {
+                            word.push('\\');
+                        }
+                        if next != '\n' {
+                            word.push(next);
+                        }
+                        self.pos += 2;
+                    }
+                    Some('$' | '`') => self.expansion(word)?,
+                    Some(c) => {
+                        word.push(c);
+                        self.pos += 1;
+                    }
+                    None => return Err("unterminated double quote".to_string()),
+                }
+            },
+            '\\' => {
+                match self.peek(0) {
+                    // Line continuation
+                    Some('\n') => {}
+                    Some(c) => word.push(c),
+                    None => {}
+                }
+                self.pos += 1;
+                Ok(())
+            }
+            '$' | '`' => {
+                self.pos -= 1;
+                self.expansion(word)
+            }
+            c => {
+                word.push(c);
+                Ok(())
+            }
+        }
+    }
+
+    /// `$(...)`, `` `...` ``, `$((...))`, `${...}` or a plain `$`. Commands
+    /// inside substitutions are collected; the word keeps the text.
+    fn expansion(&mut self, word: &mut String) -> Result<(), String> {
+        let start = self.pos;
+        if self.peek(0) == Some('`') {
+            self.pos += 1;
+            let mut inner = String::new();
+            loop {
+                match self.peek(0) {
+                    Some('`') => break,
+                    Some('\\') if self.peek(1).is_some() => {
+                        inner.push(self.peek(1).unwrap_or_default());
+                        self.pos += 2;
+                    }
+                    Some(c) => {
+                        inner.push(c);
+                        self.pos += 1;
+                    }
+                    None => return Err("unterminated backquote".to_string()),
+                }
+            }
+            self.pos += 1;
+            self.commands.extend(parse(&inner)?);
+        } else {
+            self.pos += 1;
+            match (self.peek(0), self.peek(1)) {
+                (Some('('), Some('(')) => self.skip_balanced('(', ')')?,
+                (Some('('), _) => {
+                    self.pos += 1;
+                    self.list(true)?;
+                }
+                (Some('{'), _) => self.skip_balanced('{', '}')?,
+                _ => {}
+            }
+        }
+        word.extend(&self.chars[start..self.pos]);
+        Ok(())
+    }
+
+    /// Skip from an opening delimiter to its match, as in `$((1 + 2))` or
+    /// `${x:-default}`, collecting the commands of substitutions inside.
+    fn skip_balanced(&mut self, open: char, close: char) -> Result<(), String> {
+        let mut depth = 0usize;
+        let mut inner = String::new();
+        while let Some(c) = self.peek(0) {
+            if depth > 0 {
+                match c {
+                    '$' | '`' => {
+                        self.expansion(&mut inner)?;
+                        continue;
+                    }
+                    '\'' | '"' | '\\' => {
+                        self.word_part(&mut inner)?;
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            self.pos += 1;
+            if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+            }
+        }
+        Err(format!("unterminated `{open}`"))
+    }
+
+    /// Skip a redirection operator and its target, which is not an argument.
+    fn redirection(&mut self, heredocs: &mut Vec<(String, bool)>) -> Result<(), String> {
+        let mut op = String::new();
+        // `&>`, `&>>`, `>`, `>>`, `>&`, `>|`, `<`, `<<`, `<<-`, `<<<`, `<&`, `<>`
+        if self.take(&mut op, &['&']) {
+            self.take(&mut op, &['>']);
+            self.take(&mut op, &['>']);
+        } else if self.take(&mut op, &['>']) {
+            self.take(&mut op, &['>', '&', '|']);
+        } else if self.take(&mut op, &['<']) && self.take(&mut op, &['<', '&', '>']) && op == "<<" {
+            self.take(&mut op, &['<', '-']);
+        }
+        while matches!(self.peek(0), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+        let mut target = String::new();
+        while let Some(c) = self.peek(0) {
+            if matches!(
+                c,
+                ' ' | '\t' | '\n' | ';' | '|' | '&' | '<' | '>' | '(' | ')'
+            ) {
+                break;
+            }
+            self.word_part(&mut target)?;
+        }
+        if op.starts_with("<<") && !op.starts_with("<<<") {
+            heredocs.push((target, op.ends_with('-')));
+        }
+        Ok(())
+    }
+
+    /// Append the next character to `op` if it is one of `allowed`.
+    fn take(&mut self, op: &mut String, allowed: &[char]) -> bool {
+        match self.peek(0).filter(|c| allowed.contains(c)) {
+            Some(c) => {
+                op.push(c);
+                self.pos += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Here-document bodies start on the line after their operator.
+    fn skip_heredocs(&mut self, heredocs: Vec<(String, bool)>) {
+        for (delimiter, strip_tabs) in heredocs {
+            while self.pos < self.chars.len() {
+                let end = self.chars[self.pos..]
+                    .iter()
+                    .position(|&c| c == '\n')
+                    .map_or(self.chars.len(), |i| self.pos + i);
+                let line: String = self.chars[self.pos..end].iter().collect();
+                self.pos = (end + 1).min(self.chars.len());
+                let line = if strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    &line
+                };
+                if line == delimiter {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Record the command made of `words`, and those it wraps.
+    fn finish(&mut self, words: Vec<String>) {
+        let mut words = words.as_slice();
+        let mut env = Vec::new();
+        while let Some((first, rest)) = words.split_first()
+            && (LEADING_KEYWORDS.contains(&first.as_str()) || is_assignment(first))
+        {
+            if is_assignment(first) {
+                env.push(first.clone());
+            }
+            words = rest;
+        }
+        let Some((program, args)) = words.split_first() else {
+            return;
+        };
+        // Loop and case headers name no program
+        if matches!(program.as_str(), "for" | "select" | "case" | "function") {
+            return;
+        }
+        let command = SimpleCommand {
+            program: program.clone(),
+            args: args.to_vec(),
+            env,
+        };
+        let name = command.name().to_string();
+        self.commands.push(command);
+
+        if WRAPPERS.contains(&name.as_str()) {
+            let mut i = 0;
+            let mut skipped_duration = false;
+            while let Some(arg) = args.get(i) {
+                if takes_value(&name, arg) {
+                    i += 2;
+                } else if arg.starts_with('-') || is_assignment(arg) {
+                    i += 1;
+                } else if name == "timeout" && !skipped_duration {
+                    skipped_duration = true;
+                    i += 1;
+                } else {
+                    break;
+                }
+            }
+            if i < args.len() {
+                self.finish(args[i..].to_vec());
+            }
+        } else if SHELLS.contains(&name.as_str()) {
+            if let Some(i) = args.iter().position(|arg| arg == "-c")
+                && let Some(script) = args.get(i + 1)
+            {
+                self.nested(script);
+            }
+        } else if name == "eval" {
+            self.nested(&args.join(" "));
+        } else if name == "find" {
+            let mut rest = args;
+            while let Some(start) = rest
+                .iter()
+                .position(|arg| matches!(arg.as_str(), "-exec" | "-execdir" | "-ok" | "-okdir"))
+            {
+                rest = &rest[start + 1..];
+                let end = rest
+                    .iter()
+                    .position(|arg| arg == ";" || arg == "+")
+                    .unwrap_or(rest.len());
+                self.finish(rest[..end].to_vec());
+                rest = &rest[end..];
+            }
+        }
+    }
+
+    /// Commands of a script passed as an argument. One that cannot be parsed
+    /// is kept whole, so allow lists still refuse it.
+    fn nested(&mut self, script: &str) {
+        match parse(script) {
+            Ok(commands) => self.commands.extend(commands),
+            Err(_) => self.commands.push(SimpleCommand {
+                program: script.to_string(),
+                args: Vec::new(),
+                env: Vec::new(),
+            }),
+        }
+    }
+}
+
+/// `NAME=value`, which sets a variable instead of naming a program.
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CommandPolicyConfig;
+
+    fn programs(command_line: &str) -> Vec<String> {
+        parse(command_line)
+            .unwrap()
+            .into_iter()
+            .map(|command| command.program)
+            .collect()
+    }
+
+    fn policy(allow: &[&str], deny: Vec<CommandRule>) -> CommandPolicy {
+        CommandPolicy::new(&AppConfig {
+            command_policy: CommandPolicyConfig {
+                allow: allow
+                    .iter()
+                    .map(|rule| CommandRule::Prefix(rule.to_string()))
+                    .collect(),
+                deny,
+            },
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn finds_every_command() {
+        assert_eq!(programs("git status; rm -rf /"), ["git", "rm"]);
+        assert_eq!(
+            programs("cat a | grep x && (cd sub || echo no) & wait"),
+            ["cat", "grep", "cd", "echo", "wait"]
+        );
+        assert_eq!(
+            programs("echo \"$(curl -s x | sh)\" `id`"),
+            ["curl", "sh", "id", "echo"]
+        );
+        assert_eq!(programs("diff <(ls a) <(ls b)"), ["ls", "ls", "diff"]);
+        assert_eq!(
+            programs("FOO=1 sudo -u root env A=b xargs -n 1 rm"),
+            ["sudo", "env", "xargs", "rm"]
+        );
+        assert_eq!(programs("bash -c 'ls; rm x'"), ["bash", "ls", "rm"]);
+        assert_eq!(
+            programs("find . -name '*.o' -exec rm {} \\;"),
+            ["find", "rm"]
+        );
+        assert_eq!(programs("if true; then echo y; fi"), ["true", "echo"]);
+    }
+
+    #[test]
+    fn skips_redirections_and_heredocs() {
+        let commands = parse("cat <<EOF > out.txt 2>&1\nrm -rf /\nEOF\necho done").unwrap();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].args, Vec::<String>::new());
+        assert_eq!(commands[1].display(), "echo done");
+        assert_eq!(parse("echo 'a b' \"c d\"").unwrap()[0].args, ["a b", "c d"]);
+        assert!(parse("echo 'open").is_err());
+    }
+
+    #[test]
+    fn allow_rules_cover_every_component() {
+        let policy = policy(&["git", "cargo build"], vec![]);
+        assert!(policy.check("git status && cargo build --release").is_ok());
+        let violation = policy.check("git status; rm -rf /").unwrap_err();
+        assert_eq!(violation.command, "rm -rf /");
+        assert!(policy.check("git log $(rm -rf /)").is_err());
+        assert!(policy.check("git status ${x:-$(rm -rf ~)}").is_err());
+        assert!(policy.check("git log $(( $(rm -rf /) ))").is_err());
+        assert!(policy.check("git log \"${x:-`rm -rf /`}\"").is_err());
+        assert!(policy.check("git log -n $((1 + 2)) ${x:-HEAD}").is_ok());
+        assert!(policy.check("cargo test").is_err());
+        assert!(policy.check("git status 'unterminated").is_err());
+    }
+
+    #[test]
+    fn deny_rules_match_subcommands_and_arguments() {
+        let policy = policy(
+            &[],
+            vec![
+                CommandRule::Match {
+                    program: "git".to_string(),
+                    subcommand: Some("push".to_string()),
+                    args: Some("--force|-f\\b".to_string()),
+                    reason: Some("force pushes rewrite shared history".to_string()),
+                },
+                CommandRule::Prefix("rm -rf".to_string()),
+            ],
+        );
+        assert!(policy.check("git push origin main").is_ok());
+        let violation = policy.check("echo hi && git push -f").unwrap_err();
+        assert_eq!(violation.reason, "force pushes rewrite shared history");
+        assert!(policy.check("xargs /bin/rm -rf < list").is_err());
+        assert!(policy.check("rm -r tmp").is_ok());
+        assert!(CommandPolicy::default().check("anything 'goes").is_ok());
+    }
+}
@@ -2,13 +2,18 @@ use crate::analysis::RepoMap;
 use crate::config::AppConfig;
 use crate::mcp::client::McpClient;
 use crate::session::{SessionData, SessionManager};
+use crate::tools::bash_jobs::BashJobs;
+use crate::tools::checkpoints::Checkpoints;
+use crate::tools::command_policy::{CommandPolicy, PolicyViolation};
 use crate::tools::execute;
 use crate::tools::find_file;
 use crate::tools::list;
+use crate::tools::permissions::Permissions;
 use crate::tools::read;
 use crate::tools::read_many;
 use crate::tools::search_repomap;
 use crate::tools::search_text;
+use crate::tools::shell_session::PersistentShell;
 use crate::tools::todo_read;
 use crate::tools::todo_write;
 use crate::tools::write;
@@ -28,6 +33,11 @@ pub struct FsTools {
     pub session_manager: Option<Arc<Mutex<SessionManager>>>,
     pub config: Arc<AppConfig>,
     remote_tools: Arc<RwLock<Option<RemoteToolRegistry>>>,
+    permissions: Arc<Permissions>,
+    bash_jobs: Arc<BashJobs>,
+    shell: Arc<PersistentShell>,
+    command_policy: Arc<CommandPolicy>,
+    checkpoints: Arc<Checkpoints>,
 }
 
 impl Default for FsTools {
@@ -42,6 +52,11 @@ impl FsTools {
             search_repomap_tools: search_repomap::RepomapSearchTools::new(),
             repomap,
             session_manager: None,
+            permissions: Arc::new(Permissions::new(&config)),
+            bash_jobs: Arc::new(BashJobs::default()),
+            shell: Arc::new(PersistentShell::default()),
+            command_policy: Arc::new(CommandPolicy::new(&config)),
+            checkpoints: Arc::new(Checkpoints::new(&config.project_root)),
             config,
             remote_tools: Arc::new(RwLock::new(None)),
         }
@@ -52,6 +67,22 @@ impl FsTools {
         self
     }
 
+    /// Permission checks shared by every clone of these tools.
+    pub fn permissions(&self) -> &Arc<Permissions> {
+        &self.permissions
+    }
+
+    /// File checkpoints shared by every clone of these tools.
+    pub fn checkpoints(&self) -> &Arc<Checkpoints> {
+        &self.checkpoints
+    }
+
+    /// Save the pre-image of `path` to the current turn's checkpoint before
+    /// a tool writes to it. Paths outside the project are never recorded.
+    pub fn checkpoint_file(&self, path: &str) -> Result<()> {
+        self.checkpoints.snapshot(std::path::Path::new(path))
+    }
+
     /// Update the current session with tool call count
     pub fn update_session_with_tool_call_count(&self) -> Result<()> {
         if let Some(session_manager) = &self.session_manager {
@@ -70,6 +101,24 @@ impl FsTools {
         Ok(())
     }
 
+    /// Record a switch to a fallback model in the current session
+    pub fn record_model_switch(&self, from: &str, to: &str, reason: &str) -> Result<()> {
+        if let Some(session_manager) = &self.session_manager {
+            let mut session_mgr = session_manager.lock().unwrap();
+            session_mgr.record_model_switch(from, to, reason)?;
+        }
+        Ok(())
+    }
+
+    /// Record a finished sub-agent run in the current session
+    pub fn record_sub_agent(&self, run: crate::session::SubAgentRun) -> Result<()> {
+        if let Some(session_manager) = &self.session_manager {
+            let mut session_mgr = session_manager.lock().unwrap();
+            session_mgr.record_sub_agent(run)?;
+        }
+        Ok(())
+    }
+
     /// Record a failed tool call in the current session
     pub fn record_tool_call_failure(&self, tool_name: &str) -> Result<()> {
         if let Some(session_manager) = &self.session_manager {
@@ -221,6 +270,11 @@ impl FsTools {
                             description,
                             parameters: params_value,
                             strict: None,
+                            read_only: tool
+                                .annotations
+                                .as_ref()
+                                .and_then(|a| a.read_only_hint)
+                                .unwrap_or(false),
                             client: client.clone(),
                         };
 
@@ -272,6 +326,19 @@ impl FsTools {
 
         self.update_session_with_tool_call_count()?;
 
+        // Remote tools that run shell commands obey the same policy
+        if let Some(command) = remote_shell_command(args)
+            && let Err(violation) = self.check_command(&command)
+        {
+            self.record_tool_call_failure(alias)?;
+            return Err(anyhow!(
+                "Remote MCP tool '{}' on server '{}' was refused: {}",
+                tool.remote_name,
+                tool.server_name,
+                violation
+            ));
+        }
+
         let arguments: Option<JsonMap<String, JsonValue>> = match args {
             JsonValue::Null => None,
             JsonValue::Object(map) => Some(map.clone()),
@@ -363,6 +430,21 @@ impl FsTools {
         }
     }
 
+    pub fn fs_read_image(&self, path: &str) -> Result<crate::llm::ImagePart> {
+        self.update_session_with_tool_call_count()?;
+
+        match read::fs_read_image(path, &self.config) {
+            Ok(image) => {
+                self.record_tool_call_success("fs_read")?;
+                Ok(image)
+            }
+            Err(e) => {
+                self.record_tool_call_failure("fs_read")?;
+                Err(e)
+            }
+        }
+    }
+
     pub fn fs_read_many_files(
         &self,
         paths: Vec<String>,
@@ -408,6 +490,7 @@ impl FsTools {
     pub fn fs_write(&self, path: &str, content: &str) -> Result<()> {
         // Update session with tool call count
         self.update_session_with_tool_call_count()?;
+        self.checkpoint_file(path)?;
 
         match write::fs_write(path, content, &self.config) {
             Ok(result) => {
@@ -422,24 +505,42 @@ impl FsTools {
     }
 
     pub async fn execute_bash(&self, command: &str) -> Result<String> {
+        self.execute_bash_with(command, &execute::BashOptions::default())
+            .await
+    }
+
+    /// Run `command` in the foreground with the given timeout and live output,
+    /// in the persistent shell session when `persistent_shell` is on.
+    pub async fn execute_bash_with(
+        &self,
+        command: &str,
+        options: &execute::BashOptions,
+    ) -> Result<String> {
         // Update session with tool call count
         self.update_session_with_tool_call_count()?;
 
         // Check if the command is allowed
-        if !self.is_command_allowed(command) {
-            tracing::warn!("Command '{}' is not allowed", command);
+        if let Err(violation) = self.check_command(command) {
+            tracing::warn!("Command '{}' is not allowed: {}", command, violation);
             self.record_tool_call_failure("execute_bash")?;
             // Return a structured result indicating the command is not allowed
             let result = execute::ExecuteBashResult {
                 stdout: String::new(),
-                stderr: format!("Command '{}' is not allowed", command),
+                stderr: format!("Command '{}' is not allowed: {}", command, violation),
                 exit_code: None,
                 success: false,
+                sandbox_violation: None,
+                cwd: None,
             };
             return Ok(serde_json::to_string(&result)?);
         }
 
-        match execute::execute_bash(command, &self.config).await {
+        let result = if self.config.persistent_shell {
+            self.shell.run(command, &self.config, options).await
+        } else {
+            execute::run_bash(command, &self.config, options).await
+        };
+        match result {
             Ok(result) => {
                 self.record_tool_call_success("execute_bash")?;
                 Ok(result)
@@ -452,12 +553,48 @@ impl FsTools {
                     stderr: e.to_string(),
                     exit_code: None,
                     success: false,
+                    sandbox_violation: None,
+                    cwd: None,
                 };
                 Ok(serde_json::to_string(&result)?)
             }
         }
     }
 
+    /// Start `command` as a background job and return its id.
+    pub fn start_bash_job(&self, command: &str) -> Result<String> {
+        self.update_session_with_tool_call_count()?;
+        if let Err(violation) = self.check_command(command) {
+            self.record_tool_call_failure("execute_bash")?;
+            return Err(anyhow!(
+                "Command '{}' is not allowed: {}",
+                command,
+                violation
+            ));
+        }
+        match self.bash_jobs.start(command, &self.config) {
+            Ok(id) => {
+                self.record_tool_call_success("execute_bash")?;
+                Ok(id)
+            }
+            Err(e) => {
+                self.record_tool_call_failure("execute_bash")?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Replace the persistent shell session with a fresh one in the project
+    /// root, starting with the next command.
+    pub fn reset_shell(&self) {
+        self.shell.reset();
+    }
+
+    /// Background jobs started by the agent.
+    pub fn bash_jobs(&self) -> &Arc<BashJobs> {
+        &self.bash_jobs
+    }
+
     /// Finds files in the project based on a filename or pattern.
     ///
     /// This method allows the LLM agent to search for files within the project
@@ -590,18 +727,19 @@ impl FsTools {
         }
     }
 
-    /// Check if a command is allowed based on the allowed_commands list
+    /// Check if a command is allowed by the command policy
     pub fn is_command_allowed(&self, command: &str) -> bool {
-        // If no allowed commands are specified, allow all commands (backward compatibility)
-        if self.config.allowed_commands.is_empty() {
-            return true;
-        }
+        self.check_command(command).is_ok()
+    }
 
-        // Check if the command matches any of the allowed commands (prefix match)
-        self.config.allowed_commands.iter().any(|allowed| {
-            // Exact match or prefix match (with space or end of string)
-            command == allowed || command.starts_with(&format!("{} ", allowed))
-        })
+    /// Check every command of a command line against the command policy,
+    /// which includes the allowed_commands list.
+    pub fn check_command(&self, command: &str) -> Result<(), PolicyViolation> {
+        self.command_policy.check(command)
+    }
+
+    pub fn command_policy(&self) -> &Arc<CommandPolicy> {
+        &self.command_policy
     }
 }
 
@@ -619,6 +757,8 @@ pub struct RemoteToolInfo {
     pub description: Option<String>,
     pub parameters: JsonValue,
     pub strict: Option<bool>,
+    /// Server-declared `readOnlyHint`; such tools may run concurrently
+    pub read_only: bool,
     client: Arc<AsyncMutex<McpClient>>,
 }
 
@@ -632,6 +772,25 @@ impl std::fmt::Debug for RemoteToolInfo {
     }
 }
 
+/// Command line passed to a remote tool, from a `command` or `cmd` argument
+/// given as a string or as an argument vector.
+fn remote_shell_command(args: &JsonValue) -> Option<String> {
+    let value = args.get("command").or_else(|| args.get("cmd"))?;
+    match value {
+        JsonValue::String(command) => Some(command.clone()),
+        JsonValue::Array(argv) => Some(
+            argv.iter()
+                .map(|arg| {
+                    let arg = arg.as_str().map_or_else(|| arg.to_string(), str::to_string);
+                    format!("'{}'", arg.replace('\'', r"'\''"))
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        _ => None,
+    }
+}
+
 fn sanitize_identifier(input: &str) -> String {
     let mut result = String::with_capacity(input.len());
     for (idx, ch) in input.chars().enumerate() {
@@ -702,11 +861,12 @@ mod tests {
         let project_root = temp_dir.path().to_path_buf();
 
         // Create a config with allowed commands
-        let cfg = AppConfig {
+        let mut cfg = AppConfig {
             project_root: project_root.clone(),
             allowed_commands: vec!["echo".to_string(), "ls".to_string()],
             ..Default::default()
         };
+        cfg.sandbox.enabled = false;
 
         let fs_tools = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg));
 
@@ -723,11 +883,12 @@ mod tests {
         let project_root = temp_dir.path().to_path_buf();
 
         // Create a config with allowed commands
-        let cfg = AppConfig {
+        let mut cfg = AppConfig {
             project_root: project_root.clone(),
             allowed_commands: vec!["echo".to_string(), "ls".to_string()],
             ..Default::default()
         };
+        cfg.sandbox.enabled = false;
 
         let fs_tools = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg));
 
@@ -746,11 +907,12 @@ mod tests {
         let project_root = temp_dir.path().to_path_buf();
 
         // Create a config without allowed commands
-        let cfg = AppConfig {
+        let mut cfg = AppConfig {
             project_root: project_root.clone(),
             allowed_commands: vec![], // Empty list means all commands are allowed
             ..Default::default()
         };
+        cfg.sandbox.enabled = false;
 
         let fs_tools = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg));
 
@@ -894,17 +1056,45 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_is_command_allowed_checks_every_command() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let cfg = AppConfig {
+            project_root: temp_dir.path().to_path_buf(),
+            allowed_commands: vec!["git".to_string()],
+            ..Default::default()
+        };
+
+        let fs_tools = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg));
+
+        assert!(fs_tools.is_command_allowed("git status | git diff"));
+        // Chained and substituted commands must be allowed on their own
+        let violation = fs_tools.check_command("git status; rm -rf /").unwrap_err();
+        assert_eq!(violation.command, "rm -rf /");
+        assert!(!fs_tools.is_command_allowed("git log $(rm -rf /)"));
+
+        // Remote tools that take a command line are checked as well
+        assert_eq!(
+            remote_shell_command(&json!({"command": ["rm", "-rf", "it's"]})).as_deref(),
+            Some(r"'rm' '-rf' 'it'\''s'")
+        );
+        assert_eq!(remote_shell_command(&json!({"path": "src"})), None);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_execute_bash_complex_allowed_command() -> Result<()> {
         let temp_dir = TempDir::new()?;
         let project_root = temp_dir.path().to_path_buf();
 
         // Create a config with a complex allowed command
-        let cfg = AppConfig {
+        let mut cfg = AppConfig {
             project_root: project_root.clone(),
             allowed_commands: vec!["echo 'hello world'".to_string()],
             ..Default::default()
         };
+        cfg.sandbox.enabled = false;
 
         let fs_tools = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg));
 
@@ -921,11 +1111,12 @@ mod tests {
         let project_root = temp_dir.path().to_path_buf();
 
         // Create a config with no allowed commands (should allow all)
-        let cfg = AppConfig {
+        let mut cfg = AppConfig {
             project_root: project_root.clone(),
             allowed_commands: vec![],
             ..Default::default()
         };
+        cfg.sandbox.enabled = false;
 
         let fs_tools = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg));
 
@@ -12,11 +12,12 @@ async fn test_execute_bash_with_permissions_allowed() -> Result<()> {
     let project_root = temp_dir.path().to_path_buf();
 
     // Create a config with allowed commands
-    let cfg = AppConfig {
+    let mut cfg = AppConfig {
         project_root: project_root.clone(),
         allowed_commands: vec!["echo".to_string(), "ls".to_string()],
         ..Default::default()
     };
+    cfg.sandbox.enabled = false;
 
     let fs_tools = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg));
 
@@ -33,11 +34,12 @@ async fn test_execute_bash_with_permissions_not_allowed() -> Result<()> {
     let project_root = temp_dir.path().to_path_buf();
 
     // Create a config with allowed commands
-    let cfg = AppConfig {
+    let mut cfg = AppConfig {
         project_root: project_root.clone(),
         allowed_commands: vec!["echo".to_string(), "ls".to_string()],
         ..Default::default()
     };
+    cfg.sandbox.enabled = false;
 
     let fs_tools = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg));
 
@@ -56,11 +58,12 @@ async fn test_execute_bash_with_permissions_no_config() -> Result<()> {
     let project_root = temp_dir.path().to_path_buf();
 
     // Create a config without allowed commands
-    let cfg = AppConfig {
+    let mut cfg = AppConfig {
         project_root: project_root.clone(),
         allowed_commands: vec![], // Empty list means all commands are allowed
         ..Default::default()
     };
+    cfg.sandbox.enabled = false;
 
     let fs_tools = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg));
 
@@ -210,11 +213,12 @@ async fn test_execute_bash_complex_allowed_command() -> Result<()> {
     let project_root = temp_dir.path().to_path_buf();
 
     // Create a config with a complex allowed command
-    let cfg = AppConfig {
+    let mut cfg = AppConfig {
         project_root: project_root.clone(),
         allowed_commands: vec!["echo 'hello world'".to_string()],
         ..Default::default()
     };
+    cfg.sandbox.enabled = false;
 
     let fs_tools = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg));
 
@@ -231,11 +235,12 @@ async fn test_execute_bash_with_empty_allowed_commands() -> Result<()> {
     let project_root = temp_dir.path().to_path_buf();
 
     // Create a config with no allowed commands (should allow all)
-    let cfg = AppConfig {
+    let mut cfg = AppConfig {
         project_root: project_root.clone(),
         allowed_commands: vec![],
         ..Default::default()
     };
+    cfg.sandbox.enabled = false;
 
     let fs_tools = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg));
 
@@ -263,3 +268,25 @@ async fn test_execute_bash_with_empty_allowed_commands() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_fs_write_outside_project_is_not_checkpointed() -> Result<()> {
+    let project = TempDir::new()?;
+    let outside = TempDir::new()?;
+    let secret = outside.path().join("id_rsa");
+    std::fs::write(&secret, "secret")?;
+    let cfg = AppConfig {
+        project_root: project.path().to_path_buf(),
+        ..Default::default()
+    };
+    let fs_tools = FsTools::new(Arc::new(RwLock::new(None)), Arc::new(cfg));
+
+    fs_tools.checkpoints().begin_turn("s", 0, "write")?;
+    let _ = fs_tools.fs_write(secret.to_str().unwrap(), "changed");
+    let inside = project.path().join("a.txt");
+    fs_tools.fs_write(inside.to_str().unwrap(), "new")?;
+
+    let files = fs_tools.checkpoints().current_files();
+    assert_eq!(files, vec![inside.canonicalize()?]);
+    Ok(())
+}
@@ -1,9 +1,12 @@
 use crate::config::AppConfig;
 use crate::llm::types::{ToolDef, ToolFunctionDef};
-use anyhow::{Context, Result};
+use crate::tools::bash_jobs::ShellProcess;
+use crate::tools::sandbox::{Sandbox, SandboxViolation};
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::process::Command;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExecuteBashResult {
@@ -11,71 +14,167 @@ pub struct ExecuteBashResult {
     pub stderr: String,
     pub exit_code: Option<i32>,
     pub success: bool,
+    /// Set when the sandbox blocked or prevented the command
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox_violation: Option<SandboxViolation>,
+    /// Working directory after the command, reported by the persistent shell
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
 }
 
 pub fn tool_def() -> ToolDef {
+    bash_tool_def(false)
+}
+
+/// `execute_bash` as offered when `persistent_shell` is on.
+pub fn session_tool_def() -> ToolDef {
+    bash_tool_def(true)
+}
+
+fn bash_tool_def(persistent_shell: bool) -> ToolDef {
+    let intro = if persistent_shell {
+        "Executes a bash command in a persistent shell session that starts in the project root. `cd`, exported variables, activated virtualenvs and sourced scripts carry over between calls, so there is no need to prefix commands with `cd dir &&`. It returns the combined output in `stdout`, the exit code and the shell's working directory afterwards (`cwd`). Pass `reset_session` to start over in a fresh shell in the project root; background jobs always start in the project root."
+    } else {
+        "Executes an arbitrary bash command with the project root directory set as the working directory. It captures and returns both standard output (stdout) and standard error (stderr), as well as the exit code."
+    };
+    let mut properties = json!({
+        "command": {"type": "string"},
+        "timeout_secs": {
+            "type": "integer",
+            "description": format!("Seconds before a foreground command is killed (default {DEFAULT_TIMEOUT_SECS}, max {MAX_TIMEOUT_SECS}).")
+        },
+        "run_in_background": {
+            "type": "boolean",
+            "description": "Start the command and return a job id right away instead of waiting for it."
+        }
+    });
+    if persistent_shell {
+        properties["reset_session"] = json!({
+            "type": "boolean",
+            "description": "Replace the shell session with a fresh one before running the command."
+        });
+    }
     ToolDef {
         kind: "function".to_string(),
         function: ToolFunctionDef {
             name: "execute_bash".to_string(),
-            description: "Executes an arbitrary bash command with the project root directory set as the working directory. It captures and returns both standard output (stdout) and standard error (stderr), as well as the exit code. Use this for tasks that require shell interaction, such as running build commands (`cargo build`), tests (`cargo test`), or external utilities (`git status`). Be cautious with commands that modify the file system (e.g., `rm`, `mv`) and consider their impact beforehand. Interactive commands are not supported.".to_string(),
+            description: format!(
+                "{intro} Use this for tasks that require shell interaction, such as running build commands (`cargo build`), tests (`cargo test`), or external utilities (`git status`). Be cautious with commands that modify the file system (e.g., `rm`, `mv`) and consider their impact beforehand. Interactive commands are not supported. Foreground commands are killed after `timeout_secs` (default {DEFAULT_TIMEOUT_SECS}, at most {MAX_TIMEOUT_SECS}) and long output is truncated in the middle. Start servers, watchers and other long-running processes with `run_in_background`, which returns a job id for `bash_output` and `bash_kill`. Commands may run in a sandbox where only the project root is writable and the network is off; when it blocks something, the result has a `sandbox_violation` explaining what."
+            ),
             strict: None,
             parameters: json!({
                 "type": "object",
-                "properties": {
-                    "command": {"type": "string"}
-                },
+                "properties": properties,
                 "required": ["command"]
             }),
         },
     }
 }
 
+/// Time a foreground command may run when the call does not say.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 120;
+/// Upper bound of `timeout_secs`; longer tasks belong in the background.
+pub const MAX_TIMEOUT_SECS: u64 = 600;
+
+/// How a foreground command is run.
+#[derive(Debug, Clone)]
+pub struct BashOptions {
+    pub timeout: Duration,
+    // Receives output lines as they arrive, for the TUI
+    pub live_output: Option<Sender<String>>,
+}
+
+impl Default for BashOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            live_output: None,
+        }
+    }
+}
+
 pub async fn execute_bash(command: &str, config: &AppConfig) -> Result<String> {
-    // Change to the project root directory before executing the command
-    let project_root = &config.project_root;
-
-    let output = Command::new("bash")
-        .arg("-c")
-        .arg(command)
-        .current_dir(project_root)
-        .output()
-        .await
-        .with_context(|| {
-            format!(
-                "Failed to execute command: {command} in directory: {}",
-                project_root.display()
-            )
-        })?;
-
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-    let exit_code = output.status.code();
-    let success = output.status.success();
+    run_bash(command, config, &BashOptions::default()).await
+}
+
+/// Run `command` in the project root until it exits or `options.timeout`
+/// passes, when its whole process group is killed.
+pub async fn run_bash(command: &str, config: &AppConfig, options: &BashOptions) -> Result<String> {
+    let mut process = match ShellProcess::spawn(command, config, options.live_output.clone()) {
+        Ok(process) => process,
+        Err(e) => {
+            return match e.downcast::<SandboxViolation>() {
+                Ok(violation) => blocked_by_sandbox(violation),
+                Err(e) => Err(e),
+            };
+        }
+    };
+
+    let (status, timed_out) = match tokio::time::timeout(options.timeout, process.wait()).await {
+        Ok(status) => (status?, false),
+        Err(_) => {
+            process.kill();
+            (process.wait().await?, true)
+        }
+    };
+
+    let (stdout, mut stderr) = process.take_output();
+    if timed_out {
+        stderr.push_str(&format!(
+            "\n[timed out after {}s; the command and its child processes were killed. Pass a larger timeout_secs or use run_in_background for servers and watchers]",
+            options.timeout.as_secs()
+        ));
+    }
+    let exit_code = status.code();
+    let success = status.success() && !timed_out;
+    let sandbox_violation = Sandbox::from_config(config)
+        .filter(|_| !success)
+        .and_then(|sandbox| sandbox.violation(&format!("{stderr}\n{stdout}")));
 
     let result = ExecuteBashResult {
         stdout,
         stderr,
         exit_code,
         success,
+        sandbox_violation,
+        cwd: None,
     };
 
     Ok(serde_json::to_string(&result)?)
 }
 
+pub(crate) fn blocked_by_sandbox(violation: SandboxViolation) -> Result<String> {
+    let result = ExecuteBashResult {
+        stdout: String::new(),
+        stderr: violation.message.clone(),
+        exit_code: None,
+        success: false,
+        sandbox_violation: Some(violation),
+        cwd: None,
+    };
+    Ok(serde_json::to_string(&result)?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config::AppConfig;
     use tempfile::TempDir;
 
+    /// Config running commands unsandboxed in `root`.
+    fn config(root: &TempDir) -> AppConfig {
+        let mut config = AppConfig {
+            project_root: root.path().to_path_buf(),
+            ..Default::default()
+        };
+        config.sandbox.enabled = false;
+        config
+    }
+
     #[tokio::test]
     async fn test_execute_bash_success() {
         let temp_dir = TempDir::new().unwrap();
-        let config = AppConfig {
-            project_root: temp_dir.path().to_path_buf(),
-            ..Default::default()
-        };
+        let config = config(&temp_dir);
         let result_str = execute_bash("echo 'hello'", &config).await.unwrap();
         let result: ExecuteBashResult = serde_json::from_str(&result_str).unwrap();
         assert_eq!(result.stdout.trim(), "hello");
@@ -87,10 +186,7 @@ mod tests {
     #[tokio::test]
     async fn test_execute_bash_failure() {
         let temp_dir = TempDir::new().unwrap();
-        let config = AppConfig {
-            project_root: temp_dir.path().to_path_buf(),
-            ..Default::default()
-        };
+        let config = config(&temp_dir);
         let result = execute_bash("invalid_command", &config).await;
         assert!(result.is_ok()); // The function should return Ok with a JSON string even for command failures
         let result: ExecuteBashResult = serde_json::from_str(&result.unwrap()).unwrap();
@@ -100,10 +196,7 @@ mod tests {
     #[tokio::test]
     async fn test_execute_bash_with_stderr() {
         let temp_dir = TempDir::new().unwrap();
-        let config = AppConfig {
-            project_root: temp_dir.path().to_path_buf(),
-            ..Default::default()
-        };
+        let config = config(&temp_dir);
         let result_str = execute_bash("echo 'test error' >&2; exit 1", &config)
             .await
             .unwrap();
@@ -117,10 +210,7 @@ mod tests {
     #[tokio::test]
     async fn test_execute_bash_with_exit_code_zero() {
         let temp_dir = TempDir::new().unwrap();
-        let config = AppConfig {
-            project_root: temp_dir.path().to_path_buf(),
-            ..Default::default()
-        };
+        let config = config(&temp_dir);
         let result_str = execute_bash("exit 0", &config).await.unwrap();
         let result: ExecuteBashResult = serde_json::from_str(&result_str).unwrap();
         assert_eq!(result.stdout, "");
@@ -130,12 +220,30 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_execute_bash_with_non_zero_exit_code() {
+    async fn test_run_bash_timeout_kills_the_process_group() {
         let temp_dir = TempDir::new().unwrap();
-        let config = AppConfig {
-            project_root: temp_dir.path().to_path_buf(),
-            ..Default::default()
+        let config = config(&temp_dir);
+        let (tx, rx) = std::sync::mpsc::channel();
+        let options = BashOptions {
+            timeout: Duration::from_secs(1),
+            live_output: Some(tx),
         };
+        let started = std::time::Instant::now();
+        let result_str = run_bash("echo started; sleep 30 & sleep 30", &config, &options)
+            .await
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        let result: ExecuteBashResult = serde_json::from_str(&result_str).unwrap();
+        assert!(!result.success);
+        assert_eq!(result.stdout.trim(), "started");
+        assert!(result.stderr.contains("timed out after 1s"));
+        assert_eq!(rx.try_recv().unwrap(), "::shell_output:started");
+    }
+
+    #[tokio::test]
+    async fn test_execute_bash_with_non_zero_exit_code() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = config(&temp_dir);
         let result_str = execute_bash("exit 42", &config).await.unwrap();
         let result: ExecuteBashResult = serde_json::from_str(&result_str).unwrap();
         assert_eq!(result.stdout, "");
@@ -1,13 +1,22 @@
 pub mod apply_patch;
+pub mod bash_jobs;
+pub mod checkpoints;
+pub mod command_policy;
 mod common;
 pub mod edit;
 pub mod execute;
 pub mod find_file;
 pub mod list;
+pub mod permissions;
 pub mod read;
 pub mod read_many;
+pub mod read_only;
+pub mod sandbox;
 pub mod search_repomap;
 pub mod search_text;
+pub mod shell_session;
+pub mod spawn_agent;
+pub mod submit_plan;
 pub mod todo_read;
 pub mod todo_write;
 pub mod write;
@@ -0,0 +1,539 @@
+use crate::config::{AppConfig, PermissionMode, PermissionsConfig};
+use crate::tools::command_policy;
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+use tracing::warn;
+
+/// How the user answered an approval prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Approval {
+    /// Run this call only
+    Once,
+    /// Run this call and matching ones until the program exits
+    Session,
+    /// Like `Session`, and save the rule to `.doge/config.toml`
+    Always,
+    Deny,
+}
+
+/// Tool call waiting for the user's approval.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PermissionRequest {
+    pub id: u64,
+    pub tool: String,
+    /// Arguments of the call, pretty-printed
+    pub arguments: String,
+    /// Rule offered when allowing for the session or always
+    pub rule: String,
+}
+
+/// Allow rule: `tool` matches every call of the tool, `tool(pattern)` only
+/// calls whose command (execute_bash) or path matches the pattern. `*`
+/// matches any run of characters, including `/`. Paths outside the project
+/// are only matched by absolute patterns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    tool: String,
+    pattern: Option<String>,
+}
+
+impl Rule {
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let (tool, pattern) = match text.split_once('(') {
+            Some((tool, rest)) => (tool.trim(), Some(rest.strip_suffix(')')?.trim())),
+            None => (text, None),
+        };
+        if tool.is_empty() || pattern.is_some_and(str::is_empty) {
+            return None;
+        }
+        Some(Self {
+            tool: tool.to_string(),
+            pattern: pattern.map(String::from),
+        })
+    }
+
+    pub fn matches(&self, tool: &str, subject: Option<&str>) -> bool {
+        if !wildcard_match(&self.tool, tool) {
+            return false;
+        }
+        let Some(pattern) = &self.pattern else {
+            return true;
+        };
+        let Some(subject) = subject else {
+            return false;
+        };
+        if tool != "execute_bash" && subject.starts_with('/') && !pattern.starts_with('/') {
+            return false;
+        }
+        // A wildcard must not approve whatever is chained after an allowed command
+        pattern == subject
+            || (!(tool == "execute_bash" && chains_commands(subject))
+                && wildcard_match(pattern, subject))
+    }
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.pattern {
+            Some(pattern) => write!(f, "{}({pattern})", self.tool),
+            None => f.write_str(&self.tool),
+        }
+    }
+}
+
+/// Decides whether a tool call may run, asking the user through the TUI when
+/// the tool is in ask mode and no allow rule covers the call.
+#[derive(Debug)]
+pub struct Permissions {
+    config: PermissionsConfig,
+    project_root: PathBuf,
+    // Configured rules followed by the ones approved in this session
+    rules: Mutex<Vec<Rule>>,
+    // TUI inbox; without one (exec mode) calls needing approval are refused
+    prompter: Mutex<Option<Sender<String>>>,
+    pending: Mutex<HashMap<u64, oneshot::Sender<Approval>>>,
+    next_id: AtomicU64,
+}
+
+impl Permissions {
+    pub fn new(config: &AppConfig) -> Self {
+        let rules = config
+            .permissions
+            .allow
+            .iter()
+            .filter_map(|text| {
+                let rule = Rule::parse(text);
+                if rule.is_none() {
+                    warn!(rule = %text, "ignoring malformed permission rule");
+                }
+                rule
+            })
+            .collect();
+        Self {
+            config: config.permissions.clone(),
+            project_root: config.project_root.clone(),
+            rules: Mutex::new(rules),
+            prompter: Mutex::new(None),
+            pending: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Send approval prompts to the TUI through `tx`.
+    pub fn set_prompter(&self, tx: Sender<String>) {
+        *self.prompter.lock().unwrap() = Some(tx);
+    }
+
+    pub fn mode(&self, tool: &str) -> PermissionMode {
+        self.config.mode_for_tool(tool)
+    }
+
+    /// Fail unless `tool` may run with `args`, waiting for the user's answer
+    /// when it has to be approved.
+    pub async fn check(&self, tool: &str, args: &Value) -> Result<()> {
+        match self.mode(tool) {
+            PermissionMode::Allow => return Ok(()),
+            PermissionMode::Deny => {
+                bail!("permission denied: `{tool}` is disabled by the [permissions] config")
+            }
+            PermissionMode::Ask => {}
+        }
+        let subject = self.subject(tool, args);
+        if self
+            .rules
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|rule| rule.matches(tool, subject.as_deref()))
+        {
+            return Ok(());
+        }
+
+        let rule = suggest_rule(tool, subject.as_deref());
+        let Some(prompter) = self.prompter.lock().unwrap().clone() else {
+            bail!(
+                "`{tool}` requires approval, but there is no one to ask in this mode; allow it with a rule such as `{rule}` under [permissions] in .doge/config.toml"
+            );
+        };
+        let request = PermissionRequest {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            tool: tool.to_string(),
+            arguments: serde_json::to_string_pretty(args)?,
+            rule: rule.to_string(),
+        };
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(request.id, tx);
+        if prompter
+            .send(format!("::permission:{}", serde_json::to_string(&request)?))
+            .is_err()
+        {
+            self.pending.lock().unwrap().remove(&request.id);
+            bail!("`{tool}` requires approval, but the approval prompt is unavailable");
+        }
+
+        match rx.await {
+            Ok(Approval::Deny) => Err(anyhow!("the user denied this `{tool}` call")),
+            Ok(_) => Ok(()),
+            Err(_) => Err(anyhow!("the approval request for `{tool}` was cancelled")),
+        }
+    }
+
+    /// Answer the pending `request`. Allowing for the session or always also
+    /// adds its rule; `Always` saves it and returns the file written. The call
+    /// goes ahead even when saving fails.
+    pub fn answer(
+        &self,
+        request: &PermissionRequest,
+        approval: Approval,
+    ) -> Result<Option<PathBuf>> {
+        if matches!(approval, Approval::Session | Approval::Always)
+            && let Some(rule) = Rule::parse(&request.rule)
+        {
+            let mut rules = self.rules.lock().unwrap();
+            if !rules.contains(&rule) {
+                rules.push(rule);
+            }
+        }
+        if let Some(tx) = self.pending.lock().unwrap().remove(&request.id) {
+            let _ = tx.send(approval);
+        }
+        if approval == Approval::Always {
+            return save_rule(&self.project_root, &request.rule).map(Some);
+        }
+        Ok(None)
+    }
+
+    /// Drop every pending request; the waiting calls fail.
+    pub fn cancel_pending(&self) {
+        self.pending.lock().unwrap().clear();
+    }
+
+    /// Human-readable summary of the modes and rules, for `/permissions`.
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = vec![format!("Default mode: {}", self.config.default_mode)];
+        let mut modes: Vec<_> = self.config.tools.iter().collect();
+        modes.sort_by_key(|(tool, _)| *tool);
+        lines.extend(
+            modes
+                .into_iter()
+                .map(|(tool, mode)| format!("  {tool}: {mode}")),
+        );
+        let rules = self.rules.lock().unwrap();
+        if rules.is_empty() {
+            lines.push("Allow rules: none".to_string());
+        } else {
+            lines.push("Allow rules:".to_string());
+            lines.extend(rules.iter().map(|rule| format!("  {rule}")));
+        }
+        lines
+    }
+
+    /// What allow-rule patterns are matched against: the command of
+    /// execute_bash, or the path a file tool works on relative to the project.
+    /// Paths are resolved first, so `src/../.doge` is `.doge`, and ones
+    /// outside the project stay absolute.
+    fn subject(&self, tool: &str, args: &Value) -> Option<String> {
+        if tool == "execute_bash" {
+            return args.get("command")?.as_str().map(|c| c.trim().to_string());
+        }
+        let path = args
+            .get("path")
+            .or_else(|| args.get("file_path"))?
+            .as_str()?;
+        let root = self
+            .project_root
+            .canonicalize()
+            .unwrap_or_else(|_| self.project_root.clone());
+        let path = resolve(&root.join(path));
+        let relative = path.strip_prefix(&root).unwrap_or(&path);
+        Some(relative.to_string_lossy().into_owned())
+    }
+}
+
+/// Rule offered for a call: the command and its subcommand, or the directory
+/// of the path, so that similar calls are covered too.
+fn suggest_rule(tool: &str, subject: Option<&str>) -> Rule {
+    let pattern = subject.map(|subject| {
+        if tool == "execute_bash" {
+            if chains_commands(subject) {
+                return subject.to_string();
+            }
+            let mut words = subject.split_whitespace();
+            let mut prefix = words.next().unwrap_or_default().to_string();
+            if let Some(sub) = words.next()
+                && sub
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                && !sub.starts_with('-')
+            {
+                prefix.push(' ');
+                prefix.push_str(sub);
+            }
+            format!("{prefix}*")
+        } else {
+            match Path::new(subject).parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => format!("{}/*", dir.display()),
+                _ => subject.to_string(),
+            }
+        }
+    });
+    Rule {
+        tool: tool.to_string(),
+        pattern,
+    }
+}
+
+/// Whether `command` runs more than one command or redirects output.
+fn chains_commands(command: &str) -> bool {
+    command.contains(['>', '<'])
+        || command_policy::parse(command).map_or(true, |commands| commands.len() != 1)
+}
+
+/// `path` with `.` and `..` removed and the symlinks of its existing part
+/// resolved.
+fn resolve(path: &Path) -> PathBuf {
+    let mut lexical = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                lexical.pop();
+            }
+            other => lexical.push(other),
+        }
+    }
+    let mut existing = lexical.as_path();
+    let mut missing = Vec::new();
+    loop {
+        if let Ok(resolved) = existing.canonicalize() {
+            return missing.iter().rev().fold(resolved, |p, name| p.join(name));
+        }
+        match (existing.file_name(), existing.parent()) {
+            (Some(name), Some(parent)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return lexical,
+        }
+    }
+}
+
+/// Glob match where `*` is the only wildcard.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Append `rule` to `permissions.allow` in the project config, keeping the
+/// rest of the file as written.
+fn save_rule(project_root: &Path, rule: &str) -> Result<PathBuf> {
+    let path = project_root.join(".doge").join("config.toml");
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("read {}", path.display())),
+    };
+    let mut doc: toml_edit::DocumentMut = text
+        .parse()
+        .with_context(|| format!("parse {}", path.display()))?;
+
+    let permissions = doc
+        .entry("permissions")
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .ok_or_else(|| anyhow!("`permissions` in {} is not a table", path.display()))?;
+    let allow = permissions
+        .entry("allow")
+        .or_insert(toml_edit::value(toml_edit::Array::new()))
+        .as_array_mut()
+        .ok_or_else(|| anyhow!("`permissions.allow` in {} is not an array", path.display()))?;
+    if !allow.iter().any(|v| v.as_str() == Some(rule)) {
+        allow.push(rule);
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    }
+    std::fs::write(&path, doc.to_string()).with_context(|| format!("write {}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn permissions(root: &Path, allow: &[&str]) -> Permissions {
+        let cfg = AppConfig {
+            project_root: root.to_path_buf(),
+            permissions: PermissionsConfig {
+                default_mode: PermissionMode::Ask,
+                tools: HashMap::from([("fs_read".to_string(), PermissionMode::Allow)]),
+                allow: allow.iter().map(|r| r.to_string()).collect(),
+            },
+            ..Default::default()
+        };
+        Permissions::new(&cfg)
+    }
+
+    #[test]
+    fn rules_match_commands_and_paths() {
+        let rule = Rule::parse("execute_bash(cargo test*)").unwrap();
+        assert!(rule.matches("execute_bash", Some("cargo test --all")));
+        assert!(!rule.matches("execute_bash", Some("cargo build")));
+        assert!(!rule.matches("execute_bash", Some("cargo test; rm -rf /")));
+        assert!(!rule.matches("fs_write", Some("cargo test")));
+
+        let rule = Rule::parse("fs_write(src/*.rs)").unwrap();
+        assert!(rule.matches("fs_write", Some("src/tools/mod.rs")));
+        assert!(!rule.matches("fs_write", Some("Cargo.toml")));
+        assert!(
+            !Rule::parse("fs_write(*)")
+                .unwrap()
+                .matches("fs_write", Some("/etc/passwd"))
+        );
+        assert!(
+            Rule::parse("fs_write(/tmp/*)")
+                .unwrap()
+                .matches("fs_write", Some("/tmp/a"))
+        );
+        let rule = Rule::parse("execute_bash(cargo test*)").unwrap();
+        assert!(rule.matches("execute_bash", Some("cargo test -- \"a;b\"")));
+        assert!(!rule.matches("execute_bash", Some("cargo test $(rm -rf /)")));
+        assert!(!rule.matches("execute_bash", Some("cargo test > /etc/passwd")));
+        assert!(
+            Rule::parse("todo_write")
+                .unwrap()
+                .matches("todo_write", None)
+        );
+        assert!(Rule::parse("mcp_*").unwrap().matches("mcp_search", None));
+        assert_eq!(Rule::parse("fs_write()"), None);
+        assert_eq!(Rule::parse("fs_write(src/*"), None);
+
+        assert_eq!(
+            suggest_rule("execute_bash", Some("git push --force")).to_string(),
+            "execute_bash(git push*)"
+        );
+        assert_eq!(
+            suggest_rule("execute_bash", Some("ls -la")).to_string(),
+            "execute_bash(ls*)"
+        );
+        assert_eq!(
+            suggest_rule("edit", Some("src/main.rs")).to_string(),
+            "edit(src/*)"
+        );
+        assert_eq!(suggest_rule("todo_write", None).to_string(), "todo_write");
+    }
+
+    #[tokio::test]
+    async fn ask_mode_fails_closed_without_a_prompter() {
+        let dir = TempDir::new().unwrap();
+        let perms = permissions(dir.path(), &["execute_bash(cargo test*)"]);
+        assert!(perms.check("fs_read", &json!({"path": "a"})).await.is_ok());
+        assert!(
+            perms
+                .check("execute_bash", &json!({"command": "cargo test -q"}))
+                .await
+                .is_ok()
+        );
+        let err = perms
+            .check("execute_bash", &json!({"command": "rm -rf target"}))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("execute_bash(rm*)"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn approvals_are_remembered_and_saved() -> Result<()> {
+        let dir = TempDir::new()?;
+        std::fs::create_dir_all(dir.path().join(".doge"))?;
+        std::fs::write(
+            dir.path().join(".doge/config.toml"),
+            "# project settings\nmodel = \"m\"\n",
+        )?;
+        let perms = std::sync::Arc::new(permissions(dir.path(), &[]));
+        let (tx, rx) = std::sync::mpsc::channel();
+        perms.set_prompter(tx);
+
+        let args = json!({"path": dir.path().join("src/lib.rs"), "content": "x"});
+        let call = tokio::spawn({
+            let perms = perms.clone();
+            let args = args.clone();
+            async move { perms.check("fs_write", &args).await }
+        });
+        let msg = tokio::task::spawn_blocking(move || rx.recv()).await??;
+        let request: PermissionRequest =
+            serde_json::from_str(msg.strip_prefix("::permission:").unwrap())?;
+        assert_eq!(request.rule, "fs_write(src/*)");
+        assert!(request.arguments.contains("\"content\""));
+
+        let saved = perms.answer(&request, Approval::Always)?;
+        call.await??;
+        let text = std::fs::read_to_string(saved.unwrap())?;
+        assert!(
+            text.starts_with("# project settings\nmodel = \"m\"\n"),
+            "{text}"
+        );
+        let parsed: crate::config::FileConfig = toml::from_str(&text)?;
+        assert_eq!(
+            parsed.permissions.unwrap().allow.unwrap(),
+            ["fs_write(src/*)"]
+        );
+
+        // Covered by the new rule, so no prompt is needed
+        perms
+            .check("fs_write", &json!({"path": "src/main.rs", "content": "y"}))
+            .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn paths_are_resolved_before_matching() -> Result<()> {
+        let dir = TempDir::new()?;
+        std::fs::create_dir_all(dir.path().join("src"))?;
+        let outside = TempDir::new()?;
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("src/link"))?;
+        let perms = permissions(dir.path(), &["fs_write(src/*)", "edit(*)"]);
+        let allowed = |tool: &'static str, path: std::path::PathBuf| {
+            let perms = &perms;
+            async move {
+                perms
+                    .check(tool, &json!({"path": path, "content": "x"}))
+                    .await
+                    .is_ok()
+            }
+        };
+
+        assert!(allowed("fs_write", "src/./lib.rs".into()).await);
+        assert!(allowed("fs_write", dir.path().join("src/new/../lib.rs")).await);
+        assert!(!allowed("fs_write", "src/../.doge/config.toml".into()).await);
+        assert!(!allowed("fs_write", dir.path().join("src/../.doge/config.toml")).await);
+        assert!(!allowed("fs_write", "src/link/secret".into()).await);
+        assert!(allowed("edit", "docs/readme.md".into()).await);
+        assert!(!allowed("edit", "../outside.txt".into()).await);
+        Ok(())
+    }
+}
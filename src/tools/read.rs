@@ -1,5 +1,5 @@
 use crate::config::AppConfig;
-use crate::llm::types::{ToolDef, ToolFunctionDef};
+use crate::llm::types::{ImagePart, ToolDef, ToolFunctionDef};
 use anyhow::{Context, Result};
 use serde::Serialize;
 use serde_json::json;
@@ -14,7 +14,7 @@ pub fn tool_def() -> ToolDef {
         function: ToolFunctionDef {
             name: "fs_read".to_string(),
             strict: None,
-            description: "Reads the content of a text file from the absolute path. You can specify a starting line and a maximum number of lines to read. This is useful for inspecting file contents, reading specific sections of large files, or understanding the implementation details of a function or class. Do not use this for binary files or extremely large files.".to_string(),
+            description: "Reads the content of a text file from the absolute path. You can specify a starting line and a maximum number of lines to read. This is useful for inspecting file contents, reading specific sections of large files, or understanding the implementation details of a function or class. PNG, JPEG, GIF and WebP images are returned as images when the model supports vision. Do not use this for other binary files or extremely large files.".to_string(),
             parameters: json!({
                 "type": "object",
                 "properties": {
@@ -88,7 +88,8 @@ pub struct FsReadResult {
     pub warnings: Vec<String>,
 }
 
-pub fn fs_read(path: &str, opts: FsReadOptions, config: &AppConfig) -> Result<FsReadResult> {
+/// Absolute `path` inside the project root or an allowed path.
+fn ensure_readable<'a>(path: &'a str, config: &AppConfig) -> Result<&'a Path> {
     let p = Path::new(path);
 
     // Ensure the path is absolute
@@ -111,6 +112,24 @@ pub fn fs_read(path: &str, opts: FsReadOptions, config: &AppConfig) -> Result<Fs
             path
         );
     }
+    Ok(p)
+}
+
+/// Read an image file so it can be shown to a vision-capable model.
+pub fn fs_read_image(path: &str, config: &AppConfig) -> Result<ImagePart> {
+    let p = ensure_readable(path, config)?;
+    if !config.supports_vision(&config.model) {
+        anyhow::bail!(
+            "{} is an image, but {} does not accept image input",
+            path,
+            config.model
+        );
+    }
+    crate::llm::load_image(p)
+}
+
+pub fn fs_read(path: &str, opts: FsReadOptions, config: &AppConfig) -> Result<FsReadResult> {
+    let p = ensure_readable(path, config)?;
 
     let meta = fs::metadata(p).with_context(|| format!("metadata {}", p.display()))?;
     if !meta.is_file() {
@@ -0,0 +1,1533 @@
+use crate::tools::command_policy::{self, SimpleCommand};
+
+/// Redirections that do not write files.
+const HARMLESS_REDIRECTIONS: &[&str] = &["2>&1", "2>/dev/null", ">/dev/null", "&>/dev/null"];
+
+/// Options a read-only command may be given; any other option makes it count
+/// as mutating. `short` uses getopt syntax: a letter followed by `:` takes a
+/// value, one followed by `::` an optional value attached to it. Long options
+/// ending in `=` take a value.
+struct Options {
+    short: &'static str,
+    long: &'static [&'static str],
+    // Most operands, for commands whose further operands are output files
+    max_operands: Option<usize>,
+}
+
+const fn options(short: &'static str, long: &'static [&'static str]) -> Options {
+    Options {
+        short,
+        long,
+        max_operands: None,
+    }
+}
+
+/// How a short option uses the rest of its word.
+enum Short {
+    Flag,
+    Value,
+    OptionalValue,
+}
+
+impl Options {
+    fn short(&self, letter: char) -> Option<Short> {
+        if letter == ':' {
+            return None;
+        }
+        let rest = &self.short[self.short.find(letter)? + letter.len_utf8()..];
+        Some(if rest.starts_with("::") {
+            Short::OptionalValue
+        } else if rest.starts_with(':') {
+            Short::Value
+        } else {
+            Short::Flag
+        })
+    }
+
+    fn takes_value(&self, long: &str) -> bool {
+        self.long.iter().any(|o| o.strip_suffix('=') == Some(long))
+    }
+
+    /// The operands of `args`, or `None` when it has an option not allowed.
+    fn operands<'a>(&self, args: &'a [String]) -> Option<Vec<&'a str>> {
+        let mut operands = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                operands.extend(args.map(String::as_str));
+                break;
+            } else if let Some(long) = arg.strip_prefix("--") {
+                match long.split_once('=') {
+                    Some((name, _)) if self.takes_value(name) => {}
+                    Some(_) => return None,
+                    None if self.long.contains(&long) => {}
+                    None if self.takes_value(long) => {
+                        args.next()?;
+                    }
+                    None => return None,
+                }
+            } else if let Some(letters) = arg.strip_prefix('-').filter(|l| !l.is_empty()) {
+                for (i, letter) in letters.char_indices() {
+                    match self.short(letter)? {
+                        Short::Flag => {}
+                        Short::Value => {
+                            if i + letter.len_utf8() == letters.len() {
+                                args.next()?;
+                            }
+                            break;
+                        }
+                        Short::OptionalValue => break,
+                    }
+                }
+            } else {
+                operands.push(arg.as_str());
+            }
+        }
+        self.max_operands
+            .is_none_or(|max| operands.len() <= max)
+            .then_some(operands)
+    }
+}
+
+/// Commands that only inspect the system, with the options that keep them
+/// from writing files or running other programs.
+const READ_ONLY_COMMANDS: &[(&str, Options)] = &[
+    (
+        "cat",
+        options(
+            "AbeEnstTuv",
+            &[
+                "show-all",
+                "number-nonblank",
+                "show-ends",
+                "number",
+                "squeeze-blank",
+                "show-tabs",
+                "show-nonprinting",
+            ],
+        ),
+    ),
+    (
+        "head",
+        options(
+            "c:n:qvz0123456789",
+            &[
+                "bytes=",
+                "lines=",
+                "quiet",
+                "silent",
+                "verbose",
+                "zero-terminated",
+            ],
+        ),
+    ),
+    (
+        "tail",
+        options(
+            "c:fFn:qs:vz0123456789",
+            &[
+                "bytes=",
+                "follow",
+                "follow=",
+                "lines=",
+                "quiet",
+                "silent",
+                "sleep-interval=",
+                "verbose",
+                "zero-terminated",
+                "retry",
+                "pid=",
+            ],
+        ),
+    ),
+    ("less", options("FiNRSX", &[])),
+    (
+        "wc",
+        options(
+            "clmwL",
+            &["bytes", "chars", "lines", "words", "max-line-length"],
+        ),
+    ),
+    (
+        "ls",
+        options(
+            "1aAbBcCdfFghHiklLmnNopqQrRsStuUvwxXZ",
+            &[
+                "all",
+                "almost-all",
+                "human-readable",
+                "si",
+                "color",
+                "color=",
+                "classify",
+                "directory",
+                "recursive",
+                "reverse",
+                "size",
+                "sort=",
+                "time=",
+                "time-style=",
+                "full-time",
+                "format=",
+                "width=",
+                "ignore=",
+                "hide=",
+                "inode",
+                "dereference",
+                "numeric-uid-gid",
+                "group-directories-first",
+                "indicator-style=",
+                "quoting-style=",
+                "literal",
+            ],
+        ),
+    ),
+    (
+        "tree",
+        options(
+            "adfilpsugDhFrtvxCnJL:P:I:",
+            &[
+                "noreport",
+                "dirsfirst",
+                "gitignore",
+                "prune",
+                "matchdirs",
+                "ignore-case",
+                "charset=",
+                "filelimit=",
+            ],
+        ),
+    ),
+    ("pwd", options("LP", &[])),
+    (
+        "stat",
+        options(
+            "Lfc:t",
+            &["dereference", "file-system", "format=", "printf=", "terse"],
+        ),
+    ),
+    (
+        "file",
+        options(
+            "bLhikNzm:",
+            &[
+                "brief",
+                "mime",
+                "mime-type",
+                "mime-encoding",
+                "dereference",
+                "no-dereference",
+            ],
+        ),
+    ),
+    (
+        "du",
+        options(
+            "abchHkLmsSxPd:t:",
+            &[
+                "all",
+                "apparent-size",
+                "bytes",
+                "total",
+                "human-readable",
+                "si",
+                "summarize",
+                "max-depth=",
+                "threshold=",
+                "exclude=",
+            ],
+        ),
+    ),
+    (
+        "df",
+        options(
+            "ahHiklPTxt:",
+            &[
+                "all",
+                "human-readable",
+                "si",
+                "inodes",
+                "local",
+                "portability",
+                "print-type",
+                "total",
+                "output",
+                "output=",
+                "type=",
+                "exclude-type=",
+            ],
+        ),
+    ),
+    (
+        "grep",
+        options(
+            "EFGPivwxyclLnhHbosqrRzZaIUT0123456789e:f:m:A:B:C:d:D:",
+            &[
+                "extended-regexp",
+                "fixed-strings",
+                "basic-regexp",
+                "perl-regexp",
+                "regexp=",
+                "file=",
+                "ignore-case",
+                "no-ignore-case",
+                "word-regexp",
+                "line-regexp",
+                "count",
+                "color",
+                "color=",
+                "colour",
+                "colour=",
+                "files-with-matches",
+                "files-without-match",
+                "max-count=",
+                "only-matching",
+                "quiet",
+                "silent",
+                "no-messages",
+                "invert-match",
+                "byte-offset",
+                "line-number",
+                "with-filename",
+                "no-filename",
+                "label=",
+                "null",
+                "null-data",
+                "text",
+                "binary-files=",
+                "directories=",
+                "devices=",
+                "recursive",
+                "dereference-recursive",
+                "include=",
+                "exclude=",
+                "exclude-from=",
+                "exclude-dir=",
+                "after-context=",
+                "before-context=",
+                "context=",
+            ],
+        ),
+    ),
+    (
+        "rg",
+        options(
+            "iIsSwxFnNlcHhouvLz0UPe:f:g:t:T:A:B:C:m:M:j:r:E:",
+            &[
+                "ignore-case",
+                "smart-case",
+                "case-sensitive",
+                "word-regexp",
+                "line-regexp",
+                "fixed-strings",
+                "line-number",
+                "no-line-number",
+                "files",
+                "files-with-matches",
+                "files-without-match",
+                "count",
+                "count-matches",
+                "context=",
+                "after-context=",
+                "before-context=",
+                "max-count=",
+                "max-columns=",
+                "type=",
+                "type-not=",
+                "type-list",
+                "glob=",
+                "iglob=",
+                "hidden",
+                "no-ignore",
+                "no-ignore-vcs",
+                "follow",
+                "json",
+                "vimgrep",
+                "heading",
+                "no-heading",
+                "color=",
+                "column",
+                "only-matching",
+                "replace=",
+                "multiline",
+                "multiline-dotall",
+                "pcre2",
+                "max-depth=",
+                "max-filesize=",
+                "sort=",
+                "sortr=",
+                "trim",
+                "stats",
+                "null",
+                "no-messages",
+                "invert-match",
+                "with-filename",
+                "no-filename",
+                "encoding=",
+                "threads=",
+                "regexp=",
+                "file=",
+                "unrestricted",
+                "text",
+                "quiet",
+                "passthru",
+            ],
+        ),
+    ),
+    (
+        "ag",
+        options(
+            "iQsSwlLcvufzat0G:A:B:C:m:g:",
+            &[
+                "ignore-case",
+                "literal",
+                "case-sensitive",
+                "smart-case",
+                "word-regexp",
+                "files-with-matches",
+                "files-without-matches",
+                "count",
+                "invert-match",
+                "unrestricted",
+                "follow",
+                "all-types",
+                "all-text",
+                "hidden",
+                "skip-vcs-ignores",
+                "depth=",
+                "context=",
+                "after=",
+                "before=",
+                "max-count=",
+                "file-search-regex=",
+                "ignore=",
+                "nocolor",
+                "color",
+                "noheading",
+                "heading",
+                "nobreak",
+                "column",
+                "numbers",
+                "nonumbers",
+                "vimgrep",
+                "stats",
+            ],
+        ),
+    ),
+    (
+        "fd",
+        options(
+            "HIsipgFaLl0e:t:E:d:c:j:S:",
+            &[
+                "hidden",
+                "no-ignore",
+                "no-ignore-vcs",
+                "case-sensitive",
+                "ignore-case",
+                "glob",
+                "fixed-strings",
+                "absolute-path",
+                "full-path",
+                "follow",
+                "list-details",
+                "print0",
+                "extension=",
+                "type=",
+                "exclude=",
+                "max-depth=",
+                "min-depth=",
+                "exact-depth=",
+                "max-results=",
+                "color=",
+                "size=",
+                "changed-within=",
+                "changed-before=",
+                "owner=",
+                "threads=",
+            ],
+        ),
+    ),
+    (
+        "sort",
+        options(
+            "bdfgiMhnRrVcCsuzk:t:S:",
+            &[
+                "ignore-leading-blanks",
+                "dictionary-order",
+                "ignore-case",
+                "general-numeric-sort",
+                "ignore-nonprinting",
+                "month-sort",
+                "human-numeric-sort",
+                "numeric-sort",
+                "random-sort",
+                "reverse",
+                "version-sort",
+                "check",
+                "check=",
+                "stable",
+                "unique",
+                "zero-terminated",
+                "key=",
+                "field-separator=",
+                "buffer-size=",
+                "parallel=",
+            ],
+        ),
+    ),
+    (
+        "uniq",
+        Options {
+            short: "cdDiuzf:s:w:",
+            long: &[
+                "count",
+                "repeated",
+                "all-repeated",
+                "ignore-case",
+                "unique",
+                "zero-terminated",
+                "skip-fields=",
+                "skip-chars=",
+                "check-chars=",
+            ],
+            // A second operand is the output file
+            max_operands: Some(1),
+        },
+    ),
+    (
+        "cut",
+        options(
+            "b:c:d:f:nsz",
+            &[
+                "bytes=",
+                "characters=",
+                "delimiter=",
+                "fields=",
+                "complement",
+                "only-delimited",
+                "output-delimiter=",
+                "zero-terminated",
+            ],
+        ),
+    ),
+    (
+        "tr",
+        options(
+            "cCdst",
+            &["complement", "delete", "squeeze-repeats", "truncate-set1"],
+        ),
+    ),
+    (
+        "diff",
+        options(
+            "abBcdeEilnNpqrsStTuwyZC:U:x:X:I:F:W:",
+            &[
+                "brief",
+                "report-identical-files",
+                "unified",
+                "unified=",
+                "context",
+                "context=",
+                "side-by-side",
+                "ignore-case",
+                "ignore-all-space",
+                "ignore-space-change",
+                "ignore-blank-lines",
+                "ignore-tab-expansion",
+                "ignore-trailing-space",
+                "text",
+                "recursive",
+                "new-file",
+                "exclude=",
+                "exclude-from=",
+                "color",
+                "color=",
+                "strip-trailing-cr",
+                "minimal",
+                "label=",
+                "suppress-common-lines",
+                "no-dereference",
+                "show-c-function",
+                "show-function-line=",
+            ],
+        ),
+    ),
+    (
+        "cmp",
+        options(
+            "bilsn:",
+            &[
+                "print-bytes",
+                "ignore-initial=",
+                "verbose",
+                "bytes=",
+                "quiet",
+                "silent",
+            ],
+        ),
+    ),
+    ("echo", options("neE", &[])),
+    ("printf", options("", &[])),
+    ("which", options("a", &["all"])),
+    ("type", options("afptP", &[])),
+    ("whoami", options("", &[])),
+    (
+        "date",
+        options(
+            "uRd:r:",
+            &[
+                "utc",
+                "universal",
+                "rfc-email",
+                "iso-8601",
+                "iso-8601=",
+                "rfc-3339=",
+                "date=",
+                "reference=",
+            ],
+        ),
+    ),
+    (
+        "uname",
+        options(
+            "asnrvmpio",
+            &[
+                "all",
+                "kernel-name",
+                "nodename",
+                "kernel-release",
+                "kernel-version",
+                "machine",
+                "processor",
+                "hardware-platform",
+                "operating-system",
+            ],
+        ),
+    ),
+    ("printenv", options("0", &["null"])),
+    (
+        "basename",
+        options("as:z", &["multiple", "suffix=", "zero"]),
+    ),
+    ("dirname", options("z", &["zero"])),
+    (
+        "realpath",
+        options(
+            "eEmLPqsz",
+            &[
+                "canonicalize-existing",
+                "canonicalize-missing",
+                "logical",
+                "physical",
+                "quiet",
+                "strip",
+                "no-symlinks",
+                "zero",
+                "relative-to=",
+                "relative-base=",
+            ],
+        ),
+    ),
+    ("readlink", options("efmnqsvz", &["canonicalize"])),
+    (
+        "md5sum",
+        options(
+            "bctwz",
+            &[
+                "binary", "check", "tag", "text", "zero", "quiet", "status", "strict", "warn",
+            ],
+        ),
+    ),
+    (
+        "sha256sum",
+        options(
+            "bctwz",
+            &[
+                "binary", "check", "tag", "text", "zero", "quiet", "status", "strict", "warn",
+            ],
+        ),
+    ),
+    (
+        "jq",
+        options(
+            "nrjacCMSseR",
+            &[
+                "null-input",
+                "raw-input",
+                "slurp",
+                "raw-output",
+                "join-output",
+                "ascii-output",
+                "compact-output",
+                "color-output",
+                "monochrome-output",
+                "sort-keys",
+                "exit-status",
+                "tab",
+                "indent",
+                "arg",
+                "argjson",
+            ],
+        ),
+    ),
+    (
+        "nl",
+        Options {
+            short: "b:d:f:h:i:l:n:ps:v:w:",
+            long: &[
+                "body-numbering=",
+                "section-delimiter=",
+                "footer-numbering=",
+                "header-numbering=",
+                "line-increment=",
+                "join-blank-lines=",
+                "number-format=",
+                "no-renumber",
+                "number-separator=",
+                "starting-line-number=",
+                "number-width=",
+            ],
+            max_operands: Some(1),
+        },
+    ),
+    (
+        "column",
+        options(
+            "tnxeJc:s:o:N:R:T:W:H:O:",
+            &[
+                "table",
+                "fillrows",
+                "json",
+                "keep-empty-lines",
+                "output-width=",
+                "separator=",
+                "output-separator=",
+                "table-columns=",
+                "table-right=",
+                "table-truncate=",
+                "table-wrap=",
+                "table-hide=",
+                "table-order=",
+            ],
+        ),
+    ),
+];
+
+/// Global options git may be given before the subcommand.
+const GIT_GLOBAL: Options = options("PC:", &["no-pager", "no-optional-locks"]);
+
+/// Options shared by the git subcommands that show history and diffs.
+const GIT_LOG_LONG: &[&str] = &[
+    "oneline",
+    "graph",
+    "decorate",
+    "decorate=",
+    "stat",
+    "stat=",
+    "numstat",
+    "shortstat",
+    "compact-summary",
+    "summary",
+    "dirstat",
+    "dirstat=",
+    "name-only",
+    "name-status",
+    "raw",
+    "patch",
+    "no-patch",
+    "format=",
+    "pretty",
+    "pretty=",
+    "abbrev-commit",
+    "no-abbrev-commit",
+    "abbrev=",
+    "date=",
+    "since=",
+    "until=",
+    "after=",
+    "before=",
+    "author=",
+    "committer=",
+    "grep=",
+    "all",
+    "branches",
+    "branches=",
+    "tags",
+    "tags=",
+    "remotes",
+    "remotes=",
+    "reverse",
+    "max-count=",
+    "skip=",
+    "merges",
+    "no-merges",
+    "first-parent",
+    "follow",
+    "color",
+    "color=",
+    "no-color",
+    "word-diff",
+    "word-diff=",
+    "unified=",
+    "ignore-all-space",
+    "ignore-space-change",
+    "ignore-blank-lines",
+    "ignore-cr-at-eol",
+    "ignore-space-at-eol",
+    "cached",
+    "staged",
+    "merge-base",
+    "relative",
+    "relative=",
+    "full-index",
+    "binary",
+    "no-renames",
+    "find-renames",
+    "find-renames=",
+    "find-copies",
+    "diff-filter=",
+    "exit-code",
+    "quiet",
+    "no-prefix",
+    "src-prefix=",
+    "dst-prefix=",
+    "submodule",
+    "submodule=",
+    "ignore-submodules",
+    "ignore-submodules=",
+    "left-right",
+    "cherry-pick",
+    "cherry-mark",
+    "topo-order",
+    "date-order",
+    "author-date-order",
+    "full-history",
+    "simplify-by-decoration",
+    "source",
+    "show-signature",
+    "walk-reflogs",
+    "notes",
+    "no-notes",
+    "minimal",
+    "patience",
+    "histogram",
+    "diff-algorithm=",
+    "no-ext-diff",
+    "no-textconv",
+    "check",
+    "function-context",
+    "inter-hunk-context=",
+    "stat-width=",
+    "regexp-ignore-case",
+    "extended-regexp",
+    "fixed-strings",
+    "perl-regexp",
+    "all-match",
+    "invert-grep",
+];
+
+/// Short options shared by `git log`, `git show` and `git diff`.
+const GIT_LOG_SHORT: &str = "pusRwbaiEFgz0123456789n:S:G:L:U:M::C::B::l:";
+
+/// Read-only git subcommands and their options. `branch`, `tag` and
+/// `remote` only count when they list.
+const READ_ONLY_GIT: &[(&str, Options)] = &[
+    (
+        "status",
+        options(
+            "sbvzu::",
+            &[
+                "short",
+                "branch",
+                "porcelain",
+                "porcelain=",
+                "long",
+                "verbose",
+                "untracked-files",
+                "untracked-files=",
+                "ignored",
+                "ignored=",
+                "ignore-submodules",
+                "ignore-submodules=",
+                "column",
+                "column=",
+                "no-column",
+                "ahead-behind",
+                "no-ahead-behind",
+                "renames",
+                "no-renames",
+                "show-stash",
+                "null",
+            ],
+        ),
+    ),
+    ("log", options(GIT_LOG_SHORT, GIT_LOG_LONG)),
+    ("show", options(GIT_LOG_SHORT, GIT_LOG_LONG)),
+    ("diff", options(GIT_LOG_SHORT, GIT_LOG_LONG)),
+    ("reflog", options(GIT_LOG_SHORT, GIT_LOG_LONG)),
+    (
+        "shortlog",
+        options(
+            "snec",
+            &[
+                "numbered",
+                "summary",
+                "email",
+                "committer",
+                "group=",
+                "format=",
+                "all",
+                "since=",
+                "until=",
+                "after=",
+                "before=",
+                "author=",
+                "no-merges",
+            ],
+        ),
+    ),
+    (
+        "blame",
+        options(
+            "bcelfnpstwL:M::C::",
+            &[
+                "porcelain",
+                "line-porcelain",
+                "incremental",
+                "root",
+                "show-stats",
+                "show-name",
+                "show-number",
+                "show-email",
+                "date=",
+                "ignore-rev=",
+                "ignore-revs-file=",
+                "color-lines",
+                "color-by-age",
+                "abbrev=",
+                "reverse=",
+                "first-parent",
+            ],
+        ),
+    ),
+    (
+        "grep",
+        options(
+            "aiIwvhHEGPFlLcnzqobWe:f:A:B:C:m:0123456789",
+            &[
+                "cached",
+                "untracked",
+                "no-index",
+                "recurse-submodules",
+                "text",
+                "ignore-case",
+                "word-regexp",
+                "invert-match",
+                "full-name",
+                "extended-regexp",
+                "basic-regexp",
+                "perl-regexp",
+                "fixed-strings",
+                "line-number",
+                "column",
+                "files-with-matches",
+                "name-only",
+                "files-without-match",
+                "count",
+                "color",
+                "color=",
+                "no-color",
+                "break",
+                "heading",
+                "show-function",
+                "function-context",
+                "after-context=",
+                "before-context=",
+                "context=",
+                "max-depth=",
+                "max-count=",
+                "threads=",
+                "and",
+                "or",
+                "not",
+                "all-match",
+                "quiet",
+                "null",
+                "only-matching",
+                "exclude-standard",
+                "no-exclude-standard",
+            ],
+        ),
+    ),
+    (
+        "ls-files",
+        options(
+            "cdmoiskuvtfzx:X:",
+            &[
+                "cached",
+                "deleted",
+                "modified",
+                "others",
+                "ignored",
+                "stage",
+                "killed",
+                "unmerged",
+                "exclude=",
+                "exclude-from=",
+                "exclude-standard",
+                "directory",
+                "no-empty-directory",
+                "error-unmatch",
+                "full-name",
+                "recurse-submodules",
+                "abbrev",
+                "abbrev=",
+                "deduplicate",
+                "eol",
+                "format=",
+            ],
+        ),
+    ),
+    (
+        "ls-tree",
+        options(
+            "dlrtz",
+            &[
+                "long",
+                "name-only",
+                "name-status",
+                "object-only",
+                "full-name",
+                "full-tree",
+                "abbrev=",
+                "format=",
+            ],
+        ),
+    ),
+    (
+        "rev-parse",
+        options(
+            "q",
+            &[
+                "verify",
+                "quiet",
+                "short",
+                "short=",
+                "abbrev-ref",
+                "abbrev-ref=",
+                "symbolic",
+                "symbolic-full-name",
+                "show-toplevel",
+                "show-prefix",
+                "show-cdup",
+                "git-dir",
+                "git-common-dir",
+                "absolute-git-dir",
+                "is-inside-work-tree",
+                "is-inside-git-dir",
+                "is-bare-repository",
+                "all",
+                "branches",
+                "tags",
+                "remotes",
+                "path-format=",
+            ],
+        ),
+    ),
+    (
+        "describe",
+        options(
+            "",
+            &[
+                "tags",
+                "all",
+                "long",
+                "always",
+                "dirty",
+                "dirty=",
+                "contains",
+                "first-parent",
+                "exact-match",
+                "abbrev=",
+                "match=",
+                "exclude=",
+                "candidates=",
+            ],
+        ),
+    ),
+    (
+        "cat-file",
+        options(
+            "tspe",
+            &[
+                "batch",
+                "batch=",
+                "batch-check",
+                "batch-check=",
+                "batch-all-objects",
+            ],
+        ),
+    ),
+    (
+        "branch",
+        options(
+            "arlv",
+            &[
+                "all",
+                "remotes",
+                "list",
+                "verbose",
+                "show-current",
+                "contains=",
+                "no-contains=",
+                "merged=",
+                "no-merged=",
+                "sort=",
+                "points-at=",
+                "format=",
+                "column",
+                "no-column",
+                "color",
+                "color=",
+                "abbrev=",
+            ],
+        ),
+    ),
+    (
+        "tag",
+        options(
+            "ln::",
+            &[
+                "list",
+                "sort=",
+                "contains=",
+                "no-contains=",
+                "merged=",
+                "no-merged=",
+                "points-at=",
+                "format=",
+                "column",
+                "no-column",
+                "color",
+                "color=",
+            ],
+        ),
+    ),
+    ("remote", options("v", &["verbose"])),
+];
+
+/// Options of the cargo subcommands that only report on the workspace.
+const READ_ONLY_CARGO: &[(&str, Options)] = &[
+    (
+        "metadata",
+        options(
+            "qv",
+            &[
+                "format-version=",
+                "no-deps",
+                "manifest-path=",
+                "features=",
+                "all-features",
+                "no-default-features",
+                "filter-platform=",
+                "offline",
+                "locked",
+                "frozen",
+                "quiet",
+                "verbose",
+            ],
+        ),
+    ),
+    (
+        "tree",
+        options(
+            "dqvp:e:i:",
+            &[
+                "depth=",
+                "prefix=",
+                "edges=",
+                "invert=",
+                "package=",
+                "workspace",
+                "manifest-path=",
+                "features=",
+                "all-features",
+                "no-default-features",
+                "target=",
+                "charset=",
+                "format=",
+                "duplicates",
+                "no-dedupe",
+                "offline",
+                "locked",
+                "frozen",
+                "quiet",
+                "verbose",
+            ],
+        ),
+    ),
+];
+
+/// `find` tests and actions that neither write files nor run commands.
+const READ_ONLY_FIND: &[&str] = &[
+    "-name",
+    "-iname",
+    "-path",
+    "-ipath",
+    "-wholename",
+    "-iwholename",
+    "-regex",
+    "-iregex",
+    "-regextype",
+    "-type",
+    "-xtype",
+    "-size",
+    "-empty",
+    "-mtime",
+    "-mmin",
+    "-atime",
+    "-amin",
+    "-ctime",
+    "-cmin",
+    "-newer",
+    "-newermt",
+    "-user",
+    "-group",
+    "-nouser",
+    "-nogroup",
+    "-perm",
+    "-links",
+    "-inum",
+    "-samefile",
+    "-readable",
+    "-writable",
+    "-executable",
+    "-maxdepth",
+    "-mindepth",
+    "-depth",
+    "-mount",
+    "-xdev",
+    "-prune",
+    "-quit",
+    "-print",
+    "-print0",
+    "-printf",
+    "-ls",
+    "-not",
+    "-a",
+    "-and",
+    "-o",
+    "-or",
+    "-true",
+    "-false",
+    "-follow",
+    "-L",
+    "-H",
+    "-P",
+];
+
+/// Whether `command` only reads: every command it would run is a known
+/// inspection command given only options that keep it that way, and nothing
+/// is redirected into a file.
+///
+/// Unknown commands and options count as mutating, so the check errs on the
+/// side of refusing.
+pub fn is_read_only_command(command: &str) -> bool {
+    let mut command = command.to_string();
+    for redirection in HARMLESS_REDIRECTIONS {
+        command = command.replace(redirection, " ");
+    }
+    if command.contains(['>', '`']) || command.contains("$(") || command.contains("<(") {
+        return false;
+    }
+    command_policy::parse(&command).is_ok_and(|commands| commands.iter().all(is_read_only))
+}
+
+fn is_read_only(command: &SimpleCommand) -> bool {
+    if !command.env.is_empty() {
+        return false;
+    }
+    let args = command.args.as_slice();
+    match command.program.as_str() {
+        "git" => is_read_only_git(args),
+        "cargo" => match args {
+            [flag] if matches!(flag.as_str(), "--version" | "-V" | "--list") => true,
+            [sub, rest @ ..] => allowed(READ_ONLY_CARGO, sub, rest).is_some(),
+            [] => false,
+        },
+        "find" => args.iter().all(|arg| {
+            !arg.starts_with('-')
+                || READ_ONLY_FIND.contains(&arg.as_str())
+                || arg[1..]
+                    .trim_end_matches(char::is_alphabetic)
+                    .parse::<i64>()
+                    .is_ok()
+        }),
+        "sed" => is_read_only_sed(args),
+        "awk" => options("F:v:", &["field-separator=", "assign="])
+            .operands(args)
+            .and_then(|operands| operands.first().copied())
+            .is_some_and(|program| {
+                !program.contains(['|', '>', '@']) && !program.contains("system")
+            }),
+        "env" => args.is_empty(),
+        program => allowed(READ_ONLY_COMMANDS, program, args).is_some(),
+    }
+}
+
+/// Operands of `name` when it is in `table` and `args` only use the options
+/// listed for it.
+fn allowed<'a>(table: &[(&str, Options)], name: &str, args: &'a [String]) -> Option<Vec<&'a str>> {
+    let (_, options) = table.iter().find(|(n, _)| *n == name)?;
+    options.operands(args)
+}
+
+fn is_read_only_git(args: &[String]) -> bool {
+    // Global options come before the subcommand
+    let mut i = 0;
+    while let Some(arg) = args.get(i).filter(|a| a.starts_with('-')) {
+        i += if arg == "-C" { 2 } else { 1 };
+    }
+    if GIT_GLOBAL.operands(&args[..i.min(args.len())]).is_none() {
+        return false;
+    }
+    let Some((sub, rest)) = args.get(i..).and_then(|a| a.split_first()) else {
+        return false;
+    };
+    let Some(operands) = allowed(READ_ONLY_GIT, sub, rest) else {
+        return false;
+    };
+    let has = |flags: &[&str]| rest.iter().any(|a| flags.contains(&a.as_str()));
+    match sub.as_str() {
+        // Names given to these create, rename or delete unless listing
+        "branch" => operands.is_empty() || has(&["-l", "--list"]),
+        "tag" => operands.is_empty() || has(&["-l", "--list"]),
+        "remote" => matches!(operands.first(), None | Some(&"show" | &"get-url")),
+        "reflog" => !matches!(operands.first(), Some(&"expire" | &"delete" | &"drop")),
+        _ => true,
+    }
+}
+
+fn is_read_only_sed(args: &[String]) -> bool {
+    let sed = options(
+        "nEsrzue:",
+        &[
+            "quiet",
+            "silent",
+            "regexp-extended",
+            "separate",
+            "null-data",
+            "unbuffered",
+            "posix",
+            "debug",
+            "expression=",
+        ],
+    );
+    let Some(operands) = sed.operands(args) else {
+        return false;
+    };
+    // Scripts given with -e, or else the first operand
+    let mut scripts = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            break;
+        } else if let Some(script) = arg.strip_prefix("--expression=") {
+            scripts.push(script);
+        } else if arg == "--expression" {
+            scripts.extend(iter.next().map(String::as_str));
+        } else if !arg.starts_with("--")
+            && arg.starts_with('-')
+            && let Some(at) = arg.find('e')
+        {
+            match &arg[at + 1..] {
+                "" => scripts.extend(iter.next().map(String::as_str)),
+                script => scripts.push(script),
+            }
+        }
+    }
+    if scripts.is_empty() {
+        scripts.extend(operands.first());
+    }
+    !scripts.is_empty() && scripts.iter().all(|script| is_printing_sed_script(script))
+}
+
+/// Whether a sed script only selects, edits and prints lines: no `w`, `r`
+/// or `e` commands and no `w` or `e` substitution flags. Anything it cannot
+/// follow counts as writing.
+fn is_printing_sed_script(script: &str) -> bool {
+    let chars: Vec<char> = script.chars().collect();
+    let mut i = 0;
+    // Skip a delimited part such as `/regex/`, starting after the opening delimiter
+    let delimited = |i: &mut usize, delimiter: char| -> bool {
+        while let Some(&c) = chars.get(*i) {
+            *i += 1;
+            if c == '\\' {
+                *i += 1;
+            } else if c == delimiter {
+                return true;
+            }
+        }
+        false
+    };
+    loop {
+        while chars
+            .get(i)
+            .is_some_and(|c| c.is_whitespace() || *c == ';' || *c == '}')
+        {
+            i += 1;
+        }
+        let Some(&c) = chars.get(i) else {
+            return true;
+        };
+        // Addresses: numbers, `$`, `/regex/`, `\%regex%`, ranges and steps
+        if c.is_ascii_digit() || matches!(c, '$' | '/' | '\\' | ',' | '~' | '+' | '!') {
+            i += 1;
+            match c {
+                '/' if !delimited(&mut i, '/') => return false,
+                '\\' => {
+                    let Some(&delimiter) = chars.get(i) else {
+                        return false;
+                    };
+                    i += 1;
+                    if !delimited(&mut i, delimiter) {
+                        return false;
+                    }
+                }
+                _ => {}
+            }
+            // Regex address flags
+            while chars.get(i).is_some_and(|c| matches!(c, 'I' | 'M')) {
+                i += 1;
+            }
+            continue;
+        }
+        i += 1;
+        match c {
+            'p' | 'P' | 'd' | 'D' | 'n' | 'N' | 'g' | 'G' | 'h' | 'H' | 'x' | '=' | 'z' | 'F'
+            | '{' => {}
+            'l' | 'q' | 'Q' => {
+                while chars
+                    .get(i)
+                    .is_some_and(|c| c.is_ascii_digit() || *c == ' ')
+                {
+                    i += 1;
+                }
+            }
+            's' | 'y' => {
+                let Some(&delimiter) = chars.get(i).filter(|d| !matches!(d, '\\' | '\n')) else {
+                    return false;
+                };
+                i += 1;
+                if !delimited(&mut i, delimiter) || !delimited(&mut i, delimiter) {
+                    return false;
+                }
+                if c == 's' {
+                    while chars.get(i).is_some_and(|c| {
+                        matches!(c, 'g' | 'p' | 'i' | 'I' | 'm' | 'M') || c.is_ascii_digit()
+                    }) {
+                        i += 1;
+                    }
+                }
+            }
+            // Labels, branches and comments run to the end of the line
+            ':' | 'b' | 't' | 'T' | '#' => {
+                let comment = c == '#';
+                while chars
+                    .get(i)
+                    .is_some_and(|&d| d != '\n' && (comment || d != ';'))
+                {
+                    i += 1;
+                }
+            }
+            _ => return false,
+        }
+        if chars
+            .get(i)
+            .is_some_and(|c| !c.is_whitespace() && !matches!(c, ';' | '}'))
+        {
+            return false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_read_only_command() {
+        for command in [
+            "ls -la src",
+            "git status && git diff HEAD~1 -- src/main.rs",
+            "rg -n 'fn main' src | head -20",
+            "find . -name '*.rs' 2>/dev/null | wc -l",
+            "git branch -a",
+            "cargo metadata --format-version 1",
+            "sed -n '1,40p' src/lib.rs",
+            "sed -n '/^fn /,/^}/p; 3q' src/lib.rs",
+            "sed -e 's/a/b/g' -e '/x/d' src/lib.rs",
+            "git log --oneline -5 --format=%H -- src",
+            "git -C sub status -uno",
+            "find . -mtime -7 -type f -print0",
+            "sort -u names.txt",
+            "tree -L 2 src",
+            "uniq -c list.txt",
+            "awk -F: '{ print $1 }' /etc/passwd",
+            "git tag -l 'v*'",
+        ] {
+            assert!(is_read_only_command(command), "{command}");
+        }
+        for command in [
+            "rm -rf target",
+            "echo hi > notes.txt",
+            "cat a >> b",
+            "git commit -am wip",
+            "git branch -D old",
+            "git branch new-feature",
+            "cargo build",
+            "find . -name '*.tmp' -delete",
+            "sed -i 's/a/b/' src/lib.rs",
+            "ls; touch x",
+            "cat $(mktemp)",
+            "FOO=1 ls",
+            "sort -o out names.txt",
+            "sort --output=out names.txt",
+            "sed -n 'w out' src/lib.rs",
+            "sed 's/a/b/w out' src/lib.rs",
+            "sed -n '1e touch x' src/lib.rs",
+            "sed -f script.sed src/lib.rs",
+            "find . -fprintf out '%p'",
+            "find . -fprint0 out",
+            "git diff --output=patch.diff",
+            "git log --output=log.txt",
+            "git log -c core.pager=sh",
+            "git -c core.pager=sh log",
+            "tree -o out",
+            "uniq in.txt out.txt",
+            "rg --pre ./run.sh pattern",
+            "fd -x rm",
+            "awk '{ print | \"sh\" }' f",
+            "./cat file",
+        ] {
+            assert!(!is_read_only_command(command), "{command}");
+        }
+    }
+
+    #[test]
+    fn options_follow_getopt_rules() {
+        let args = |line: &str| line.split(' ').map(String::from).collect::<Vec<_>>();
+        let (_, head) = READ_ONLY_COMMANDS
+            .iter()
+            .find(|(n, _)| *n == "head")
+            .unwrap();
+        assert_eq!(head.operands(&args("-n 5 a -q b")), Some(vec!["a", "b"]));
+        assert_eq!(
+            head.operands(&args("-n5 --lines=3 -- -x")),
+            Some(vec!["-x"])
+        );
+        assert_eq!(head.operands(&args("-20 a")), Some(vec!["a"]));
+        assert_eq!(head.operands(&args("-x a")), None);
+        assert_eq!(head.operands(&args("--lines")), None);
+    }
+}
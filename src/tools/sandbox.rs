@@ -0,0 +1,623 @@
+use crate::config::AppConfig;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// What the sandbox stopped a command from doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    /// Writing outside the writable paths
+    Filesystem,
+    /// Network access while it is disabled
+    Network,
+    /// The sandbox could not be set up, so the command did not run
+    Unavailable,
+}
+
+/// Sandbox failure reported back to the agent with the command's result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SandboxViolation {
+    pub kind: ViolationKind,
+    pub message: String,
+}
+
+impl std::fmt::Display for SandboxViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for SandboxViolation {}
+
+/// Output that means a write hit a read-only mount.
+const FILESYSTEM_ERRORS: &[&str] = &["Read-only file system"];
+
+/// Output that means a connection or lookup found no network.
+const NETWORK_ERRORS: &[&str] = &[
+    "Network is unreachable",
+    "Could not resolve host",
+    "Temporary failure in name resolution",
+    "Name or service not known",
+    "failed to lookup address",
+];
+
+/// Runs shell commands in a fresh mount namespace and, unless the network is
+/// allowed, a network namespace with only loopback that all sandboxed
+/// commands share. The project root and the configured writable
+/// paths are bind-mounted writable; every other mount is remounted read-only.
+/// The project's `.doge` directory and, unless `git_writable` is configured,
+/// its `.git` directory stay read-only so a command cannot change the agent's
+/// configuration or plant git hooks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sandbox {
+    network: bool,
+    writable: Vec<PathBuf>,
+    protected: Vec<PathBuf>,
+}
+
+/// Directories under the project root that commands may not write to.
+const PROTECTED_DIRS: &[&str] = &[".doge", ".git"];
+
+/// Output of a git command that could not write to the repository.
+const GIT_WRITE_ERRORS: &[&str] = &[".git/", "/.git'", "/.git\""];
+
+impl Sandbox {
+    /// The sandbox described by `cfg`, or `None` when it is disabled.
+    pub fn from_config(cfg: &AppConfig) -> Option<Self> {
+        if !cfg.sandbox.enabled {
+            return None;
+        }
+        let writable = std::iter::once(cfg.project_root.clone())
+            .chain(cfg.sandbox.writable_paths.iter().map(|path| {
+                match path.strip_prefix("~").ok().zip(dirs::home_dir()) {
+                    Some((rest, home)) => home.join(rest),
+                    None => cfg.project_root.join(path),
+                }
+            }))
+            .collect();
+        let protected = PROTECTED_DIRS
+            .iter()
+            .filter(|&&dir| !(dir == ".git" && cfg.sandbox.git_writable))
+            .map(|dir| cfg.project_root.join(dir))
+            .collect();
+        Some(Self {
+            network: cfg.sandbox.network,
+            writable,
+            protected,
+        })
+    }
+
+    /// Confine `cmd`, which is about to run in `cwd`.
+    pub fn confine(&self, cmd: &mut Command, cwd: &Path) -> Result<()> {
+        #[cfg(target_os = "linux")]
+        {
+            let plan = linux::Plan::new(self, cwd)?;
+            // SAFETY: the closure only makes raw system calls on data
+            // prepared above; it neither allocates nor takes locks.
+            unsafe {
+                cmd.pre_exec(move || plan.apply());
+            }
+            Ok(())
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (cmd, cwd);
+            anyhow::bail!("the sandbox is only supported on Linux")
+        }
+    }
+
+    /// Explain a failed command's output when the sandbox caused the failure.
+    pub fn violation(&self, output: &str) -> Option<SandboxViolation> {
+        if FILESYSTEM_ERRORS.iter().any(|e| output.contains(e)) {
+            if self.protected.iter().any(|dir| dir.ends_with(".git"))
+                && GIT_WRITE_ERRORS.iter().any(|e| output.contains(e))
+            {
+                return Some(SandboxViolation {
+                    kind: ViolationKind::Filesystem,
+                    message: "the sandbox keeps the project's .git directory read-only, so git commands that change the repository (add, commit, checkout, ...) fail; leave committing to the user, or set `git_writable = true` in the [sandbox] config to allow it".to_string(),
+                });
+            }
+            return Some(SandboxViolation {
+                kind: ViolationKind::Filesystem,
+                message: format!(
+                    "the sandbox only allows writes under {}, except the project's .doge and .git directories; add a path to `writable_paths` in the [sandbox] config if the command needs it",
+                    self.writable_list()
+                ),
+            });
+        }
+        if !self.network && NETWORK_ERRORS.iter().any(|e| output.contains(e)) {
+            return Some(SandboxViolation {
+                kind: ViolationKind::Network,
+                message: "network access is disabled in the sandbox; set `network = true` in the [sandbox] config if the command needs it".to_string(),
+            });
+        }
+        None
+    }
+
+    /// Violation for a command that could not be started in the sandbox.
+    pub fn unavailable(error: impl std::fmt::Display) -> SandboxViolation {
+        SandboxViolation {
+            kind: ViolationKind::Unavailable,
+            message: format!(
+                "could not set up the sandbox ({error}); unprivileged user namespaces may be disabled on this system. Set `enabled = false` in the [sandbox] config to run commands without it"
+            ),
+        }
+    }
+
+    fn writable_list(&self) -> String {
+        self.writable
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::Sandbox;
+    use anyhow::{Context, Result, anyhow};
+    use std::ffi::CString;
+    use std::fs::File;
+    use std::io;
+    use std::os::fd::{AsRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::process::CommandExt;
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Stdio};
+    use std::ptr;
+    use std::sync::OnceLock;
+
+    /// Pseudo filesystems left as they are.
+    const SKIPPED_MOUNTS: &[&str] = &["/proc", "/sys", "/dev"];
+
+    /// Everything the child needs, prepared before forking since only
+    /// async-signal-safe calls are allowed in between fork and exec.
+    pub(super) struct Plan {
+        // Namespaces to join instead of creating fresh ones
+        shared: Option<&'static SharedNetwork>,
+        uid_map: Vec<u8>,
+        gid_map: Vec<u8>,
+        writable: Vec<CString>,
+        // Mount points to remount read-only, with the flags they must keep
+        read_only: Vec<(CString, libc::c_ulong)>,
+        // Directories inside writable paths to bind read-only over themselves
+        protected: Vec<(CString, libc::c_ulong)>,
+        cwd: CString,
+    }
+
+    impl Plan {
+        pub(super) fn new(sandbox: &Sandbox, cwd: &Path) -> Result<Self> {
+            // Paths are compared with mount points, so resolve symlinks;
+            // missing ones cannot be mounted and are left out
+            let writable: Vec<PathBuf> = sandbox
+                .writable
+                .iter()
+                .filter_map(|path| path.canonicalize().ok())
+                .collect();
+            let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")
+                .context("read /proc/self/mountinfo")?;
+            let mounts: Vec<_> = mountinfo.lines().filter_map(parse_mount).collect();
+            let read_only = mounts
+                .iter()
+                .filter(|(point, options)| {
+                    !options.split(',').any(|o| o == "ro")
+                        && !SKIPPED_MOUNTS.iter().any(|skip| point.starts_with(skip))
+                        && !writable.iter().any(|w| point.starts_with(w))
+                })
+                .map(|(point, options)| Ok((c_path(point)?, locked_flags(options))))
+                .collect::<Result<_>>()?;
+            // `.doge` is created up front so a command cannot create it
+            // with a configuration of its own
+            let protected = sandbox
+                .protected
+                .iter()
+                .filter_map(|path| {
+                    if path.ends_with(".doge") {
+                        let _ = std::fs::create_dir_all(path);
+                    }
+                    path.canonicalize().ok()
+                })
+                .map(|path| {
+                    // The bind keeps the flags of the mount it lives on
+                    let options = mounts
+                        .iter()
+                        .filter(|(point, _)| path.starts_with(point))
+                        .max_by_key(|(point, _)| point.as_os_str().len())
+                        .map_or("", |(_, options)| options.as_str());
+                    Ok((c_path(&path)?, locked_flags(options)))
+                })
+                .collect::<Result<_>>()?;
+
+            // SAFETY: getuid and getgid cannot fail
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            let shared = if sandbox.network {
+                None
+            } else {
+                Some(SharedNetwork::get()?)
+            };
+            Ok(Self {
+                shared,
+                uid_map: format!("{uid} {uid} 1").into_bytes(),
+                gid_map: format!("{gid} {gid} 1").into_bytes(),
+                writable: writable.iter().map(|w| c_path(w)).collect::<Result<_>>()?,
+                read_only,
+                protected,
+                cwd: c_path(cwd)?,
+            })
+        }
+
+        /// Runs in the child between fork and exec.
+        pub(super) fn apply(&self) -> io::Result<()> {
+            // SAFETY: plain system calls on NUL-terminated strings and file
+            // descriptors owned by self
+            unsafe {
+                match self.shared {
+                    Some(shared) => {
+                        check(libc::setns(shared.user.as_raw_fd(), libc::CLONE_NEWUSER))?;
+                        check(libc::setns(shared.net.as_raw_fd(), libc::CLONE_NEWNET))?;
+                        check(libc::unshare(libc::CLONE_NEWNS))?;
+                    }
+                    None => {
+                        check(libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS))?;
+                        write_id_maps(&self.uid_map, &self.gid_map)?;
+                    }
+                }
+
+                check(libc::mount(
+                    ptr::null(),
+                    c"/".as_ptr(),
+                    ptr::null(),
+                    libc::MS_REC | libc::MS_PRIVATE,
+                    ptr::null(),
+                ))?;
+                // Fresh writable mounts, unaffected by the remounts below
+                for path in &self.writable {
+                    check(libc::mount(
+                        path.as_ptr(),
+                        path.as_ptr(),
+                        ptr::null(),
+                        libc::MS_BIND | libc::MS_REC,
+                        ptr::null(),
+                    ))?;
+                }
+                for (point, locked) in &self.read_only {
+                    let res = libc::mount(
+                        ptr::null(),
+                        point.as_ptr(),
+                        ptr::null(),
+                        libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY | locked,
+                        ptr::null(),
+                    );
+                    if res != 0 {
+                        let err = io::Error::last_os_error();
+                        // Mount points the user cannot reach need no protection
+                        if !matches!(err.raw_os_error(), Some(libc::ENOENT | libc::EACCES)) {
+                            return Err(err);
+                        }
+                    }
+                }
+                for (path, locked) in &self.protected {
+                    check(libc::mount(
+                        path.as_ptr(),
+                        path.as_ptr(),
+                        ptr::null(),
+                        libc::MS_BIND | libc::MS_REC,
+                        ptr::null(),
+                    ))?;
+                    check(libc::mount(
+                        ptr::null(),
+                        path.as_ptr(),
+                        ptr::null(),
+                        libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY | locked,
+                        ptr::null(),
+                    ))?;
+                }
+                // The old working directory is on the now read-only mount
+                check(libc::chdir(self.cwd.as_ptr()))?;
+            }
+            Ok(())
+        }
+    }
+
+    /// User and network namespaces shared by every command when network
+    /// access is off, so a server started in the background can be reached
+    /// over localhost by later commands.
+    pub(super) struct SharedNetwork {
+        user: OwnedFd,
+        net: OwnedFd,
+    }
+
+    static SHARED_NETWORK: OnceLock<Result<SharedNetwork, String>> = OnceLock::new();
+
+    impl SharedNetwork {
+        fn get() -> Result<&'static Self> {
+            SHARED_NETWORK
+                .get_or_init(|| Self::create().map_err(|e| format!("{e:#}")))
+                .as_ref()
+                .map_err(|e| anyhow!("{e}"))
+        }
+
+        /// Start a helper in fresh namespaces and keep them alive through
+        /// file descriptors once the helper is gone.
+        fn create() -> Result<Self> {
+            // SAFETY: getuid and getgid cannot fail
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            let uid_map = format!("{uid} {uid} 1").into_bytes();
+            let gid_map = format!("{gid} {gid} 1").into_bytes();
+            let mut cmd = Command::new("sleep");
+            cmd.arg("60")
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null());
+            // SAFETY: only raw system calls on data prepared above
+            unsafe {
+                cmd.pre_exec(move || {
+                    check(libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET))?;
+                    write_id_maps(&uid_map, &gid_map)?;
+                    bring_up_loopback();
+                    Ok(())
+                });
+            }
+            let mut helper = cmd.spawn().context("start the sandbox network helper")?;
+            let namespaces = (|| {
+                let ns = PathBuf::from(format!("/proc/{}/ns", helper.id()));
+                Ok(Self {
+                    user: File::open(ns.join("user"))
+                        .context("open the user namespace")?
+                        .into(),
+                    net: File::open(ns.join("net"))
+                        .context("open the network namespace")?
+                        .into(),
+                })
+            })();
+            let _ = helper.kill();
+            let _ = helper.wait();
+            namespaces
+        }
+    }
+
+    /// The command keeps its own uid and gid, and so loses the namespace's
+    /// capabilities at exec.
+    unsafe fn write_id_maps(uid_map: &[u8], gid_map: &[u8]) -> io::Result<()> {
+        // SAFETY: the paths are NUL-terminated literals
+        unsafe {
+            write_file(c"/proc/self/setgroups", b"deny")?;
+            write_file(c"/proc/self/uid_map", uid_map)?;
+            write_file(c"/proc/self/gid_map", gid_map)
+        }
+    }
+
+    /// Mount point and per-mount options of a /proc/self/mountinfo line.
+    fn parse_mount(line: &str) -> Option<(PathBuf, String)> {
+        let mut fields = line.split(' ');
+        let point = fields.nth(4)?;
+        let options = fields.next()?;
+        Some((PathBuf::from(unescape(point)), options.to_string()))
+    }
+
+    /// Undo the octal escapes mountinfo uses for spaces and the like.
+    fn unescape(field: &str) -> String {
+        let bytes = field.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'\\'
+                && let Some(code) = field
+                    .get(i + 1..i + 4)
+                    .and_then(|octal| u8::from_str_radix(octal, 8).ok())
+            {
+                out.push(code);
+                i += 4;
+            } else {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    /// Flags a remount inside a user namespace has to keep.
+    fn locked_flags(options: &str) -> libc::c_ulong {
+        options
+            .split(',')
+            .map(|option| match option {
+                "nosuid" => libc::MS_NOSUID,
+                "nodev" => libc::MS_NODEV,
+                "noexec" => libc::MS_NOEXEC,
+                "noatime" => libc::MS_NOATIME,
+                "nodiratime" => libc::MS_NODIRATIME,
+                "relatime" => libc::MS_RELATIME,
+                "strictatime" => libc::MS_STRICTATIME,
+                _ => 0,
+            })
+            .fold(0, |flags, flag| flags | flag)
+    }
+
+    fn c_path(path: &Path) -> Result<CString> {
+        CString::new(path.as_os_str().as_bytes())
+            .with_context(|| format!("path contains a NUL byte: {}", path.display()))
+    }
+
+    fn check(res: libc::c_int) -> io::Result<()> {
+        if res == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    unsafe fn write_file(path: &std::ffi::CStr, data: &[u8]) -> io::Result<()> {
+        // SAFETY: path is NUL-terminated and data outlives the call
+        unsafe {
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let written = libc::write(fd, data.as_ptr().cast(), data.len());
+            let err = io::Error::last_os_error();
+            libc::close(fd);
+            if written == data.len() as isize {
+                Ok(())
+            } else {
+                Err(err)
+            }
+        }
+    }
+
+    /// Best effort: the command still runs when this fails.
+    unsafe fn bring_up_loopback() {
+        // SAFETY: ifreq is plain data and the socket is closed again
+        unsafe {
+            let fd = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+            if fd < 0 {
+                return;
+            }
+            let mut req: libc::ifreq = std::mem::zeroed();
+            for (dst, src) in req.ifr_name.iter_mut().zip(b"lo") {
+                *dst = *src as libc::c_char;
+            }
+            req.ifr_ifru.ifru_flags = (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+            libc::ioctl(fd, libc::SIOCSIFFLAGS as _, &req);
+            libc::close(fd);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn reads_mountinfo_lines() {
+            let (point, options) = parse_mount(
+                "36 35 98:0 / /mnt/my\\040disk rw,nosuid,relatime shared:1 - ext4 /dev/sda1 rw",
+            )
+            .unwrap();
+            assert_eq!(point, PathBuf::from("/mnt/my disk"));
+            assert_eq!(locked_flags(&options), libc::MS_NOSUID | libc::MS_RELATIME);
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::tools::execute::{ExecuteBashResult, execute_bash};
+    use tempfile::TempDir;
+
+    async fn run(command: &str, config: &AppConfig) -> ExecuteBashResult {
+        serde_json::from_str(&execute_bash(command, config).await.unwrap()).unwrap()
+    }
+
+    fn config(project: &TempDir) -> AppConfig {
+        let mut config = AppConfig {
+            project_root: project.path().to_path_buf(),
+            ..Default::default()
+        };
+        config.sandbox.enabled = true;
+        config.sandbox.writable_paths.clear();
+        config
+    }
+
+    /// Config whose sandbox only allows writes to the project, or `None` when
+    /// this system cannot create the namespaces (unprivileged user namespaces
+    /// disabled), leaving nothing to test.
+    async fn sandboxed(project: &TempDir) -> Option<AppConfig> {
+        let config = config(project);
+        match run("true", &config).await.sandbox_violation {
+            Some(violation) if violation.kind == ViolationKind::Unavailable => None,
+            violation => {
+                assert_eq!(violation, None);
+                Some(config)
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn writes_are_confined_to_the_project() {
+        let project = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        let Some(config) = sandboxed(&project).await else {
+            return;
+        };
+
+        let inside = run(
+            "mkdir -p out && echo ok > out/file && cat out/file",
+            &config,
+        )
+        .await;
+        assert!(inside.success, "{inside:?}");
+        assert_eq!(inside.stdout.trim(), "ok");
+
+        let target = outside.path().join("stolen");
+        let blocked = run(&format!("touch {}", target.display()), &config).await;
+        assert!(!blocked.success);
+        assert!(!target.exists());
+        let violation = blocked.sandbox_violation.expect("violation reported");
+        assert_eq!(violation.kind, ViolationKind::Filesystem);
+
+        let offline = run("exec 3<>/dev/tcp/192.0.2.1/80", &config).await;
+        assert!(!offline.success);
+        assert_eq!(
+            offline.sandbox_violation.map(|v| v.kind),
+            Some(ViolationKind::Network)
+        );
+
+        // Separate commands see the same localhost
+        let first = run("readlink /proc/self/ns/net", &config).await;
+        let second = run("readlink /proc/self/ns/net", &config).await;
+        assert!(first.success, "{first:?}");
+        assert_eq!(first.stdout, second.stdout);
+    }
+
+    #[tokio::test]
+    async fn doge_and_git_dirs_stay_read_only() {
+        let project = TempDir::new().unwrap();
+        std::fs::create_dir_all(project.path().join(".git/hooks")).unwrap();
+        let Some(mut config) = sandboxed(&project).await else {
+            return;
+        };
+
+        for path in [".doge/config.toml", ".git/hooks/pre-commit"] {
+            let blocked = run(&format!("echo x > {path}"), &config).await;
+            assert!(!blocked.success, "{path}: {blocked:?}");
+            assert!(!project.path().join(path).exists());
+            assert_eq!(
+                blocked.sandbox_violation.map(|v| v.kind),
+                Some(ViolationKind::Filesystem)
+            );
+        }
+        let inside = run("echo x > src.rs", &config).await;
+        assert!(inside.success, "{inside:?}");
+
+        config.sandbox.git_writable = true;
+        let hook = run("echo x > .git/hooks/pre-commit", &config).await;
+        assert!(hook.success, "{hook:?}");
+    }
+
+    #[test]
+    fn git_writes_get_their_own_explanation() {
+        let project = TempDir::new().unwrap();
+        let mut config = config(&project);
+        let output = "fatal: Unable to create '/p/.git/index.lock': Read-only file system";
+
+        let sandbox = Sandbox::from_config(&config).unwrap();
+        let violation = sandbox.violation(output).unwrap();
+        assert!(violation.message.contains("git_writable"), "{violation}");
+        let other = sandbox.violation("touch: /etc/x: Read-only file system");
+        assert!(!other.unwrap().message.contains("git_writable"));
+
+        config.sandbox.git_writable = true;
+        let sandbox = Sandbox::from_config(&config).unwrap();
+        assert_eq!(sandbox.protected, [project.path().join(".doge")]);
+        assert!(
+            !sandbox
+                .violation(output)
+                .unwrap()
+                .message
+                .contains("git_writable")
+        );
+    }
+}
@@ -0,0 +1,605 @@
+use crate::config::AppConfig;
+use crate::tools::bash_jobs::OutputBuffer;
+use crate::tools::execute::{self, BashOptions, ExecuteBashResult};
+use crate::tools::sandbox::{Sandbox, SandboxViolation};
+use anyhow::{Context, Result, anyhow};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use tempfile::NamedTempFile;
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+
+/// How long a new shell gets to come up, and an interrupted command to stop.
+const SETTLE: Duration = Duration::from_secs(5);
+
+/// Settings that keep programs from paging, prompting or echoing into the
+/// captured output.
+const SHELL_ENV: &[(&str, &str)] = &[
+    ("TERM", "dumb"),
+    ("PAGER", "cat"),
+    ("GIT_PAGER", "cat"),
+    ("PS0", ""),
+    ("PS1", ""),
+    ("PS2", ""),
+];
+
+/// The shell `execute_bash` runs in when `persistent_shell` is on, so `cd`,
+/// exported variables and sourced scripts carry over between calls. It is
+/// started on first use and replaced after [`PersistentShell::reset`].
+#[derive(Debug, Default)]
+pub struct PersistentShell {
+    session: tokio::sync::Mutex<Option<ShellSession>>,
+    // Bumped by reset; a session from an older generation is replaced
+    generation: AtomicU64,
+}
+
+impl PersistentShell {
+    /// Run `command` in the session and report its output, exit code and the
+    /// working directory it left behind.
+    pub async fn run(
+        &self,
+        command: &str,
+        config: &AppConfig,
+        options: &BashOptions,
+    ) -> Result<String> {
+        let mut guard = self.session.lock().await;
+        let generation = self.generation.load(Ordering::SeqCst);
+        let mut notes = Vec::new();
+        if let Some(session) = guard.as_mut()
+            && (session.generation != generation || !session.recover().await)
+        {
+            if session.generation == generation {
+                notes.push("[the previous command could not be stopped; started a new shell session in the project root]".to_string());
+            }
+            *guard = None;
+        }
+        let session = match guard.as_mut() {
+            Some(session) => session,
+            None => match ShellSession::start(config, generation).await {
+                Ok(session) => guard.insert(session),
+                Err(e) => {
+                    return match e.downcast::<SandboxViolation>() {
+                        Ok(violation) => execute::blocked_by_sandbox(violation),
+                        Err(e) => Err(e),
+                    };
+                }
+            },
+        };
+
+        let (stdout, outcome) = session.run(command, options).await?;
+        let (exit_code, cwd, timed_out) = match outcome {
+            Outcome::Done { code, cwd } => (Some(code), Some(cwd), false),
+            Outcome::TimedOut { code, cwd } => {
+                notes.push(format!(
+                    "[timed out after {}s; the command was interrupted and the shell session kept. Pass a larger timeout_secs or use run_in_background for servers and watchers]",
+                    options.timeout.as_secs()
+                ));
+                (Some(code), Some(cwd), true)
+            }
+            Outcome::Stuck => {
+                notes.push(format!(
+                    "[timed out after {}s and could not be interrupted; the shell session was killed and the next command starts a new one in the project root]",
+                    options.timeout.as_secs()
+                ));
+                *guard = None;
+                (None, None, true)
+            }
+            Outcome::Exited(status) => {
+                notes.push(
+                    "[the shell exited; the next command starts a new session in the project root]"
+                        .to_string(),
+                );
+                *guard = None;
+                (status.and_then(|s| s.code()), None, false)
+            }
+        };
+
+        let success = exit_code == Some(0) && !timed_out;
+        let sandbox_violation = Sandbox::from_config(config)
+            .filter(|_| !success)
+            .and_then(|sandbox| sandbox.violation(&stdout));
+        let result = ExecuteBashResult {
+            stdout,
+            // A terminal has a single output stream
+            stderr: notes.join("\n"),
+            exit_code,
+            success,
+            sandbox_violation,
+            cwd,
+        };
+        Ok(serde_json::to_string(&result)?)
+    }
+
+    /// Start over with a fresh shell in the project root on the next command.
+    pub fn reset(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// How a command in the session ended.
+#[derive(Debug)]
+enum Outcome {
+    Done {
+        code: i32,
+        cwd: String,
+    },
+    /// Interrupted after the timeout; the shell is still usable
+    TimedOut {
+        code: i32,
+        cwd: String,
+    },
+    /// Still running after the timeout and an interrupt
+    Stuck,
+    /// The shell itself exited, e.g. after `exit`
+    Exited(Option<ExitStatus>),
+}
+
+/// Interactive bash on a pseudo-terminal. Each command is sourced from a
+/// script file and followed by a marker line carrying its exit code and the
+/// shell's working directory, which tells where its output ends.
+#[derive(Debug)]
+struct ShellSession {
+    child: Child,
+    pid: i32,
+    master: File,
+    output: UnboundedReceiver<Vec<u8>>,
+    script: NamedTempFile,
+    generation: u64,
+    next_id: u64,
+    // Marker id of a command whose tool call was cancelled before it ended
+    unfinished: Option<u64>,
+}
+
+impl ShellSession {
+    async fn start(config: &AppConfig, generation: u64) -> Result<Self> {
+        let project_root = &config.project_root;
+        let (master, slave) = open_pty().context("open a pseudo-terminal for the shell")?;
+        let mut cmd = Command::new("bash");
+        cmd.args(["--noprofile", "--norc", "--noediting", "-i"])
+            .current_dir(project_root)
+            .envs(SHELL_ENV.iter().copied())
+            .env_remove("PROMPT_COMMAND")
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        // SAFETY: setsid and ioctl are async-signal-safe
+        unsafe {
+            cmd.pre_exec(|| {
+                // Own session with the terminal as its controlling tty, so
+                // job control and Ctrl-C work as in a terminal
+                if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let sandbox = Sandbox::from_config(config);
+        if let Some(sandbox) = &sandbox {
+            sandbox
+                .confine(&mut cmd, project_root)
+                .map_err(|e| anyhow!(Sandbox::unavailable(e)))?;
+        }
+        let child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(e) if sandbox.is_some() => return Err(anyhow!(Sandbox::unavailable(e))),
+            Err(e) => return Err(e).context("Failed to start the shell session"),
+        };
+        // The parent's copies of the terminal must go, or reads never end
+        drop(cmd);
+
+        let (tx, output) = unbounded_channel();
+        let mut reader = master.try_clone()?;
+        std::thread::spawn(move || {
+            let mut chunk = [0u8; 8192];
+            loop {
+                match reader.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        if tx.send(chunk[..n].to_vec()).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    // EIO once the shell and everything it started are gone
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let mut session = Self {
+            pid: child.id().map(|pid| pid as i32).unwrap_or_default(),
+            child,
+            master,
+            output,
+            script: NamedTempFile::with_prefix("dgc-shell-")?,
+            generation,
+            next_id: 0,
+            unfinished: None,
+        };
+        // Keep commands out of the history and the shell quiet
+        let id = session.send("set +o history; unset PROMPT_COMMAND")?;
+        let mut ignored = Collected::new(None);
+        match tokio::time::timeout(SETTLE, session.wait_for(id, &mut ignored)).await {
+            Ok(Outcome::Done { .. }) => Ok(session),
+            _ => Err(anyhow!(
+                "the shell session did not start: {}",
+                ignored.buffer.take().trim()
+            )),
+        }
+    }
+
+    async fn run(&mut self, command: &str, options: &BashOptions) -> Result<(String, Outcome)> {
+        self.discard_stale_output();
+        std::fs::write(self.script.path(), command)?;
+        let source = format!(". {}", shell_quote(&self.script.path().to_string_lossy()));
+        let id = self.send(&source)?;
+        self.unfinished = Some(id);
+
+        let mut out = Collected::new(options.live_output.clone());
+        let outcome = match tokio::time::timeout(options.timeout, self.wait_for(id, &mut out)).await
+        {
+            Ok(outcome) => outcome,
+            Err(_) => match self.interrupt(&mut out).await {
+                Some(Outcome::Done { code, cwd }) => Outcome::TimedOut { code, cwd },
+                Some(outcome) => outcome,
+                None => Outcome::Stuck,
+            },
+        };
+        self.unfinished = None;
+        out.finish();
+        Ok((out.buffer.take(), outcome))
+    }
+
+    /// Stop a command left running by a cancelled call. False when the shell
+    /// is gone or would not stop.
+    async fn recover(&mut self) -> bool {
+        if self.unfinished.take().is_none() {
+            return self.child.try_wait().ok().flatten().is_none();
+        }
+        let mut ignored = Collected::new(None);
+        matches!(
+            self.interrupt(&mut ignored).await,
+            Some(Outcome::Done { .. })
+        )
+    }
+
+    /// Send Ctrl-C, then kill the foreground job if that was not enough, and
+    /// wait for the shell to report back each time.
+    async fn interrupt(&mut self, out: &mut Collected) -> Option<Outcome> {
+        self.master.write_all(b"\x03").ok()?;
+        // Let the signal land before the marker line reaches the terminal
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let id = self.send("").ok()?;
+        if let Ok(outcome) = tokio::time::timeout(SETTLE, self.wait_for(id, out)).await {
+            return Some(outcome);
+        }
+
+        // SAFETY: tcgetpgrp and killpg have no memory-safety requirements
+        unsafe {
+            let foreground = libc::tcgetpgrp(self.master.as_raw_fd());
+            if foreground <= 0 || foreground == self.pid {
+                return None;
+            }
+            libc::killpg(foreground, libc::SIGKILL);
+        }
+        tokio::time::timeout(SETTLE, self.wait_for(id, out))
+            .await
+            .ok()
+    }
+
+    /// Write `command` followed by its marker; returns the marker id. Both
+    /// go on one line, so a command reading its input cannot swallow the
+    /// marker and Ctrl-C drops both.
+    fn send(&mut self, command: &str) -> Result<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+        // The format string keeps the typed line from containing the marker
+        let report = format!(
+            "printf '{}%s %d %s\\n' {id} \"$?\" \"$PWD\"",
+            self.marker_prefix()
+        );
+        let line = if command.is_empty() {
+            format!("{report}\n")
+        } else {
+            format!("{command}; {report}\n")
+        };
+        self.master
+            .write_all(line.as_bytes())
+            .context("write to the shell session")?;
+        Ok(id)
+    }
+
+    fn marker_prefix(&self) -> String {
+        format!("__dgc_{}_", self.pid)
+    }
+
+    async fn wait_for(&mut self, id: u64, out: &mut Collected) -> Outcome {
+        let prefix = self.marker_prefix();
+        loop {
+            tokio::select! {
+                chunk = self.output.recv() => match chunk {
+                    Some(chunk) => {
+                        if let Some((marker, code, cwd)) = out.feed(&chunk, &prefix)
+                            && marker == id
+                        {
+                            return Outcome::Done { code, cwd };
+                        }
+                    }
+                    None => return Outcome::Exited(self.child.wait().await.ok()),
+                },
+                status = self.child.wait() => {
+                    // Output still in flight; jobs left running may keep the
+                    // terminal open, so do not wait for it to close
+                    let _ = tokio::time::timeout(Duration::from_millis(100), async {
+                        while let Some(chunk) = self.output.recv().await {
+                            out.feed(&chunk, &prefix);
+                        }
+                    })
+                    .await;
+                    return Outcome::Exited(status.ok());
+                }
+            }
+        }
+    }
+
+    fn discard_stale_output(&mut self) {
+        while self.output.try_recv().is_ok() {}
+    }
+}
+
+impl Drop for ShellSession {
+    fn drop(&mut self) {
+        kill_session(self.pid);
+    }
+}
+
+/// Output of one command, split into lines so the markers can be found.
+struct Collected {
+    buffer: OutputBuffer,
+    live: Option<Sender<String>>,
+    // Trailing bytes of an incomplete UTF-8 sequence
+    pending: Vec<u8>,
+    // Incomplete last line
+    line: String,
+}
+
+impl Collected {
+    fn new(live: Option<Sender<String>>) -> Self {
+        Self {
+            buffer: OutputBuffer::default(),
+            live,
+            pending: Vec::new(),
+            line: String::new(),
+        }
+    }
+
+    /// Take in a chunk of terminal output. Returns the id, exit code and
+    /// working directory of the first marker found in it.
+    fn feed(&mut self, chunk: &[u8], prefix: &str) -> Option<(u64, i32, String)> {
+        self.pending.extend_from_slice(chunk);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => self.pending.len(),
+        };
+        let text = String::from_utf8_lossy(&self.pending[..valid]).into_owned();
+        self.pending.drain(..valid);
+        self.line.push_str(&text);
+
+        while let Some(end) = self.line.find('\n') {
+            let line: String = self.line.drain(..=end).collect();
+            let line = line.trim_end_matches(['\n', '\r']);
+            match line.find(prefix) {
+                Some(start) => {
+                    // Output without a trailing newline shares the marker's line
+                    if start > 0 {
+                        self.emit(&line[..start], false);
+                    }
+                    if let Some(marker) = parse_marker(&line[start + prefix.len()..]) {
+                        return Some(marker);
+                    }
+                }
+                None => self.emit(line, true),
+            }
+        }
+        None
+    }
+
+    /// Flush what is left of an unterminated line.
+    fn finish(&mut self) {
+        let rest = std::mem::take(&mut self.line);
+        if !rest.is_empty() {
+            self.emit(&rest, false);
+        }
+    }
+
+    fn emit(&mut self, line: &str, newline: bool) {
+        self.buffer.push(line);
+        if newline {
+            self.buffer.push("\n");
+        }
+        if let Some(tx) = &self.live {
+            let _ = tx.send(format!("::shell_output:{line}"));
+        }
+    }
+}
+
+/// `<id> <exit code> <cwd>` after the marker prefix.
+fn parse_marker(rest: &str) -> Option<(u64, i32, String)> {
+    let mut parts = rest.splitn(3, ' ');
+    let id = parts.next()?.parse().ok()?;
+    let code = parts.next()?.parse().ok()?;
+    let cwd = parts.next().unwrap_or_default().to_string();
+    Some((id, code, cwd))
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Pseudo-terminal without echo or output post-processing, so the output
+/// reads as it would from a pipe.
+fn open_pty() -> Result<(File, File)> {
+    let (mut master, mut slave) = (0, 0);
+    let size = libc::winsize {
+        ws_row: 50,
+        ws_col: 200,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: openpty fills in two new descriptors, which the Files own
+    unsafe {
+        if libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            &size,
+        ) != 0
+        {
+            return Err(io::Error::last_os_error().into());
+        }
+        let (master, slave) = (File::from_raw_fd(master), File::from_raw_fd(slave));
+        for fd in [master.as_raw_fd(), slave.as_raw_fd()] {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+        let mut termios: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(slave.as_raw_fd(), &mut termios) != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        termios.c_lflag &= !(libc::ECHO | libc::ECHONL);
+        termios.c_oflag &= !libc::OPOST;
+        if libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok((master, slave))
+    }
+}
+
+/// Kill the shell and everything still running in its session, including
+/// background jobs it started.
+fn kill_session(sid: i32) {
+    if sid <= 0 {
+        return;
+    }
+    let members = std::fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<i32>().ok())
+        .filter(|pid| session_of(*pid) == Some(sid));
+    // SAFETY: kill and killpg have no memory-safety requirements
+    unsafe {
+        for pid in members {
+            libc::kill(pid, libc::SIGKILL);
+        }
+        libc::killpg(sid, libc::SIGKILL);
+    }
+}
+
+fn session_of(pid: i32) -> Option<i32> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The command name may contain spaces and parentheses
+    let fields = &stat[stat.rfind(')')? + 1..];
+    fields.split_whitespace().nth(3)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn run(shell: &PersistentShell, command: &str, config: &AppConfig) -> ExecuteBashResult {
+        run_with(shell, command, config, &BashOptions::default()).await
+    }
+
+    async fn run_with(
+        shell: &PersistentShell,
+        command: &str,
+        config: &AppConfig,
+        options: &BashOptions,
+    ) -> ExecuteBashResult {
+        serde_json::from_str(&shell.run(command, config, options).await.unwrap()).unwrap()
+    }
+
+    fn config(root: &TempDir) -> AppConfig {
+        let mut config = AppConfig {
+            project_root: root.path().to_path_buf(),
+            persistent_shell: true,
+            ..Default::default()
+        };
+        config.sandbox.enabled = false;
+        config
+    }
+
+    #[test]
+    fn markers_are_found_after_partial_lines() {
+        let mut out = Collected::new(None);
+        assert_eq!(out.feed(b"one\ntw", "__m_"), None);
+        let done = out.feed(b"o__m_3 1 /tmp/a b\n", "__m_");
+        assert_eq!(done, Some((3, 1, "/tmp/a b".to_string())));
+        assert_eq!(out.buffer.take(), "one\ntwo");
+    }
+
+    #[tokio::test]
+    async fn state_carries_over_between_commands() {
+        let root = TempDir::new().unwrap();
+        std::fs::create_dir(root.path().join("sub")).unwrap();
+        let config = config(&root);
+        let shell = PersistentShell::default();
+
+        let first = run(&shell, "cd sub && export GREETING=hello", &config).await;
+        assert!(first.success, "{first:?}");
+        let sub = root.path().join("sub").canonicalize().unwrap();
+        assert_eq!(first.cwd.as_deref(), Some(sub.to_str().unwrap()));
+
+        let second = run(
+            &shell,
+            "echo \"$GREETING from $(basename \"$PWD\")\"",
+            &config,
+        )
+        .await;
+        assert_eq!(second.stdout, "hello from sub\n");
+
+        let failed = run(&shell, "false", &config).await;
+        assert_eq!(failed.exit_code, Some(1));
+        assert!(!failed.success);
+
+        shell.reset();
+        let fresh = run(&shell, "echo \"[$GREETING]\"", &config).await;
+        assert_eq!(fresh.stdout, "[]\n");
+        let root_dir = root.path().canonicalize().unwrap();
+        assert_eq!(fresh.cwd.as_deref(), Some(root_dir.to_str().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn timeouts_interrupt_the_command_but_keep_the_shell() {
+        let root = TempDir::new().unwrap();
+        let config = config(&root);
+        let shell = PersistentShell::default();
+        run(&shell, "export KEPT=yes", &config).await;
+
+        let options = BashOptions {
+            timeout: Duration::from_millis(500),
+            live_output: None,
+        };
+        let slow = run_with(&shell, "echo started; sleep 30", &config, &options).await;
+        assert!(!slow.success);
+        // bash ends the interrupted line itself
+        assert_eq!(slow.stdout.trim_end(), "started");
+        assert!(slow.stderr.contains("timed out"), "{slow:?}");
+
+        let after = run(&shell, "echo $KEPT", &config).await;
+        assert_eq!(after.stdout, "yes\n");
+
+        let exited = run(&shell, "exit 3", &config).await;
+        assert_eq!(exited.exit_code, Some(3));
+        let restarted = run(&shell, "echo \"[$KEPT]\"", &config).await;
+        assert_eq!(restarted.stdout, "[]\n");
+    }
+}
@@ -0,0 +1,80 @@
+use crate::llm::types::{ToolDef, ToolFunctionDef};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Iteration budget of a sub-agent when the caller does not pick one.
+pub const DEFAULT_MAX_ITERATIONS: usize = 30;
+
+/// Largest iteration budget a sub-agent may be given.
+pub const MAX_ITERATIONS: usize = 100;
+
+const DESCRIPTION: &str = r#"
+Delegate a self-contained task to a sub-agent that works in its own fresh context and returns only its final summary.
+Use it for broad exploration (e.g. "find every place that builds an HTTP client and explain how retries are configured") so that the many file reads it needs do not flood this conversation.
+The sub-agent sees none of this conversation: describe the task completely, including paths, names and what the summary must contain.
+By default the sub-agent can only use read-only tools (fs_list, fs_read, fs_read_many_files, find_file, search_text, search_repomap); pass `tools: "all"` to let it edit files and run commands as well.
+Sub-agents cannot spawn further agents.
+"#;
+
+/// Tools a sub-agent may use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubAgentTools {
+    /// Read-only exploration tools
+    #[default]
+    ReadOnly,
+    /// Every tool of the parent agent except `spawn_agent`
+    All,
+}
+
+/// Arguments for the `spawn_agent` tool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpawnAgentArgs {
+    /// Complete description of the task, including what the summary must contain
+    pub task: String,
+    #[serde(default)]
+    pub tools: SubAgentTools,
+    /// Iteration budget, capped at `MAX_ITERATIONS`
+    #[serde(default)]
+    pub max_iterations: Option<usize>,
+}
+
+impl SpawnAgentArgs {
+    pub fn iteration_budget(&self) -> usize {
+        self.max_iterations
+            .unwrap_or(DEFAULT_MAX_ITERATIONS)
+            .clamp(1, MAX_ITERATIONS)
+    }
+}
+
+pub fn tool_def() -> ToolDef {
+    ToolDef {
+        kind: "function".to_string(),
+        function: ToolFunctionDef {
+            name: "spawn_agent".to_string(),
+            description: DESCRIPTION.trim().to_string(),
+            strict: None,
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "task": {
+                        "type": "string",
+                        "description": "Complete, self-contained description of the task and of what the final summary must contain."
+                    },
+                    "tools": {
+                        "type": "string",
+                        "enum": ["read_only", "all"],
+                        "description": "Tools available to the sub-agent. Defaults to `read_only`."
+                    },
+                    "max_iterations": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "maximum": MAX_ITERATIONS,
+                        "description": format!("Maximum number of model turns. Defaults to {DEFAULT_MAX_ITERATIONS}.")
+                    }
+                },
+                "required": ["task"]
+            }),
+        },
+    }
+}
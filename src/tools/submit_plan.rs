@@ -0,0 +1,138 @@
+use crate::llm::types::{ToolDef, ToolFunctionDef};
+use crate::tools::todo_write::TodoItem;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+const DESCRIPTION: &str = r#"
+Submit the implementation plan for the user to review. Only available in plan mode, where it is the required way to finish.
+Investigate first with the read-only tools, then call this once with a short summary of the approach and the ordered steps.
+Each step should be a concrete, verifiable change (which files, which functions, which tests).
+The user approves, edits or rejects the plan; after approval the steps become the todo list and you carry them out.
+"#;
+
+/// Implementation plan proposed in plan mode.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    /// The approach in a few sentences
+    pub summary: String,
+    /// Ordered steps, one change each
+    pub steps: Vec<String>,
+}
+
+impl Plan {
+    /// Markdown rendering, also used as the editable form of the plan.
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("{}\n", self.summary.trim());
+        if !self.steps.is_empty() {
+            out.push('\n');
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            out.push_str(&format!("{}. {}\n", i + 1, step.trim()));
+        }
+        out
+    }
+
+    /// Parse the form written by [`Plan::to_markdown`]: numbered or bulleted
+    /// lines are steps, everything before the first step is the summary.
+    pub fn from_markdown(text: &str) -> Self {
+        let mut plan = Plan::default();
+        let mut summary = Vec::new();
+        for line in text.lines() {
+            let trimmed = line.trim();
+            if let Some(step) = list_item(trimmed) {
+                plan.steps.push(step.to_string());
+            } else if let Some(last) = plan.steps.last_mut()
+                && !trimmed.is_empty()
+            {
+                // Continuation of a wrapped step
+                last.push(' ');
+                last.push_str(trimmed);
+            } else if plan.steps.is_empty() {
+                summary.push(line);
+            }
+        }
+        plan.summary = summary.join("\n").trim().to_string();
+        plan
+    }
+
+    /// The steps as a fresh todo list.
+    pub fn todo_items(&self) -> Vec<TodoItem> {
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| TodoItem {
+                id: format!("plan-{}", i + 1),
+                content: step.clone(),
+                status: "pending".to_string(),
+            })
+            .collect()
+    }
+}
+
+fn list_item(line: &str) -> Option<&str> {
+    let rest = if let Some(rest) = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")) {
+        rest
+    } else {
+        let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            return None;
+        }
+        line[digits..]
+            .strip_prefix(". ")
+            .or_else(|| line[digits..].strip_prefix(") "))?
+    };
+    let rest = rest.trim();
+    (!rest.is_empty()).then_some(rest)
+}
+
+pub fn tool_def() -> ToolDef {
+    ToolDef {
+        kind: "function".to_string(),
+        function: ToolFunctionDef {
+            name: "submit_plan".to_string(),
+            description: DESCRIPTION.trim().to_string(),
+            strict: None,
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "summary": {
+                        "type": "string",
+                        "description": "The approach in a few sentences."
+                    },
+                    "steps": {
+                        "type": "array",
+                        "items": {"type": "string"},
+                        "description": "Ordered implementation steps."
+                    }
+                },
+                "required": ["summary", "steps"]
+            }),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_round_trips_and_accepts_hand_edits() {
+        let plan = Plan {
+            summary: "Add a retry budget.".into(),
+            steps: vec!["Add the config field".into(), "Test it".into()],
+        };
+        assert_eq!(Plan::from_markdown(&plan.to_markdown()), plan);
+
+        let edited = Plan::from_markdown(
+            "Add a retry budget\nacross providers.\n\n1) Add the config\n   field\n- Wire it up\n* Test it\n",
+        );
+        assert_eq!(edited.summary, "Add a retry budget\nacross providers.");
+        assert_eq!(
+            edited.steps,
+            ["Add the config field", "Wire it up", "Test it"]
+        );
+        let todos = edited.todo_items();
+        assert_eq!(todos[2].id, "plan-3");
+        assert!(todos.iter().all(|t| t.status == "pending"));
+    }
+}
@@ -1,6 +1,6 @@
 use crate::analysis::RepoMap;
 use crate::hooks::HookManager;
-use crate::llm::OpenAIClient;
+use crate::llm::LlmProvider;
 
 use crate::session::SessionManager;
 use crate::tools::FsTools;
@@ -21,7 +21,7 @@ pub struct TuiExecutor {
     pub(crate) cfg: crate::config::AppConfig,
     pub(crate) tools: FsTools,
     pub(crate) repomap: Arc<RwLock<Option<RepoMap>>>,
-    pub(crate) client: Option<OpenAIClient>,
+    pub(crate) client: Option<Arc<dyn LlmProvider>>,
     #[allow(dead_code)]
     pub(crate) history: crate::llm::ChatHistory,
     pub(crate) ui_tx: Option<std::sync::mpsc::Sender<String>>,
@@ -57,6 +57,10 @@ impl TuiExecutor {
 
     /// Set the UI sender for sending messages to the TUI
     pub fn set_ui_tx(&mut self, ui_tx: Option<std::sync::mpsc::Sender<String>>) {
+        // Tool calls needing approval are asked about in the TUI
+        if let Some(tx) = &ui_tx {
+            self.tools.permissions().set_prompter(tx.clone());
+        }
         self.ui_tx = ui_tx;
     }
 
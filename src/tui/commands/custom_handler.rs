@@ -41,6 +41,8 @@ impl TuiExecutor {
                     content: Some(processed_content.clone()),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
                 });
             }
             
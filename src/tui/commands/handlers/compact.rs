@@ -41,10 +41,9 @@ impl TuiExecutor {
         }
 
         // Prepare parameters and clones for the async task
-        let mut client = self.client.as_ref().unwrap().clone();
+        let client = self.client.as_ref().unwrap().with_reasoning_disabled();
         let model = self.cfg.model.clone();
         let fs_tools = self.tools.clone();
-        client.reason_enable = false;
         let cfg = self.cfg.clone();
         let params = crate::llm::CompactParams {
             client,
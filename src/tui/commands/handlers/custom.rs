@@ -1,4 +1,5 @@
 use crate::tui::commands::core::TuiExecutor;
+use crate::tui::commands::handlers::exec::record_run_cost;
 use crate::tui::view::TuiApp;
 use std::collections::HashMap;
 use std::fs;
@@ -124,6 +125,8 @@ impl TuiExecutor {
                     content: Some(processed_content.clone()),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
                 });
             }
 
@@ -180,6 +183,8 @@ impl TuiExecutor {
                     content: Some(sys_prompt),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
                 });
 
                 // Add existing conversation history
@@ -192,6 +197,8 @@ impl TuiExecutor {
                     content: Some(content.clone()),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
+                    images: vec![],
                 });
                 let fs = self.tools.clone();
                 let conversation_history = self.conversation_history.clone();
@@ -213,7 +220,7 @@ impl TuiExecutor {
                     }
 
                     let res = crate::llm::run_agent_loop(
-                        &c,
+                        c.as_ref(),
                         &model,
                         &fs,
                         msgs,
@@ -226,6 +233,7 @@ impl TuiExecutor {
                     // Get token usage after the agent loop completes
                     let tokens_used = c.get_prompt_tokens_used();
                     let total_tokens = c.get_tokens_used();
+                    record_run_cost(c.as_ref(), &cfg, &session_manager, tx.as_ref());
                     match res {
                         Ok((updated_messages, _final_msg)) => {
                             // Execute hooks after the agent loop completes
@@ -283,6 +291,8 @@ impl TuiExecutor {
                                     content: Some(content.clone()),
                                     tool_calls: vec![],
                                     tool_call_id: None,
+                                    reasoning: None,
+                                    images: vec![],
                                 });
 
                                 // Also save conversation history to session
@@ -6,16 +6,22 @@ use crate::tui::commands::handlers::custom::load_custom_commands;
 use crate::tui::commands::handlers::slash_commands::cancel::handle_cancel;
 use crate::tui::commands::handlers::slash_commands::clear::handle_clear;
 use crate::tui::commands::handlers::slash_commands::compact::handle_compact;
+use crate::tui::commands::handlers::slash_commands::cost::handle_cost;
 use crate::tui::commands::handlers::slash_commands::edit_symbol::handle_edit_symbol;
 use crate::tui::commands::handlers::slash_commands::git_worktree::handle_git_worktree;
 use crate::tui::commands::handlers::slash_commands::help::handle_help;
 use crate::tui::commands::handlers::slash_commands::map::handle_map;
 use crate::tui::commands::handlers::slash_commands::open::handle_open;
+use crate::tui::commands::handlers::slash_commands::permissions::handle_permissions;
+use crate::tui::commands::handlers::slash_commands::plan::handle_plan;
 use crate::tui::commands::handlers::slash_commands::quit::handle_quit;
 use crate::tui::commands::handlers::slash_commands::rebuild_repomap::handle_rebuild_repomap;
+use crate::tui::commands::handlers::slash_commands::rewind::handle_rewind;
 use crate::tui::commands::handlers::slash_commands::theme::handle_theme;
+use crate::tui::commands::handlers::slash_commands::thinking::handle_thinking;
 use crate::tui::commands::handlers::slash_commands::tokens::handle_tokens;
 use crate::tui::commands::handlers::slash_commands::tools::handle_tools;
+use crate::tui::commands::handlers::slash_commands::undo::handle_undo;
 
 // Refactored to delegate slash commands to dedicated modules for better modularity and maintainability.
 // This allows each command to be tested independently and keeps dispatch.rs focused on routing.
@@ -24,7 +30,7 @@ impl CommandHandler for TuiExecutor {
     fn handle(&mut self, line: &str, ui: &mut TuiApp) {
         // This function was extracted from the big handlers.rs for readability.
         if self.ui_tx.is_none() {
-            self.ui_tx = ui.sender();
+            self.set_ui_tx(ui.sender());
         }
         let line = line.trim();
         if line.is_empty() {
@@ -37,6 +43,8 @@ impl CommandHandler for TuiExecutor {
             "/quit" => handle_quit(self, ui),
             "/clear" => handle_clear(self, ui),
             "/tokens" => handle_tokens(self, ui),
+            "/cost" => handle_cost(self, ui),
+            "/thinking" => handle_thinking(self, ui),
             "/rebuild-repomap" => handle_rebuild_repomap(self, ui),
             "/cancel" => handle_cancel(self, ui),
             "/compact" => handle_compact(self, ui),
@@ -46,7 +54,14 @@ impl CommandHandler for TuiExecutor {
                 Ok(message) => ui.push_log(message),
                 Err(e) => ui.push_log(format!("Error: {}", e)),
             },
+            "/plan" => handle_plan(self, line, ui),
+            "/permissions" => handle_permissions(self, line, ui),
+            "/undo" => handle_undo(self, ui),
+            "/rewind" => handle_rewind(self, line, ui),
             line if line.starts_with("/open ") => handle_open(self, line, ui),
+            line if line.starts_with("/permissions ") => handle_permissions(self, line, ui),
+            line if line.starts_with("/plan ") => handle_plan(self, line, ui),
+            line if line.starts_with("/rewind ") => handle_rewind(self, line, ui),
             line if line.starts_with("/theme ") => handle_theme(self, line, ui),
             _ => {
                 // Rest of content moved to exec.rs
@@ -1,9 +1,17 @@
 use crate::tui::view::TuiApp;
 
+use std::sync::Mutex;
+use std::sync::mpsc::Sender;
 use tokio::sync::watch;
 use tokio_util::sync::CancellationToken;
 use tracing::info;
 
+use crate::config::AppConfig;
+use crate::cost::CostSummary;
+use crate::features::auto_commit::{self, TurnCommit, TurnStart};
+use crate::llm::ImagePart;
+use crate::llm::provider::LlmProvider;
+use crate::session::SessionManager;
 use crate::tui::commands::core::TuiExecutor;
 
 impl TuiExecutor {
@@ -17,6 +25,14 @@ impl TuiExecutor {
         }
 
         if !line.starts_with('/') {
+            let images = match self.collect_images(line, ui) {
+                Ok(images) => images,
+                Err(e) => {
+                    ui.push_log(format!("> {line}"));
+                    ui.push_log(format!("[ERROR] {e}"));
+                    return;
+                }
+            };
             {
                 let mut sm = self.session_manager.lock().unwrap();
                 if sm.current_session.is_none() {
@@ -43,12 +59,17 @@ impl TuiExecutor {
             let rest = line;
             self.last_user_prompt = Some(rest.to_string());
             ui.push_log(format!("> {rest}"));
+            if !images.is_empty() {
+                ui.push_log(format!("[INFO] Attached {} image(s).", images.len()));
+            }
 
             match self.client.as_ref() {
                 Some(c) => {
                     let rt = tokio::runtime::Handle::current();
                     let model = self.cfg.model.clone();
                     let content = rest.to_string();
+                    // Store the last user input for retrying after compact
+                    ui.last_user_input = Some(content.clone());
                     let c = c.clone();
                     let tx = self.ui_tx.clone();
                     // Prepare a fresh line for the final output
@@ -81,6 +102,8 @@ impl TuiExecutor {
                         content: Some(sys_prompt),
                         tool_calls: vec![],
                         tool_call_id: None,
+                        reasoning: None,
+                        images: vec![],
                     });
 
                     // Add existing conversation history
@@ -88,11 +111,39 @@ impl TuiExecutor {
                         msgs.extend(history.clone());
                     }
 
+                    // Checkpoint the files this turn changes for /undo and /rewind
+                    let session_id = self
+                        .session_manager
+                        .lock()
+                        .unwrap()
+                        .get_current_session_id();
+                    if let Ok(id) = session_id
+                        && let Err(e) =
+                            self.tools
+                                .checkpoints()
+                                .begin_turn(&id, msgs.len() - 1, &content)
+                    {
+                        tracing::error!(?e, "Failed to start a file checkpoint for this turn");
+                    }
+                    // Changes already pending now are the user's and stay out of the auto-commit
+                    let turn_start = if self.cfg.auto_commit {
+                        TurnStart::capture(&self.cfg.project_root).unwrap_or_else(|e| {
+                            ui.push_log(format!(
+                                "[commit][error] Cannot auto-commit this instruction: {e:#}"
+                            ));
+                            None
+                        })
+                    } else {
+                        None
+                    };
+
                     msgs.push(crate::llm::ChatMessage {
                         role: "user".into(),
                         content: Some(content.clone()),
                         tool_calls: vec![],
                         tool_call_id: None,
+                        reasoning: None,
+                        images: images.clone(),
                     });
                     let fs = self.tools.clone();
                     let conversation_history = self.conversation_history.clone();
@@ -114,7 +165,7 @@ impl TuiExecutor {
                         }
 
                         let res = crate::llm::run_agent_loop(
-                            &c,
+                            c.as_ref(),
                             &model,
                             &fs,
                             msgs,
@@ -127,6 +178,7 @@ impl TuiExecutor {
                         // Get token usage after the agent loop completes
                         let tokens_used = c.get_prompt_tokens_used();
                         let total_tokens = c.get_tokens_used();
+                        record_run_cost(c.as_ref(), &cfg, &session_manager, tx.as_ref());
                         match res {
                             Ok((updated_messages, final_msg)) => {
                                 // Execute hooks after the agent loop completes
@@ -135,8 +187,24 @@ impl TuiExecutor {
                                     content: Some(final_msg.content.clone()),
                                     tool_calls: vec![],
                                     tool_call_id: None,
+                                    reasoning: None,
+                                    images: vec![],
                                 };
 
+                                // Commit the files this instruction changed
+                                if let Some(start) = &turn_start {
+                                    commit_turn(
+                                        c.as_ref(),
+                                        &model,
+                                        &fs,
+                                        start,
+                                        &content,
+                                        &session_manager,
+                                        tx.as_ref(),
+                                    )
+                                    .await;
+                                }
+
                                 // This requires hook_manager to be cloned, which is complex
                                 // For now, execute hooks in a different way or skip in async context
                                 if let Some(tx) = tx {
@@ -173,6 +241,16 @@ impl TuiExecutor {
                                     }
                                 }
                             }
+                            Err(e) if matches!(
+                                e.downcast_ref::<crate::llm::LlmErrorKind>(),
+                                Some(crate::llm::LlmErrorKind::ContextLengthExceeded)
+                            ) => {
+                                // The input is retried once the history has been compacted
+                                if let Some(tx) = tx {
+                                    let _ = tx.send("::status:error".into());
+                                    let _ = tx.send("::trigger_compact".into());
+                                }
+                            }
                             Err(e) => {
                                 if let Some(tx) = tx {
                                     let _ = tx.send(format!("LLM error: {e}"));
@@ -191,6 +269,8 @@ impl TuiExecutor {
                                         content: Some(content.clone()),
                                         tool_calls: vec![],
                                         tool_call_id: None,
+                                        reasoning: None,
+                                        images,
                                     });
 
                                     // Also save conversation history to session
@@ -220,3 +300,86 @@ impl TuiExecutor {
         }
     }
 }
+
+impl TuiExecutor {
+    /// Images for a prompt: pasted ones still referenced in `line` plus image
+    /// files mentioned as `@path`. Fails when the model cannot see them.
+    fn collect_images(&self, line: &str, ui: &mut TuiApp) -> anyhow::Result<Vec<ImagePart>> {
+        let mut images = ui.take_pasted_images(line);
+        for path in crate::llm::image_references(line, &self.cfg.project_root) {
+            images.push(crate::llm::load_image(&path)?);
+        }
+        if !images.is_empty() && !self.cfg.supports_vision(&self.cfg.model) {
+            anyhow::bail!(
+                "{} does not accept image input; the message was not sent",
+                self.cfg.model
+            );
+        }
+        Ok(images)
+    }
+}
+
+/// Price the requests made since the last run, add them to the session and
+/// report the session total to the UI.
+pub(crate) fn record_run_cost(
+    client: &dyn LlmProvider,
+    cfg: &AppConfig,
+    session_manager: &Mutex<SessionManager>,
+    tx: Option<&Sender<String>>,
+) {
+    let run_cost = CostSummary::from_usages(&client.usage_log().drain(), &cfg.price_table());
+    if run_cost.is_empty() {
+        return;
+    }
+    let Ok(mut sm) = session_manager.lock() else {
+        return;
+    };
+    if let Err(e) = sm.update_current_session_with_cost(&run_cost) {
+        tracing::error!(?e, "Failed to update session with cost");
+    }
+    if let (Some(tx), Some(total)) = (tx, sm.current_session_cost()) {
+        let _ = tx.send(format!("::cost:{:.6}", total.cost_usd));
+    }
+}
+
+/// Auto-commit the files the finished instruction changed and report the
+/// commit to the UI.
+async fn commit_turn(
+    client: &dyn LlmProvider,
+    model: &str,
+    fs: &crate::tools::FsTools,
+    start: &TurnStart,
+    prompt: &str,
+    session_manager: &Mutex<SessionManager>,
+    tx: Option<&Sender<String>>,
+) {
+    let Ok(session) = session_manager.lock().unwrap().get_current_session_id() else {
+        return;
+    };
+    let writer = client.with_reasoning_disabled();
+    let result =
+        auto_commit::commit_turn(writer.as_ref(), model, fs, start, prompt, &session).await;
+    record_run_cost(client, &fs.config, session_manager, tx);
+    let Some(tx) = tx else {
+        return;
+    };
+    match result {
+        Ok(TurnCommit { commit, skipped }) => {
+            if let Some((commit, summary)) = commit {
+                let short = &commit[..commit.len().min(8)];
+                let _ = tx.send(format!("[commit] {short} {summary}"));
+            }
+            if !skipped.is_empty() {
+                let _ = tx.send(format!(
+                    "[commit] Left out files that already had uncommitted changes: {}",
+                    skipped.join(", ")
+                ));
+            }
+        }
+        Err(e) => {
+            let _ = tx.send(format!(
+                "[commit][error] Failed to commit the changes: {e:#}"
+            ));
+        }
+    }
+}
@@ -51,6 +51,7 @@ impl TuiExecutor {
                     content: Some(sys_prompt),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
                 });
 
                 // Add existing conversation history
@@ -63,6 +64,7 @@ impl TuiExecutor {
                     content: Some(content.clone()),
                     tool_calls: vec![],
                     tool_call_id: None,
+                    reasoning: None,
                 });
                 let fs = self.tools.clone();
                 let conversation_history = self.conversation_history.clone();
@@ -104,6 +106,7 @@ impl TuiExecutor {
                                 content: Some(final_msg.content.clone()),
                                 tool_calls: vec![],
                                 tool_call_id: None,
+                                reasoning: None,
                             };
                             
                             // Access the hook manager through the executor (self)
@@ -214,6 +217,7 @@ impl TuiExecutor {
                                     content: Some(content.clone()),
                                     tool_calls: vec![],
                                     tool_call_id: None,
+                                    reasoning: None,
                                 });
 
                                 // Also save conversation history to session
@@ -6,6 +6,8 @@ use crate::tui::view::TuiApp;
 pub fn handle_cancel(executor: &mut TuiExecutor, ui: &mut TuiApp) {
     if let Some(tx) = &executor.cancel_tx {
         let _ = tx.send(true);
+        executor.tools.permissions().cancel_pending();
+        ui.permission_prompts.clear();
         if let Some(tx) = &executor.ui_tx {
             let _ = tx.send("::status:cancelled".into());
         }
@@ -17,6 +17,9 @@ pub fn handle_clear(executor: &mut TuiExecutor, ui: &mut TuiApp) {
         return;
     }
 
+    // The new session gets a fresh shell as well
+    executor.tools.reset_shell();
+
     // Reset LLM client tokens
     if let Some(client) = &executor.client {
         client.set_tokens(0);
@@ -27,6 +30,8 @@ pub fn handle_clear(executor: &mut TuiExecutor, ui: &mut TuiApp) {
     ui.tokens_prompt_used = 0;
     ui.tokens_used = 0;
     ui.tokens_total_used = None;
+    ui.context_usage = None;
+    ui.session_cost = None;
     ui.dirty = true;
 
     ui.push_log("Cleared conversation history and started new session. Tokens reset to 0.");
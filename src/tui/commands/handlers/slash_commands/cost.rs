@@ -0,0 +1,20 @@
+use crate::tui::commands::core::TuiExecutor;
+use crate::tui::view::TuiApp;
+
+/// Delegate /cost to the dedicated handler.
+/// Shows the spend of the current session, broken down by model.
+pub fn handle_cost(executor: &mut TuiExecutor, ui: &mut TuiApp) {
+    let report = executor
+        .session_manager
+        .lock()
+        .ok()
+        .and_then(|sm| sm.current_session_cost().map(|cost| cost.report()));
+    match report {
+        Some(report) => {
+            for line in report.lines() {
+                ui.push_log(line.to_string());
+            }
+        }
+        None => ui.push_log("No active session."),
+    }
+}
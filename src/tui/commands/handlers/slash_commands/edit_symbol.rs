@@ -11,7 +11,7 @@ use crate::config::AppConfig;
 use crate::diff_review::DiffReviewPayload;
 use crate::llm::{
     SymbolEditRequest, SymbolEditResponse, build_symbol_edit_chat_request,
-    parse_symbol_edit_response, read_symbol_source,
+    parse_symbol_edit_payload, read_symbol_source, symbol_edit_schema,
 };
 use crate::tools::apply_patch::{ApplyPatchParams, apply_patch as apply_patch_tool};
 use crate::tui::commands::core::TuiExecutor;
@@ -127,8 +127,16 @@ fn enqueue_symbol_edit_request(
     let cfg = executor.cfg.clone();
 
     let crate::llm::types::ChatRequest {
-        model, messages, ..
+        model,
+        messages,
+        response_format,
+        ..
     } = chat_req;
+    let schema = response_format
+        .as_ref()
+        .and_then(|format| format.schema())
+        .cloned()
+        .unwrap_or_else(symbol_edit_schema);
 
     let symbol_label = format!(
         "{} ({})",
@@ -143,8 +151,11 @@ fn enqueue_symbol_edit_request(
         );
         let _ = ui_tx.send("::status:processing".to_string());
 
-        let response = match client.chat_once(&model, messages, None).await {
-            Ok(choice) => choice.content,
+        let payload = match client
+            .chat_structured(&model, messages, &schema, None)
+            .await
+        {
+            Ok(payload) => payload,
             Err(e) => {
                 send_ui(
                     &ui_tx,
@@ -155,16 +166,8 @@ fn enqueue_symbol_edit_request(
             }
         };
 
-        if response.trim().is_empty() {
-            send_ui(
-                &ui_tx,
-                "[edit-symbol][error] LLM returned an empty response.",
-            );
-            let _ = ui_tx.send("::status:error".to_string());
-            return;
-        }
-
-        let parsed = match parse_symbol_edit_response(&response) {
+        let raw = payload.to_string();
+        let parsed = match parse_symbol_edit_payload(payload) {
             Ok(resp) => resp,
             Err(e) => {
                 send_ui(
@@ -175,13 +178,16 @@ fn enqueue_symbol_edit_request(
                     &ui_tx,
                     format!(
                         "[edit-symbol] Raw response snippet:\n{}",
-                        truncate_for_log(&response)
+                        truncate_for_log(&raw)
                     ),
                 );
                 let _ = ui_tx.send("::status:error".to_string());
                 return;
             }
         };
+        if let Some(explanation) = &parsed.explanation {
+            send_ui(&ui_tx, format!("[edit-symbol] {explanation}"));
+        }
 
         match apply_symbol_edit_response(parsed, request, &cfg).await {
             Ok(changed_path) => {
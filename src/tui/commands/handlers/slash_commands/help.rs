@@ -12,8 +12,20 @@ pub fn handle_help(executor: &mut TuiExecutor, ui: &mut TuiApp) {
     ui.push_log("  /theme <name> - Switch theme (dark/light)");
     ui.push_log("  /tools - List available tools");
     ui.push_log("  /tokens - Show token usage");
+    ui.push_log("  /cost - Show session spend by model");
+    ui.push_log("  /thinking - Expand or collapse model reasoning");
     ui.push_log("  /compact - Compact conversation history to reduce token usage");
     ui.push_log("  /cancel - Cancel the current operation");
+    ui.push_log(
+        "  /plan [on|off|approve|edit|reject] - Toggle plan mode or act on the plan under review",
+    );
+    ui.push_log(
+        "  /permissions [allow [session|always]|deny] - Show tool permissions or answer the pending approval",
+    );
+    ui.push_log("  /undo - Revert the files changed by the last turn");
+    ui.push_log(
+        "  /rewind [turn] - List checkpoints or restore files and conversation to before a turn",
+    );
     ui.push_log("");
 
     ui.push_log("Repository Analysis:");
@@ -44,6 +56,7 @@ pub fn handle_help(executor: &mut TuiExecutor, ui: &mut TuiApp) {
     ui.push_log("Other controls:");
     ui.push_log("  @ - File completion");
     ui.push_log("  ! - Shell mode (at start of empty line)");
+    ui.push_log("  Shift+Tab - Toggle plan mode");
     ui.push_log("  Esc - Cancel operation or exit shell mode");
     ui.push_log("  Ctrl+C - Cancel (press twice to exit)");
 }
@@ -1,13 +1,19 @@
 pub mod cancel;
 pub mod clear;
 pub mod compact;
+pub mod cost;
 pub mod edit_symbol;
 pub mod git_worktree;
 pub mod help;
 pub mod map;
 pub mod open;
+pub mod permissions;
+pub mod plan;
 pub mod quit;
 pub mod rebuild_repomap;
+pub mod rewind;
 pub mod theme;
+pub mod thinking;
 pub mod tokens;
 pub mod tools;
+pub mod undo;
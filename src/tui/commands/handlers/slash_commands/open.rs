@@ -2,6 +2,7 @@ use crate::tui::commands::core::TuiExecutor;
 use crate::tui::view::TuiApp;
 use crossterm::{cursor, execute, terminal};
 use std::env;
+use std::path::Path;
 use std::process;
 
 /// Delegate /open to the dedicated handler.
@@ -23,7 +24,17 @@ pub fn handle_open(executor: &mut TuiExecutor, line: &str, ui: &mut TuiApp) {
         ui.push_log(format!("not found: {}", abs.display()));
         return;
     }
-    // Leave TUI alt screen temporarily while spawning editor in blocking mode
+    let status = run_editor(&abs);
+
+    match status {
+        Ok(s) if s.success() => ui.push_log(format!("opened: {}", abs.display())),
+        Ok(s) => ui.push_log(format!("editor exited with status {s}")),
+        Err(e) => ui.push_log(format!("failed to launch editor: {e}")),
+    }
+}
+
+/// Run the user's editor on `path`, leaving the TUI screen while it is open.
+pub(crate) fn run_editor(path: &Path) -> std::io::Result<process::ExitStatus> {
     let mut stdout = std::io::stdout();
     let _ = execute!(stdout, terminal::LeaveAlternateScreen, cursor::Show);
     let _ = terminal::disable_raw_mode();
@@ -33,15 +44,10 @@ pub fn handle_open(executor: &mut TuiExecutor, line: &str, ui: &mut TuiApp) {
         .ok()
         .or_else(|| env::var("VISUAL").ok())
         .unwrap_or_else(|| "vi".to_string());
-    let status = process::Command::new(&editor).arg(&abs).status();
+    let status = process::Command::new(&editor).arg(path).status();
 
     // Re-enter TUI
     let _ = terminal::enable_raw_mode();
     let _ = execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide);
-
-    match status {
-        Ok(s) if s.success() => ui.push_log(format!("opened: {}", abs.display())),
-        Ok(s) => ui.push_log(format!("editor exited with status {s}")),
-        Err(e) => ui.push_log(format!("failed to launch editor: {e}")),
-    }
+    status
 }
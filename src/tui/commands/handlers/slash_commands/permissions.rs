@@ -0,0 +1,55 @@
+use crate::tools::permissions::Approval;
+use crate::tui::commands::core::TuiExecutor;
+use crate::tui::view::TuiApp;
+
+/// Delegate /permissions to the dedicated handler.
+/// Without arguments it lists the tool modes and allow rules; `allow`,
+/// `allow session`, `allow always` and `deny` answer the oldest pending
+/// approval prompt.
+pub fn handle_permissions(executor: &mut TuiExecutor, line: &str, ui: &mut TuiApp) {
+    let arg = line.strip_prefix("/permissions").unwrap_or("").trim();
+    let approval = match arg {
+        "" => {
+            ui.push_log("Tool permissions:");
+            for line in executor.tools.permissions().describe() {
+                ui.push_log(format!("  {line}"));
+            }
+            ui.dirty = true;
+            return;
+        }
+        "allow" => Approval::Once,
+        "allow session" => Approval::Session,
+        "allow always" => Approval::Always,
+        "deny" => Approval::Deny,
+        _ => {
+            ui.push_log("usage: /permissions [allow [session|always]|deny]");
+            return;
+        }
+    };
+
+    let Some(prompt) = ui.permission_prompts.pop_front() else {
+        ui.push_log("[permission] No tool call is waiting for approval.");
+        return;
+    };
+    let request = prompt.request;
+    let result = executor.tools.permissions().answer(&request, approval);
+    match approval {
+        Approval::Once => ui.push_log(format!("[permission] Allowed `{}` once.", request.tool)),
+        Approval::Session => ui.push_log(format!(
+            "[permission] Allowed `{}` for this session.",
+            request.rule
+        )),
+        Approval::Always => {
+            ui.push_log(format!("[permission] Always allowing `{}`.", request.rule))
+        }
+        Approval::Deny => ui.push_log(format!("[permission] Denied `{}`.", request.tool)),
+    }
+    match result {
+        Ok(Some(path)) => ui.push_log(format!("[permission] Rule saved to {}", path.display())),
+        Ok(None) => {}
+        Err(e) => ui.push_log(format!(
+            "[permission][error] Failed to save the rule; it applies to this session only: {e}"
+        )),
+    }
+    ui.dirty = true;
+}
@@ -0,0 +1,128 @@
+use crate::tools::submit_plan::Plan;
+use crate::tui::commands::core::TuiExecutor;
+use crate::tui::commands::handlers::slash_commands::open::run_editor;
+use crate::tui::plan_review::PlanReviewState;
+use crate::tui::state::TodoItem;
+use crate::tui::view::TuiApp;
+
+/// Sent once a plan is approved so the agent starts carrying it out.
+const EXECUTE_PLAN_PROMPT: &str = "The plan below is approved. Carry it out step by step, keeping the todo list up to date with todo_write.";
+
+/// Delegate /plan to the dedicated handler.
+/// `/plan [on|off]` switches plan mode; `approve`, `edit` and `reject` act on
+/// the plan awaiting review.
+pub fn handle_plan(executor: &mut TuiExecutor, line: &str, ui: &mut TuiApp) {
+    let arg = line.strip_prefix("/plan").unwrap_or("").trim();
+    match arg {
+        "" => set_plan_mode(executor, ui, !executor.cfg.plan_mode),
+        "on" => set_plan_mode(executor, ui, true),
+        "off" => set_plan_mode(executor, ui, false),
+        "approve" => approve(executor, ui),
+        "edit" => edit(ui),
+        "reject" => {
+            if ui.plan_review.take().is_some() {
+                ui.push_log(
+                    "[plan] Rejected. Still in plan mode; describe what to change to get a new plan.",
+                );
+            } else {
+                ui.push_log("[plan] No plan is awaiting review.");
+            }
+        }
+        _ => ui.push_log("usage: /plan [on|off|approve|edit|reject]"),
+    }
+    ui.dirty = true;
+}
+
+fn set_plan_mode(executor: &mut TuiExecutor, ui: &mut TuiApp, on: bool) {
+    executor.cfg.plan_mode = on;
+    ui.plan_mode = on;
+    if on {
+        ui.push_log(
+            "[plan] Plan mode on: the agent only reads and ends with a plan for review (Shift+Tab or /plan to leave).",
+        );
+    } else {
+        ui.plan_review = None;
+        ui.push_log("[plan] Plan mode off: the agent may edit files and run commands.");
+    }
+}
+
+fn approve(executor: &mut TuiExecutor, ui: &mut TuiApp) {
+    let Some(review) = ui.plan_review.take() else {
+        ui.push_log("[plan] No plan is awaiting review.");
+        return;
+    };
+    set_plan_mode(executor, ui, false);
+
+    let todos = review.plan.todo_items();
+    match executor.tools.todo_write(todos.clone()) {
+        Ok(_) => {
+            ui.todo_list = todos
+                .into_iter()
+                .map(|t| TodoItem {
+                    id: t.id,
+                    content: t.content,
+                    status: t.status,
+                })
+                .collect();
+        }
+        Err(e) => ui.push_log(format!("[plan][error] Failed to seed the todo list: {e}")),
+    }
+
+    ui.push_log(format!(
+        "[plan] Approved {} step(s); executing.",
+        review.plan.steps.len()
+    ));
+    // The plan may have been edited, so it is repeated in full
+    ui.pending_instructions.push_back(format!(
+        "{EXECUTE_PLAN_PROMPT}\n\n{}",
+        review.plan.to_markdown()
+    ));
+}
+
+fn edit(ui: &mut TuiApp) {
+    let Some(review) = ui.plan_review.as_ref() else {
+        ui.push_log("[plan] No plan is awaiting review.");
+        return;
+    };
+    let file = match tempfile::Builder::new().suffix(".md").tempfile() {
+        Ok(file) => file,
+        Err(e) => {
+            ui.push_log(format!(
+                "[plan][error] Failed to create a temporary file: {e}"
+            ));
+            return;
+        }
+    };
+    if let Err(e) = std::fs::write(file.path(), review.plan.to_markdown()) {
+        ui.push_log(format!("[plan][error] Failed to write the plan: {e}"));
+        return;
+    }
+
+    match run_editor(file.path()) {
+        Ok(status) if status.success() => {}
+        Ok(status) => {
+            ui.push_log(format!(
+                "editor exited with status {status}; plan unchanged"
+            ));
+            return;
+        }
+        Err(e) => {
+            ui.push_log(format!("failed to launch editor: {e}"));
+            return;
+        }
+    }
+
+    match std::fs::read_to_string(file.path()).map(|text| Plan::from_markdown(&text)) {
+        Ok(plan) if plan.steps.is_empty() => {
+            ui.push_log("[plan] The edited plan has no steps; keeping the previous one.");
+        }
+        Ok(plan) => {
+            ui.push_log(format!(
+                "[plan] Plan edited: {} step(s). a=approve, e=edit, r=reject.",
+                plan.steps.len()
+            ));
+            ui.plan_review = Some(PlanReviewState::new(plan));
+        }
+        Err(e) => ui.push_log(format!("[plan][error] Failed to read the edited plan: {e}")),
+    }
+}
@@ -0,0 +1,100 @@
+use crate::tui::commands::core::TuiExecutor;
+use crate::tui::commands::handlers::slash_commands::undo::{describe_turn, turn_in_progress};
+use crate::tui::view::TuiApp;
+
+/// Delegate /rewind to the dedicated handler.
+/// Without arguments it lists the checkpointed turns of the session;
+/// `/rewind <turn>` restores the files and the conversation to the point
+/// just before that turn's prompt was sent.
+pub fn handle_rewind(executor: &mut TuiExecutor, line: &str, ui: &mut TuiApp) {
+    let arg = line.strip_prefix("/rewind").unwrap_or("").trim();
+    if turn_in_progress(ui) {
+        ui.push_log("[rewind] Wait for the current turn to finish or /cancel it first.");
+        return;
+    }
+    let session = executor
+        .session_manager
+        .lock()
+        .unwrap()
+        .get_current_session_id();
+    let Ok(session) = session else {
+        ui.push_log("[rewind] This session has no checkpoints yet.");
+        return;
+    };
+    let checkpoints = executor.tools.checkpoints().clone();
+
+    if arg.is_empty() {
+        match checkpoints.turns(&session) {
+            Ok(turns) if turns.is_empty() => {
+                ui.push_log("[rewind] This session has no checkpoints yet.")
+            }
+            Ok(turns) => {
+                ui.push_log("Checkpoints (use /rewind <turn> to go back to before a turn):");
+                for turn in &turns {
+                    ui.push_log(format!(
+                        "  {} - {} file(s)",
+                        describe_turn(turn),
+                        turn.files.len()
+                    ));
+                }
+            }
+            Err(e) => ui.push_log(format!("[rewind][error] {e:#}")),
+        }
+        ui.dirty = true;
+        return;
+    }
+    let Ok(number) = arg.parse::<u32>() else {
+        ui.push_log("usage: /rewind [turn]");
+        return;
+    };
+
+    // Auto-commits stay in the history; the restore shows up as local changes
+    let committed = checkpoints
+        .turns(&session)
+        .map(|turns| {
+            turns
+                .iter()
+                .filter(|t| t.turn >= number && t.commit.is_some())
+                .count()
+        })
+        .unwrap_or(0);
+    let turn = match checkpoints.rewind(&session, number) {
+        Ok(turn) => turn,
+        Err(e) => {
+            ui.push_log(format!("[rewind][error] {e:#}"));
+            return;
+        }
+    };
+    ui.push_log(format!(
+        "[rewind] Restored the files to before {}.",
+        describe_turn(&turn)
+    ));
+    if committed > 0 {
+        ui.push_log(format!(
+            "[rewind] {committed} auto-commit(s) were kept; the restored files are uncommitted changes."
+        ));
+    }
+
+    // Only cut the conversation when the prompt is still where it was sent;
+    // compaction rewrites the history and the old position means nothing.
+    let Ok(mut history) = executor.conversation_history.lock() else {
+        ui.push_log("[rewind][error] Failed to access conversation history.");
+        return;
+    };
+    let prompt_in_place = history.get(turn.history_len).is_some_and(|msg| {
+        msg.role == "user" && msg.content.as_deref() == Some(turn.prompt.as_str())
+    });
+    if prompt_in_place {
+        history.truncate(turn.history_len);
+        let mut sm = executor.session_manager.lock().unwrap();
+        if let Err(e) = sm.update_current_session_with_history(&history) {
+            ui.push_log(format!("[rewind][error] Failed to save the session: {e}"));
+        }
+        ui.push_log("[rewind] The conversation was rewound as well.");
+    } else {
+        ui.push_log(
+            "[rewind] The conversation was compacted after that turn, so it was left as it is.",
+        );
+    }
+    ui.dirty = true;
+}
@@ -0,0 +1,14 @@
+use crate::tui::commands::core::TuiExecutor;
+use crate::tui::view::TuiApp;
+
+/// Delegate /thinking to the dedicated handler.
+/// Expands or collapses the model's reasoning blocks in the log.
+pub fn handle_thinking(_executor: &mut TuiExecutor, ui: &mut TuiApp) {
+    let collapsed = !ui.thinking_collapsed;
+    ui.set_thinking_collapsed(collapsed);
+    if collapsed {
+        ui.push_log("[Thinking collapsed]");
+    } else {
+        ui.push_log("[Thinking expanded]");
+    }
+}
@@ -10,4 +10,19 @@ pub fn handle_tokens(executor: &mut TuiExecutor, ui: &mut TuiApp) {
     } else {
         ui.push_log("No LLM client available.");
     }
+
+    // Prompt cache hits are only known from per-request usage
+    let session_usage = executor
+        .session_manager
+        .lock()
+        .ok()
+        .and_then(|sm| sm.current_session_cost().cloned());
+    if let Some(usage) = session_usage.filter(|u| u.input_tokens > 0) {
+        ui.push_log(format!(
+            "Session prompt tokens: {} (cached: {}, {}%)",
+            usage.input_tokens,
+            usage.cached_tokens,
+            usage.cached_tokens * 100 / usage.input_tokens
+        ));
+    }
 }
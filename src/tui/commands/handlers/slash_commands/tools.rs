@@ -11,6 +11,8 @@ pub fn handle_tools(_executor: &mut TuiExecutor, ui: &mut TuiApp) {
     ui.push_log("  📝 fs_write: Write to a file");
     ui.push_log("  🔍 search_text: Search for text in files");
     ui.push_log("  🔧 execute_bash: Execute a shell command in the project root directory");
+    ui.push_log("  📟 bash_output: Read new output of a background command");
+    ui.push_log("  🛑 bash_kill: Stop a background command");
     ui.push_log("  📁 find_file: Find a file by name or pattern");
     ui.push_log("  🗺️ search_repomap: Search the repomap with specific criteria");
     ui.push_log("  ✏️ edit: Edit a single unique block of text within a file");
@@ -0,0 +1,105 @@
+use crate::features::auto_commit::{GitRepo, UndoneCommit};
+use crate::tools::checkpoints::Turn;
+use crate::tui::commands::core::TuiExecutor;
+use crate::tui::state::Status;
+use crate::tui::state_render::truncate_display;
+use crate::tui::view::TuiApp;
+
+/// Delegate /undo to the dedicated handler.
+/// Reverts the files changed by the latest turn that changed any, from the
+/// checkpoints taken before each write. A turn that was auto-committed is
+/// taken back through git instead. The conversation is kept as it is.
+pub fn handle_undo(executor: &mut TuiExecutor, ui: &mut TuiApp) {
+    if turn_in_progress(ui) {
+        ui.push_log("[undo] Wait for the current turn to finish or /cancel it first.");
+        return;
+    }
+    let session = executor
+        .session_manager
+        .lock()
+        .unwrap()
+        .get_current_session_id();
+    let Ok(session) = session else {
+        ui.push_log("[undo] No file changes to undo in this session.");
+        return;
+    };
+    let checkpoints = executor.tools.checkpoints().clone();
+    match checkpoints.last_changed_turn(&session) {
+        Ok(Some(turn)) if turn.commit.is_some() => undo_commit(executor, &session, &turn, ui),
+        Ok(Some(_)) => match checkpoints.undo(&session) {
+            Ok(Some(turn)) => {
+                ui.push_log(format!(
+                    "[undo] Reverted {} file(s) changed by {}",
+                    turn.files.len(),
+                    describe_turn(&turn)
+                ));
+                for file in &turn.files {
+                    ui.push_log(format!("  {}", file.path.display()));
+                }
+            }
+            Ok(None) => ui.push_log("[undo] No file changes to undo in this session."),
+            Err(e) => ui.push_log(format!("[undo][error] {e:#}")),
+        },
+        Ok(None) => ui.push_log("[undo] No file changes to undo in this session."),
+        Err(e) => ui.push_log(format!("[undo][error] {e:#}")),
+    }
+    ui.dirty = true;
+}
+
+/// Take back the auto-commit of `turn`: reset it away while it is the
+/// unpushed tip of the branch, revert it otherwise.
+fn undo_commit(executor: &TuiExecutor, session: &str, turn: &Turn, ui: &mut TuiApp) {
+    let commit = turn.commit.as_deref().unwrap_or_default();
+    let short = &commit[..commit.len().min(8)];
+    let Some(repo) = GitRepo::discover(&executor.cfg.project_root) else {
+        ui.push_log(format!(
+            "[undo][error] {} was committed as {short}, but the project is no longer a git repository.",
+            describe_turn(turn)
+        ));
+        return;
+    };
+    let outcome = match repo.undo_commit(commit) {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            ui.push_log(format!("[undo][error] {e:#}"));
+            return;
+        }
+    };
+    match outcome {
+        UndoneCommit::Reset => ui.push_log(format!(
+            "[undo] Dropped commit {short} of {}",
+            describe_turn(turn)
+        )),
+        UndoneCommit::Reverted => ui.push_log(format!(
+            "[undo] Reverted commit {short} of {} with a new commit",
+            describe_turn(turn)
+        )),
+    }
+    if let Err(e) = executor.tools.checkpoints().discard(session, turn) {
+        ui.push_log(format!("[undo][error] {e:#}"));
+    }
+}
+
+/// Checkpoints must not be restored while the agent may still be writing.
+pub(crate) fn turn_in_progress(ui: &TuiApp) -> bool {
+    matches!(
+        ui.status,
+        Status::Preparing
+            | Status::Sending
+            | Status::Waiting
+            | Status::Streaming
+            | Status::Processing
+    )
+}
+
+/// `turn 3 "first line of the prompt"`
+pub(crate) fn describe_turn(turn: &Turn) -> String {
+    let prompt = turn.prompt.lines().next().unwrap_or("");
+    let short = truncate_display(prompt, 60);
+    let ellipsis = if short.len() < turn.prompt.len() {
+        "..."
+    } else {
+        ""
+    };
+    format!("turn {} \"{short}{ellipsis}\"", turn.turn)
+}
@@ -1,6 +1,6 @@
 use crate::analysis::{Analyzer, RepoMap};
 use crate::hooks::{HookManager, repomap_update::RepomapUpdateHook};
-use crate::llm::OpenAIClient;
+use crate::llm::build_provider;
 use crate::session::SessionManager;
 use crate::tools::FsTools;
 use crate::tui::commands::core::TuiExecutor;
@@ -56,10 +56,7 @@ impl TuiExecutor {
             info!("Repomap initialization skipped due to --no-repomap flag");
         }
 
-        let client = match cfg.api_key.clone() {
-            Some(key) => Some(OpenAIClient::new(cfg.base_url.clone(), key)?),
-            None => None,
-        };
+        let client = build_provider(&cfg)?;
         // Load system prompt
         let sys_prompt = build_system_prompt(&cfg);
         let mut history = crate::llm::ChatHistory::new(12_000, Some(sys_prompt));
@@ -118,10 +115,7 @@ impl TuiExecutor {
             info!("Repomap initialization skipped due to --no-repomap flag");
         }
 
-        let client = match cfg.api_key.clone() {
-            Some(key) => Some(OpenAIClient::new(cfg.base_url.clone(), key)?),
-            None => None,
-        };
+        let client = build_provider(&cfg)?;
         // Load system prompt
         let sys_prompt = build_system_prompt(&cfg);
         let mut history = crate::llm::ChatHistory::new(12_000, Some(sys_prompt));
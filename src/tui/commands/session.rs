@@ -33,6 +33,7 @@ impl TuiExecutor {
                 };
                 match session_manager.create_session(initial_prompt) {
                     Ok(()) => {
+                        ui.session_cost = None;
                         if let Some(info) = (*session_manager).current_session_info() {
                             ui.push_log(format!("Created new session:\n{}", info));
                         }
@@ -52,6 +53,10 @@ impl TuiExecutor {
                         // Clear the TUI display
                         ui.clear_log();
                         ui.push_log(format!("Switched to session: {}", id));
+                        ui.session_cost = session_manager
+                            .current_session_cost()
+                            .filter(|cost| !cost.is_empty())
+                            .map(|cost| cost.cost_usd);
 
                         // Load conversation history from session
                         if let (Some(session), Ok(mut history)) = (
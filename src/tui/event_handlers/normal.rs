@@ -122,6 +122,13 @@ pub fn handle_normal_mode_key(
             app.dirty = true;
         }
 
+        KeyEvent {
+            code: KeyCode::BackTab,
+            ..
+        } => {
+            app.dispatch("/plan");
+        }
+
         KeyEvent {
             code: KeyCode::Esc, ..
         } => {
@@ -164,17 +171,32 @@ pub fn handle_normal_mode_key(
             modifiers,
             ..
         } if modifiers.contains(KeyModifiers::CONTROL) => {
-            // Handle Ctrl+V to paste clipboard content
+            // Handle Ctrl+V to paste clipboard content; images are attached to the next message
             match arboard::Clipboard::new() {
                 Ok(mut clipboard) => match clipboard.get_text() {
                     Ok(contents) => {
                         app.textarea.insert_str(&contents);
                         app.dirty = true;
                     }
-                    Err(e) => {
-                        app.push_log(format!("[Clipboard] Failed to read clipboard: {}", e));
-                        app.dirty = true;
-                    }
+                    Err(text_err) => match clipboard
+                        .get_image()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|img| {
+                            crate::llm::png_from_rgba(
+                                img.width as u32,
+                                img.height as u32,
+                                &img.bytes,
+                            )
+                        }) {
+                        Ok(image) => app.paste_image(image),
+                        Err(_) => {
+                            app.push_log(format!(
+                                "[Clipboard] Failed to read clipboard: {}",
+                                text_err
+                            ));
+                            app.dirty = true;
+                        }
+                    },
                 },
                 Err(e) => {
                     app.push_log(format!("[Clipboard] Failed to access clipboard: {}", e));
@@ -27,51 +27,38 @@ pub fn handle_session_list_key(
             app.session_list_state = None;
             app.dirty = true;
         }
-        KeyCode::Up => {
-            if session_count > 0 {
-                session_list_state.selected_index =
-                    session_list_state.selected_index.saturating_sub(1);
-                app.dirty = true;
-            }
+        KeyCode::Up if session_count > 0 => {
+            session_list_state.selected_index = session_list_state.selected_index.saturating_sub(1);
+            app.dirty = true;
         }
-        KeyCode::Down => {
-            if session_count > 0 {
-                session_list_state.selected_index =
-                    (session_list_state.selected_index + 1).min(session_count - 1);
-                app.dirty = true;
-            }
+        KeyCode::Down if session_count > 0 => {
+            session_list_state.selected_index =
+                (session_list_state.selected_index + 1).min(session_count - 1);
+            app.dirty = true;
         }
-        KeyCode::Enter => {
-            if session_count > 0
-                && session_list_state.selected_index < session_list_state.sessions.len()
-            {
-                let selected_session_id = session_list_state.sessions
-                    [session_list_state.selected_index]
-                    .id
-                    .clone();
-                // Switch to the selected session
-                // This will be handled by the command handler
-                app.pending_instructions
-                    .push_back(format!("/session switch {}", selected_session_id));
-                // Exit session list mode
-                app.input_mode = InputMode::Normal;
-                app.session_list_state = None;
-                app.dirty = true;
-            }
+        KeyCode::Enter if session_list_state.selected_index < session_count => {
+            let selected_session_id = session_list_state.sessions
+                [session_list_state.selected_index]
+                .id
+                .clone();
+            // Switch to the selected session
+            // This will be handled by the command handler
+            app.pending_instructions
+                .push_back(format!("/session switch {}", selected_session_id));
+            // Exit session list mode
+            app.input_mode = InputMode::Normal;
+            app.session_list_state = None;
+            app.dirty = true;
         }
-        KeyCode::Char('d') => {
-            if session_count > 0
-                && session_list_state.selected_index < session_list_state.sessions.len()
-            {
-                let session_id = session_list_state.sessions[session_list_state.selected_index]
-                    .id
-                    .clone();
-                // Delete the selected session
-                // This will be handled by the command handler
-                app.pending_instructions
-                    .push_back(format!("/session delete {}", session_id));
-                app.dirty = true;
-            }
+        KeyCode::Char('d') if session_list_state.selected_index < session_count => {
+            let session_id = session_list_state.sessions[session_list_state.selected_index]
+                .id
+                .clone();
+            // Delete the selected session
+            // This will be handled by the command handler
+            app.pending_instructions
+                .push_back(format!("/session delete {}", session_id));
+            app.dirty = true;
         }
         _ => {}
     }
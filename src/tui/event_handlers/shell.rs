@@ -4,6 +4,7 @@ use tokio::process::Command;
 use tokio::spawn;
 use tui_textarea::{Input, TextArea};
 
+use crate::tools::sandbox::Sandbox;
 use crate::tui::state::{InputMode, TuiApp, save_input_history};
 
 /// Handle keys when in Shell input mode.
@@ -32,23 +33,52 @@ pub fn handle_shell_mode_key(
                 app.draft.clear();
 
                 let tx = app.inbox_tx.clone().unwrap();
+                let sandbox = app.sandbox.clone();
+                let policy = app.command_policy.clone();
                 spawn(async move {
+                    if let Err(violation) = policy.check(&command) {
+                        tx.send(format!(
+                            "::shell_output:[policy] Not running the command: {violation}"
+                        ))
+                        .ok();
+                        return;
+                    }
                     tx.send("::status:shell_running".to_string()).ok();
                     let command_with_redirect = format!("{} 2>&1", command);
-                    let output = Command::new("bash")
-                        .arg("-c")
-                        .arg(&command_with_redirect)
-                        .output()
-                        .await;
+                    let mut cmd = Command::new("bash");
+                    cmd.arg("-c").arg(&command_with_redirect);
+                    if let Some(sandbox) = &sandbox
+                        && let Err(e) = std::env::current_dir()
+                            .map_err(anyhow::Error::from)
+                            .and_then(|cwd| sandbox.confine(&mut cmd, &cwd))
+                    {
+                        tx.send("::status:done".to_string()).ok();
+                        let violation = Sandbox::unavailable(e);
+                        tx.send(format!("::shell_output:[sandbox] {}", violation.message))
+                            .ok();
+                        return;
+                    }
+                    let output = cmd.output().await;
                     tx.send("::status:done".to_string()).ok();
 
                     match output {
                         Ok(output) => {
-                            if !output.stdout.is_empty() {
-                                let output_str =
-                                    String::from_utf8_lossy(&output.stdout).to_string();
+                            let output_str = String::from_utf8_lossy(&output.stdout).to_string();
+                            if !output_str.is_empty() {
                                 tx.send(format!("::shell_output:{}", output_str)).ok();
                             }
+                            if let Some(violation) = sandbox
+                                .filter(|_| !output.status.success())
+                                .and_then(|sandbox| sandbox.violation(&output_str))
+                            {
+                                tx.send(format!("::shell_output:[sandbox] {}", violation.message))
+                                    .ok();
+                            }
+                        }
+                        Err(e) if sandbox.is_some() => {
+                            let violation = Sandbox::unavailable(e);
+                            tx.send(format!("::shell_output:[sandbox] {}", violation.message))
+                                .ok();
                         }
                         Err(e) => {
                             tx.send(format!("::shell_output:Failed to execute command: {}", e))
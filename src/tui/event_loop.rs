@@ -8,10 +8,14 @@ use std::time::{Duration, Instant};
 use tracing::debug;
 
 use crate::diff_review::DiffReviewPayload;
+use crate::tools::permissions::PermissionRequest;
+use crate::tools::submit_plan::Plan;
 use crate::tui::diff_review::DiffReviewState;
 use crate::tui::event_handlers::{
     handle_normal_mode_key, handle_session_list_key, handle_shell_mode_key,
 };
+use crate::tui::permission_prompt::PermissionPromptState;
+use crate::tui::plan_review::PlanReviewState;
 use crate::tui::state::{InputMode, Status, TuiApp};
 use serde::Deserialize;
 
@@ -99,6 +103,43 @@ impl TuiApp {
                         continue;
                     }
 
+                    if let Some(payload) = msg.strip_prefix("::permission:") {
+                        match serde_json::from_str::<PermissionRequest>(payload) {
+                            Ok(request) => {
+                                self.push_log(format!(
+                                    "[permission] `{}` needs approval: y=allow once, s=allow for this session, a=always allow `{}`, n=deny.",
+                                    request.tool, request.rule
+                                ));
+                                self.permission_prompts
+                                    .push_back(PermissionPromptState::new(request));
+                            }
+                            Err(e) => {
+                                self.push_log(format!(
+                                    "[permission][error] Unreadable approval request: {e}"
+                                ));
+                            }
+                        }
+                        self.dirty = true;
+                        continue;
+                    }
+
+                    if let Some(payload) = msg.strip_prefix("::plan:") {
+                        match serde_json::from_str::<Plan>(payload) {
+                            Ok(plan) => {
+                                self.push_log(format!(
+                                    "[plan] Ready for review: {} step(s). Use a=approve, e=edit, r=reject.",
+                                    plan.steps.len()
+                                ));
+                                self.plan_review = Some(PlanReviewState::new(plan));
+                            }
+                            Err(e) => {
+                                self.push_log(format!("[plan][error] Unreadable plan: {e}"));
+                            }
+                        }
+                        self.dirty = true;
+                        continue;
+                    }
+
                     if let Some(output) = msg.strip_prefix("::diff_output:") {
                         let payload = DiffReviewPayload {
                             diff: output.to_string(),
@@ -178,6 +219,10 @@ impl TuiApp {
                             self.spinner_state = 0;
                         }
                         "::status:waiting" => {
+                            if is_streaming {
+                                self.finalize_and_append_llm_response("");
+                                is_streaming = false;
+                            }
                             self.status = Status::Waiting;
                             self.dirty = true;
                             self.spinner_state = 0;
@@ -192,6 +237,11 @@ impl TuiApp {
                             self.spinner_state = 0;
                         }
                         "::status:processing" => {
+                            // Text streamed before tool calls is complete at this point
+                            if is_streaming {
+                                self.finalize_and_append_llm_response("");
+                                is_streaming = false;
+                            }
                             self.status = Status::Processing;
                             self.dirty = true;
                             self.spinner_state = 0;
@@ -244,6 +294,11 @@ impl TuiApp {
                             self.append_stream_token_structured(payload);
                             self.dirty = true;
                         }
+                        _ if msg.starts_with("::reasoning:") => {
+                            let payload = &msg["::reasoning:".len()..];
+                            self.append_reasoning_token(payload);
+                            self.dirty = true;
+                        }
                         _ if msg.starts_with("::status:done:") => {
                             let content = &msg["::status:done:".len()..];
                             debug!(
@@ -349,6 +404,34 @@ impl TuiApp {
                             // Reset processing_start_time to stop the timer
                             self.processing_start_time = None;
                         }
+                        _ if msg.starts_with("::context:") => {
+                            // Format: ::context:used:{n},window:{m}
+                            let mut used = None;
+                            let mut window = None;
+                            for part in msg["::context:".len()..].split(',') {
+                                if let Some(v) = part.strip_prefix("used:") {
+                                    used = v.parse::<u32>().ok();
+                                } else if let Some(v) = part.strip_prefix("window:") {
+                                    window = v.parse::<u32>().ok();
+                                }
+                            }
+                            if let (Some(used), Some(window)) = (used, window) {
+                                self.context_usage = Some((used, window));
+                                self.dirty = true;
+                            }
+                        }
+                        _ if msg.starts_with("::cost:") => {
+                            // Format: ::cost:{session total in USD}
+                            if let Ok(cost) = msg["::cost:".len()..].parse::<f64>() {
+                                self.session_cost = Some(cost);
+                                self.dirty = true;
+                            }
+                        }
+                        _ if msg.starts_with("::model:") => {
+                            // Format: ::model:{name of the model now answering}
+                            self.model = Some(msg["::model:".len()..].to_string());
+                            self.dirty = true;
+                        }
                         _ if msg.starts_with("::todo_list:") => {
                             let todo_list_json = &msg["::todo_list:".len()..];
                             if let Ok(todo_list) = serde_json::from_str::<
@@ -405,9 +488,15 @@ impl TuiApp {
             if event::poll(Duration::from_millis(50))?
                 && let Event::Key(k) = event::read()?
             {
+                if self.process_permission_key(k) {
+                    continue;
+                }
                 if self.process_diff_review_key(k)? {
                     continue;
                 }
+                if self.process_plan_review_key(k) {
+                    continue;
+                }
 
                 // Global key handlers
                 if k.code == KeyCode::Char('c') && k.modifiers.contains(KeyModifiers::CONTROL) {
@@ -509,6 +598,57 @@ impl TuiApp {
         }
     }
 
+    fn process_permission_key(&mut self, key: KeyEvent) -> bool {
+        if self.permission_prompts.is_empty() || !self.textarea.is_empty() {
+            return false;
+        }
+
+        match key.code {
+            KeyCode::Char('y') => self.dispatch("/permissions allow"),
+            KeyCode::Char('s') => self.dispatch("/permissions allow session"),
+            KeyCode::Char('a') => self.dispatch("/permissions allow always"),
+            KeyCode::Char('n') | KeyCode::Esc => self.dispatch("/permissions deny"),
+            KeyCode::Up => self.scroll_permission_prompt(-1),
+            KeyCode::Down => self.scroll_permission_prompt(1),
+            KeyCode::PageUp => self.scroll_permission_prompt(-20),
+            KeyCode::PageDown => self.scroll_permission_prompt(20),
+            _ => return false,
+        }
+        self.dirty = true;
+        true
+    }
+
+    fn scroll_permission_prompt(&mut self, delta: isize) {
+        if let Some(prompt) = self.permission_prompts.front_mut() {
+            prompt.scroll_by(delta);
+        }
+    }
+
+    fn process_plan_review_key(&mut self, key: KeyEvent) -> bool {
+        if self.plan_review.is_none() || !self.textarea.is_empty() {
+            return false;
+        }
+
+        match key.code {
+            KeyCode::Char('a') => self.dispatch("/plan approve"),
+            KeyCode::Char('e') => self.dispatch("/plan edit"),
+            KeyCode::Char('r') => self.dispatch("/plan reject"),
+            KeyCode::Up => self.scroll_plan_review(-1),
+            KeyCode::Down => self.scroll_plan_review(1),
+            KeyCode::PageUp => self.scroll_plan_review(-20),
+            KeyCode::PageDown => self.scroll_plan_review(20),
+            _ => return false,
+        }
+        self.dirty = true;
+        true
+    }
+
+    fn scroll_plan_review(&mut self, delta: isize) {
+        if let Some(review) = self.plan_review.as_mut() {
+            review.scroll_by(delta);
+        }
+    }
+
     fn dismiss_diff_review(&mut self) {
         if self.diff_review.take().is_some() {
             self.push_log("[diff] Closed diff preview. Changes remain applied.".to_string());
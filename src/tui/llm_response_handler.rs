@@ -1,62 +1,84 @@
-use crate::tui::state::TuiApp; // import TuiApp
+use crate::tui::state::{LogEntry, TuiApp}; // import TuiApp
 use regex::Regex;
 use tracing::debug; // import tracing
 
+// Sanitize incoming token to avoid terminal-control sequences that can break raw mode
+fn sanitize_for_display(input: &str) -> String {
+    // Remove common ANSI CSI sequences
+    let csi_re = Regex::new(r"\x1b\[[0-9;?]*[ -/]*[@-~]").unwrap();
+    // Remove OSC sequences: ESC ] ... BEL or ESC \
+    let osc_re = Regex::new(r"\x1b\].*?(?:\x07|\x1b\\)").unwrap();
+
+    let mut s = csi_re.replace_all(input, "").to_string();
+    s = osc_re.replace_all(&s, "").to_string();
+
+    // Handle carriage returns - convert \r\n to \n and remove standalone \r
+    let s = s.replace("\r\n", "\n").replace('\r', "");
+
+    // Remove other control chars except newline and tab
+    s.chars()
+        .filter(|&c| !c.is_control() || c == '\n' || c == '\t')
+        .collect()
+}
+
 // Implement LLM response handling logic for TuiApp
 impl TuiApp {
     // New: structured handling for LLM streaming tokens (immediate log addition)
     pub fn append_stream_token_structured(&mut self, s: &str) {
-        // Sanitize incoming token to avoid terminal-control sequences that can break raw mode
-        fn sanitize_for_display(input: &str) -> String {
-            // Remove common ANSI CSI sequences
-            let csi_re = Regex::new(r"\x1b\[[0-9;?]*[ -/]*[@-~]").unwrap();
-            // Remove OSC sequences: ESC ] ... BEL or ESC \
-            let osc_re = Regex::new(r"\x1b\].*?(?:\x07|\x1b\\)").unwrap();
-
-            let mut s = csi_re.replace_all(input, "").to_string();
-            s = osc_re.replace_all(&s, "").to_string();
-
-            // Handle carriage returns - convert \r\n to \n and remove standalone \r
-            let s = s.replace("\r\n", "\n").replace('\r', "");
-
-            // Remove other control chars except newline and tab
-            s.chars()
-                .filter(|&c| !c.is_control() || c == '\n' || c == '\t')
-                .collect()
-        }
-
         let clean = sanitize_for_display(s);
 
+        if clean.is_empty() {
+            return;
+        }
+
         // Append received (sanitized) token to the parsing buffer for final processing
         self.llm_parsing_buffer.push_str(&clean);
         debug!(appended_content = %clean, "Appended token to llm_parsing_buffer");
 
+        // A new response segment starts on its own line; it is re-rendered as
+        // markdown once finalized
         if self.current_stream_start.is_none() {
             self.current_stream_start = Some(self.log.len());
+            self.last_llm_response_content = None;
+            self.push_log("  ".to_string());
         }
 
         // Accumulate content in last_llm_response_content for duplicate checking
-        let accumulated_content = match &self.last_llm_response_content {
-            Some(existing) => format!("{}{}", existing, clean),
-            None => clean.clone(),
-        };
-        self.last_llm_response_content = Some(accumulated_content);
-
-        // For immediate display during streaming, add the sanitized token with margin
-        // This will be replaced by structured content when streaming completes
-        if !clean.trim().is_empty() {
-            // Handle content with newlines by preserving the exact structure
-            // But use split('\n') instead of lines() to preserve trailing newlines
-            let parts: Vec<&str> = clean.split('\n').collect();
-            for (i, part) in parts.iter().enumerate() {
-                let line_with_margin = format!("  {}", part); // 2-space margin for streaming content
-                self.push_log(line_with_margin);
-                // Add empty line for all but the last part
-                if i < parts.len() - 1 {
-                    self.push_log("  ".to_string()); // Empty line with margin
-                }
+        self.last_llm_response_content
+            .get_or_insert_with(String::new)
+            .push_str(&clean);
+
+        // Tokens continue the current line; each newline starts a new margined line
+        let mut parts = clean.split('\n');
+        if let Some(first) = parts.next() {
+            match self.log.last_mut() {
+                Some(LogEntry::Plain(line)) => line.push_str(first),
+                _ => self.push_log(format!("  {first}")),
             }
         }
+        for part in parts {
+            self.push_log(format!("  {part}"));
+        }
+    }
+
+    /// Add streamed reasoning to the thinking block at the end of the log,
+    /// starting a new block when something else was logged in between.
+    pub fn append_reasoning_token(&mut self, s: &str) {
+        let clean = sanitize_for_display(s);
+        if clean.is_empty() {
+            return;
+        }
+
+        // Reasoning after visible text closes that text segment so the
+        // thinking block is not replaced when the segment is finalized
+        if self.current_stream_start.is_some() {
+            self.finalize_and_append_llm_response("");
+        }
+
+        match self.log.last_mut() {
+            Some(LogEntry::Thinking { text, .. }) => text.push_str(&clean),
+            _ => self.push_thinking(&clean),
+        }
     }
 
     // Keep existing method (with left margin) temporarily; replaced by the new implementation
@@ -106,35 +128,36 @@ impl TuiApp {
         debug!("Set is_llm_response_active to true");
 
         // Clear the parsing buffer as streaming is complete
-        self.llm_parsing_buffer.clear();
-
-        // Check if content is already displayed (duplicate check)
-        let should_add_content = match &self.last_llm_response_content {
-            Some(existing) => {
-                // If the existing content is not the same as the new content, add it
-                // This handles both streaming and non-streaming cases
-                existing != content
-            }
-            None => {
-                // If there's no existing content, add the new content
-                !content.is_empty()
-            }
-        };
+        let streamed = std::mem::take(&mut self.llm_parsing_buffer);
 
-        if should_add_content {
-            debug!(provided_content = %content, "Adding content");
-
-            if let Some(start) = self.current_stream_start.take()
-                && start <= self.log.len()
-            {
+        if let Some(start) = self.current_stream_start.take() {
+            // Replace the raw streamed lines with the rendered response. An empty
+            // `content` means the streamed text itself is the final response.
+            if start <= self.log.len() {
                 self.log.truncate(start);
             }
-
-            self.push_markdown_response(content);
-            self.last_llm_response_content = Some(content.to_string());
+            let final_content = if content.is_empty() {
+                streamed.as_str()
+            } else {
+                content
+            };
+            debug!(provided_content = %final_content, "Rendering streamed content");
+            self.push_markdown_response(final_content);
+            self.last_llm_response_content = Some(final_content.to_string());
         } else {
-            debug!("Skipping content addition due to duplicate check");
-            self.current_stream_start = None;
+            // Check if content is already displayed (duplicate check)
+            let should_add_content = match &self.last_llm_response_content {
+                Some(existing) => existing != content,
+                None => !content.is_empty(),
+            };
+
+            if should_add_content {
+                debug!(provided_content = %content, "Adding content");
+                self.push_markdown_response(content);
+                self.last_llm_response_content = Some(content.to_string());
+            } else {
+                debug!("Skipping content addition due to duplicate check");
+            }
         }
 
         // Reset the flag after the response has been fully added
@@ -5,6 +5,8 @@ pub mod diff_review;
 pub mod event_handlers;
 pub mod event_loop;
 pub mod llm_response_handler;
+pub mod permission_prompt;
+pub mod plan_review;
 pub mod rendering;
 pub mod state;
 pub mod state_render;
@@ -22,3 +24,5 @@ mod test_scroll_formatting;
 mod test_scroll_logging;
 #[cfg(test)]
 mod test_scroll_render;
+#[cfg(test)]
+mod test_streaming;
@@ -0,0 +1,34 @@
+use crate::tools::permissions::PermissionRequest;
+
+/// Tool call shown to the user for approval.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionPromptState {
+    pub request: PermissionRequest,
+    pub scroll: usize,
+}
+
+impl PermissionPromptState {
+    pub fn new(request: PermissionRequest) -> Self {
+        Self { request, scroll: 0 }
+    }
+
+    /// Lines shown in the approval panel.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Tool: {}", self.request.tool),
+            "Arguments:".to_string(),
+        ];
+        lines.extend(
+            self.request
+                .arguments
+                .lines()
+                .map(|line| format!("  {line}")),
+        );
+        lines
+    }
+
+    pub fn scroll_by(&mut self, delta: isize) {
+        let max_scroll = self.lines().len().saturating_sub(1);
+        self.scroll = self.scroll.saturating_add_signed(delta).min(max_scroll);
+    }
+}
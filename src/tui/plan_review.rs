@@ -0,0 +1,24 @@
+use crate::tools::submit_plan::Plan;
+
+/// Plan submitted in plan mode, waiting to be approved, edited or rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanReviewState {
+    pub plan: Plan,
+    pub scroll: usize,
+}
+
+impl PlanReviewState {
+    pub fn new(plan: Plan) -> Self {
+        Self { plan, scroll: 0 }
+    }
+
+    /// Lines shown in the review panel.
+    pub fn lines(&self) -> Vec<String> {
+        self.plan.to_markdown().lines().map(String::from).collect()
+    }
+
+    pub fn scroll_by(&mut self, delta: isize) {
+        let max_scroll = self.lines().len().saturating_sub(1);
+        self.scroll = self.scroll.saturating_add_signed(delta).min(max_scroll);
+    }
+}
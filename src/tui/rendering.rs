@@ -3,7 +3,7 @@ use crate::tui::state::{RenderPlan, TuiApp, build_render_plan};
 use crate::tui::theme::Theme;
 use ratatui::{
     prelude::*,
-    widgets::{Block, Borders, Clear, List, ListItem, Paragraph},
+    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap},
 };
 use std::fmt::Write;
 // use tracing::debug;
@@ -146,6 +146,14 @@ impl TuiApp {
             && let Some(session_list_state) = &self.session_list_state
         {
             self.render_session_list(f, area, session_list_state, theme);
+        } else if !self.permission_prompts.is_empty() {
+            let columns = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(55), Constraint::Percentage(45)])
+                .split(area);
+
+            self.render_log_panel(f, columns[0], plan, theme);
+            self.render_permission_prompt(f, columns[1], theme);
         } else if self.diff_review.is_some() {
             // For diff review mode, we use a horizontal split
             let columns = Layout::default()
@@ -157,6 +165,14 @@ impl TuiApp {
             self.render_log_panel(f, columns[0], plan, theme);
             // Render diff review on the right
             self.render_diff_review(f, columns[1], theme);
+        } else if self.plan_review.is_some() {
+            let columns = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(55), Constraint::Percentage(45)])
+                .split(area);
+
+            self.render_log_panel(f, columns[0], plan, theme);
+            self.render_plan_review(f, columns[1], theme);
         } else {
             // For normal mode, use the full area for the log panel
             // Ensure that if we were previously in diff review mode, the right-side area is cleared
@@ -306,7 +322,7 @@ impl TuiApp {
 
             // Fill remaining space with blank lines to prevent artifacts
             if 1 < layout[1].height as usize {
-                let blank_lines_needed = (layout[1].height as usize - 1).max(0);
+                let blank_lines_needed = (layout[1].height as usize).saturating_sub(1);
                 let blank_lines: Vec<Line> =
                     (0..blank_lines_needed).map(|_| Line::raw(" ")).collect();
 
@@ -333,6 +349,71 @@ impl TuiApp {
         f.render_widget(instructions, layout[2]);
     }
 
+    fn render_permission_prompt(&self, f: &mut Frame, area: Rect, theme: &Theme) {
+        let Some(prompt) = self.permission_prompts.front() else {
+            return;
+        };
+
+        f.render_widget(Clear, area);
+
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(5), Constraint::Length(4)])
+            .split(area);
+
+        let lines: Vec<Line> = prompt.lines().into_iter().map(Line::raw).collect();
+        let scroll = prompt.scroll.min(u16::MAX as usize) as u16;
+        let title = match self.permission_prompts.len() {
+            1 => "Approve Tool Call (↑/↓ scroll)".to_string(),
+            n => format!("Approve Tool Call (1 of {n}, ↑/↓ scroll)"),
+        };
+        let paragraph = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .style(theme.log_style)
+            .wrap(Wrap { trim: false })
+            .scroll((scroll, 0));
+        f.render_widget(paragraph, layout[0]);
+
+        let instructions = Paragraph::new(format!(
+            "y allow once, s allow for this session, n deny\na always allow {}",
+            prompt.request.rule
+        ))
+        .style(theme.footer_style)
+        .block(Block::default().borders(Borders::ALL));
+        f.render_widget(instructions, layout[1]);
+    }
+
+    fn render_plan_review(&self, f: &mut Frame, area: Rect, theme: &Theme) {
+        let Some(review) = &self.plan_review else {
+            return;
+        };
+
+        f.render_widget(Clear, area);
+
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(5), Constraint::Length(3)])
+            .split(area);
+
+        let lines: Vec<Line> = review.lines().into_iter().map(Line::raw).collect();
+        let scroll = review.scroll.min(u16::MAX as usize) as u16;
+        let paragraph = Paragraph::new(lines)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Proposed Plan (↑/↓ scroll)"),
+            )
+            .style(theme.log_style)
+            .wrap(Wrap { trim: false })
+            .scroll((scroll, 0));
+        f.render_widget(paragraph, layout[0]);
+
+        let instructions = Paragraph::new("Review plan: a approve and execute, e edit, r reject")
+            .style(theme.footer_style)
+            .block(Block::default().borders(Borders::ALL));
+        f.render_widget(instructions, layout[1]);
+    }
+
     fn render_session_list(
         &self,
         f: &mut Frame,
@@ -375,7 +456,8 @@ impl TuiApp {
         let list_height = items.len();
         let border_height = 2; // top and bottom border
         if list_height + border_height < area.height as usize {
-            let blank_lines_needed = (area.height as usize - list_height - border_height).max(0);
+            let blank_lines_needed =
+                (area.height as usize).saturating_sub(list_height + border_height);
             if blank_lines_needed > 0 {
                 let blank_lines: Vec<Line> =
                     (0..blank_lines_needed).map(|_| Line::raw(" ")).collect();
@@ -515,6 +597,12 @@ impl TuiApp {
         } else {
             footer_text.push_str("Total: N/A | ");
         }
+        if let Some(percent) = self.context_percent() {
+            write!(footer_text, "Context: {}% | ", percent).unwrap();
+        }
+        if let Some(cost) = self.session_cost {
+            write!(footer_text, "Cost: {} | ", crate::cost::format_usd(cost)).unwrap();
+        }
 
         // Repomap status
         let repomap_str = match self.repomap_status {
@@ -532,6 +620,9 @@ impl TuiApp {
             crate::tui::state::InputMode::SessionList => "SessionList",
         };
         footer_text.push_str(&format!("Mode: {} | ", mode_str));
+        if self.plan_mode {
+            footer_text.push_str("PLAN | ");
+        }
 
         // Elapsed time
         if let Some(start_time) = self.processing_start_time {
@@ -1,4 +1,8 @@
-use crate::{config::IGNORE_FILE, tui::diff_review::DiffReviewState, tui::theme::Theme};
+use crate::{
+    config::IGNORE_FILE, tui::diff_review::DiffReviewState,
+    tui::permission_prompt::PermissionPromptState, tui::plan_review::PlanReviewState,
+    tui::theme::Theme,
+};
 use anyhow::Result;
 use crossterm::{
     cursor, execute,
@@ -127,6 +131,11 @@ impl StyledLine {
 pub enum LogEntry {
     Plain(String),
     Markdown(String),
+    /// Model reasoning, shown as a one-line summary while collapsed
+    Thinking {
+        text: String,
+        collapsed: bool,
+    },
 }
 
 fn flush_segment(segments: &mut Vec<StyledSpan>, buffer: &mut String, style: Style) {
@@ -367,6 +376,52 @@ fn render_plain_entry(text: &str, width: usize, theme: &Theme) -> Vec<StyledLine
     lines
 }
 
+fn render_thinking_entry(
+    text: &str,
+    collapsed: bool,
+    width: usize,
+    theme: &Theme,
+) -> Vec<StyledLine> {
+    let style = theme.thinking_style.add_modifier(Modifier::ITALIC);
+    let body = text.trim();
+    let line_count = body.lines().count();
+    let header = if collapsed {
+        format!(
+            "▸ Thinking ({line_count} line{}) — /thinking to expand",
+            if line_count == 1 { "" } else { "s" }
+        )
+    } else {
+        "▾ Thinking".to_string()
+    };
+    let mut lines = wrap_segments(
+        &[StyledSpan {
+            content: header,
+            style,
+        }],
+        width.max(1),
+    );
+    if collapsed {
+        return lines;
+    }
+
+    let margin = "  │ ";
+    let available_width = width.saturating_sub(margin.chars().count()).max(1);
+    for part in body.split('\n') {
+        let mut wrapped = wrap_segments(
+            &[StyledSpan {
+                content: part.to_string(),
+                style,
+            }],
+            available_width,
+        );
+        for line in &mut wrapped {
+            line.prepend_margin(margin, theme.thinking_style);
+        }
+        lines.extend(wrapped);
+    }
+    lines
+}
+
 fn parse_ordered_marker(line: &str) -> Option<(&str, &str)> {
     let chars = line.char_indices();
     let mut has_digit = false;
@@ -585,6 +640,9 @@ impl LogEntry {
         match self {
             LogEntry::Plain(text) => render_plain_entry(text, width, theme),
             LogEntry::Markdown(text) => render_markdown_entry(text, width, theme),
+            LogEntry::Thinking { text, collapsed } => {
+                render_thinking_entry(text, *collapsed, width, theme)
+            }
         }
     }
 }
@@ -678,6 +736,8 @@ pub struct TuiApp {
     /// Index in `log` where the current streaming response started so it can be replaced
     /// with the final markdown-rendered content.
     pub current_stream_start: Option<usize>,
+    /// Whether reasoning blocks are shown collapsed (toggled by /thinking)
+    pub thinking_collapsed: bool,
     // Input mode
     pub input_mode: InputMode,
     // session management
@@ -689,6 +749,10 @@ pub struct TuiApp {
     pub tokens_prompt_used: u32,
     // Total tokens (if available)
     pub tokens_total_used: Option<u32>,
+    // Estimated prompt tokens of the last request and the model's context window
+    pub context_usage: Option<(u32, u32)>,
+    // Spend of the current session in USD, once any request has been priced
+    pub session_cost: Option<f64>,
     // redraw flag
     pub dirty: bool,
     // scroll state
@@ -705,6 +769,15 @@ pub struct TuiApp {
     pub auto_compact_pending: bool,
     pub pending_instructions: VecDeque<String>,
     pub diff_review: Option<DiffReviewState>,
+    // plan mode (toggled by /plan or Shift+Tab) and the plan awaiting review
+    pub plan_mode: bool,
+    pub plan_review: Option<PlanReviewState>,
+    // Tool calls waiting for approval, oldest first
+    pub permission_prompts: VecDeque<PermissionPromptState>,
+    // Sandbox for shell-mode commands; None when disabled
+    pub sandbox: Option<crate::tools::sandbox::Sandbox>,
+    // Allow and deny rules shell-mode commands are checked against
+    pub command_policy: crate::tools::command_policy::CommandPolicy,
     // todo list
     pub todo_list: Vec<TodoItem>,
     /// If true, the todo list received from `todo_write` that contained only
@@ -714,6 +787,8 @@ pub struct TuiApp {
     pub hide_todo_on_next_instruction: bool,
     // last user input for retrying after compact
     pub last_user_input: Option<String>,
+    // clipboard images pasted into the input, referenced as "[image #N]"
+    pub pasted_images: Vec<crate::llm::ImagePart>,
     // session list state
     pub session_list_state: Option<SessionListState>,
     /// Status of the repomap
@@ -737,8 +812,14 @@ impl TuiApp {
             "/session".to_string(),
             "/rebuild-repomap".to_string(),
             "/tokens".to_string(),
+            "/cost".to_string(),
+            "/thinking".to_string(),
             "/cancel".to_string(),
             "/compact".to_string(),
+            "/plan".to_string(),
+            "/permissions".to_string(),
+            "/undo".to_string(),
+            "/rewind".to_string(),
             "/git-worktree".to_string(),
         ];
 
@@ -877,10 +958,13 @@ impl TuiApp {
             is_llm_response_active: false, // Initialize the flag
             last_llm_response_content: None,
             current_stream_start: None,
+            thinking_collapsed: true,
             input_mode: InputMode::default(),
             tokens_used: 0,
             tokens_prompt_used: 0,
             tokens_total_used: None,
+            context_usage: None,
+            session_cost: None,
             dirty: true, // initial full render
             scroll_state: ScrollState::default(),
             completion_candidates: Vec::new(),
@@ -895,11 +979,17 @@ impl TuiApp {
             auto_compact_pending: false,
             pending_instructions: VecDeque::new(),
             diff_review: None,
+            plan_mode: false,
+            plan_review: None,
+            permission_prompts: VecDeque::new(),
+            sandbox: None,
+            command_policy: Default::default(),
             // todo list
             todo_list: Vec::new(),
             hide_todo_on_next_instruction: false,
             // last user input for retrying after compact
             last_user_input: None,
+            pasted_images: Vec::new(),
             // session list state
             session_list_state: None,
             // repomap status
@@ -1014,6 +1104,15 @@ impl TuiApp {
         }
     }
 
+    /// Share of the context window used by the last request, in percent.
+    pub fn context_percent(&self) -> Option<u32> {
+        let (used, window) = self.context_usage?;
+        if window == 0 {
+            return None;
+        }
+        Some((used as u64 * 100 / window as u64) as u32)
+    }
+
     pub fn push_markdown_response(&mut self, content: &str) {
         if content.trim().is_empty() {
             return;
@@ -1038,6 +1137,41 @@ impl TuiApp {
         }
     }
 
+    /// Start a new reasoning block at the end of the log.
+    pub fn push_thinking(&mut self, text: &str) {
+        let lines_before = self.log.len();
+        self.log.push(LogEntry::Thinking {
+            text: text.to_string(),
+            collapsed: self.thinking_collapsed,
+        });
+
+        if self.log.len() > self.max_log_lines {
+            let overflow = self.log.len() - self.max_log_lines;
+            self.log.drain(0..overflow);
+        }
+
+        if !self.scroll_state.auto_scroll {
+            let new_lines = self.log.len().saturating_sub(lines_before);
+            self.scroll_state.new_messages =
+                self.scroll_state.new_messages.saturating_add(new_lines);
+        }
+
+        if self.scroll_state.auto_scroll {
+            self.scroll_state.offset = 0;
+        }
+    }
+
+    /// Collapse or expand every reasoning block, including future ones.
+    pub fn set_thinking_collapsed(&mut self, collapsed: bool) {
+        self.thinking_collapsed = collapsed;
+        for entry in &mut self.log {
+            if let LogEntry::Thinking { collapsed: c, .. } = entry {
+                *c = collapsed;
+            }
+        }
+        self.dirty = true;
+    }
+
     /// Scroll up by the specified number of lines
     pub fn scroll_up(&mut self, lines: usize) {
         self.scroll_state.auto_scroll = false;
@@ -1100,6 +1234,25 @@ impl TuiApp {
         self.dirty = true;
     }
 
+    /// Attach a pasted image and insert its placeholder at the cursor.
+    pub fn paste_image(&mut self, image: crate::llm::ImagePart) {
+        self.pasted_images.push(image);
+        let placeholder = format!("[image #{}]", self.pasted_images.len());
+        self.textarea.insert_str(&placeholder);
+        self.dirty = true;
+    }
+
+    /// Pasted images whose placeholder is still in `line`; all of them are
+    /// cleared for the next input.
+    pub fn take_pasted_images(&mut self, line: &str) -> Vec<crate::llm::ImagePart> {
+        std::mem::take(&mut self.pasted_images)
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| line.contains(&format!("[image #{}]", i + 1)))
+            .map(|(_, image)| image)
+            .collect()
+    }
+
     pub fn dispatch(&mut self, line: &str) {
         // Clear the last LLM response content as a new user command is being processed
         self.last_llm_response_content = None;
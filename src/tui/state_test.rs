@@ -49,6 +49,8 @@ fn test_get_all_commands_with_custom_commands() {
     assert!(all_commands.contains(&"/session".to_string()));
     assert!(all_commands.contains(&"/rebuild-repomap".to_string()));
     assert!(all_commands.contains(&"/tokens".to_string()));
+    assert!(all_commands.contains(&"/cost".to_string()));
+    assert!(all_commands.contains(&"/thinking".to_string()));
     assert!(all_commands.contains(&"/git-worktree".to_string()));
 
     assert!(all_commands.contains(&"/cancel".to_string()));
@@ -12,6 +12,7 @@ mod tests {
             .map(|entry| match entry {
                 LogEntry::Plain(text) => text.clone(),
                 LogEntry::Markdown(text) => text.clone(),
+                LogEntry::Thinking { text, .. } => text.clone(),
             })
             .collect()
     }
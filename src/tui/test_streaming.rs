@@ -0,0 +1,88 @@
+#[cfg(test)]
+mod tests {
+    use crate::tui::state::{LogEntry, TuiApp};
+
+    fn plain_lines(app: &TuiApp) -> Vec<String> {
+        app.log
+            .iter()
+            .map(|e| match e {
+                LogEntry::Plain(s) | LogEntry::Markdown(s) => s.clone(),
+                LogEntry::Thinking { text, .. } => text.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stream_tokens_extend_the_current_line() {
+        let mut app = TuiApp::new("test", None, "dark").unwrap();
+        app.push_log("> hello");
+
+        app.append_stream_token_structured("Hel");
+        app.append_stream_token_structured("lo wor");
+        app.append_stream_token_structured("ld\nsecond");
+        app.append_stream_token_structured(" line");
+
+        assert_eq!(
+            plain_lines(&app),
+            vec!["> hello", "  Hello world", "  second line"]
+        );
+        assert_eq!(
+            app.last_llm_response_content.as_deref(),
+            Some("Hello world\nsecond line")
+        );
+    }
+
+    #[test]
+    fn finalize_replaces_streamed_lines_with_markdown() {
+        let mut app = TuiApp::new("test", None, "dark").unwrap();
+        app.push_log("> hi");
+        app.append_stream_token_structured("**bold**");
+        app.append_stream_token_structured(" text");
+
+        app.finalize_and_append_llm_response("");
+
+        assert_eq!(app.log.len(), 2);
+        assert!(matches!(&app.log[1], LogEntry::Markdown(s) if s == "**bold** text"));
+        assert!(app.current_stream_start.is_none());
+    }
+
+    #[test]
+    fn finalize_prefers_provided_content() {
+        let mut app = TuiApp::new("test", None, "dark").unwrap();
+        app.append_stream_token_structured("partial");
+        app.finalize_and_append_llm_response("final answer");
+
+        assert_eq!(app.log.len(), 1);
+        assert!(matches!(&app.log[0], LogEntry::Markdown(s) if s == "final answer"));
+
+        // A second segment after tool output starts fresh
+        app.push_log("🛠️ tool");
+        app.append_stream_token_structured("next");
+        assert_eq!(plain_lines(&app).last().unwrap(), "  next");
+        app.finalize_and_append_llm_response("");
+        assert!(matches!(app.log.last(), Some(LogEntry::Markdown(s)) if s == "next"));
+    }
+
+    #[test]
+    fn reasoning_streams_into_a_collapsible_block() {
+        let mut app = TuiApp::new("test", None, "dark").unwrap();
+        app.push_log("> why?");
+        app.append_reasoning_token("First check ");
+        app.append_reasoning_token("the tests.\nThen answer.");
+        app.append_stream_token_structured("Done");
+        app.finalize_and_append_llm_response("");
+
+        assert_eq!(app.log.len(), 3);
+        assert!(matches!(
+            &app.log[1],
+            LogEntry::Thinking { text, collapsed: true }
+                if text == "First check the tests.\nThen answer."
+        ));
+        assert!(matches!(&app.log[2], LogEntry::Markdown(s) if s == "Done"));
+
+        let theme = app.theme.clone();
+        assert_eq!(app.log[1].render(80, &theme).len(), 1);
+        app.set_thinking_collapsed(false);
+        assert_eq!(app.log[1].render(80, &theme).len(), 3);
+    }
+}
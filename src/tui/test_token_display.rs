@@ -38,4 +38,16 @@ mod tests {
         // Check that the model is still included
         assert!(plan.footer_lines[0].contains("model:test-model"));
     }
+
+    #[test]
+    fn test_context_percent() {
+        let mut app = crate::tui::state::TuiApp::new("test", None, "dark").unwrap();
+        assert_eq!(app.context_percent(), None);
+
+        app.context_usage = Some((50_000, 200_000));
+        assert_eq!(app.context_percent(), Some(25));
+
+        app.context_usage = Some((10, 0));
+        assert_eq!(app.context_percent(), None);
+    }
 }
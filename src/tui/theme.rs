@@ -12,6 +12,7 @@ pub struct Theme {
     pub code_block_style: Style,
     pub completion_style: Style,
     pub completion_selected_style: Style,
+    pub thinking_style: Style,
 }
 
 impl Theme {
@@ -26,6 +27,7 @@ impl Theme {
             code_block_style: Style::default().fg(Color::LightCyan),
             completion_style: Style::default().fg(Color::Gray),
             completion_selected_style: Style::default().bg(Color::DarkGray).fg(Color::White),
+            thinking_style: Style::default().fg(Color::DarkGray),
         }
     }
 
@@ -40,6 +42,7 @@ impl Theme {
             code_block_style: Style::default().fg(Color::Magenta),
             completion_style: Style::default().fg(Color::DarkGray),
             completion_selected_style: Style::default().bg(Color::Gray).fg(Color::Black),
+            thinking_style: Style::default().fg(Color::Gray),
         }
     }
 }
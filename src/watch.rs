@@ -12,7 +12,7 @@ use tokio::time::sleep;
 use tracing::{error, info, warn};
 
 use crate::config::AppConfig;
-use crate::llm::client_core::OpenAIClient;
+use crate::llm::LlmProvider;
 use crate::llm::types::ChatMessage;
 
 use std::sync::{Arc, Mutex};
@@ -24,8 +24,8 @@ pub async fn run_watch_mode(cfg: AppConfig) -> Result<()> {
     // Load and parse .gitignore
     let gitignore = load_gitignore()?;
 
-    let llm_client = OpenAIClient::new(&cfg.base_url, cfg.api_key.as_ref().unwrap())?
-        .with_llm_config(cfg.llm.clone());
+    let llm_client = crate::llm::build_provider(&cfg)?
+        .ok_or_else(|| anyhow::anyhow!("API key is not set; cannot run watch mode"))?;
     let model = cfg.model.clone();
 
     // Use Arc<Mutex<>> for thread-safe access to file processing tracking
@@ -146,7 +146,7 @@ fn glob_match(pattern: &str, path: &str) -> bool {
 // Debounce function to prevent rapid multiple calls for the same file
 async fn debounce_file_change(
     path: PathBuf,
-    llm_client: OpenAIClient,
+    llm_client: Arc<dyn LlmProvider>,
     model: String,
     last_processed: Arc<Mutex<HashMap<PathBuf, Instant>>>,
     cfg: AppConfig,
@@ -170,7 +170,7 @@ async fn debounce_file_change(
     }
 
     // Actually handle the file change
-    if let Err(e) = handle_file_change(&llm_client, &model, path.clone(), &cfg).await {
+    if let Err(e) = handle_file_change(llm_client.as_ref(), &model, path.clone(), &cfg).await {
         error!("Error handling file change: {}", e);
     } else {
         // Update the last processed time
@@ -184,7 +184,7 @@ async fn debounce_file_change(
 }
 
 async fn handle_file_change(
-    llm_client: &OpenAIClient,
+    llm_client: &dyn LlmProvider,
     model: &str,
     path: PathBuf,
     cfg: &AppConfig,
@@ -244,7 +244,7 @@ async fn handle_file_change(
 }
 
 async fn execute_llm_task(
-    llm_client: &OpenAIClient,
+    llm_client: &dyn LlmProvider,
     model: &str,
     file_content: &str,
     instruction: &str,
@@ -275,12 +275,16 @@ async fn execute_llm_task(
             content: Some(system_prompt),
             tool_calls: vec![],
             tool_call_id: None,
+            reasoning: None,
+            images: vec![],
         },
         ChatMessage {
             role: "user".to_string(),
             content: Some(user_prompt),
             tool_calls: vec![],
             tool_call_id: None,
+            reasoning: None,
+            images: vec![],
         },
     ];
 